@@ -1,7 +1,7 @@
 use std::{fmt::Display, fs::File, io::{BufReader, BufWriter}, process::exit};
 
 use colored::{ColoredString, Colorize};
-use rpg_helper::api::{data::{context::Context, tag::Tag}, parse::json::ParseJson};
+use rpg_helper::api::{data::{context::{Context, TagFilter}, tag::{Tag, TagRegistry}, text::TextValue}, parse::json::ParseJson};
 
 use crate::cmd::{default, CmdContext};
 
@@ -13,13 +13,14 @@ pub struct CtxData
     pub open: Option<Context>,
     pub dirty: bool,            // Dirty being true means we need to warn to save before close
     pub ctx_submode: CtxSubmode,
+    pub registry: TagRegistry,  // Names of the tags used by the open dataset
 }
 
 impl CtxData
 {
     pub fn new() -> CtxData
     {
-        CtxData { open: None, dirty: false, ctx_submode: CtxSubmode::Default }
+        CtxData { open: None, dirty: false, ctx_submode: CtxSubmode::Default, registry: TagRegistry::new() }
     }
 
     pub fn to_prompt(&self) -> String
@@ -37,7 +38,7 @@ impl CtxData
                     "[Data] >> ".to_string()
                 }
             },
-            CtxSubmode::Set(ctx_data) => ctx_data.to_prompt(),
+            CtxSubmode::Set(ctx_data) => ctx_data.to_prompt(&self.registry),
         }
     }
 }
@@ -142,6 +143,8 @@ Available commands (Data Mode):
     new                                            - Creates a new open dataset
     set [a|c|e|m|t|x]                              - Opens mode [Data - Set] to set a value in the open dataset
     get [value|text] <TAG_NAME>                    - Get the value within the open dataset
+    get query <QUERY>                              - Get the tags matching a query, such as "ability.*.exp" or "**.magnitude",
+                                                     or the values of tags with "ability.* where value >= 5"
     display <TAG_NAME>                             - Display all the data for the given tag
     remove [a|c|e|m|t|x] <TAG_NAME>                - Removes an attribute, conditional, equation, modifier, tag, 
                                                      or text with the given tag name.
@@ -339,7 +342,80 @@ fn get(parts: Vec<&str>, ctx_data: &mut CtxData, s: &str) -> Result<ColoredStrin
                 return Err("No dataset is open to read.".red());
             }
         },
-        "text" => todo!(),
+        "text" =>
+        {
+            if let Some(d) = &ctx_data.open
+            {
+                match ctx_data.registry.get_tag(s)
+                {
+                    Ok(t) =>
+                    {
+                        info!("[Data - Open] Command used: \"get text {}\"", s);
+                        if let Some(v) = t.and_then(|t| d.get_text(&t))
+                        {
+                            Ok(format!("Got text: {}", text_name(v, &ctx_data.registry)).cyan())
+                        }
+                        else
+                        {
+                            Ok(format!("Found no text for \"{}\"", s).cyan())
+                        }
+                    },
+                    Err(e) =>
+                    {
+                        error!("[Data - Open] Parse error on input tag \"{}\":\n{:?}", s, e);
+                        Err(format!("Could not parse given tag \"{}\":\n{:?}", s, e).red())
+                    },
+                }
+            }
+            else
+            {
+                warn!("[Data] Attempt to get when no dataset is open");
+                return Err("No dataset is open to read.".red());
+            }
+        },
+        "query" =>
+        {
+            if let Some(d) = &ctx_data.open
+            {
+                match TagFilter::from_str(s, &mut ctx_data.registry)
+                {
+                    Ok(filter) =>
+                    {
+                        info!("[Data - Open] Command used: \"get query {}\"", s);
+                        let registry = &ctx_data.registry;
+                        let result = if filter.has_value_predicates()
+                        {
+                            d.query_values(&filter).map(|v| v.into_iter().map(|(t, v)| format!("{}: {}", tag_name(&t, registry), v)).collect::<Vec<_>>())
+                        }
+                        else
+                        {
+                            d.query_tags(&filter).map(|v| v.into_iter().map(|t| tag_name(&t, registry)).collect::<Vec<_>>())
+                        };
+
+                        match result
+                        {
+                            Ok(found) if found.is_empty() => Ok(format!("Found no tags matching \"{}\"", s).cyan()),
+                            Ok(found) => Ok(found.join("\n").cyan()),
+                            Err(e) =>
+                            {
+                                error!("[Data - Open] Could not query \"{}\":\n{:?}", s, e);
+                                Err(format!("Could not query \"{}\":\n{}", s, d.diagnose(s, &e, registry)).red())
+                            },
+                        }
+                    },
+                    Err(e) =>
+                    {
+                        error!("[Data - Open] Parse error on input query \"{}\":\n{:?}", s, e);
+                        Err(format!("Could not parse given query:\n{}", e.to_diagnostic()).red())
+                    },
+                }
+            }
+            else
+            {
+                warn!("[Data] Attempt to get when no dataset is open");
+                return Err("No dataset is open to read.".red());
+            }
+        },
         _ =>
         {
             warn!("[Data] Attempt to use \"get\" command, invalid target \"{}\" for command", parts[1]);
@@ -450,4 +526,16 @@ fn close(parts: Vec<&str>, cmd_context: &mut CmdContext) -> Result<ColoredString
             Ok("Closed dataset".cyan())
         },
     }
-}
\ No newline at end of file
+}
+
+/// The name of a tag, or its debug form if the tag is not in the registry
+fn tag_name(tag: &Tag, registry: &TagRegistry) -> String
+{
+    tag.to_string(registry).unwrap_or_else(|| format!("{:?}", tag))
+}
+
+/// The text, or the name of the selected tag of an enum, or its debug form if that tag is not in the registry
+fn text_name(val: &TextValue, registry: &TagRegistry) -> String
+{
+    val.to_string(registry).unwrap_or_else(|| format!("{:?}", val))
+}
@@ -1,9 +1,9 @@
 use std::process::exit;
 
 use colored::{ColoredString, Colorize};
-use rpg_helper::api::data::{effect::Effect, equation::Equation, tag::Tag};
+use rpg_helper::api::data::{effect::Effect, equation::Equation, tag::{Tag, TagRegistry}, text::TextValue};
 
-use crate::cmd::{data::CtxSubmode, CmdContext};
+use crate::cmd::{data::{tag_name, text_name, CtxSubmode}, CmdContext};
 
 
 #[derive(Clone, Debug)]
@@ -14,7 +14,7 @@ pub enum CtxData
     Equation(EqCtxData),
     Modifier(),
     Tag(TagCtxData),
-    Text(),
+    Text(TextCtxData),
 }
 
 impl CtxData
@@ -46,10 +46,10 @@ impl CtxData
 
     pub fn new_text() -> Self
     {
-        CtxData::Text()
+        CtxData::Text(TextCtxData::RequestTextName)
     }
 
-    pub fn to_prompt(&self) -> String
+    pub fn to_prompt(&self, registry: &TagRegistry) -> String
     {
         match self
         {
@@ -81,7 +81,15 @@ impl CtxData
                     TagCtxData::ConfirmTagName(n) => format!("Confirm tag name \"{}\" [y/n]: ", n),
                 }
             },
-            CtxData::Text() => todo!(),
+            CtxData::Text(t) =>
+            {
+                match t
+                {
+                    TextCtxData::RequestTextName => "Please enter a text name: ".to_string(),
+                    TextCtxData::RequestTextValue(tag) => format!("Please enter text for \"{}\" (or \"<options>: <selected>\" for an enum): ", tag_name(tag, registry)),
+                    TextCtxData::ConfirmText(tag, val) => format!("Confirm text \"{}: {}\" [y/n]: ", tag_name(tag, registry), text_name(val, registry)),
+                }
+            },
         }
     }
 }
@@ -109,6 +117,14 @@ pub enum EqCtxData
     ConfirmEq(Tag, String),
 }
 
+#[derive(Clone, Debug)]
+pub enum TextCtxData
+{
+    RequestTextName,
+    RequestTextValue(Tag),
+    ConfirmText(Tag, TextValue),
+}
+
 pub fn execute_command(s: &str, cmd_context: &mut CmdContext) -> Result<ColoredString, ColoredString> 
 {
     match cmd_context
@@ -136,7 +152,7 @@ pub fn execute_command(s: &str, cmd_context: &mut CmdContext) -> Result<ColoredS
                         CtxData::Equation(_) => execute_equation(s, cmd_context),
                         CtxData::Modifier() => todo!(),
                         CtxData::Tag(_) => execute_tag(s, cmd_context),
-                        CtxData::Text() => todo!(),
+                        CtxData::Text(_) => execute_text(s, cmd_context),
                     }
                 },
             }
@@ -328,6 +344,117 @@ fn execute_equation(s: &str, cmd_context: &mut CmdContext) -> Result<ColoredStri
     }
 }
 
+/// Input of the form "<options>: <selected>" where both sides are tags is read as an enum,
+/// otherwise the input is read as free text with any surrounding quotes removed.
+fn parse_text_value(s: &str, registry: &mut TagRegistry) -> Result<TextValue, String>
+{
+    let s = s.trim();
+    if let Some((options, selected)) = s.split_once(':')
+    {
+        if let (Ok(options), Ok(selected)) = (registry.get_or_register_tag(options.trim()), registry.get_or_register_tag(selected.trim()))
+        {
+            return TextValue::new_enum(options, selected).map_err(|e| format!("{:?}", e));
+        }
+    }
+    Ok(TextValue::Text(s.strip_prefix('"').and_then(|s| s.strip_suffix('"')).unwrap_or(s).to_string()))
+}
+
+fn execute_text(s: &str, cmd_context: &mut CmdContext) -> Result<ColoredString, ColoredString>
+{
+    if let CmdContext::Data(data_ctx) = cmd_context
+    {
+        if let CtxSubmode::Set(ctx) = &mut data_ctx.ctx_submode
+        {
+            if let CtxData::Text(ctx) = ctx
+            {
+                match ctx.clone()
+                {
+                    TextCtxData::RequestTextName =>
+                    {
+                        match data_ctx.registry.get_or_register_tag(s)
+                        {
+                            Ok(tag) => 
+                            {
+                                info!("[Set - Text] Created tag \"{}\"", s);
+                                *ctx = TextCtxData::RequestTextValue(tag);
+                                Ok(format!("Text name formatted: \"{}\"", s).cyan())
+                            },
+                            Err(e) =>
+                            {
+                                error!("[Set - Text] Parse error on input tag \"{}\":\n{:?}", s, e);
+                                Err(format!("Could not parse given tag \"{}\":\n{:?}", s, e).red())
+                            },
+                        }
+                    },
+                    TextCtxData::RequestTextValue(tag) =>
+                    {
+                        match parse_text_value(s, &mut data_ctx.registry)
+                        {
+                            Ok(val) =>
+                            {
+                                let val_name = text_name(&val, &data_ctx.registry);
+                                info!("[Set - Text] Text formatted \"{}\"", val_name);
+                                *ctx = TextCtxData::ConfirmText(tag.clone(), val);
+                                Ok(format!("Text value formatted: \"{}\"", val_name).cyan())
+                            },
+                            Err(e) =>
+                            {
+                                error!("[Set - Text] Error on text creation \"{}\":\n{}", s, e);
+                                Err(format!("Got error from input text \"{}\":\n{}", s, e).red())
+                            },
+                        }
+                    },
+                    TextCtxData::ConfirmText(tag, val) =>
+                    {
+                        let s = s.trim();
+                        let (tag_str, val_str) = (tag_name(&tag, &data_ctx.registry), text_name(&val, &data_ctx.registry));
+                        if s == "y" || s == "yes"
+                        {
+                            info!("[Set - Text] Set text \"{}: {}\"", tag_str, val_str);
+                            match data_ctx.open.as_mut().unwrap().apply_effect(&Effect::SetText(tag.clone(), val.clone()))
+                            {
+                                Ok(_) => (),
+                                Err(e) =>
+                                {
+                                    error!("[Set - Text] Could not set text \"{}: {}\":\n{:?}", tag_str, val_str, e);
+                                    return Err(format!("Could not apply text to data set \"{}: {}\":\n{:?}", tag_str, val_str, e).red())
+                                },
+                            };
+                            data_ctx.ctx_submode = CtxSubmode::Default;
+                            Ok(format!("Set text \"{}: {}\"", tag_str, val_str).cyan())
+                        }
+                        else if s == "n" || s == "no"
+                        {
+                            info!("[Set - Text] Discard text \"{}: {}\"", tag_str, val_str);
+                            *ctx = TextCtxData::RequestTextName;
+                            Ok(format!("Discarding text: \"{}: {}\"", tag_str, val_str).cyan())
+                        }
+                        else
+                        {
+                            warn!("[Set - Text] Confirming text, unrecognized response \"{}\"", s);
+                            Err(format!("Unrecognized response \"{}\", please input \"yes\" or \"no\"", s).red())
+                        }
+                    },
+                }
+            }
+            else
+            {
+                error!("[Set] Tried to execute command in invalid context: \"{:?}\"", cmd_context);
+                exit(1)
+            }
+        }
+        else
+        {
+            error!("[Set] Tried to execute command in invalid context: \"{:?}\"", cmd_context);
+            exit(1)
+        }
+    }
+    else
+    {
+        error!("[Set] Tried to execute command in invalid context: \"{:?}\"", cmd_context);
+        exit(1)
+    }
+}
 
 fn execute_tag(s: &str, cmd_context: &mut CmdContext) -> Result<ColoredString, ColoredString>
 {
@@ -1,6 +1,6 @@
 use std::{cell::RefCell, rc::Rc};
 
-use rpg_helper::api::data::{equation::Equation, tag::Tag};
+use rpg_helper::api::data::{diagnostic::Diagnostic, equation::Equation, tag::{Tag, TagRegistry}};
 use web_sys::{wasm_bindgen::JsCast, HtmlInputElement};
 use validator::ValidationErrors;
 use yew::prelude::*;
@@ -27,6 +27,9 @@ pub struct Props
     pub node_ref: NodeRef,
     #[prop_or_default]
     pub errors: Rc<RefCell<ValidationErrors>>,
+    /// Names the tags of any error found in the input
+    #[prop_or_default]
+    pub registry: Rc<RefCell<TagRegistry>>,
 }
 
 /// This component handles user input for creation of an equation.
@@ -39,17 +42,21 @@ pub struct Props
 /// The input performs validation (error checks the input)
 /// before the created equation is handed off in the callback.
 /// If the input provided is not a valid equation, then the
-/// equation callback will not fire.
+/// equation callback will not fire, and the part of the input
+/// causing the error is highlighted.
 #[function_component(EquationInput)]
 pub fn equation_input(props: &Props) -> Html
 {
     let error_message = use_state(|| None);
+    let diagnostic = use_state(|| None::<Diagnostic>);
     let value = use_state(|| props.default_value.clone());
     let onchange = 
     {
         let e_id = props.equation_id.clone();
         let callback = props.onchange.clone();
         let error_message = error_message.clone();
+        let diagnostic = diagnostic.clone();
+        let registry = props.registry.clone();
         let allowed = props.allowed_tag_values.clone();
         let value = value.clone();
         Callback::from(move |e: Event|
@@ -57,6 +64,7 @@ pub fn equation_input(props: &Props) -> Html
                 if let Some(target) = e.target()
                 {
                     let input_string = target.unchecked_into::<HtmlInputElement>().value();
+                    diagnostic.set(None);
                     if input_string.is_empty()
                     {
                         error_message.set(Some("Empty input not valid".to_string()));
@@ -102,7 +110,9 @@ pub fn equation_input(props: &Props) -> Html
                                 Err(e) =>
                                 {
                                     log::warn!("Failed to parse equation: {:?}", e);
-                                    error_message.set(Some(format!("{:?}", e)));
+                                    let d = e.to_diagnostic(&input_string, &registry.borrow());
+                                    error_message.set(Some(d.message.clone()));
+                                    diagnostic.set(Some(d));
                                 },
                             }
                         }
@@ -118,6 +128,13 @@ pub fn equation_input(props: &Props) -> Html
         )
     };
 
+    let error_view = match (&*diagnostic, &*error_message)
+    {
+        (Some(d), _) => view_diagnostic(d),
+        (None, Some(message)) => html! { <span class="input-error">{message}</span> },
+        (None, None) => html! {},
+    };
+
     html!
     {
         <>
@@ -132,14 +149,38 @@ pub fn equation_input(props: &Props) -> Html
                 ref={props.node_ref.clone()}
             />
             // TODO: Use validation errors to communicate errors, don't display always all-the-time
-            if let Some(message) = &*error_message
-            {
-                <span class="input-error">{message}</span>
-            }
+            {error_view}
         </>
     }
 }
 
+/// Shows the message of the diagnostic with the erroring part of the input marked,
+/// followed by any notes and suggestions
+fn view_diagnostic(d: &Diagnostic) -> Html
+{
+    let source = match d.span
+    {
+        Some(span) =>
+        {
+            let before = d.source.get(..span.start).unwrap_or("").to_string();
+            let marked = d.source.get(span.start..span.end).unwrap_or("").to_string();
+            let after = d.source.get(span.end..).unwrap_or("").to_string();
+            html! { <code>{before}<mark>{marked}</mark>{after}</code> }
+        },
+        None => html! {},
+    };
+
+    html!
+    {
+        <span class="input-error">
+            {d.message.clone()}
+            {source}
+            { for d.notes.iter().map(|note| html! { <span class="input-error-note">{note}</span> }) }
+            { for d.suggestions.iter().map(|s| html! { <span class="input-error-note">{format!("Did you mean \"{}\"?", s.replacement)}</span> }) }
+        </span>
+    }
+}
+
 fn handle_equation_complete(equation: Equation, callback: &Callback<Equation>, error_message: &UseStateHandle<Option<String>>)
 {
     callback.emit(equation);
@@ -108,7 +108,11 @@ pub fn date_editor(props: &Props) -> Html
             }
         })
     };
-    let allowed: Vec<Tag> = tag_set.iter().flat_map(|t| [t.add_prefix(DateSpec::get_ordering_lhs_tag()), t.add_prefix(DateSpec::get_ordering_rhs_tag())]).collect();
+    let allowed: Vec<Tag> =
+    {
+        let current = props.current.borrow();
+        tag_set.iter().flat_map(|t| [t.add_prefix(current.get_ordering_lhs_tag()), t.add_prefix(current.get_ordering_rhs_tag())]).collect()
+    };
 
     html!
     {
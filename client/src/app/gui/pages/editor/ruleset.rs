@@ -1,6 +1,6 @@
 use std::{cell::RefCell, rc::Rc};
 
-use rpg_helper::api::{data::tag::Tag, display::icon::Icon, rpg::timeline::DateSpec};
+use rpg_helper::api::{data::{context::Context, tag::{Tag, TagRegistry}}, display::icon::Icon, rpg::timeline::{Calendar, DateSpec}};
 use yew::prelude::*;
 
 use crate::app::gui::{atoms::{icon::IconHtml, input::{equation_input::EquationInput, tag_input::TagInput}}, molecules::tooltip::helper_tooltip::HelperTooltip, organisms::input::date_spec_editor::DateSpecEditor, pages::{editor::editor_bar::EditorBar, BasePage}};
@@ -11,7 +11,17 @@ pub fn ruleset_editor() -> Html
     let equation_id = Rc::new(RefCell::new(Tag::from_str("test.equation").unwrap()));
     let allowed = Rc::new(RefCell::new(vec![Tag::from_str("lhs.Year").unwrap(), Tag::from_str("rhs.Year").unwrap()]));
     
-    let date_spec = use_state(|| Rc::new(RefCell::new(DateSpec::default())));
+    let registry = use_state(|| Rc::new(RefCell::new(TagRegistry::new())));
+    let date_spec =
+    {
+        let registry = registry.clone();
+        use_state(move ||
+        {
+            let mut registry = registry.borrow_mut();
+            let time_ctx_id = registry.get_or_register_subtag("mundane").unwrap();
+            Rc::new(RefCell::new(Calendar::new(time_ctx_id, Context::new(), &mut registry).get_date_spec(&mut registry)))
+        })
+    };
     let onchange = Callback::from(|_: DateSpec| {});
     html!
     {
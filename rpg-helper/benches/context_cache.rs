@@ -0,0 +1,111 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rpg_helper::api::data::{conditional::Conditional, context::Context, equation::Equation, modifier::{Modifier, ModifierChange, ModifierTarget}, tag::{Tag, TagRegistry}};
+
+const ABILITIES: usize = 60;
+const ARTS: usize = 15;
+const SPELLS: usize = 100;
+
+fn tag(registry: &mut TagRegistry, s: &str) -> Tag
+{
+    registry.get_or_register_tag(s).unwrap()
+}
+
+/// Numbers can not start a subtag in an equation, so names are made of letters instead (0 => a, 26 => ba)
+fn name(mut i: usize) -> String
+{
+    let mut result = String::new();
+    loop
+    {
+        result.insert(0, (b'a' + (i % 26) as u8) as char);
+        i /= 26;
+        if i == 0
+        {
+            return result;
+        }
+    }
+}
+
+/// Builds a context roughly the size of an Ars Magica magus
+/// - Abilities and arts with experience and a score derived from it
+/// - Spells with a level summed from their magnitudes
+/// - Totals aggregating over all of the above
+/// - Modifiers applied by state of the character (and to every ability)
+fn ars_magica_context(registry: &mut TagRegistry) -> (Context, Vec<Tag>)
+{
+    let mut tag = |s: &str| tag(registry, s);
+    let mut ctx = Context::new();
+    let mut values = vec![];
+
+    ctx.set_conditional(Conditional::new(tag("character.is magus"), "character.magus").unwrap()).unwrap();
+    ctx.add_explicit_tag(&tag("character.magus"));
+
+    for i in 0..ABILITIES
+    {
+        let n = name(i);
+        ctx.set_attribute(&tag(&format!("ability exp.{}", n)), (i * 5) as f32).unwrap();
+        ctx.set_equation(Equation::new(tag(&format!("ability.{}", n)), &format!("rounddown((sqrt(8 * ability exp.{} / 5 + 1)-1)/2)", n)).unwrap()).unwrap();
+        ctx.set_conditional(Conditional::new(tag(&format!("ability.{}.affinity", n)), &format!("ability exp.{} > 50", n)).unwrap()).unwrap();
+        values.push(tag(&format!("ability.{}", n)));
+    }
+
+    for i in 0..ARTS
+    {
+        let n = name(i);
+        ctx.set_attribute(&tag(&format!("art exp.{}", n)), (i * 10) as f32).unwrap();
+        ctx.set_equation(Equation::new(tag(&format!("art.{}", n)), &format!("rounddown((sqrt(8 * art exp.{} + 1)-1)/2)", n)).unwrap()).unwrap();
+        values.push(tag(&format!("art.{}", n)));
+    }
+
+    for i in 0..SPELLS
+    {
+        let n = name(i);
+        ctx.set_attribute(&tag(&format!("spell.{}.lvl.magnitude.range", n)), (i % 3) as f32).unwrap();
+        ctx.set_attribute(&tag(&format!("spell.{}.lvl.magnitude.duration", n)), (i % 4) as f32).unwrap();
+        ctx.set_attribute(&tag(&format!("spell.{}.lvl.magnitude.target", n)), (i % 2) as f32).unwrap();
+        ctx.set_attribute(&tag(&format!("spell.{}.lvl.flat", n)), 4.0).unwrap();
+        ctx.set_equation(Equation::new(tag(&format!("spell.{}.lvl", n)), &format!("sum(spell.{}.lvl.magnitude) * 5 + spell.{}.lvl.flat", n, n)).unwrap()).unwrap();
+        ctx.set_equation(Equation::new(tag(&format!("spell.{}.casting total", n)), &format!("art.{} + art.{} + ability.{}", name(i % ARTS), name((i + 1) % ARTS), name(i % ABILITIES))).unwrap()).unwrap();
+        values.push(tag(&format!("spell.{}.lvl", n)));
+        values.push(tag(&format!("spell.{}.casting total", n)));
+    }
+
+    ctx.set_modifier(Modifier::new(tag("modifier.affinity"), ModifierTarget::MatchingStart(tag("ability")), tag("affinity"), ModifierChange::BasicValue(1.0))).unwrap();
+    ctx.set_modifier(Modifier::new(tag("modifier.gift"), ModifierTarget::Single(tag("art.a")), tag("character.is magus"), ModifierChange::FromOtherValue(tag("ability.a")))).unwrap();
+
+    ctx.set_equation(Equation::new(tag("total.ability exp"), "avg(ability) * count(ability)").unwrap()).unwrap();
+    ctx.set_equation(Equation::new(tag("total.spell levels"), "deepsum(spell)").unwrap()).unwrap();
+    values.push(tag("total.ability exp"));
+    values.push(tag("total.spell levels"));
+
+    (ctx, values)
+}
+
+fn eval_all(ctx: &Context, values: &Vec<Tag>)
+{
+    for v in values.iter()
+    {
+        black_box(ctx.get_value(v).unwrap());
+    }
+}
+
+fn context_cache_benchmark(c: &mut Criterion)
+{
+    let mut registry = TagRegistry::new();
+    let (ctx, values) = ars_magica_context(&mut registry);
+    c.bench_function("get_value cold", |b| b.iter(|| eval_all(&ctx, &values)));
+
+    let (mut ctx, values) = ars_magica_context(&mut registry);
+    ctx.enable_cache();
+    eval_all(&ctx, &values);
+    c.bench_function("get_value warm", |b| b.iter(|| eval_all(&ctx, &values)));
+
+    let exp = tag(&mut registry, "ability exp.d");
+    c.bench_function("get_value after set_attribute", |b| b.iter(||
+    {
+        ctx.set_attribute(&exp, 15.0).unwrap();
+        eval_all(&ctx, &values);
+    }));
+}
+
+criterion_group!(benches, context_cache_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rpg_helper::api::data::tag::{Tag, TagRegistry, TagSet};
+
+const ABILITIES: usize = 200;
+const SPELLS: usize = 2000;
+
+fn tag(registry: &mut TagRegistry, s: &str) -> Tag
+{
+    registry.get_or_register_tag(s).unwrap()
+}
+
+/// The previous implementation of `TagSet`, which counts every prefix of a tag in a map
+/// and scans the whole map for prefix queries. Kept here to compare against.
+struct HashMapTagSet
+{
+    tags: HashMap<Tag, i32>,
+}
+
+impl HashMapTagSet
+{
+    fn new() -> HashMapTagSet
+    {
+        HashMapTagSet { tags: HashMap::new() }
+    }
+
+    fn add_tag(&mut self, t: &Tag)
+    {
+        for st in t.as_collective_subtags()
+        {
+            *self.tags.entry(st).or_insert(0) += 1;
+        }
+    }
+
+    fn get_matching_prefix(&self, prefix: &Tag) -> Vec<Tag>
+    {
+        self.tags.iter().filter(|(t, c)| **c > 0 && t.has_prefix(prefix)).map(|(t, _)| t.clone()).collect()
+    }
+
+    fn get_immediate_matching_prefix(&self, prefix: &Tag) -> Vec<Tag>
+    {
+        let num_subtags = prefix.count_subtags() + 1;
+        self.tags.iter().filter(|(t, c)| **c > 0 && t.count_subtags() == num_subtags && t.has_prefix(prefix)).map(|(t, _)| t.clone()).collect()
+    }
+}
+
+/// The tags of a large saga, with many spells and abilities (each with a few subtags of their own)
+fn saga_tags(registry: &mut TagRegistry) -> Vec<Tag>
+{
+    let mut tag = |s: &str| tag(registry, s);
+    let mut tags = vec![];
+    for i in 0..ABILITIES
+    {
+        tags.push(tag(&format!("ability.ability {}", i)));
+        tags.push(tag(&format!("ability.ability {}.exp", i)));
+        tags.push(tag(&format!("ability.ability {}.speciality", i)));
+    }
+    for i in 0..SPELLS
+    {
+        tags.push(tag(&format!("spell.spell {}.lvl.magnitude.range", i)));
+        tags.push(tag(&format!("spell.spell {}.lvl.magnitude.duration", i)));
+        tags.push(tag(&format!("spell.spell {}.lvl.magnitude.target", i)));
+        tags.push(tag(&format!("spell.spell {}.casting total", i)));
+    }
+    tags
+}
+
+fn tagset_benchmark(c: &mut Criterion)
+{
+    let mut registry = TagRegistry::new();
+    let tags = saga_tags(&mut registry);
+
+    let mut trie = TagSet::new();
+    let mut map = HashMapTagSet::new();
+    for t in tags.iter()
+    {
+        trie.add_tag(t);
+        map.add_tag(t);
+    }
+
+    c.bench_function("add_tag trie", |b| b.iter(||
+    {
+        let mut set = TagSet::new();
+        tags.iter().for_each(|t| set.add_tag(t));
+        black_box(set);
+    }));
+    c.bench_function("add_tag hashmap", |b| b.iter(||
+    {
+        let mut set = HashMapTagSet::new();
+        tags.iter().for_each(|t| set.add_tag(t));
+        black_box(set.tags.len());
+    }));
+
+    // A small result from a large set, such as the magnitudes of a single spell
+    let magnitude = tag(&mut registry, "spell.spell 50.lvl.magnitude");
+    c.bench_function("get_matching_prefix trie", |b| b.iter(|| black_box(trie.get_matching_prefix(&magnitude))));
+    c.bench_function("get_matching_prefix hashmap", |b| b.iter(|| black_box(map.get_matching_prefix(&magnitude))));
+
+    let ability = tag(&mut registry, "ability");
+    c.bench_function("get_immediate_matching_prefix trie", |b| b.iter(|| black_box(trie.get_immediate_matching_prefix(&ability))));
+    c.bench_function("get_immediate_matching_prefix hashmap", |b| b.iter(|| black_box(map.get_immediate_matching_prefix(&ability))));
+}
+
+criterion_group!(benches, tagset_benchmark);
+criterion_main!(benches);
@@ -1,15 +1,23 @@
 use serde::{Deserialize, Serialize};
 
 pub mod attribute;
+pub mod cache;
+pub mod changeset;
 pub mod conditional;
 pub mod context;
+pub mod dependency;
+pub mod diagnostic;
 pub mod effect;
 pub mod error;
 pub mod evaltree;
 pub mod equation;
+pub mod journal;
+pub mod layer;
 pub mod modifier;
 pub mod tag;
 pub mod template;
+pub mod text;
+pub mod trace;
 
 #[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
 pub enum DataType
@@ -19,4 +27,5 @@ pub enum DataType
     Condition,
     Modifier,
     Equation,
+    Text,
 }
\ No newline at end of file
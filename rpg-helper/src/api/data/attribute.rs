@@ -1,4 +1,4 @@
-use crate::api::data::{tag::{Tag, TagTemplate}, template::Template};
+use crate::api::data::{tag::{RemapTags, Tag, TagMapping, TagTemplate}, template::Template};
 
 use std::collections::HashMap;
 
@@ -106,6 +106,22 @@ impl AttributeSet
     }
 }
 
+impl RemapTags for Attribute
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        self.name.remap_tags(remap);
+    }
+}
+
+impl RemapTags for AttributeSet
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        self.attributes.remap_tags(remap);
+    }
+}
+
 impl IntoIterator for AttributeSet
 {
     type Item = (Tag, Attribute);
@@ -0,0 +1,191 @@
+use std::{cell::RefCell, collections::{HashMap, HashSet}};
+
+use crate::api::data::{dependency::DependencyGraph, tag::Tag};
+
+/// Caches the evaluated values and conditionals of a Context.
+///
+/// Values are stored the first time they are evaluated and are kept until
+/// something they depend upon changes. To know what depends on what, the
+/// cache keeps a reverse index of the context's dependency graph, which
+/// must be updated whenever the structure of the context changes
+/// (a value, equation, conditional, or modifier is added or removed).
+/// Only the tags whose dependencies changed are indexed again (See `ValueCache::reindex`).
+///
+/// The cache is not part of the data of a context, so it is never
+/// serialized and two contexts are equal regardless of what they have cached.
+#[derive(Debug, Clone, Default)]
+pub struct ValueCache
+{
+    values: RefCell<HashMap<Tag, f32>>,
+    conditions: RefCell<HashMap<Tag, bool>>,
+    /// Input is a tag, output is every tag which directly reads it.
+    /// Modifiers are also entries, with their output being the values they modify.
+    dependents: HashMap<Tag, HashSet<Tag>>,
+    /// Input is a prefix, output is every tag which aggregates over that prefix.
+    prefix_dependents: HashMap<Tag, HashSet<Tag>>,
+    /// The reverse of the index above, so the entries of a tag can be replaced when it is indexed again.
+    /// Input is a tag, output is every tag (and modifier) it reads, and every prefix it aggregates over.
+    dependencies: HashMap<Tag, (Vec<Tag>, Vec<Tag>)>,
+}
+
+impl PartialEq for ValueCache
+{
+    fn eq(&self, _: &Self) -> bool
+    {
+        true
+    }
+}
+
+impl ValueCache
+{
+    pub fn new(graph: &DependencyGraph) -> ValueCache
+    {
+        let mut result = ValueCache::default();
+        result.rebuild_index(graph);
+        result
+    }
+
+    pub fn get_value(&self, t: &Tag) -> Option<f32>
+    {
+        self.values.borrow().get(t).cloned()
+    }
+
+    pub fn store_value(&self, t: &Tag, v: f32)
+    {
+        self.values.borrow_mut().insert(t.clone(), v);
+    }
+
+    pub fn get_condition(&self, t: &Tag) -> Option<bool>
+    {
+        self.conditions.borrow().get(t).cloned()
+    }
+
+    pub fn store_condition(&self, t: &Tag, b: bool)
+    {
+        self.conditions.borrow_mut().insert(t.clone(), b);
+    }
+
+    /// Number of values and conditionals currently cached
+    pub fn len(&self) -> usize
+    {
+        self.values.borrow().len() + self.conditions.borrow().len()
+    }
+
+    /// Whether no values or conditionals are currently cached
+    pub fn is_empty(&self) -> bool
+    {
+        self.values.borrow().is_empty() && self.conditions.borrow().is_empty()
+    }
+
+    /// Removes every cached value, keeping the dependency index
+    pub fn clear(&mut self)
+    {
+        self.values.get_mut().clear();
+        self.conditions.get_mut().clear();
+    }
+
+    /// Replaces the reverse dependency index with the one given by the graph.
+    /// Cached values are kept, so anything affected by the change in structure
+    /// should be invalidated as well.
+    pub fn rebuild_index(&mut self, graph: &DependencyGraph)
+    {
+        self.dependents.clear();
+        self.prefix_dependents.clear();
+        self.dependencies.clear();
+
+        for (from, to, via) in graph.iter()
+        {
+            self.add_dependency(from, to);
+            if let Some(modifier) = via
+            {
+                self.add_dependency(from, modifier);
+            }
+        }
+
+        for (prefix, tags) in graph.iter_prefix_dependencies()
+        {
+            for t in tags.iter()
+            {
+                self.prefix_dependents.entry(prefix.clone()).or_default().insert(t.clone());
+                self.dependencies.entry(t.clone()).or_default().1.push(prefix.clone());
+            }
+        }
+    }
+
+    /// Replaces what a single tag depends upon in the index, given as (dependency, modifier causing it)
+    /// along with the prefixes the tag aggregates over. This keeps the index up to date when only
+    /// part of the structure of the context changes, without rebuilding the whole index.
+    pub fn reindex(&mut self, t: &Tag, dependencies: &[(Tag, Option<Tag>)], prefixes: &[Tag])
+    {
+        if let Some((old, old_prefixes)) = self.dependencies.remove(t)
+        {
+            for d in old.iter()
+            {
+                if let Some(dependents) = self.dependents.get_mut(d)
+                {
+                    dependents.remove(t);
+                }
+            }
+            for p in old_prefixes.iter()
+            {
+                if let Some(dependents) = self.prefix_dependents.get_mut(p)
+                {
+                    dependents.remove(t);
+                }
+            }
+        }
+
+        for (to, via) in dependencies.iter()
+        {
+            self.add_dependency(t, to);
+            if let Some(modifier) = via
+            {
+                self.add_dependency(t, modifier);
+            }
+        }
+        for p in prefixes.iter()
+        {
+            self.prefix_dependents.entry(p.clone()).or_default().insert(t.clone());
+            self.dependencies.entry(t.clone()).or_default().1.push(p.clone());
+        }
+    }
+
+    fn add_dependency(&mut self, from: &Tag, to: &Tag)
+    {
+        self.dependents.entry(to.clone()).or_default().insert(from.clone());
+        self.dependencies.entry(from.clone()).or_default().0.push(to.clone());
+    }
+
+    /// Removes the cached value of the given tag and
+    /// every value which depends upon it (directly or not).
+    pub fn invalidate(&mut self, t: &Tag)
+    {
+        let mut visited = HashSet::new();
+        let mut to_visit = vec![t.clone()];
+
+        while let Some(t) = to_visit.pop()
+        {
+            if !visited.insert(t.clone())
+            {
+                continue;
+            }
+
+            self.values.get_mut().remove(&t);
+            self.conditions.get_mut().remove(&t);
+
+            if let Some(dependents) = self.dependents.get(&t)
+            {
+                to_visit.extend(dependents.iter().cloned());
+            }
+
+            // Aggregates over any prefix of this tag could now include (or exclude) it
+            for prefix in t.as_collective_subtags()
+            {
+                if let Some(dependents) = self.prefix_dependents.get(&prefix)
+                {
+                    to_visit.extend(dependents.iter().cloned());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,426 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::api::data::{conditional::Conditional, equation::Equation, modifier::{Modifier, StackingPolicy}, tag::Tag, text::TextValue};
+
+/// How a single value changed between two contexts
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+pub enum Change<T>
+{
+    Added(T),
+    Removed(T),
+    Changed { old: T, new: T },
+}
+
+impl<T> Change<T>
+{
+    /// The value before the change, if there was one
+    pub fn get_old(&self) -> Option<&T>
+    {
+        match self
+        {
+            Change::Added(_) => None,
+            Change::Removed(old) | Change::Changed { old, .. } => Some(old),
+        }
+    }
+
+    /// The value after the change, if there is one
+    pub fn get_new(&self) -> Option<&T>
+    {
+        match self
+        {
+            Change::Removed(_) => None,
+            Change::Added(new) | Change::Changed { new, .. } => Some(new),
+        }
+    }
+
+    /// The change which undoes this change
+    pub fn invert(self) -> Change<T>
+    {
+        match self
+        {
+            Change::Added(new) => Change::Removed(new),
+            Change::Removed(old) => Change::Added(old),
+            Change::Changed { old, new } => Change::Changed { old: new, new: old },
+        }
+    }
+
+    fn into_values(self) -> (Option<T>, Option<T>)
+    {
+        match self
+        {
+            Change::Added(new) => (None, Some(new)),
+            Change::Removed(old) => (Some(old), None),
+            Change::Changed { old, new } => (Some(old), Some(new)),
+        }
+    }
+}
+
+impl<T: PartialEq> Change<T>
+{
+    /// The change from an old value to a new value, where None is a missing value.
+    /// Returns None if nothing changed.
+    pub fn from_values(old: Option<T>, new: Option<T>) -> Option<Change<T>>
+    {
+        match (old, new)
+        {
+            (Some(old), Some(new)) if old != new => Some(Change::Changed { old, new }),
+            (Some(_), Some(_)) | (None, None) => None,
+            (Some(old), None) => Some(Change::Removed(old)),
+            (None, Some(new)) => Some(Change::Added(new)),
+        }
+    }
+}
+
+/// The changes which turn one context into another, grouped by the kind of value
+/// and keyed by the tag of each changed value. See `Context::diff`
+///
+/// A value which changes kind (Ex: an attribute replaced by an equation of the same name)
+/// is removed from one group and added to another.
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize, Clone)]
+pub struct ContextChangeset
+{
+    pub attributes: HashMap<Tag, Change<f32>>,
+    pub equations: HashMap<Tag, Change<Equation>>,
+    pub conditionals: HashMap<Tag, Change<Conditional>>,
+    pub modifiers: HashMap<Tag, Change<Modifier>>,
+    /// Keyed by the stacking group of the policy
+    pub stacking_policies: HashMap<Tag, Change<StackingPolicy>>,
+    pub texts: HashMap<Tag, Change<TextValue>>,
+    /// The count of each state tag. A tag is added when its count becomes non-zero
+    /// and removed when its count becomes zero.
+    pub state_tags: HashMap<Tag, Change<i32>>,
+    /// Keyed by the old name of each alias, with its new name as the value
+    #[serde(default)]
+    pub aliases: HashMap<Tag, Change<Tag>>,
+}
+
+impl ContextChangeset
+{
+    pub fn new() -> ContextChangeset
+    {
+        ContextChangeset::default()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.len() == 0
+    }
+
+    /// The number of values changed
+    pub fn len(&self) -> usize
+    {
+        self.attributes.len() + self.equations.len() + self.conditionals.len() + self.modifiers.len()
+            + self.stacking_policies.len() + self.texts.len() + self.state_tags.len() + self.aliases.len()
+    }
+
+    /// The changeset which undoes this changeset when applied
+    pub fn invert(self) -> ContextChangeset
+    {
+        ContextChangeset
+        {
+            attributes: invert_changes(self.attributes),
+            equations: invert_changes(self.equations),
+            conditionals: invert_changes(self.conditionals),
+            modifiers: invert_changes(self.modifiers),
+            stacking_policies: invert_changes(self.stacking_policies),
+            texts: invert_changes(self.texts),
+            state_tags: invert_changes(self.state_tags),
+            aliases: invert_changes(self.aliases),
+        }
+    }
+
+    /// Combines the changes made after this changeset into this changeset, as if
+    /// both were made at once. A value changed back to what it was is no longer a change.
+    pub fn compose(&mut self, later: ContextChangeset)
+    {
+        compose_changes(&mut self.attributes, later.attributes);
+        compose_changes(&mut self.equations, later.equations);
+        compose_changes(&mut self.conditionals, later.conditionals);
+        compose_changes(&mut self.modifiers, later.modifiers);
+        compose_changes(&mut self.stacking_policies, later.stacking_policies);
+        compose_changes(&mut self.texts, later.texts);
+        compose_changes(&mut self.state_tags, later.state_tags);
+        compose_changes(&mut self.aliases, later.aliases);
+    }
+
+    /// Given the changes of two sides from a common base, splits the changes of "theirs"
+    /// into those which can be applied on top of this side and those which conflict.
+    ///
+    /// A value conflicts when both sides changed it differently. When both sides made the
+    /// same change, the change is already part of this side, so it is neither applied nor a conflict.
+    pub fn merge(&self, theirs: &ContextChangeset) -> (ContextChangeset, MergeConflicts)
+    {
+        let mut changes = ContextChangeset::new();
+        let mut conflicts = MergeConflicts::default();
+        split_conflicts(&self.attributes, &theirs.attributes, &mut changes.attributes, &mut conflicts.ours.attributes, &mut conflicts.theirs.attributes);
+        split_conflicts(&self.equations, &theirs.equations, &mut changes.equations, &mut conflicts.ours.equations, &mut conflicts.theirs.equations);
+        split_conflicts(&self.conditionals, &theirs.conditionals, &mut changes.conditionals, &mut conflicts.ours.conditionals, &mut conflicts.theirs.conditionals);
+        split_conflicts(&self.modifiers, &theirs.modifiers, &mut changes.modifiers, &mut conflicts.ours.modifiers, &mut conflicts.theirs.modifiers);
+        split_conflicts(&self.stacking_policies, &theirs.stacking_policies, &mut changes.stacking_policies, &mut conflicts.ours.stacking_policies, &mut conflicts.theirs.stacking_policies);
+        split_conflicts(&self.texts, &theirs.texts, &mut changes.texts, &mut conflicts.ours.texts, &mut conflicts.theirs.texts);
+        split_conflicts(&self.state_tags, &theirs.state_tags, &mut changes.state_tags, &mut conflicts.ours.state_tags, &mut conflicts.theirs.state_tags);
+        split_conflicts(&self.aliases, &theirs.aliases, &mut changes.aliases, &mut conflicts.ours.aliases, &mut conflicts.theirs.aliases);
+        (changes, conflicts)
+    }
+}
+
+/// The values both sides of a merge changed differently, with the change each side made.
+/// The merged context keeps the value of "ours" until the conflict is resolved.
+/// See `Context::merge`
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize, Clone)]
+pub struct MergeConflicts
+{
+    pub ours: ContextChangeset,
+    pub theirs: ContextChangeset,
+}
+
+impl MergeConflicts
+{
+    pub fn is_empty(&self) -> bool
+    {
+        self.ours.is_empty() && self.theirs.is_empty()
+    }
+}
+
+/// Finds the changes between the values of two contexts, where each value is keyed by its tag
+pub(crate) fn diff_values<'a, T, A, B>(a: A, b: B) -> HashMap<Tag, Change<T>>
+where
+    T: PartialEq + Clone,
+    A: IntoIterator<Item = (&'a Tag, T)>,
+    B: IntoIterator<Item = (&'a Tag, T)>,
+{
+    let a: HashMap<&Tag, T> = a.into_iter().collect();
+    let b: HashMap<&Tag, T> = b.into_iter().collect();
+    let mut result = HashMap::new();
+    for (t, old) in a.iter()
+    {
+        match b.get(t)
+        {
+            Some(new) if new != old => { result.insert((*t).clone(), Change::Changed { old: old.clone(), new: new.clone() }); },
+            Some(_) => (),
+            None => { result.insert((*t).clone(), Change::Removed(old.clone())); },
+        }
+    }
+    for (t, new) in b.into_iter()
+    {
+        if !a.contains_key(t)
+        {
+            result.insert(t.clone(), Change::Added(new));
+        }
+    }
+    result
+}
+
+/// Records the change of a value from old to new, if the value changed
+pub(crate) fn insert_change<T: PartialEq>(changes: &mut HashMap<Tag, Change<T>>, t: Tag, old: Option<T>, new: Option<T>)
+{
+    if let Some(c) = Change::from_values(old, new)
+    {
+        changes.insert(t, c);
+    }
+}
+
+fn invert_changes<T>(changes: HashMap<Tag, Change<T>>) -> HashMap<Tag, Change<T>>
+{
+    changes.into_iter().map(|(t, c)| (t, c.invert())).collect()
+}
+
+fn compose_changes<T: PartialEq>(changes: &mut HashMap<Tag, Change<T>>, later: HashMap<Tag, Change<T>>)
+{
+    for (t, change) in later.into_iter()
+    {
+        match changes.remove(&t)
+        {
+            Some(earlier) =>
+            {
+                let (old, _) = earlier.into_values();
+                let (_, new) = change.into_values();
+                if let Some(c) = Change::from_values(old, new)
+                {
+                    changes.insert(t, c);
+                }
+            },
+            None => { changes.insert(t, change); },
+        }
+    }
+}
+
+fn split_conflicts<T: PartialEq + Clone>(ours: &HashMap<Tag, Change<T>>, theirs: &HashMap<Tag, Change<T>>, changes: &mut HashMap<Tag, Change<T>>, our_conflicts: &mut HashMap<Tag, Change<T>>, their_conflicts: &mut HashMap<Tag, Change<T>>)
+{
+    for (t, change) in theirs.iter()
+    {
+        match ours.get(t)
+        {
+            None => { changes.insert(t.clone(), change.clone()); },
+            Some(our_change) if our_change != change =>
+            {
+                our_conflicts.insert(t.clone(), our_change.clone());
+                their_conflicts.insert(t.clone(), change.clone());
+            },
+            Some(_) => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit_tests
+{
+    use crate::api::data::{changeset::{Change, ContextChangeset}, context::Context, equation::Equation, modifier::{Modifier, ModifierChange, ModifierTarget}, tag::{Tag, TagRegistry}};
+
+    fn tag(s: &str) -> Tag
+    {
+        Tag::from_str(s).unwrap()
+    }
+
+    /// A character with a few abilities, used as the common base of diffs and merges
+    fn base_context() -> Context
+    {
+        let mut ctx = Context::new();
+        ctx.set_attribute(&tag("ability.latin.exp"), 5.0).unwrap();
+        ctx.set_attribute(&tag("ability.magic theory.exp"), 30.0).unwrap();
+        ctx.set_attribute(&tag("characteristic.intelligence"), 2.0).unwrap();
+        ctx.set_equation(Equation::new(tag("ability.magic theory"), "rounddown((sqrt(8 * ability.magic theory.exp / 5 + 1) - 1) / 2)").unwrap()).unwrap();
+        ctx.add_explicit_tag(&tag("character.magus"));
+        ctx
+    }
+
+    #[test]
+    fn diff_test()
+    {
+        let a = base_context();
+        assert!(Context::diff(&a, &a).is_empty());
+
+        let mut b = a.clone();
+        b.set_attribute(&tag("ability.latin.exp"), 15.0).unwrap();
+        b.remove_attribute(&tag("characteristic.intelligence")).unwrap();
+        b.set_equation(Equation::new(tag("ability.latin"), "rounddown((sqrt(8 * ability.latin.exp / 5 + 1) - 1) / 2)").unwrap()).unwrap();
+        b.add_explicit_tag(&tag("character.magus"));
+        b.add_explicit_tag(&tag("spell.range.voice"));
+
+        let changes = Context::diff(&a, &b);
+        assert_eq!(changes.len(), 5);
+        assert_eq!(changes.attributes.get(&tag("ability.latin.exp")), Some(&Change::Changed { old: 5.0, new: 15.0 }));
+        assert_eq!(changes.attributes.get(&tag("characteristic.intelligence")), Some(&Change::Removed(2.0)));
+        assert!(matches!(changes.equations.get(&tag("ability.latin")), Some(Change::Added(_))));
+        assert_eq!(changes.state_tags.get(&tag("character.magus")), Some(&Change::Changed { old: 1, new: 2 }));
+        assert_eq!(changes.state_tags.get(&tag("spell.range.voice")), Some(&Change::Added(1)));
+
+        // The reverse diff undoes each change
+        let reverse = Context::diff(&b, &a);
+        assert_eq!(reverse.attributes.get(&tag("characteristic.intelligence")), Some(&Change::Added(2.0)));
+        assert_eq!(reverse.state_tags.get(&tag("spell.range.voice")), Some(&Change::Removed(1)));
+
+        let mut applied = a.clone();
+        applied.apply_changeset(&changes).unwrap();
+        assert!(Context::diff(&applied, &b).is_empty());
+        assert_eq!(applied.get_value(&tag("ability.latin")).unwrap(), Some(2.0));
+        applied.apply_changeset(&reverse).unwrap();
+        assert!(Context::diff(&applied, &a).is_empty());
+    }
+
+    #[test]
+    fn apply_changes_kind_test()
+    {
+        // An attribute replaced by an equation of the same name is a removal and an addition
+        let a = base_context();
+        let mut b = a.clone();
+        b.remove_attribute(&tag("characteristic.intelligence")).unwrap();
+        b.set_equation(Equation::new(tag("characteristic.intelligence"), "1 + 2").unwrap()).unwrap();
+
+        let changes = Context::diff(&a, &b);
+        assert_eq!(changes.len(), 2);
+        let mut applied = a.clone();
+        applied.apply_changeset(&changes).unwrap();
+        assert_eq!(applied.get_value(&tag("characteristic.intelligence")).unwrap(), Some(3.0));
+
+        // A change which can not be applied leaves the context unchanged
+        let mut invalid = ContextChangeset::new();
+        invalid.attributes.insert(tag("ability.latin.exp"), Change::Changed { old: 5.0, new: 20.0 });
+        invalid.attributes.insert(tag("ability.magic theory"), Change::Added(3.0));
+        let mut applied = a.clone();
+        assert!(applied.apply_changeset(&invalid).is_err());
+        assert!(Context::diff(&applied, &a).is_empty());
+    }
+
+    #[test]
+    fn merge_test()
+    {
+        let base = base_context();
+
+        let mut ours = base.clone();
+        ours.set_attribute(&tag("ability.latin.exp"), 15.0).unwrap();
+        ours.set_attribute(&tag("ability.magic theory.exp"), 50.0).unwrap();
+        ours.add_explicit_tag(&tag("spell.range.voice"));
+
+        let mut theirs = base.clone();
+        theirs.set_attribute(&tag("ability.latin.exp"), 15.0).unwrap();
+        theirs.set_attribute(&tag("ability.magic theory.exp"), 75.0).unwrap();
+        theirs.remove_attribute(&tag("characteristic.intelligence")).unwrap();
+        theirs.set_modifier(Modifier::new(tag("virtue.puissant magic theory"), ModifierTarget::Single(tag("ability.magic theory")), tag("always"), ModifierChange::BasicValue(2.0))).unwrap();
+
+        let (merged, conflicts) = Context::merge(&base, &ours, &theirs).unwrap();
+
+        // Both sides changed the magic theory exp differently, so ours is kept and the conflict reported
+        assert_eq!(conflicts.ours.len(), 1);
+        assert_eq!(conflicts.ours.attributes.get(&tag("ability.magic theory.exp")), Some(&Change::Changed { old: 30.0, new: 50.0 }));
+        assert_eq!(conflicts.theirs.attributes.get(&tag("ability.magic theory.exp")), Some(&Change::Changed { old: 30.0, new: 75.0 }));
+        assert_eq!(merged.get_value(&tag("ability.magic theory.exp")).unwrap(), Some(50.0));
+
+        // Changes made by only one side (or the same by both) are kept
+        assert_eq!(merged.get_value(&tag("ability.latin.exp")).unwrap(), Some(15.0));
+        assert!(merged.has_tag(&tag("spell.range.voice")));
+        assert!(!merged.has_attribute(&tag("characteristic.intelligence")));
+        assert!(merged.has_modifier(&tag("virtue.puissant magic theory")));
+
+        let (_, conflicts) = Context::merge(&base, &ours, &ours).unwrap();
+        assert!(conflicts.is_empty());
+    }
+    #[test]
+    fn alias_diff_test()
+    {
+        let mut registry = TagRegistry::new();
+        registry.get_or_register_tag("ability.magic theory.score.exp").unwrap();
+        let a = base_context();
+        let mut b = a.clone();
+        b.rename_tag(&tag("ability.magic theory"), &tag("ability.magic theory.score"), &registry).unwrap();
+        b.add_alias(&tag("ability.magic theory"), &tag("ability.magic theory.score"));
+
+        let changes = Context::diff(&a, &b);
+        assert_eq!(changes.aliases.get(&tag("ability.magic theory")), Some(&Change::Added(tag("ability.magic theory.score"))));
+        let mut applied = a.clone();
+        applied.apply_changeset(&changes).unwrap();
+        assert!(Context::diff(&applied, &b).is_empty());
+        assert_eq!(applied.get_value(&tag("ability.magic theory.exp")).unwrap(), Some(30.0));
+
+        applied.apply_changeset(&changes.invert()).unwrap();
+        assert!(Context::diff(&applied, &a).is_empty());
+        assert!(applied.get_aliases().is_empty());
+    }
+
+    /// Tests a changeset written through one registry can be loaded into another
+    #[test]
+    fn serde_registry_test()
+    {
+        let mut registry = TagRegistry::new();
+        let latin = registry.get_or_register_tag("ability.latin.exp").unwrap();
+        let magus = registry.get_or_register_tag("character.magus").unwrap();
+        let old = registry.get_or_register_tag("ability.magic theory").unwrap();
+        let score = registry.get_or_register_tag("ability.magic theory.score").unwrap();
+
+        let mut changes = ContextChangeset::new();
+        changes.attributes.insert(latin, Change::Changed { old: 5.0, new: 15.0 });
+        changes.state_tags.insert(magus, Change::Added(1));
+        changes.aliases.insert(old, Change::Added(score));
+        let json = registry.to_json_string(&changes).unwrap();
+        assert!(json.contains("\"ability.latin.exp\""));
+
+        let mut other = TagRegistry::new_with_reserved(&["character", "score"]);
+        let loaded: ContextChangeset = other.from_json_str(&json).unwrap();
+        assert_eq!(loaded.len(), 3);
+        assert_eq!(loaded.attributes.get(&other.get_tag("ability.latin.exp").unwrap().unwrap()), Some(&Change::Changed { old: 5.0, new: 15.0 }));
+        assert_eq!(loaded.aliases.get(&other.get_tag("ability.magic theory").unwrap().unwrap()), Some(&Change::Added(other.get_tag("ability.magic theory.score").unwrap().unwrap())));
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&other.to_json_string(&loaded).unwrap()).unwrap(), serde_json::from_str::<serde_json::Value>(&json).unwrap());
+    }
+}
@@ -1,6 +1,6 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
-use crate::api::data::{attribute::{Attribute, AttributeSet}, conditional::{Conditional, ConditionalSet}, effect::Effect, equation::{Equation, EquationSet}, error::{ConflictError, DataError, TemplateError}, modifier::{Modifier, ModifierSet}, tag::{Tag, TagSet}, template::{Template, TemplateValue}, DataType};
+use crate::api::data::{attribute::{Attribute, AttributeSet}, cache::ValueCache, changeset::{diff_values, insert_change, ContextChangeset, MergeConflicts}, conditional::{Conditional, ConditionalSet}, dependency::DependencyGraph, diagnostic::{Diagnostic, Span}, effect::Effect, equation::{Equation, EquationSet}, error::{ConflictError, DataError, DoesNotExistError, ParseError, ParseErrorType, QueryParseError, TemplateError}, evaltree::{EvalError, EvalTree}, modifier::{Modifier, ModifierSet, ModifierTarget, StackingPolicy}, tag::{NamedMapping, RemapTags, Subtag, Tag, TagAliases, TagMapping, TagRegistry, TagRename, TagSet}, template::{Template, TemplateInputType, TemplateInputValue, TemplateValue}, text::{TextSet, TextValue}, trace::{BaseTrace, ConditionalTrace, ModifierAmountTrace, ModifierTrace, Trace, ValueTrace}, DataType};
 
 use serde::{Deserialize, Serialize};
 
@@ -17,6 +17,9 @@ use serde::{Deserialize, Serialize};
 /// An equation acts like an attribute but depends upon the value of
 /// other attributes in the dataset. For example, in Ars Magica, the
 /// value of "Ability.Magic Theory" depends on the value of "Ability.Magic Theory.Exp"
+/// 
+/// A text holds a non-numeric value, either free text (such as a character's name)
+/// or one tag chosen from a prefix (such as a spell's Form). See `TextValue`
 #[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
 pub struct Context
 {
@@ -30,8 +33,17 @@ pub struct Context
     modifiers: ModifierSet,
     equations: EquationSet,
     conditionals: ConditionalSet,
+    #[serde(default)]
+    texts: TextSet,
+    /// Old names of tags which resolve to their new names. See `Context::add_alias`
+    #[serde(default)]
+    aliases: TagAliases,
+    /// Opt-in cache of evaluated values. See `Context::enable_cache`
+    #[serde(skip)]
+    cache: Option<ValueCache>,
 }
 
+/// Identifies a layer pushed onto a `LayeredContext`
 pub type LayerHandle = u32;
 
 impl Context
@@ -45,31 +57,92 @@ impl Context
             modifiers: ModifierSet::new(),
             equations: EquationSet::new(),
             conditionals: ConditionalSet::new(),
+            texts: TextSet::new(),
+            aliases: TagAliases::new(),
+            cache: None,
+        }
+    }
+
+    /// Starts caching the results of `get_value` and `eval_conditional`.
+    /// 
+    /// Cached results are invalidated as the context changes, so only the values
+    /// depending on what changed are evaluated again. This is useful for large
+    /// contexts which are read much more often than they are changed (such as a character sheet).
+    pub fn enable_cache(&mut self)
+    {
+        self.cache = Some(ValueCache::new(&self.build_dependency_graph()));
+    }
+
+    pub fn disable_cache(&mut self)
+    {
+        self.cache = None;
+    }
+
+    pub fn is_cache_enabled(&self) -> bool
+    {
+        self.cache.is_some()
+    }
+
+    /// Clears cached values which depend upon the given tag.
+    /// If the change alters what some tags read when they are evaluated (such as setting an
+    /// equation or modifier), those tags are indexed again by the cache.
+    fn invalidate(&mut self, t: &Tag, reindexed: &[Tag])
+    {
+        if self.cache.is_none()
+        {
+            return;
+        }
+
+        let dependencies: Vec<_> = reindexed.iter().map(|r| (r, self.get_direct_dependencies(r), self.get_prefix_dependencies(r))).collect();
+        if let Some(cache) = &mut self.cache
+        {
+            // Invalidate with the old index as well as the new index,
+            // as what depends on the tag may be different after the change.
+            cache.invalidate(t);
+            if !dependencies.is_empty()
+            {
+                for (r, d, p) in dependencies
+                {
+                    cache.reindex(r, &d, &p);
+                }
+                cache.invalidate(t);
+            }
         }
     }
 
     pub fn has_tag(&self, t: &Tag) -> bool
     {
+        let t: &Tag = &self.aliases.resolve(t);
         self.tags.has_tag(t) || self.state_tags.has_tag(t)
     }
 
     pub fn has_attribute(&self, attribute_name: &Tag) -> bool
     {
+        let attribute_name: &Tag = &self.aliases.resolve(attribute_name);
         self.atrs.has_attribute(attribute_name)
     }
 
     pub fn has_modifier(&self, modifier_name: &Tag) -> bool
     {
+        let modifier_name: &Tag = &self.aliases.resolve(modifier_name);
         self.modifiers.has_modifier(modifier_name)
     }
 
     pub fn has_equation(&self, equation_name: &Tag) -> bool
     {
+        let equation_name: &Tag = &self.aliases.resolve(equation_name);
         self.equations.has_equation(equation_name)
     }
     
+    pub fn has_text(&self, text_name: &Tag) -> bool
+    {
+        let text_name: &Tag = &self.aliases.resolve(text_name);
+        self.texts.has_text(text_name)
+    }
+
     pub fn has_conditional(&self, conditional_name: &Tag) -> bool
     {
+        let conditional_name: &Tag = &self.aliases.resolve(conditional_name);
         self.conditionals.has_conditional(conditional_name)
     }
 
@@ -87,8 +160,10 @@ impl Context
     /// to use effects to modify state of their contexts.
     pub fn add_explicit_tag(&mut self, tag: &Tag)
     {
+        let tag: &Tag = &self.aliases.resolve(tag);
         self.tags.add_tag(tag);
         self.state_tags.add_tag(tag);
+        self.invalidate(tag, &[]);
     }
 
     /// Used to remove some state of the context
@@ -100,23 +175,50 @@ impl Context
     /// to use effects to modify state of their contexts.
     pub fn remove_explicit_tag(&mut self, tag: &Tag)
     {
+        let tag: &Tag = &self.aliases.resolve(tag);
         self.tags.remove_tag(tag);
         self.state_tags.remove_tag(tag);
+        self.invalidate(tag, &[]);
     }
 
     /// Creates a new context that combines this context plus another context.
     /// If there are conflicting keys, the values of "other" are perfered
     /// over self
+    /// 
+    /// If the combined context would contain a cycle of evaluation, this
+    /// context is left unchanged and the cycles are returned as an error.
     pub fn layer_context(&mut self, other: &Self) -> Result<(), DataError>
+    {
+        let previous = self.clone();
+
+        // Most of the context can change while layering, so the cache is rebuilt once we are done
+        let cached = self.cache.take().is_some();
+
+        // Cycles are only checked once everything is layered, as a partially layered context can look cyclic.
+        let result = self.layer_values(other).and_then(|_| self.ensure_no_cycles());
+        if result.is_err()
+        {
+            *self = previous;
+        }
+        else if cached
+        {
+            self.enable_cache();
+        }
+        result
+    }
+
+    fn layer_values(&mut self, other: &Self) -> Result<(), DataError>
     {
         // For every value in other, set the value inside self.
         // This overrides any conflicts in self with the value of other.
         // (try_for_each is used as we could fail to set a value. Thus, we return that error.
         //  We discard previous values with an empty map)
-        other.atrs.iter().try_for_each(|(tag, atr)| self.set_attribute(tag, atr.get_value()).map(|_| ()))?;
-        other.modifiers.iter().try_for_each(|(_, modifier)| self.set_modifier(modifier.clone()).map(|_| ()))?;
-        other.equations.iter().try_for_each(|(_, equation)| self.set_equation(equation.clone()).map(|_| ()))?;
-        other.conditionals.iter().try_for_each(|(_, conditional)| self.set_conditional(conditional.clone()).map(|_| ()))?;
+        other.atrs.iter().try_for_each(|(tag, atr)| self.insert_attribute(tag, atr.get_value()).map(|_| ()))?;
+        other.modifiers.iter().try_for_each(|(_, modifier)| self.insert_modifier(modifier.clone()).map(|_| ()))?;
+        other.modifiers.iter_stacking_policies().for_each(|(group, policy)| { self.set_stacking_policy(group, *policy); });
+        other.equations.iter().try_for_each(|(_, equation)| self.insert_equation(equation.clone()).map(|_| ()))?;
+        other.conditionals.iter().try_for_each(|(_, conditional)| self.insert_conditional(conditional.clone()).map(|_| ()))?;
+        other.texts.iter().try_for_each(|(tag, text)| self.set_text(tag, text.clone()).map(|_| ()))?;
         other.state_tags.iter_primary_tags().for_each(|(t, i)|
         {
             for _ in 0..*i
@@ -128,54 +230,513 @@ impl Context
         Ok(())
     }
 
+    /// Combines a stack of contexts, ordered from the bottom layer to the top layer, where lookups fall through
+    /// the layers. Each value comes from the highest layer which has it, even when a lower layer has it as
+    /// another kind of value (Ex: a character's attribute hiding a ruleset's equation of the same name).
+    /// Modifiers of different names and the state tags of every layer apply together.
+    ///
+    /// Fails if the combined context would contain a cycle of evaluation.
+    pub fn from_layers<'a, I>(layers: I) -> Result<Context, DataError>
+    where
+        I: DoubleEndedIterator<Item = &'a Context>,
+    {
+        let mut result = Context::new();
+        for layer in layers.rev()
+        {
+            let resolved = |t: &Tag| result.has_value(t) || result.has_conditional(t) || result.has_modifier(t) || result.has_text(t);
+            let atrs: Vec<_> = layer.atrs.iter().filter(|(t, _)| !resolved(t)).map(|(t, atr)| (t, atr.get_value())).collect();
+            let equations: Vec<_> = layer.equations.iter().filter(|(t, _)| !resolved(t)).map(|(_, e)| e.clone()).collect();
+            let conditionals: Vec<_> = layer.conditionals.iter().filter(|(t, _)| !resolved(t)).map(|(_, c)| c.clone()).collect();
+            let modifiers: Vec<_> = layer.modifiers.iter().filter(|(t, _)| !resolved(t)).map(|(_, m)| m.clone()).collect();
+            let texts: Vec<_> = layer.texts.iter().filter(|(t, _)| !resolved(t)).map(|(t, v)| (t, v.clone())).collect();
+            let policies: HashSet<&Tag> = result.modifiers.iter_stacking_policies().map(|(group, _)| group).collect();
+            let policies: Vec<_> = layer.modifiers.iter_stacking_policies().filter(|(group, _)| !policies.contains(group)).map(|(group, policy)| (group, *policy)).collect();
+
+            atrs.into_iter().try_for_each(|(t, v)| result.insert_attribute(t, v).map(|_| ()))?;
+            equations.into_iter().try_for_each(|e| result.insert_equation(e).map(|_| ()))?;
+            conditionals.into_iter().try_for_each(|c| result.insert_conditional(c).map(|_| ()))?;
+            modifiers.into_iter().try_for_each(|m| result.insert_modifier(m).map(|_| ()))?;
+            texts.into_iter().try_for_each(|(t, v)| result.set_text(t, v).map(|_| ()))?;
+            policies.into_iter().for_each(|(group, policy)| { result.set_stacking_policy(group, policy); });
+            for (t, count) in layer.state_tags.iter_primary_tags().filter(|(_, c)| **c > 0)
+            {
+                result.tags.add_tag_count(t, *count);
+                result.state_tags.add_tag_count(t, *count);
+            }
+        }
+        // Cycles are only checked once every layer is combined, as each value is only known once the layers above it are
+        result.ensure_no_cycles()?;
+        Ok(result)
+    }
+
+    /// Finds the changes which turn context "a" into context "b".
+    /// Applying the changeset to "a" (See `Context::apply_changeset`) results in the values of "b".
+    pub fn diff(a: &Context, b: &Context) -> ContextChangeset
+    {
+        let mut result = ContextChangeset::new();
+        result.attributes = diff_values(a.atrs.iter().map(|(t, atr)| (t, atr.get_value())), b.atrs.iter().map(|(t, atr)| (t, atr.get_value())));
+        result.equations = diff_values(a.equations.iter().map(|(t, e)| (t, e.clone())), b.equations.iter().map(|(t, e)| (t, e.clone())));
+        result.conditionals = diff_values(a.conditionals.iter().map(|(t, c)| (t, c.clone())), b.conditionals.iter().map(|(t, c)| (t, c.clone())));
+        result.modifiers = diff_values(a.modifiers.iter().map(|(t, m)| (t, m.clone())), b.modifiers.iter().map(|(t, m)| (t, m.clone())));
+        result.stacking_policies = diff_values(a.modifiers.iter_stacking_policies().map(|(t, p)| (t, *p)), b.modifiers.iter_stacking_policies().map(|(t, p)| (t, *p)));
+        result.texts = diff_values(a.texts.iter().map(|(t, v)| (t, v.clone())), b.texts.iter().map(|(t, v)| (t, v.clone())));
+        result.state_tags = diff_values(a.state_tags.iter_primary_tags().filter(|(_, c)| **c > 0).map(|(t, c)| (t, *c)), b.state_tags.iter_primary_tags().filter(|(_, c)| **c > 0).map(|(t, c)| (t, *c)));
+        result.aliases = diff_values(a.aliases.iter().map(|(from, to)| (from, to.clone())), b.aliases.iter().map(|(from, to)| (from, to.clone())));
+        result
+    }
+
+    /// Applies each change of a changeset to this context. Only the new value of a change
+    /// is used, so a changed value is set even if this context held a different old value.
+    ///
+    /// If a change can not be applied or the resulting context would contain a cycle of evaluation,
+    /// this context is left unchanged and the error is returned.
+    pub fn apply_changeset(&mut self, changes: &ContextChangeset) -> Result<(), DataError>
+    {
+        let cached = self.cache.take().is_some();
+
+        // Like layering, cycles are only checked once every change is applied
+        let mut applied = ContextChangeset::new();
+        let result = self.apply_changes(changes, &mut applied).and_then(|_| self.ensure_no_cycles());
+        if result.is_err()
+        {
+            // Undoes the changes already applied, which restores the values this context held before
+            self.apply_changes(&applied.invert(), &mut ContextChangeset::new())?;
+        }
+        if cached
+        {
+            self.enable_cache();
+        }
+        result
+    }
+
+    /// Applies each change of a changeset, recording the changes actually made to this context in "applied"
+    fn apply_changes(&mut self, changes: &ContextChangeset, applied: &mut ContextChangeset) -> Result<(), DataError>
+    {
+        // Removals are applied first, so a value can change kind (Ex: an attribute replaced by an equation).
+        // Aliases are removed before and added after the values, so values are set under the names they were diffed with
+        for (from, _) in changes.aliases.iter().filter(|(_, c)| c.get_new().is_none())
+        {
+            let old = self.aliases.remove(from);
+            insert_change(&mut applied.aliases, from.clone(), old, None);
+        }
+        for (t, _) in changes.attributes.iter().filter(|(_, c)| c.get_new().is_none())
+        {
+            let old = self.remove_attribute(t)?;
+            insert_change(&mut applied.attributes, t.clone(), old, None);
+        }
+        for (t, _) in changes.equations.iter().filter(|(_, c)| c.get_new().is_none())
+        {
+            let old = self.remove_equation(t)?;
+            insert_change(&mut applied.equations, t.clone(), old, None);
+        }
+        for (t, _) in changes.conditionals.iter().filter(|(_, c)| c.get_new().is_none())
+        {
+            let old = self.remove_conditional(t)?;
+            insert_change(&mut applied.conditionals, t.clone(), old, None);
+        }
+        for (t, _) in changes.modifiers.iter().filter(|(_, c)| c.get_new().is_none())
+        {
+            let old = self.remove_modifier(t)?;
+            insert_change(&mut applied.modifiers, t.clone(), old, None);
+        }
+        for (t, _) in changes.texts.iter().filter(|(_, c)| c.get_new().is_none())
+        {
+            let old = self.remove_text(t)?;
+            insert_change(&mut applied.texts, t.clone(), old, None);
+        }
+
+        for (t, v) in changes.attributes.iter().filter_map(|(t, c)| c.get_new().map(|v| (t, *v)))
+        {
+            let old = self.insert_attribute(t, v)?;
+            insert_change(&mut applied.attributes, t.clone(), old, Some(v));
+        }
+        for e in changes.equations.values().filter_map(|c| c.get_new())
+        {
+            let e = self.resolve_aliases(e.clone());
+            let old = self.insert_equation(e.clone())?;
+            insert_change(&mut applied.equations, e.name.clone(), old, Some(e));
+        }
+        for c in changes.conditionals.values().filter_map(|c| c.get_new())
+        {
+            let c = self.resolve_aliases(c.clone());
+            let old = self.insert_conditional(c.clone())?;
+            insert_change(&mut applied.conditionals, c.name.clone(), old, Some(c));
+        }
+        for m in changes.modifiers.values().filter_map(|c| c.get_new())
+        {
+            let m = self.resolve_aliases(m.clone());
+            let old = self.insert_modifier(m.clone())?;
+            insert_change(&mut applied.modifiers, m.name.clone(), old, Some(m));
+        }
+        for (t, v) in changes.texts.iter().filter_map(|(t, c)| c.get_new().map(|v| (t, v)))
+        {
+            let old = self.set_text(t, v.clone())?;
+            insert_change(&mut applied.texts, t.clone(), old, Some(v.clone()));
+        }
+        for (group, change) in changes.stacking_policies.iter()
+        {
+            let old = match change.get_new()
+            {
+                Some(policy) => self.set_stacking_policy(group, *policy),
+                None => self.remove_stacking_policy(group),
+            };
+            insert_change(&mut applied.stacking_policies, group.clone(), old, change.get_new().copied());
+        }
+        for (t, change) in changes.state_tags.iter()
+        {
+            let t = self.aliases.resolve(t).into_owned();
+            let old = self.state_tags.get_primary_tag_count(&t);
+            let count = change.get_new().copied().unwrap_or(0) - old;
+            if count != 0
+            {
+                // Like effects, state tag changes only count the tag as a state of this context
+                self.state_tags.add_tag_count(&t, count);
+                self.invalidate(&t, &[]);
+                insert_change(&mut applied.state_tags, t, (old != 0).then_some(old), change.get_new().copied());
+            }
+        }
+        for (from, to) in changes.aliases.iter().filter_map(|(from, c)| c.get_new().map(|to| (from, to)))
+        {
+            let old = self.aliases.insert(from.clone(), to.clone());
+            insert_change(&mut applied.aliases, from.clone(), old, Some(to.clone()));
+        }
+        Ok(())
+    }
+
+    /// Merges the changes two contexts made to a common base context. The changes of "theirs"
+    /// are applied on top of "ours", except for the values both sides changed differently.
+    /// Those keep the value of "ours" and are returned as conflicts rather than being overwritten.
+    ///
+    /// Fails if the changes of both sides can not be combined, such as when they would form
+    /// a cycle of evaluation together.
+    pub fn merge(base: &Context, ours: &Context, theirs: &Context) -> Result<(Context, MergeConflicts), DataError>
+    {
+        let (changes, conflicts) = Context::diff(base, ours).merge(&Context::diff(base, theirs));
+        let mut result = ours.clone();
+        result.apply_changeset(&changes)?;
+        Ok((result, conflicts))
+    }
+
     /// Modifies a given dataset according to the effect.
-    /// Returns the modified dataset or an error if the modification failed.
-    pub fn apply_effect(&mut self, e: &Effect) -> Result<(), DataError>
+    /// Returns the inverse of the effect, which is a changeset that undoes the effect
+    /// when applied (See `Context::apply_changeset`), or an error if the modification failed.
+    pub fn apply_effect(&mut self, e: &Effect) -> Result<ContextChangeset, DataError>
+    {
+        self.apply_effect_changes(e).map(|changes| changes.invert())
+    }
+
+    /// Applies an effect, returning the changes it made
+    fn apply_effect_changes(&mut self, e: &Effect) -> Result<ContextChangeset, DataError>
     {
+        let mut changes = ContextChangeset::new();
         match e
         {
-            Effect::AddStateTag(tag) => self.state_tags.add_tag(tag),
-            Effect::RemoveStateTag(tag) => self.state_tags.remove_tag(tag),
-            Effect::SetAttribute(tag, nv) => { self.set_attribute(tag, *nv)?; },
-            Effect::SetEquation(equation) => { self.set_equation(equation.clone())?; },
-            Effect::SetConditional(conditional) => { self.set_conditional(conditional.clone())?; },
-            Effect::SetModifier(modifier) => { self.set_modifier(modifier.clone())?; },
+            Effect::AddStateTag(tag) | Effect::RemoveStateTag(tag) =>
+            {
+                let tag = self.aliases.resolve(tag).into_owned();
+                let old = self.state_tags.get_primary_tag_count(&tag);
+                if let Effect::AddStateTag(_) = e
+                {
+                    self.state_tags.add_tag(&tag);
+                }
+                else
+                {
+                    self.state_tags.remove_tag(&tag);
+                }
+                self.invalidate(&tag, &[]);
+                let new = self.state_tags.get_primary_tag_count(&tag);
+                insert_change(&mut changes.state_tags, tag, (old != 0).then_some(old), (new != 0).then_some(new));
+            },
+            Effect::SetAttribute(tag, nv) => self.set_attribute_changes(tag, *nv, &mut changes)?,
+            Effect::SetEquation(equation) =>
+            {
+                let equation = self.resolve_aliases(equation.clone());
+                let old = self.set_equation(equation.clone())?;
+                insert_change(&mut changes.equations, equation.name.clone(), old, Some(equation));
+            },
+            Effect::SetConditional(conditional) =>
+            {
+                let conditional = self.resolve_aliases(conditional.clone());
+                let old = self.set_conditional(conditional.clone())?;
+                insert_change(&mut changes.conditionals, conditional.name.clone(), old, Some(conditional));
+            },
+            Effect::SetModifier(modifier) =>
+            {
+                let modifier = self.resolve_aliases(modifier.clone());
+                let old = self.set_modifier(modifier.clone())?;
+                insert_change(&mut changes.modifiers, modifier.name.clone(), old, Some(modifier));
+            },
+            Effect::SetText(tag, text) =>
+            {
+                let tag = self.aliases.resolve(tag).into_owned();
+                let text = self.resolve_aliases(text.clone());
+                let old = self.set_text(&tag, text.clone())?;
+                insert_change(&mut changes.texts, tag, old, Some(text));
+            },
             Effect::SetAttributeFromValue(tag, val) => { 
                 if let Some(val) = self.get_value(val)?
                 {
-                    self.set_attribute(tag, val)?; 
+                    self.set_attribute_changes(tag, val, &mut changes)?;
+                }
+            },
+            Effect::AddToAttribute(tag, amount) =>
+            {
+                let old = self.get_attribute_value(tag).unwrap_or(0.0);
+                self.set_attribute_changes(tag, old + amount, &mut changes)?;
+            },
+            Effect::MultiplyAttribute(tag, amount) =>
+            {
+                if let Some(old) = self.get_attribute_value(tag)
+                {
+                    self.set_attribute_changes(tag, old * amount, &mut changes)?;
+                }
+            },
+            Effect::Sequence(effects) =>
+            {
+                for e in effects.iter()
+                {
+                    match self.apply_effect_changes(e)
+                    {
+                        Ok(c) => changes.compose(c),
+                        Err(e) =>
+                        {
+                            // Undoes the effects already applied, each of which could be applied to the same values
+                            self.apply_changes(&changes.invert(), &mut ContextChangeset::new())?;
+                            return Err(e);
+                        },
+                    }
+                }
+            },
+            Effect::ApplyIf(condition, effect) =>
+            {
+                if self.eval_conditional(condition)?
+                {
+                    changes = self.apply_effect_changes(effect)?;
                 }
             },
         }
+        Ok(changes)
+    }
+
+    fn set_attribute_changes(&mut self, t: &Tag, nv: f32, changes: &mut ContextChangeset) -> Result<(), DataError>
+    {
+        let t = self.aliases.resolve(t).into_owned();
+        let old = self.set_attribute(&t, nv)?;
+        insert_change(&mut changes.attributes, t, old, Some(nv));
         Ok(())
     }
 
+    /// The value of an attribute without any modifiers applied, which is
+    /// what relative effects (such as `Effect::AddToAttribute`) change.
+    fn get_attribute_value(&self, t: &Tag) -> Option<f32>
+    {
+        let t: &Tag = &self.aliases.resolve(t);
+        self.atrs.get(t).map(|a| a.get_value())
+    }
+
     /// Gets the value of an attribute (including equation aliases) 
     /// accounting for all modifiers applied.
     /// 
     /// Can error from modifier or equation evaluation failures
     pub fn get_value(&self, t: &Tag) -> Result<Option<f32>, DataError>
     {
-        if let Some(a) = self.atrs.get(t)
+        let t: &Tag = &self.aliases.resolve(t);
+        if let Some(v) = self.cache.as_ref().and_then(|c| c.get_value(t))
+        {
+            return Ok(Some(v));
+        }
+
+        let v = if let Some(a) = self.atrs.get(t)
         { 
-            Ok(Some(self.modifiers.apply_modifiers(self, t, a.get_value())?))
+            self.modifiers.apply_modifiers(self, t, a.get_value())?
         }
         else if self.equations.has_equation(t)
         {
-            Ok(Some(self.modifiers.apply_modifiers(self, t, self.equations.eval(t, self)?)?))
+            self.modifiers.apply_modifiers(self, t, self.equations.eval(t, self)?)?
         }
         else
         {
-            Ok(None)
+            return Ok(None);
+        };
+
+        if let Some(cache) = &self.cache
+        {
+            cache.store_value(t, v);
+        }
+        Ok(Some(v))
+    }
+
+    /// Gets the value of an attribute or equation along with an explanation
+    /// of how that value was found (See `ValueTrace`).
+    /// 
+    /// Returns None if there is no value for the tag, same as `get_value`
+    pub fn get_value_trace(&self, t: &Tag) -> Result<Option<ValueTrace>, DataError>
+    {
+        let t: &Tag = &self.aliases.resolve(t);
+        let base = if let Some(a) = self.atrs.get(t)
+        {
+            BaseTrace::Attribute(a.get_value())
+        }
+        else if let Some(e) = self.equations.get(t)
+        {
+            BaseTrace::Equation { equation: e.get_equation_string(), references: self.get_reference_traces(e.get_dependencies(self))?, result: e.eval(self)? }
+        }
+        else
+        {
+            return Ok(None);
+        };
+
+        let mut modifiers = vec![];
+        let mut passed = vec![];
+        for (modifier, condition) in self.modifiers.get_applicable_modifiers(t)?
+        {
+            let condition = self.eval_conditional_trace(&condition)?;
+            let amount = match modifier.change.get_other_value()
+            {
+                Some(other) if condition.result =>
+                {
+                    let trace = self.get_value_trace(other)?.ok_or_else(|| DataError::value_dne(other.clone()))?;
+                    ModifierAmountTrace::FromOtherValue(other.clone(), Some(Box::new(trace)))
+                },
+                Some(other) => ModifierAmountTrace::FromOtherValue(other.clone(), None),
+                None => ModifierAmountTrace::BasicValue(modifier.change.eval(self)?),
+            };
+            if condition.result
+            {
+                passed.push((modifier, modifiers.len()));
+            }
+            modifiers.push(ModifierTrace { name: modifier.name.clone(), condition, change: modifier.change.clone(), amount, group: modifier.group.clone(), suppressed: false });
+        }
+
+        // Mark the modifiers which passed their condition but lost out to another modifier of their stacking group
+        let amounts: Vec<(&Modifier, f32)> = passed.iter().map(|(m, i)| (*m, modifiers[*i].amount.get_amount().unwrap_or_default())).collect();
+        for ((_, i), applied) in passed.iter().zip(self.modifiers.resolve_stacking_groups(&amounts))
+        {
+            modifiers[*i].suppressed = !applied;
+        }
+
+        let result = self.get_value(t)?.ok_or_else(|| DataError::value_dne(t.clone()))?;
+        Ok(Some(ValueTrace { name: t.clone(), base, modifiers, result }))
+    }
+
+    /// Evaluates a conditional along with an explanation of the values it referenced (See `ConditionalTrace`).
+    pub fn eval_conditional_trace(&self, conditional_name: &Tag) -> Result<ConditionalTrace, DataError>
+    {
+        let conditional_name: &Tag = &self.aliases.resolve(conditional_name);
+        self.ensure_target_conditional(conditional_name)?;
+        if let Some(c) = self.conditionals.get(conditional_name)
+        {
+            Ok(ConditionalTrace
+            {
+                name: conditional_name.clone(),
+                equation: c.get_equation_string(),
+                references: self.get_reference_traces(c.get_dependencies(self))?,
+                result: self.eval_conditional(conditional_name)?,
+            })
+        }
+        else
+        {
+            Err(DataError::condition_dne(conditional_name.clone()))
         }
     }
 
+    /// Traces each tag referenced by an equation or conditional, the same way the tag is evaluated in an EvalTree
+    fn get_reference_traces(&self, mut references: Vec<Tag>) -> Result<Vec<Trace>, DataError>
+    {
+        // Tags can be referenced more than once in an equation, but only need to be explained once
+        let mut seen = HashSet::new();
+        references.retain(|t| seen.insert(t.clone()));
+
+        references.iter().map(|t|
+        {
+            if self.has_conditional(t)
+            {
+                Ok(Trace::Conditional(self.eval_conditional_trace(t)?))
+            }
+            else if let Some(v) = self.get_value_trace(t)?
+            {
+                Ok(Trace::Value(v))
+            }
+            else if let Some(text) = self.get_text(t)
+            {
+                Ok(Trace::Text(t.clone(), text.clone()))
+            }
+            else
+            {
+                Ok(Trace::Tag(t.clone(), self.has_tag(t)))
+            }
+        }).collect()
+    }
+
     /// Gets all the tags contained in the ctx
     pub fn get_tagset(&self) -> &TagSet
     {
         &self.tags
     }
 
+    /// Finds every tag in the ctx which matches the filter, in sorted order.
+    /// If the filter has value predicates, only tags with a value satisfying them are found.
+    pub fn query_tags(&self, filter: &TagFilter) -> Result<Vec<Tag>, DataError>
+    {
+        if filter.has_value_predicates()
+        {
+            return Ok(self.query_values(filter)?.into_iter().map(|(t, _)| t).collect());
+        }
+
+        let mut result: Vec<Tag> = self.tags.iter().filter(|t| self.tags.has_tag(t) && filter.matches(t)).cloned().collect();
+        result.sort();
+        Ok(result)
+    }
+
+    /// Finds every value (attribute or equation) in the ctx which matches the filter,
+    /// given as (tag, value) pairs in sorted order of the tags.
+    pub fn query_values(&self, filter: &TagFilter) -> Result<Vec<(Tag, f32)>, DataError>
+    {
+        let mut result = vec![];
+        for t in self.tags.iter().filter(|t| self.has_value(t) && filter.matches(t))
+        {
+            if let Some(v) = self.get_value(t)?
+            {
+                if filter.accepts_value(v)
+                {
+                    result.push((t.clone(), v));
+                }
+            }
+        }
+        result.sort_by(|(t1, _), (t2, _)| t1.cmp(t2));
+        Ok(result)
+    }
+
+    /// Describes an error from the given source (such as an equation string) as a diagnostic.
+    /// When the error is caused by a missing tag, the closest tag in the ctx is suggested.
+    pub fn diagnose(&self, source: &str, error: &DataError, registry: &TagRegistry) -> Diagnostic
+    {
+        let result = error.to_diagnostic(source, registry);
+        let missing = match error
+        {
+            DataError::Evaluation(EvalError::ValueNotFound(t)) => t,
+            DataError::DoesNotExist(DoesNotExistError::Tag(t))
+                | DataError::DoesNotExist(DoesNotExistError::Attribute(t))
+                | DataError::DoesNotExist(DoesNotExistError::Condition(t))
+                | DataError::DoesNotExist(DoesNotExistError::Modifier(t))
+                | DataError::DoesNotExist(DoesNotExistError::Equation(t))
+                | DataError::DoesNotExist(DoesNotExistError::Value(t))
+                | DataError::DoesNotExist(DoesNotExistError::Text(t)) => t,
+            _ => return result,
+        };
+
+        let known: Vec<Tag> = self.tags.iter().cloned().collect();
+        match missing.to_string(registry).and_then(|missing| registry.suggest_tag(&missing, &known))
+        {
+            Some(suggestion) =>
+            {
+                let span = result.span.unwrap_or(Span::new(0, 0));
+                result.with_suggestion(span, suggestion)
+            },
+            None => result,
+        }
+    }
+
     /// Sets the value of an attribute directly. This should ONLY be
     /// used for initialization, as this circumvents the effect and modifier
     /// system.
@@ -186,17 +747,36 @@ impl Context
     /// Returns the previous value if it existed
     pub fn set_attribute(&mut self, t: &Tag, nv: f32) -> Result<Option<f32>, DataError>
     {
+        let t: &Tag = &self.aliases.resolve(t);
+        let old = self.insert_attribute(t, nv)?;
+        // Only a new attribute changes what is evaluated (such as by matching a modifier's target)
+        if old.is_none()
+        {
+            if let Err(e) = self.ensure_no_cycles_through(std::slice::from_ref(t))
+            {
+                self.remove_attribute(t)?;
+                return Err(e);
+            }
+        }
+        Ok(old)
+    }
+
+    fn insert_attribute(&mut self, t: &Tag, nv: f32) -> Result<Option<f32>, DataError>
+    {
+        let t: &Tag = &self.aliases.resolve(t);
         self.ensure_target_attribute(t)?;
         if let Some(a) = self.atrs.get_mut(t)
         {
             let old = a.get_value();
             a.set_value(nv);
+            self.invalidate(t, &[]);
             Ok(Some(old))
         }
         else
         {
             self.tags.add_tag(t);
             self.atrs.set_attribute(t, nv);
+            self.invalidate(t, std::slice::from_ref(t));
             Ok(None)
         }
     }
@@ -209,11 +789,14 @@ impl Context
     /// to evaluate those values.
     pub fn remove_attribute(&mut self, t: &Tag) -> Result<Option<f32>, DataError>
     {
+        let t: &Tag = &self.aliases.resolve(t);
         self.ensure_target_attribute(t)?;
         if self.atrs.has_attribute(t)
         {
             self.tags.remove_tag(t);
-            Ok(self.atrs.remove_attribute(t).map(|a| a.get_value()))
+            let old = self.atrs.remove_attribute(t).map(|a| a.get_value());
+            self.invalidate(t, std::slice::from_ref(t));
+            Ok(old)
         }
         else
         {
@@ -221,26 +804,44 @@ impl Context
         }
     }
 
-    /// If the given modifier is not already applied to this context,
-    /// applies it. The old modifier is returned if the modifier was replaced
-    pub fn set_modifier(&mut self, m: Modifier) -> Result<Option<Modifier>, DataError>
+    /// Gets the text or enum value of the given tag, if it exists
+    pub fn get_text(&self, t: &Tag) -> Option<&TextValue>
     {
-        self.ensure_target_modifier(&m.name)?;
+        let t: &Tag = &self.aliases.resolve(t);
+        self.texts.get(t)
+    }
 
-        if !self.has_modifier(&m.name)
+    /// Sets a text value, returning the previous value if it existed.
+    /// 
+    /// Fails if the tag targets an existing value which is not a text
+    /// or if the text is an enum whose selected tag is not one of its options.
+    pub fn set_text(&mut self, t: &Tag, nv: TextValue) -> Result<Option<TextValue>, DataError>
+    {
+        let t: &Tag = &self.aliases.resolve(t);
+        let nv = self.resolve_aliases(nv);
+        self.ensure_target_text(t)?;
+        nv.validate()?;
+
+        let existed = self.has_text(t);
+        if !existed
         {
-            self.tags.add_tag(&m.name);
+            self.tags.add_tag(t);
         }
-        Ok(self.modifiers.set_modifier(m))
+        let old = self.texts.set_text(t, nv);
+        self.invalidate(t, &[]);
+        Ok(old)
     }
 
-    pub fn remove_modifier(&mut self, t: &Tag) -> Result<Option<Modifier>, DataError>
+    pub fn remove_text(&mut self, t: &Tag) -> Result<Option<TextValue>, DataError>
     {
-        self.ensure_target_modifier(&t)?;
-        if self.has_modifier(t)
+        let t: &Tag = &self.aliases.resolve(t);
+        self.ensure_target_text(t)?;
+        if self.has_text(t)
         {
             self.tags.remove_tag(t);
-            Ok(self.modifiers.remove_modifier(t))
+            let old = self.texts.remove_text(t);
+            self.invalidate(t, &[]);
+            Ok(old)
         }
         else
         {
@@ -248,36 +849,153 @@ impl Context
         }
     }
 
-    pub fn set_equation(&mut self, nv: Equation) -> Result<Option<Equation>, DataError>
+    /// If the given modifier is not already applied to this context,
+    /// applies it. The old modifier is returned if the modifier was replaced
+    /// 
+    /// Fails if the modifier would cause a cycle of evaluation, leaving the context unchanged.
+    pub fn set_modifier(&mut self, m: Modifier) -> Result<Option<Modifier>, DataError>
     {
-        self.ensure_target_equation(&nv.name)?;
-        if let Some(e) = self.equations.get_mut(&nv.name)
+        let m = self.resolve_aliases(m);
+        let name = m.name.clone();
+        let modified = self.get_modified_values(&m);
+        let old = self.insert_modifier(m)?;
+        if let Err(e) = self.ensure_no_cycles_through(&modified)
         {
-            let old = e.clone();
-            *e = nv;
-            Ok(Some(old))
+            match old
+            {
+                Some(old) => { self.insert_modifier(old)?; },
+                None => { self.remove_modifier(&name)?; },
+            }
+            return Err(e);
         }
-        else
-        {
-            self.tags.add_tag(&nv.name);
-            self.equations.set_equation(nv);
-            Ok(None)
+        Ok(old)
+    }
+
+    fn insert_modifier(&mut self, m: Modifier) -> Result<Option<Modifier>, DataError>
+    {
+        self.ensure_target_modifier(&m.name)?;
+
+        if !self.has_modifier(&m.name)
+        {
+            self.tags.add_tag(&m.name);
         }
+        let name = m.name.clone();
+        let mut modified = self.get_modified_values(&m);
+        let old = self.modifiers.set_modifier(m);
+        if let Some(old) = &old
+        {
+            modified.extend(self.get_modified_values(old));
+        }
+        self.invalidate(&name, &modified);
+        Ok(old)
+    }
+
+    /// Declares how the modifiers of a stacking group combine (See `StackingPolicy`),
+    /// returning the old policy of the group if it had one.
+    pub fn set_stacking_policy(&mut self, group: &Tag, policy: StackingPolicy) -> Option<StackingPolicy>
+    {
+        let group: &Tag = &self.aliases.resolve(group);
+        let old = self.modifiers.set_stacking_policy(group.clone(), policy);
+        self.invalidate_stacking_group(group);
+        old
+    }
+
+    pub fn get_stacking_policy(&self, group: &Tag) -> StackingPolicy
+    {
+        let group: &Tag = &self.aliases.resolve(group);
+        self.modifiers.get_stacking_policy(group)
+    }
+
+    pub fn remove_stacking_policy(&mut self, group: &Tag) -> Option<StackingPolicy>
+    {
+        let group: &Tag = &self.aliases.resolve(group);
+        let old = self.modifiers.remove_stacking_policy(group);
+        self.invalidate_stacking_group(group);
+        old
+    }
+
+    /// The values modified by a stacking group depend on the policy of the group
+    fn invalidate_stacking_group(&mut self, group: &Tag)
+    {
+        let names: Vec<Tag> = self.modifiers.iter().filter(|(_, m)| m.group.as_ref() == Some(group)).map(|(t, _)| t.clone()).collect();
+        names.iter().for_each(|t| self.invalidate(t, &[]));
+    }
+
+    pub fn remove_modifier(&mut self, t: &Tag) -> Result<Option<Modifier>, DataError>
+    {
+        let t: &Tag = &self.aliases.resolve(t);
+        self.ensure_target_modifier(&t)?;
+        if self.has_modifier(t)
+        {
+            self.tags.remove_tag(t);
+            let old = self.modifiers.remove_modifier(t);
+            let modified = old.as_ref().map(|m| self.get_modified_values(m)).unwrap_or_default();
+            self.invalidate(t, &modified);
+            Ok(old)
+        }
+        else
+        {
+            Ok(None)
+        }
+    }
+
+    /// Sets an equation, returning the old equation if it was replaced.
+    /// 
+    /// Fails if the equation would cause a cycle of evaluation, leaving the context unchanged.
+    pub fn set_equation(&mut self, nv: Equation) -> Result<Option<Equation>, DataError>
+    {
+        let nv = self.resolve_aliases(nv);
+        let name = nv.name.clone();
+        let old = self.insert_equation(nv)?;
+        if let Err(e) = self.ensure_no_cycles_through(std::slice::from_ref(&name))
+        {
+            match old
+            {
+                Some(old) => { self.insert_equation(old)?; },
+                None => { self.remove_equation(&name)?; },
+            }
+            return Err(e);
+        }
+        Ok(old)
+    }
+
+    fn insert_equation(&mut self, nv: Equation) -> Result<Option<Equation>, DataError>
+    {
+        self.ensure_target_equation(&nv.name)?;
+        let name = nv.name.clone();
+        let old = if let Some(e) = self.equations.get_mut(&nv.name)
+        {
+            let old = e.clone();
+            *e = nv;
+            Some(old)
+        }
+        else
+        {
+            self.tags.add_tag(&nv.name);
+            self.equations.set_equation(nv);
+            None
+        };
+        self.invalidate(&name, std::slice::from_ref(&name));
+        Ok(old)
     }
 
     pub fn eval_equation(&self, equation_name: &Tag) -> Result<f32, DataError>
     {
+        let equation_name: &Tag = &self.aliases.resolve(equation_name);
         self.ensure_target_equation(equation_name)?;
         self.equations.eval(equation_name, self)
     }
 
     pub fn remove_equation(&mut self, equation_name: &Tag) -> Result<Option<Equation>, DataError>
     {
+        let equation_name: &Tag = &self.aliases.resolve(equation_name);
         self.ensure_target_equation(equation_name)?;
         if self.has_equation(equation_name)
         {
             self.tags.remove_tag(equation_name);
-            Ok(self.equations.remove_equation(equation_name))
+            let old = self.equations.remove_equation(equation_name);
+            self.invalidate(equation_name, std::slice::from_ref(equation_name));
+            Ok(old)
         }
         else
         {
@@ -285,36 +1003,73 @@ impl Context
         }
     }
 
+    /// Sets a conditional, returning the old conditional if it was replaced.
+    /// 
+    /// Fails if the conditional would cause a cycle of evaluation, leaving the context unchanged.
     pub fn set_conditional(&mut self, nv: Conditional) -> Result<Option<Conditional>, DataError>
+    {
+        let nv = self.resolve_aliases(nv);
+        let name = nv.name.clone();
+        let old = self.insert_conditional(nv)?;
+        if let Err(e) = self.ensure_no_cycles_through(std::slice::from_ref(&name))
+        {
+            match old
+            {
+                Some(old) => { self.insert_conditional(old)?; },
+                None => { self.remove_conditional(&name)?; },
+            }
+            return Err(e);
+        }
+        Ok(old)
+    }
+
+    fn insert_conditional(&mut self, nv: Conditional) -> Result<Option<Conditional>, DataError>
     {
         self.ensure_target_conditional(&nv.name)?;
-        if let Some(c) = self.conditionals.get_mut(&nv.name)
+        let name = nv.name.clone();
+        let old = if let Some(c) = self.conditionals.get_mut(&nv.name)
         {
             let old = c.clone();
             *c = nv;
-            Ok(Some(old))
+            Some(old)
         }
         else
         {
             self.tags.add_tag(&nv.name);
             self.conditionals.set_conditional(nv);
-            Ok(None)
-        }
+            None
+        };
+        self.invalidate(&name, std::slice::from_ref(&name));
+        Ok(old)
     }
 
     pub fn eval_conditional(&self, conditional_name: &Tag) -> Result<bool, DataError>
     {
+        let conditional_name: &Tag = &self.aliases.resolve(conditional_name);
+        if let Some(b) = self.cache.as_ref().and_then(|c| c.get_condition(conditional_name))
+        {
+            return Ok(b);
+        }
+
         self.ensure_target_conditional(conditional_name)?;
-        self.conditionals.eval(conditional_name, self)
+        let b = self.conditionals.eval(conditional_name, self)?;
+        if let Some(cache) = &self.cache
+        {
+            cache.store_condition(conditional_name, b);
+        }
+        Ok(b)
     }
 
     pub fn remove_conditional(&mut self, conditional_name: &Tag) -> Result<Option<Conditional>, DataError>
     {
+        let conditional_name: &Tag = &self.aliases.resolve(conditional_name);
         self.ensure_target_conditional(conditional_name)?;
         if self.has_conditional(conditional_name)
         {
             self.tags.remove_tag(conditional_name);
-            Ok(self.conditionals.remove_conditional(conditional_name))
+            let old = self.conditionals.remove_conditional(conditional_name);
+            self.invalidate(conditional_name, std::slice::from_ref(conditional_name));
+            Ok(old)
         }
         else
         {
@@ -322,16 +1077,248 @@ impl Context
         }
     }
 
+    /// Renames a tag, along with every tag it prefixes, everywhere in this context.
+    /// This includes the names of values, the tags referenced by equations and conditionals
+    /// (along with their equation strings), modifier targets and conditions, and state tags.
+    /// Ex: renaming "ability.magic theory" to "ability.magic theory.score" also renames
+    /// "ability.magic theory.exp" and rewrites "ability.magic theory / 5" to "ability.magic theory.score / 5"
+    /// 
+    /// Equation strings are rewritten with the names of the registry, so the tags of this context and the equations
+    /// it holds must have been made through the same registry (see `Equation::new_with_registry`).
+    /// Tags the registry can not name are kept as written.
+    /// 
+    /// Fails without changing the context if a renamed value would replace a value which is not renamed,
+    /// if the old or new name is hidden by an alias, or if the renamed references would form a cycle of evaluation.
+    /// Use `Context::add_alias` afterwards to have the old name still resolve.
+    pub fn rename_tag(&mut self, from: &Tag, to: &Tag, registry: &TagRegistry) -> Result<(), DataError>
+    {
+        // Aliased names no longer hold values, and values renamed to an aliased name could not be found
+        let rename = TagRename::new(from.clone(), to.clone());
+        if let Some(aliased) = [from, to].into_iter().find(|t| *self.aliases.resolve(t) != **t)
+        {
+            return Err(DataError::RenameConflict(aliased.clone()));
+        }
+        if let Some((alias, _)) = self.aliases.iter().find(|(alias, _)| rename.rename(alias).is_some())
+        {
+            return Err(DataError::RenameConflict(alias.clone()));
+        }
+
+        let names: HashSet<&Tag> = self.atrs.iter().map(|(t, _)| t)
+            .chain(self.equations.iter().map(|(t, _)| t))
+            .chain(self.conditionals.iter().map(|(t, _)| t))
+            .chain(self.modifiers.iter().map(|(t, _)| t))
+            .chain(self.texts.iter().map(|(t, _)| t))
+            .collect();
+        for name in names.iter()
+        {
+            if let Some(renamed) = rename.rename(name)
+            {
+                if names.contains(&renamed) && rename.rename(&renamed).is_none()
+                {
+                    return Err(DataError::RenameConflict(renamed));
+                }
+            }
+        }
+
+        let previous = self.clone();
+        self.remap_tags(&NamedMapping::new(&rename, registry));
+        let result = self.ensure_no_cycles();
+        if result.is_err()
+        {
+            *self = previous;
+        }
+        result
+    }
+
+    /// Has an old name of a tag (and the tags it prefixes) resolve to a new name, returning the
+    /// previous new name of the alias if there was one. This keeps equations, effects and lookups
+    /// written with the old name working while they are migrated (See `Context::rename_tag`).
+    /// 
+    /// Values are read and written through aliases, so setting a value of the old name sets the value of the new name.
+    pub fn add_alias(&mut self, from: &Tag, to: &Tag) -> Option<Tag>
+    {
+        let old = self.aliases.insert(from.clone(), to.clone());
+        self.refresh_cache();
+        old
+    }
+
+    pub fn remove_alias(&mut self, from: &Tag) -> Option<Tag>
+    {
+        let old = self.aliases.remove(from);
+        self.refresh_cache();
+        old
+    }
+
+    pub fn get_aliases(&self) -> &TagAliases
+    {
+        &self.aliases
+    }
+
+    /// Rewrites the old names of aliases in data being added to this context
+    fn resolve_aliases<T: RemapTags>(&self, mut value: T) -> T
+    {
+        if !self.aliases.is_empty()
+        {
+            value.remap_tags(&self.aliases);
+        }
+        value
+    }
+
+    /// Rebuilds the cache, if enabled, when the tags of the context change as a whole
+    fn refresh_cache(&mut self)
+    {
+        if self.is_cache_enabled()
+        {
+            self.enable_cache();
+        }
+    }
+
     /// Checks for attributes and equations which cause cycles
     /// of evaluation. For example:
     ///     attribute { name: test_atr }
     ///     conditional { name: test_cond, equation: test_atr == 3.0 }
     ///     modifier { name: test_mod, target: test_atr, conditional: test_cond }
     /// 
-    /// If any cycles are found, the modifier's tag causing the cycle is returned.
-    pub fn check_for_cyclic_evalutation(&self) -> Option<Vec<Tag>>
+    /// If any cycles are found, every cycle is returned as the list of tags
+    /// involved (including the modifiers causing them).
+    /// For the example above, `[test_atr, test_mod, test_cond]`
+    pub fn check_for_cyclic_evalutation(&self) -> Option<Vec<Vec<Tag>>>
+    {
+        let cycles = self.build_dependency_graph().find_cycles();
+        if cycles.is_empty()
+        {
+            None
+        }
+        else
+        {
+            Some(cycles)
+        }
+    }
+
+    /// Builds the graph of what each value, conditional, and modifier
+    /// needs to read when it is evaluated.
+    /// 
+    /// - Equations and conditionals depend upon the tags in their equation
+    /// - Values depend upon the condition and change of each modifier which can apply to them
+    pub fn build_dependency_graph(&self) -> DependencyGraph
+    {
+        let mut graph = DependencyGraph::new();
+        let mut tags: HashSet<&Tag> = self.atrs.iter().map(|(t, _)| t)
+            .chain(self.equations.iter().map(|(t, _)| t))
+            .chain(self.conditionals.iter().map(|(t, _)| t))
+            .collect();
+        // A single target modifier applies to its target before the target is set
+        tags.extend(self.modifiers.iter().filter_map(|(_, m)| match &m.target { ModifierTarget::Single(t) => Some(t), _ => None }));
+
+        for t in tags
+        {
+            self.get_direct_dependencies(t).into_iter().for_each(|(d, via)| graph.add_dependency(t, &d, via.as_ref()));
+            self.get_prefix_dependencies(t).iter().for_each(|p| graph.add_prefix_dependency(t, p));
+        }
+        graph
+    }
+
+    /// Builds the part of the dependency graph reachable from the given tags
+    fn build_reachable_dependency_graph(&self, starts: &[Tag]) -> DependencyGraph
     {
-        todo!()
+        let mut graph = DependencyGraph::new();
+        let mut visited = HashSet::new();
+        let mut to_visit: Vec<Tag> = starts.to_vec();
+        while let Some(t) = to_visit.pop()
+        {
+            if visited.insert(t.clone())
+            {
+                for (d, via) in self.get_direct_dependencies(&t)
+                {
+                    graph.add_dependency(&t, &d, via.as_ref());
+                    to_visit.push(d);
+                }
+            }
+        }
+        graph
+    }
+
+    /// Everything the given tag reads when it is evaluated, along with the modifier causing each dependency (if any).
+    /// Matching modifiers only apply to values which exist, while a single target modifier always applies to its target.
+    fn get_direct_dependencies(&self, t: &Tag) -> Vec<(Tag, Option<Tag>)>
+    {
+        let mut result = vec![];
+        if let Some(equation) = self.equations.get(t)
+        {
+            result.extend(equation.get_dependencies(self).into_iter().map(|d| (d, None)));
+        }
+        if let Some(conditional) = self.conditionals.get(t)
+        {
+            result.extend(conditional.get_dependencies(self).into_iter().map(|d| (d, None)));
+        }
+
+        // A modifier set which is inconsistent is reported once the value is evaluated
+        let is_value = self.has_value(t);
+        for (modifier, condition) in self.modifiers.get_applicable_modifiers(t).unwrap_or_default()
+        {
+            if is_value || matches!(modifier.target, ModifierTarget::Single(_))
+            {
+                result.push((condition, Some(modifier.name.clone())));
+                if let Some(other) = modifier.change.get_other_value()
+                {
+                    result.push((other.clone(), Some(modifier.name.clone())));
+                }
+            }
+        }
+        result
+    }
+
+    /// The prefixes the given tag aggregates over
+    fn get_prefix_dependencies(&self, t: &Tag) -> Vec<Tag>
+    {
+        let mut result = vec![];
+        if let Some(equation) = self.equations.get(t)
+        {
+            result.extend(equation.get_prefix_dependencies());
+        }
+        if let Some(conditional) = self.conditionals.get(t)
+        {
+            result.extend(conditional.get_prefix_dependencies());
+        }
+        result
+    }
+
+    /// The values a modifier can apply to. For matching modifiers, these are the existing values which match.
+    fn get_modified_values(&self, m: &Modifier) -> Vec<Tag>
+    {
+        let values = self.atrs.iter().map(|(t, _)| t).chain(self.equations.iter().map(|(t, _)| t));
+        match &m.target
+        {
+            ModifierTarget::Single(target) => vec![target.clone()],
+            ModifierTarget::MatchingEnd(suffix) => values.filter(|v| v.has_suffix(suffix)).cloned().collect(),
+            ModifierTarget::MatchingStart(prefix) => values.filter(|v| v.has_prefix(prefix)).cloned().collect(),
+        }
+    }
+
+    fn ensure_no_cycles(&self) -> Result<(), DataError>
+    {
+        match self.check_for_cyclic_evalutation()
+        {
+            Some(cycles) => Err(DataError::CyclicEvaluation(cycles)),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks for cycles of evaluation passing through the given tags.
+    /// 
+    /// Any cycle made by changing a single value, conditional or modifier must pass through what was changed,
+    /// so only the part of the graph reachable from the change needs to be searched rather than the whole context.
+    fn ensure_no_cycles_through(&self, changed: &[Tag]) -> Result<(), DataError>
+    {
+        let cycles = self.build_reachable_dependency_graph(changed).find_cycles();
+        if cycles.is_empty()
+        {
+            Ok(())
+        }
+        else
+        {
+            Err(DataError::CyclicEvaluation(cycles))
+        }
     }
 
     fn ensure_target_attribute(&self, t: &Tag) -> Result<(), DataError>
@@ -354,6 +1341,11 @@ impl Context
         self.ensure_target(t, DataType::Condition)
     }
 
+    fn ensure_target_text(&self, t: &Tag) -> Result<(), DataError>
+    {
+        self.ensure_target(t, DataType::Text)
+    }
+
     fn ensure_target(&self, t: &Tag, target: DataType) -> Result<(), DataError>
     {
         let conflict = if self.has_attribute(t)
@@ -372,6 +1364,10 @@ impl Context
         {
             Some(DataType::Modifier)
         }
+        else if self.has_text(t)
+        {
+            Some(DataType::Text)
+        }
         else
         {
             None
@@ -404,6 +1400,8 @@ impl Context
             modifiers: self.modifiers.clone(),
             equations: self.equations.clone(),
             conditionals: self.conditionals.clone(),
+            texts: self.texts.clone(),
+            aliases: self.aliases.clone(),
         }
     }
 
@@ -412,9 +1410,14 @@ impl Context
         let mut result = Self::new();
         result.state_tags = raw.state_tags;
         raw.atrs.into_iter().try_for_each(|(_, a)| result.set_attribute(a.get_name(), a.get_value()).map(|_| ()))?;
-        raw.modifiers.into_iter().try_for_each(|(_, m)| result.set_modifier(m).map(|_| ()))?;
-        raw.equations.into_iter().try_for_each(|(_, e)| result.set_equation(e).map(|_| ()))?;
-        raw.conditionals.into_iter().try_for_each(|(_, c)| result.set_conditional(c).map(|_| ()))?;
+        raw.modifiers.iter_stacking_policies().for_each(|(group, policy)| { result.modifiers.set_stacking_policy(group.clone(), *policy); });
+        raw.modifiers.into_iter().try_for_each(|(_, m)| result.insert_modifier(m).map(|_| ()))?;
+        raw.equations.into_iter().try_for_each(|(_, e)| result.insert_equation(e).map(|_| ()))?;
+        raw.conditionals.into_iter().try_for_each(|(_, c)| result.insert_conditional(c).map(|_| ()))?;
+        raw.texts.into_iter().try_for_each(|(t, text)| result.set_text(&t, text).map(|_| ()))?;
+        // Aliases are added last, as the values are already stored under their new names
+        result.aliases = raw.aliases;
+        result.ensure_no_cycles()?;
         Ok(result)
     }
 }
@@ -445,6 +1448,23 @@ impl From<AttributeSet> for Context
     }
 }
 
+impl RemapTags for Context
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        self.tags.remap_tags(remap);
+        self.state_tags.remap_tags(remap);
+        self.atrs.remap_tags(remap);
+        self.modifiers.remap_tags(remap);
+        self.equations.remap_tags(remap);
+        self.conditionals.remap_tags(remap);
+        self.texts.remap_tags(remap);
+        self.aliases.remap_tags(remap);
+        // Every cached value is keyed by the old tags, so the cache is built again
+        self.refresh_cache();
+    }
+}
+
 /// A context template is used to provide a collection of
 /// templates that are needed to be filled out. Once they are,
 /// a context can be created.
@@ -461,10 +1481,25 @@ pub struct ContextTemplate
 {
     ctx: Context,
     templates: Vec<TemplateValue>,  // Ensure all template values provided are truly template values (they have required inputs). Otherwise, the state of this context template will be invalid.
+    #[serde(default)]
+    inputs: HashMap<String, TemplateInputType>, // The declared types of inputs which are not filled yet. Undeclared inputs take any tag.
+    #[serde(default)]
+    number_inputs: HashMap<String, Tag>,        // The attribute each declared number input is set as
 }
 
 impl ContextTemplate
 {
+    pub fn new(ctx: Context) -> ContextTemplate
+    {
+        ContextTemplate { ctx, templates: vec![], inputs: HashMap::new(), number_inputs: HashMap::new() }
+    }
+
+    pub fn with_template(mut self, template: TemplateValue) -> Self
+    {
+        self.insert_template(template);
+        self
+    }
+
     pub fn get_partial_context(&self) -> &Context
     {
         &self.ctx
@@ -491,22 +1526,86 @@ impl ContextTemplate
             None
         }
     }
-}
 
-impl Template<Context> for ContextTemplate
-{
-    fn get_required_inputs(&self) -> HashSet<String>
+    /// Declares the type of an input, which values filling it are checked against.
+    /// The input becomes required if no template uses it, which is how number inputs are added.
+    ///
+    /// A number input is set as an attribute named by the input, so its name must be a valid tag.
+    /// The name is read the same way as the equations of the templates, so the attribute is the tag
+    /// they read. Ex: the number input "event.exp" can be used by the templated equation
+    /// "ability.[ability].exp + event.exp"
+    pub fn declare_input(&mut self, input_name: &str, input_type: TemplateInputType) -> Result<(), DataError>
     {
-        let mut result = HashSet::new();
-        for t in self.templates.iter()
+        if input_type.is_number()
         {
-            result.extend(t.get_required_inputs());
+            let attribute = EvalTree::from_str(input_name)?.as_tag().cloned()
+                .ok_or_else(|| DataError::StringInputInvalid(format!("Number input \"{}\" is not a tag.", input_name)))?;
+            self.number_inputs.insert(input_name.to_string(), attribute);
         }
+        else
+        {
+            self.number_inputs.remove(input_name);
+        }
+        self.inputs.insert(input_name.to_string(), input_type);
+        Ok(())
+    }
+
+    /// The type of an input which is not filled yet. Inputs which are not declared take any tag.
+    pub fn get_input_type(&self, input_name: &str) -> TemplateInputType
+    {
+        self.inputs.get(input_name).cloned().unwrap_or(TemplateInputType::Tag)
+    }
+
+    /// Every input which is not filled yet along with its type, ordered by name.
+    /// This is enough to build a form (such as for an event) for the template.
+    pub fn get_inputs(&self) -> Vec<(String, TemplateInputType)>
+    {
+        let mut result: Vec<(String, TemplateInputType)> = self.get_required_inputs().into_iter()
+            .map(|i| (i.clone(), self.get_input_type(&i)))
+            .collect();
+        result.sort_by(|(a, _), (b, _)| a.cmp(b));
         result
     }
 
-    fn fill_template_value(&mut self, input_name: &str, input_value: &Tag) -> Option<Context>
+    /// Fills an input of the template, returning the completed context once every input is filled.
+    ///
+    /// The value is checked against the type of the input first. If it is not valid, or a value
+    /// completed by it can not be set in the context (such as one causing a cycle), the error
+    /// is returned and the template is left unchanged.
+    pub fn fill_input(&mut self, input_name: &str, input_value: &TemplateInputValue) -> Result<Option<Context>, DataError>
     {
+        if !self.get_required_inputs().contains(input_name)
+        {
+            return Err(TemplateError::UnknownInput(input_name.to_string()).into());
+        }
+        self.get_input_type(input_name).validate(input_name, input_value)?;
+
+        let previous = self.clone();
+        let result = self.apply_input(input_name, input_value);
+        if result.is_err()
+        {
+            *self = previous;
+        }
+        result
+    }
+
+    fn apply_input(&mut self, input_name: &str, input_value: &TemplateInputValue) -> Result<Option<Context>, DataError>
+    {
+        self.inputs.remove(input_name);
+        let input_value = match input_value
+        {
+            TemplateInputValue::Number(n) =>
+            {
+                // Only declared number inputs take numbers, each of which has its attribute
+                if let Some(attribute) = self.number_inputs.remove(input_name)
+                {
+                    self.ctx.set_attribute(&attribute, *n)?;
+                }
+                return Ok(self.is_complete().then(|| self.ctx.clone()));
+            },
+            TemplateInputValue::Tag(t) => t,
+        };
+
         let completed: Vec<CtxValue> = self.templates.iter_mut()
             .filter_map(|t| match t
             {
@@ -514,6 +1613,8 @@ impl Template<Context> for ContextTemplate
                     tmp.fill_template_value(input_name, input_value).map(CtxValue::Attribute),
                 TemplateValue::Conditional(tmp) => 
                     tmp.fill_template_value(input_name, input_value).map(CtxValue::Conditional),
+                // Effects are not values of the context, so they are applied after the values are set
+                TemplateValue::Effect(_) => None,
                 TemplateValue::Equation(tmp) => 
                     tmp.fill_template_value(input_name, input_value).map(CtxValue::Equation),
                 TemplateValue::Modifier(tmp) => 
@@ -523,33 +1624,60 @@ impl Template<Context> for ContextTemplate
             })
             .collect();
 
+        let effects: Vec<Effect> = self.templates.iter_mut()
+            .filter_map(|t| match t
+            {
+                TemplateValue::Effect(tmp) => tmp.fill_template_value(input_name, input_value),
+                _ => None,
+            })
+            .collect();
+
         // Get rid of everything that was completed
         self.templates.retain(|t| !t.is_complete());
 
         // Apply completed values to context
         for c in completed
         {
-            // Currently ignoring errors
-            let _ = match c
+            match c
             {
-                CtxValue::Attribute(attr) => self.ctx.set_attribute(attr.get_name(), attr.get_value()),
-                CtxValue::Conditional(cond) => self.ctx.set_conditional(cond).map(|_| None),
-                CtxValue::Equation(eq) => self.ctx.set_equation(eq).map(|_| None),
-                CtxValue::Modifier(modif) => self.ctx.set_modifier(modif).map(|_| None),
-                CtxValue::Tag(tag) =>
-                {
-                    self.ctx.add_explicit_tag(&tag);
-                    Ok(None)
-                },
-            };
+                CtxValue::Attribute(attr) => { self.ctx.set_attribute(attr.get_name(), attr.get_value())?; },
+                CtxValue::Conditional(cond) => { self.ctx.set_conditional(cond)?; },
+                CtxValue::Equation(eq) => { self.ctx.set_equation(eq)?; },
+                CtxValue::Modifier(modif) => { self.ctx.set_modifier(modif)?; },
+                CtxValue::Tag(tag) => self.ctx.add_explicit_tag(&tag),
+            }
+        }
+        for e in effects
+        {
+            self.ctx.apply_effect(&e)?;
         }
 
-        self.templates.is_empty().then(|| self.ctx.clone())
+        Ok(self.is_complete().then(|| self.ctx.clone()))
+    }
+}
+
+impl Template<Context> for ContextTemplate
+{
+    fn get_required_inputs(&self) -> HashSet<String>
+    {
+        let mut result: HashSet<String> = self.inputs.keys().cloned().collect();
+        for t in self.templates.iter()
+        {
+            result.extend(t.get_required_inputs());
+        }
+        result
+    }
+
+    /// Fills an input with a tag. See `ContextTemplate::fill_input`, which returns the error
+    /// when the input can not be filled. Here, None is returned and the template is left unchanged.
+    fn fill_template_value(&mut self, input_name: &str, input_value: &Tag) -> Option<Context>
+    {
+        self.fill_input(input_name, &TemplateInputValue::Tag(input_value.clone())).ok().flatten()
     }
 
-    fn attempt_complete(&self) -> Result<Context, super::error::TemplateError>
+    fn attempt_complete(&self) -> Result<Context, TemplateError>
     {
-        if self.templates.is_empty()
+        if self.is_complete()
         {
             Ok(self.ctx.clone())
         }
@@ -570,21 +1698,232 @@ pub enum CtxValue
     Tag(Tag),
 }
 
-/// Used to filter a context
-/// - Filter for values (attributes or equations returned as tag, f32 pairs)
-/// - Filter for equations
-/// - Filter for conditionals
-/// - Filter for modifiers
-/// - Filter for tags
+/// Used to filter the tags of a context. See `Context::query_tags` and `Context::query_values`
 /// 
-/// Filter with a condition such as
-/// - Value equals
-/// - Contains tag
-/// - Immediate Prefix
-/// - Simple Prefix 
+/// A filter is written as a list of tag patterns separated by ',', where each subtag of a pattern
+/// can be a wildcard:
+/// - `*` matches exactly one subtag. Ex: `ability.*.exp` matches `ability.latin.exp`
+/// - `**` matches any number of subtags (including none). Ex: `**.magnitude` matches
+///   `spell.magnitude` and `spell.pilum of fire.magnitude`
+/// 
+/// A pattern starting with '!' removes the tags it matches from the result. When a filter
+/// only has negated patterns, it starts from every tag. Ex: `ability.**, !ability.latin.**`
+/// 
+/// The value of matched tags can also be checked after a `where`, with predicates joined by `and`.
+/// Ex: `ability.* where value >= 5 and value < 10`
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
 pub struct TagFilter
 {
+    include: Vec<TagPattern>,
+    exclude: Vec<TagPattern>,
+    predicates: Vec<ValuePredicate>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+struct TagPattern
+{
+    segments: Vec<PatternSegment>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+enum PatternSegment
+{
+    /// A subtag which must match exactly
+    Subtag(Subtag),
+    /// `*`
+    AnySubtag,
+    /// `**`
+    AnySubtags,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+struct ValuePredicate
+{
+    comparison: ValueComparison,
+    value: f32,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone, Copy)]
+enum ValueComparison
+{
+    Equal,
+    NotEqual,
+    LessThan,
+    LessThanEq,
+    GreaterThan,
+    GreaterThanEq,
+}
+
+impl TagFilter
+{
+    /// Parses the filter, registering the subtags named by its patterns
+    pub fn from_str(s: &str, registry: &mut TagRegistry) -> Result<TagFilter, ParseError>
+    {
+        // The where must be followed by a predicate, as "where" may be part of a tag
+        let where_start = s.match_indices("where")
+            .map(|(i, _)| i)
+            .find(|i| (*i == 0 || s[..*i].ends_with(' ')) && s[i + 5..].trim_start().starts_with("value"));
+
+        let (patterns, predicates) = match where_start
+        {
+            Some(i) => (&s[..i], Some(i + 5)),
+            None => (s, None),
+        };
+
+        let mut result = TagFilter { include: vec![], exclude: vec![], predicates: vec![] };
+        let mut offset = 0;
+        // Only predicates are given, such as "where value > 5"
+        let patterns = if patterns.trim().is_empty() && predicates.is_some() { vec![] } else { patterns.split(',').collect() };
+        for pattern in patterns
+        {
+            let trimmed = pattern.trim_start();
+            let start = offset + pattern.len() - trimmed.len();
+            if let Some(negated) = trimmed.strip_prefix('!')
+            {
+                result.exclude.push(TagPattern::from_str(s, negated, start + 1, registry)?);
+            }
+            else
+            {
+                result.include.push(TagPattern::from_str(s, trimmed, start, registry)?);
+            }
+            offset += pattern.len() + 1;
+        }
+
+        if let Some(mut offset) = predicates
+        {
+            for predicate in s[offset..].split(" and ")
+            {
+                result.predicates.push(ValuePredicate::from_str(s, predicate, offset)?);
+                offset += predicate.len() + " and ".len();
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Whether the tag matches the patterns of the filter. Value predicates are not checked.
+    pub fn matches(&self, t: &Tag) -> bool
+    {
+        (self.include.is_empty() || self.include.iter().any(|p| p.matches(t)))
+            && !self.exclude.iter().any(|p| p.matches(t))
+    }
+
+    /// Whether the filter checks values, so only tags with values can match it
+    pub fn has_value_predicates(&self) -> bool
+    {
+        !self.predicates.is_empty()
+    }
+
+    /// Whether the value satisfies every value predicate of the filter
+    pub fn accepts_value(&self, v: f32) -> bool
+    {
+        self.predicates.iter().all(|p| p.accepts(v))
+    }
+}
+
+impl TagPattern
+{
+    /// Parses the pattern `p`, which starts at the byte `start` of the full filter string `s`
+    fn from_str(s: &str, p: &str, start: usize, registry: &mut TagRegistry) -> Result<TagPattern, ParseError>
+    {
+        let mut segments = vec![];
+        let mut offset = start;
+        for segment in p.split('.')
+        {
+            let trimmed = segment.trim();
+            let segment_start = offset + segment.len() - segment.trim_start().len();
+            let span = Span::new(segment_start, segment_start + trimmed.len());
+            segments.push(match trimmed
+            {
+                "*" => PatternSegment::AnySubtag,
+                "**" => PatternSegment::AnySubtags,
+                "" => return Err(ParseError::new(s.to_string(), span, ParseErrorType::Query(QueryParseError::PatternEmpty))),
+                _ if trimmed.chars().all(|c| c.is_alphanumeric() || c == ' ') =>
+                {
+                    match registry.get_or_register_subtag(trimmed)
+                    {
+                        Ok(subtag) => PatternSegment::Subtag(subtag),
+                        Err(_) => return Err(ParseError::new(s.to_string(), span, ParseErrorType::Query(QueryParseError::SubtagInvalid))),
+                    }
+                },
+                _ => return Err(ParseError::new(s.to_string(), span, ParseErrorType::Query(QueryParseError::SubtagInvalid))),
+            });
+            offset += segment.len() + 1;
+        }
+        Ok(TagPattern { segments })
+    }
 
+    fn matches(&self, t: &Tag) -> bool
+    {
+        Self::matches_from(&self.segments, t.as_subtag_slice())
+    }
+
+    fn matches_from(segments: &[PatternSegment], subtags: &[Subtag]) -> bool
+    {
+        match segments.split_first()
+        {
+            None => subtags.is_empty(),
+            Some((PatternSegment::AnySubtags, rest)) =>
+            {
+                (0..=subtags.len()).any(|skipped| Self::matches_from(rest, &subtags[skipped..]))
+            },
+            Some((segment, rest)) =>
+            {
+                match subtags.split_first()
+                {
+                    Some((subtag, remaining)) =>
+                    {
+                        let matched = match segment
+                        {
+                            PatternSegment::Subtag(s) => s == subtag,
+                            _ => true,
+                        };
+                        matched && Self::matches_from(rest, remaining)
+                    },
+                    None => false,
+                }
+            },
+        }
+    }
+}
+
+impl ValuePredicate
+{
+    /// Parses a predicate such as `value >= 5`, which starts at the byte `start` of the full filter string `s`
+    fn from_str(s: &str, p: &str, start: usize) -> Result<ValuePredicate, ParseError>
+    {
+        let span = Span::new(start + p.len() - p.trim_start().len(), start + p.trim_end().len());
+        let error = || ParseError::new(s.to_string(), span, ParseErrorType::Query(QueryParseError::PredicateInvalid));
+
+        let rest = p.trim().strip_prefix("value").ok_or_else(error)?.trim_start();
+        let (comparison, rest) = [
+                (">=", ValueComparison::GreaterThanEq),
+                ("<=", ValueComparison::LessThanEq),
+                ("==", ValueComparison::Equal),
+                ("!=", ValueComparison::NotEqual),
+                (">", ValueComparison::GreaterThan),
+                ("<", ValueComparison::LessThan),
+            ]
+            .into_iter()
+            .find_map(|(symbol, comparison)| rest.strip_prefix(symbol).map(|rest| (comparison, rest)))
+            .ok_or_else(error)?;
+        let value = rest.trim().parse().map_err(|_| error())?;
+
+        Ok(ValuePredicate { comparison, value })
+    }
+
+    fn accepts(&self, v: f32) -> bool
+    {
+        match self.comparison
+        {
+            ValueComparison::Equal => v == self.value,
+            ValueComparison::NotEqual => v != self.value,
+            ValueComparison::LessThan => v < self.value,
+            ValueComparison::LessThanEq => v <= self.value,
+            ValueComparison::GreaterThan => v > self.value,
+            ValueComparison::GreaterThanEq => v >= self.value,
+        }
+    }
 }
 
 /// Contains simply the raw data of a context. Useful for parsing and debug, should not be used for any major logic
@@ -596,4 +1935,603 @@ pub struct RawContextData
     pub modifiers: ModifierSet,
     pub equations: EquationSet,
     pub conditionals: ConditionalSet,
-}
\ No newline at end of file
+    pub texts: TextSet,
+    pub aliases: TagAliases,
+}
+#[cfg(test)]
+mod unit_tests
+{
+    use crate::api::data::{attribute::AttributeTemplate, conditional::Conditional, context::{Context, ContextTemplate, TagFilter}, diagnostic::Span, effect::EffectTemplate, equation::{Equation, EquationTemplate}, error::{DataError, TemplateError}, evaltree::EvalError, modifier::{Modifier, ModifierAmount, ModifierChange, ModifierTarget, StackingPolicy}, tag::{RemapTags, Tag, TagRegistry, TagTemplate}, template::{Template, TemplateInputType, TemplateInputValue, TemplateValue}, text::TextValue, trace::{BaseTrace, ModifierAmountTrace, Trace}};
+
+    #[test]
+    fn cycle_test_modifier_condition()
+    {
+        let ctx = &mut Context::new();
+        let atr = Tag::from_str("test atr").unwrap();
+        let cond = Tag::from_str("test cond").unwrap();
+        let modifier = Tag::from_str("test mod").unwrap();
+
+        ctx.set_attribute(&atr, 3.0).unwrap();
+        ctx.set_conditional(Conditional::new(cond.clone(), "test atr == 3.0").unwrap()).unwrap();
+        assert!(ctx.check_for_cyclic_evalutation().is_none());
+
+        let res = ctx.set_modifier(Modifier::new(modifier.clone(), ModifierTarget::Single(atr.clone()), cond.clone(), ModifierChange::BasicValue(1.0)));
+        assert_eq!(res, Err(DataError::CyclicEvaluation(vec![vec![atr.clone(), modifier.clone(), cond.clone()]])));
+
+        // The context is left as it was
+        assert!(!ctx.has_modifier(&modifier));
+        assert_eq!(ctx.get_value(&atr).unwrap(), Some(3.0));
+    }
+
+    #[test]
+    fn cycle_test_equations()
+    {
+        let ctx = &mut Context::new();
+        ctx.set_equation(Equation::new(Tag::from_str("a").unwrap(), "b + 1").unwrap()).unwrap();
+        ctx.set_equation(Equation::new(Tag::from_str("b").unwrap(), "c * 2").unwrap()).unwrap();
+        assert!(matches!(ctx.set_equation(Equation::new(Tag::from_str("c").unwrap(), "a - 1").unwrap()), Err(DataError::CyclicEvaluation(_))));
+        assert!(!ctx.has_equation(&Tag::from_str("c").unwrap()));
+
+        // Replacing an equation with a cyclic one keeps the old equation
+        ctx.set_equation(Equation::new(Tag::from_str("c").unwrap(), "2").unwrap()).unwrap();
+        assert!(ctx.set_equation(Equation::new(Tag::from_str("c").unwrap(), "a").unwrap()).is_err());
+        assert_eq!(ctx.get_value(&Tag::from_str("a").unwrap()).unwrap(), Some(5.0));
+    }
+
+    #[test]
+    fn cycle_test_matching_modifier()
+    {
+        let ctx = &mut Context::new();
+        ctx.set_attribute(&Tag::from_str("ability.latin").unwrap(), 3.0).unwrap();
+        ctx.set_attribute(&Tag::from_str("ability.magic theory").unwrap(), 5.0).unwrap();
+        ctx.set_conditional(Conditional::new(Tag::from_str("ability.latin.high").unwrap(), "ability.latin > 4").unwrap()).unwrap();
+        ctx.set_conditional(Conditional::new(Tag::from_str("ability.magic theory.high").unwrap(), "ability.latin > 4").unwrap()).unwrap();
+
+        let res = ctx.set_modifier(Modifier::new(Tag::from_str("bonus").unwrap(), ModifierTarget::MatchingStart(Tag::from_str("ability").unwrap()), Tag::from_str("high").unwrap(), ModifierChange::BasicValue(1.0)));
+        match res
+        {
+            Err(DataError::CyclicEvaluation(cycles)) => assert_eq!(cycles, vec![vec![Tag::from_str("ability.latin").unwrap(), Tag::from_str("bonus").unwrap(), Tag::from_str("ability.latin.high").unwrap()]]),
+            _ => panic!("Expected a cycle to be found"),
+        }
+    }
+
+    #[test]
+    fn cycle_test_from_other_value()
+    {
+        let ctx = &mut Context::new();
+        ctx.set_attribute(&Tag::from_str("base").unwrap(), 1.0).unwrap();
+        ctx.set_equation(Equation::new(Tag::from_str("total").unwrap(), "base * 2").unwrap()).unwrap();
+        ctx.set_conditional(Conditional::new(Tag::from_str("always").unwrap(), "true").unwrap()).unwrap();
+
+        // Adding total to total is a cycle, adding base to total is not
+        assert!(ctx.set_modifier(Modifier::new(Tag::from_str("double").unwrap(), ModifierTarget::Single(Tag::from_str("base").unwrap()), Tag::from_str("always").unwrap(), ModifierChange::FromOtherValue(Tag::from_str("total").unwrap()))).is_err());
+        assert!(ctx.set_modifier(Modifier::new(Tag::from_str("double").unwrap(), ModifierTarget::Single(Tag::from_str("total").unwrap()), Tag::from_str("always").unwrap(), ModifierChange::FromOtherValue(Tag::from_str("base").unwrap()))).is_ok());
+        assert_eq!(ctx.get_value(&Tag::from_str("total").unwrap()).unwrap(), Some(3.0));
+    }
+
+    #[test]
+    fn cycle_test_set_attribute()
+    {
+        let ctx = &mut Context::new();
+        ctx.set_conditional(Conditional::new(Tag::from_str("ability.latin.high").unwrap(), "ability.latin > 4").unwrap()).unwrap();
+        ctx.set_conditional(Conditional::new(Tag::from_str("ability.greek.high").unwrap(), "ability.greek > 4").unwrap()).unwrap();
+
+        // A single target modifier is a cycle before its target is set
+        assert!(ctx.set_modifier(Modifier::new(Tag::from_str("puissant").unwrap(), ModifierTarget::Single(Tag::from_str("ability.latin").unwrap()), Tag::from_str("ability.latin.high").unwrap(), ModifierChange::BasicValue(1.0))).is_err());
+
+        // A matching modifier only makes a cycle once a matching value is set
+        ctx.set_modifier(Modifier::new(Tag::from_str("bonus").unwrap(), ModifierTarget::MatchingStart(Tag::from_str("ability").unwrap()), Tag::from_str("high").unwrap(), ModifierChange::BasicValue(1.0))).unwrap();
+        match ctx.set_attribute(&Tag::from_str("ability.greek").unwrap(), 5.0)
+        {
+            Err(DataError::CyclicEvaluation(cycles)) => assert_eq!(cycles, vec![vec![Tag::from_str("ability.greek").unwrap(), Tag::from_str("bonus").unwrap(), Tag::from_str("ability.greek.high").unwrap()]]),
+            _ => panic!("Expected a cycle to be found"),
+        }
+        assert!(!ctx.has_attribute(&Tag::from_str("ability.greek").unwrap()));
+        assert!(ctx.set_attribute(&Tag::from_str("ability.arabic").unwrap(), 5.0).is_ok());
+    }
+
+    #[test]
+    fn cycle_test_layer_context()
+    {
+        let ctx = &mut Context::new();
+        ctx.set_equation(Equation::new(Tag::from_str("a").unwrap(), "b + 1").unwrap()).unwrap();
+        let before = ctx.clone();
+
+        let other = &mut Context::new();
+        other.set_equation(Equation::new(Tag::from_str("b").unwrap(), "a + 1").unwrap()).unwrap();
+
+        assert!(matches!(ctx.layer_context(other), Err(DataError::CyclicEvaluation(_))));
+        assert_eq!(*ctx, before);
+    }
+
+    #[test]
+    fn cache_test_invalidate_dependents()
+    {
+        let ctx = &mut Context::new();
+        ctx.enable_cache();
+        let a = Tag::from_str("a").unwrap();
+        let b = Tag::from_str("b").unwrap();
+        let c = Tag::from_str("c").unwrap();
+        ctx.set_attribute(&a, 1.0).unwrap();
+        ctx.set_attribute(&c, 1.0).unwrap();
+        ctx.set_equation(Equation::new(b.clone(), "a * 2").unwrap()).unwrap();
+
+        assert_eq!(ctx.get_value(&b).unwrap(), Some(2.0));
+        assert_eq!(ctx.cache.as_ref().unwrap().get_value(&b), Some(2.0));
+
+        // Unrelated values keep the cache
+        ctx.set_attribute(&c, 5.0).unwrap();
+        assert_eq!(ctx.cache.as_ref().unwrap().get_value(&b), Some(2.0));
+
+        ctx.set_attribute(&a, 3.0).unwrap();
+        assert_eq!(ctx.cache.as_ref().unwrap().get_value(&b), None);
+        assert_eq!(ctx.get_value(&b).unwrap(), Some(6.0));
+    }
+
+    #[test]
+    fn cache_test_modifiers_and_state()
+    {
+        let ctx = &mut Context::new();
+        ctx.enable_cache();
+        let atr = Tag::from_str("ability.latin").unwrap();
+        ctx.set_attribute(&atr, 3.0).unwrap();
+        ctx.set_conditional(Conditional::new(Tag::from_str("is magus").unwrap(), "character.magus").unwrap()).unwrap();
+        assert_eq!(ctx.get_value(&atr).unwrap(), Some(3.0));
+
+        ctx.set_modifier(Modifier::new(Tag::from_str("magus bonus").unwrap(), ModifierTarget::Single(atr.clone()), Tag::from_str("is magus").unwrap(), ModifierChange::BasicValue(2.0))).unwrap();
+        assert_eq!(ctx.get_value(&atr).unwrap(), Some(3.0));
+
+        ctx.add_explicit_tag(&Tag::from_str("character.magus").unwrap());
+        assert_eq!(ctx.get_value(&atr).unwrap(), Some(5.0));
+
+        ctx.remove_explicit_tag(&Tag::from_str("character.magus").unwrap());
+        assert_eq!(ctx.get_value(&atr).unwrap(), Some(3.0));
+
+        ctx.add_explicit_tag(&Tag::from_str("character.magus").unwrap());
+        ctx.remove_modifier(&Tag::from_str("magus bonus").unwrap()).unwrap();
+        assert_eq!(ctx.get_value(&atr).unwrap(), Some(3.0));
+    }
+
+    #[test]
+    fn cache_test_reindex()
+    {
+        let ctx = &mut Context::new();
+        ctx.enable_cache();
+        let latin = Tag::from_str("ability.latin").unwrap();
+        let total = Tag::from_str("total").unwrap();
+        ctx.set_attribute(&latin, 3.0).unwrap();
+        ctx.set_attribute(&Tag::from_str("bonus").unwrap(), 1.0).unwrap();
+        ctx.set_equation(Equation::new(total.clone(), "ability.latin * 2").unwrap()).unwrap();
+        ctx.set_conditional(Conditional::new(Tag::from_str("ability.latin.active").unwrap(), "true").unwrap()).unwrap();
+        assert_eq!(ctx.get_value(&total).unwrap(), Some(6.0));
+
+        // The modified value is indexed again when a modifier is set, so changing what the modifier reads invalidates the total
+        ctx.set_modifier(Modifier::new(Tag::from_str("puissant").unwrap(), ModifierTarget::MatchingStart(Tag::from_str("ability").unwrap()), Tag::from_str("active").unwrap(), ModifierChange::FromOtherValue(Tag::from_str("bonus").unwrap()))).unwrap();
+        assert_eq!(ctx.get_value(&total).unwrap(), Some(8.0));
+        ctx.set_attribute(&Tag::from_str("bonus").unwrap(), 2.0).unwrap();
+        assert_eq!(ctx.get_value(&total).unwrap(), Some(10.0));
+
+        // Replacing the equation drops what it used to read
+        ctx.set_equation(Equation::new(total.clone(), "bonus").unwrap()).unwrap();
+        assert_eq!(ctx.get_value(&total).unwrap(), Some(2.0));
+        ctx.set_attribute(&latin, 10.0).unwrap();
+        assert_eq!(ctx.get_value(&total).unwrap(), Some(2.0));
+        ctx.set_attribute(&Tag::from_str("bonus").unwrap(), 3.0).unwrap();
+        assert_eq!(ctx.get_value(&total).unwrap(), Some(3.0));
+
+        // Values which are cached match the values of a context without a cache
+        let mut uncached = ctx.clone();
+        uncached.disable_cache();
+        ctx.remove_modifier(&Tag::from_str("puissant").unwrap()).unwrap();
+        uncached.remove_modifier(&Tag::from_str("puissant").unwrap()).unwrap();
+        assert_eq!(ctx.get_value(&latin).unwrap(), uncached.get_value(&latin).unwrap());
+        assert_eq!(ctx.get_value(&total).unwrap(), uncached.get_value(&total).unwrap());
+    }
+
+    #[test]
+    fn cache_test_aggregates()
+    {
+        let ctx = &mut Context::new();
+        ctx.enable_cache();
+        let total = Tag::from_str("total exp").unwrap();
+        ctx.set_attribute(&Tag::from_str("exp.latin").unwrap(), 5.0).unwrap();
+        ctx.set_equation(Equation::new(total.clone(), "sum(exp)").unwrap()).unwrap();
+        assert_eq!(ctx.get_value(&total).unwrap(), Some(5.0));
+
+        // New tags under the prefix change the aggregate
+        ctx.set_attribute(&Tag::from_str("exp.magic theory").unwrap(), 10.0).unwrap();
+        assert_eq!(ctx.get_value(&total).unwrap(), Some(15.0));
+
+        ctx.remove_attribute(&Tag::from_str("exp.latin").unwrap()).unwrap();
+        assert_eq!(ctx.get_value(&total).unwrap(), Some(10.0));
+    }
+
+    #[test]
+    fn trace_test_modifiers()
+    {
+        let ctx = &mut Context::new();
+        let exp = Tag::from_str("ability.magic theory.exp").unwrap();
+        let score = Tag::from_str("ability.magic theory").unwrap();
+        ctx.set_attribute(&exp, 75.0).unwrap();
+        ctx.set_equation(Equation::new(score.clone(), "rounddown((sqrt(8 * ability.magic theory.exp / 5 + 1)-1)/2)").unwrap()).unwrap();
+        ctx.set_conditional(Conditional::new(Tag::from_str("puissant active").unwrap(), "virtue.puissant").unwrap()).unwrap();
+        ctx.set_conditional(Conditional::new(Tag::from_str("affinity active").unwrap(), "virtue.affinity").unwrap()).unwrap();
+        ctx.set_modifier(Modifier::new(Tag::from_str("puissant magic theory").unwrap(), ModifierTarget::Single(score.clone()), Tag::from_str("puissant active").unwrap(), ModifierChange::BasicValue(2.0))).unwrap();
+        ctx.set_modifier(Modifier::new(Tag::from_str("affinity magic theory").unwrap(), ModifierTarget::Single(score.clone()), Tag::from_str("affinity active").unwrap(), ModifierChange::FromOtherValue(exp.clone()))).unwrap();
+        ctx.add_explicit_tag(&Tag::from_str("virtue.puissant").unwrap());
+
+        let trace = ctx.get_value_trace(&score).unwrap().unwrap();
+        assert_eq!(trace.result, 7.0);
+        assert_eq!(trace.result, ctx.get_value(&score).unwrap().unwrap());
+
+        match &trace.base
+        {
+            BaseTrace::Equation { references, result, .. } =>
+            {
+                assert_eq!(*result, 5.0);
+                assert_eq!(references.len(), 1);
+                assert!(matches!(&references[0], Trace::Value(v) if v.name == exp && v.base == BaseTrace::Attribute(75.0)));
+            },
+            BaseTrace::Attribute(_) => panic!("Expected equation base"),
+        }
+
+        assert_eq!(trace.modifiers.len(), 2);
+        let puissant = trace.modifiers.iter().find(|m| m.name == Tag::from_str("puissant magic theory").unwrap()).unwrap();
+        assert!(puissant.is_applied());
+        assert_eq!(puissant.condition.references, vec![Trace::Tag(Tag::from_str("virtue.puissant").unwrap(), true)]);
+
+        let affinity = trace.modifiers.iter().find(|m| m.name == Tag::from_str("affinity magic theory").unwrap()).unwrap();
+        assert!(!affinity.is_applied());
+        assert_eq!(affinity.amount, ModifierAmountTrace::FromOtherValue(exp.clone(), None));
+
+        assert!(ctx.get_value_trace(&Tag::from_str("missing").unwrap()).unwrap().is_none());
+    }
+
+    #[test]
+    fn text_test()
+    {
+        let ctx = &mut Context::new();
+        ctx.enable_cache();
+        let name = Tag::from_str("character.name").unwrap();
+        let form = Tag::from_str("spell.form").unwrap();
+        let is_fire = Tag::from_str("spell.is fire").unwrap();
+
+        ctx.set_text(&name, TextValue::Text("Bonisagus".to_string())).unwrap();
+        ctx.set_text(&form, TextValue::new_enum(Tag::from_str("form").unwrap(), Tag::from_str("form.ignem").unwrap()).unwrap()).unwrap();
+        ctx.set_conditional(Conditional::new(is_fire.clone(), "spell.form == form.ignem").unwrap()).unwrap();
+        assert!(ctx.eval_conditional(&is_fire).unwrap());
+
+        // Changing the text invalidates what depends on it
+        ctx.set_text(&form, TextValue::new_enum(Tag::from_str("form").unwrap(), Tag::from_str("form.creo").unwrap()).unwrap()).unwrap();
+        assert!(!ctx.eval_conditional(&is_fire).unwrap());
+
+        assert!(matches!(ctx.set_text(&form, TextValue::Enum { options: Tag::from_str("form").unwrap(), selected: Tag::from_str("technique.rego").unwrap() }), Err(DataError::EnumSelectionInvalid(_, _))));
+        assert!(matches!(ctx.set_attribute(&name, 1.0), Err(DataError::ConflictingExpectedType(_))));
+        assert!(matches!(ctx.set_text(&is_fire, TextValue::Text("yes".to_string())), Err(DataError::ConflictingExpectedType(_))));
+
+        assert_eq!(ctx.remove_text(&name).unwrap(), Some(TextValue::Text("Bonisagus".to_string())));
+        assert!(ctx.get_text(&name).is_none());
+    }
+
+    #[test]
+    fn query_test()
+    {
+        let mut registry = TagRegistry::new();
+        let ctx = &mut Context::new();
+        for (t, v) in [("ability.latin.exp", 50.0), ("ability.magic theory.exp", 15.0), ("ability.latin", 10.0), ("ability.magic theory", 3.0), ("spell.pilum of fire.magnitude", 3.0), ("spell.magnitude", 1.0)]
+        {
+            ctx.set_attribute(&registry.get_or_register_tag(t).unwrap(), v).unwrap();
+        }
+        ctx.add_explicit_tag(&registry.get_or_register_tag("character.magus").unwrap());
+
+        let query = |registry: &mut TagRegistry, s: &str| ctx.query_tags(&TagFilter::from_str(s, registry).unwrap()).unwrap();
+        let tags = |registry: &TagRegistry, v: Vec<&str>| { let mut v: Vec<Tag> = v.into_iter().map(|s| registry.get_tag(s).unwrap().unwrap()).collect(); v.sort(); v };
+
+        assert_eq!(query(&mut registry, "ability.*.exp"), tags(&registry, vec!["ability.latin.exp", "ability.magic theory.exp"]));
+        assert_eq!(query(&mut registry, "**.magnitude"), tags(&registry, vec!["spell.magnitude", "spell.pilum of fire.magnitude"]));
+        assert_eq!(query(&mut registry, "spell.**"), tags(&registry, vec!["spell", "spell.magnitude", "spell.pilum of fire", "spell.pilum of fire.magnitude"]));
+        assert_eq!(query(&mut registry, "ability.*, !ability.latin"), tags(&registry, vec!["ability.magic theory"]));
+        assert_eq!(query(&mut registry, "!ability.**, !spell.**"), tags(&registry, vec!["character", "character.magus"]));
+        assert_eq!(query(&mut registry, "Ability . * where value >= 5"), tags(&registry, vec!["ability.latin"]));
+        // Subtags which are not in the context match nothing
+        assert!(query(&mut registry, "ability.greek").is_empty());
+
+        let values = ctx.query_values(&TagFilter::from_str("** where value > 1 and value <= 15", &mut registry).unwrap()).unwrap();
+        let mut expected: Vec<(Tag, f32)> = [("ability.latin", 10.0), ("ability.magic theory.exp", 15.0), ("ability.magic theory", 3.0), ("spell.pilum of fire.magnitude", 3.0)]
+            .into_iter()
+            .map(|(t, v)| (registry.get_tag(t).unwrap().unwrap(), v))
+            .collect();
+        expected.sort_by(|(t1, _), (t2, _)| t1.cmp(t2));
+        assert_eq!(values, expected);
+        assert_eq!(ctx.query_values(&TagFilter::from_str("where value == 1", &mut registry).unwrap()).unwrap(), vec![(registry.get_tag("spell.magnitude").unwrap().unwrap(), 1.0)]);
+
+        assert_eq!(TagFilter::from_str("ability..exp", &mut registry).unwrap_err().span, Span::new(8, 8));
+        assert_eq!(TagFilter::from_str("ability.ex*p", &mut registry).unwrap_err().span, Span::new(8, 12));
+        assert_eq!(TagFilter::from_str("ability.* where value => 5", &mut registry).unwrap_err().span, Span::new(16, 26));
+    }
+
+    /// Tests a context written through one registry can be loaded into another, which gives its tags different ids
+    #[test]
+    fn serde_registry_test()
+    {
+        let mut registry = TagRegistry::new();
+        let latin = registry.get_or_register_tag("ability.latin").unwrap();
+        let form = registry.get_or_register_tag("spell.form").unwrap();
+        let ignem = registry.get_or_register_tag("form.ignem").unwrap();
+        let magus = registry.get_or_register_tag("character.magus").unwrap();
+
+        let mut ctx = Context::new();
+        ctx.set_attribute(&latin, 4.0).unwrap();
+        ctx.set_text(&form, TextValue::new_enum(registry.get_tag("form").unwrap().unwrap(), ignem).unwrap()).unwrap();
+        ctx.add_explicit_tag(&magus);
+        let json = registry.to_json_string(&ctx).unwrap();
+        assert!(json.contains("\"ability.latin\""));
+
+        let mut other = TagRegistry::new_with_reserved(&["character", "magus", "form"]);
+        let loaded: Context = other.from_json_str(&json).unwrap();
+        let other_latin = other.get_tag("ability.latin").unwrap().unwrap();
+        assert_ne!(other_latin, latin);
+        assert_eq!(loaded.get_value(&other_latin).unwrap(), Some(4.0));
+        assert!(loaded.has_tag(&other.get_tag("character.magus").unwrap().unwrap()));
+        assert_eq!(loaded.get_text(&other.get_tag("spell.form").unwrap().unwrap()), Some(&TextValue::Enum { options: other.get_tag("form").unwrap().unwrap(), selected: other.get_tag("form.ignem").unwrap().unwrap() }));
+
+        let reloaded_json = other.to_json_string(&loaded).unwrap();
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&reloaded_json).unwrap(), serde_json::from_str::<serde_json::Value>(&json).unwrap());
+    }
+
+    /// Tests aliases are written with the context, so old names still resolve once it is loaded
+    #[test]
+    fn serde_alias_test()
+    {
+        let mut registry = TagRegistry::new();
+        let old = registry.get_or_register_tag("ability.magic theory").unwrap();
+        let score = registry.get_or_register_tag("ability.magic theory.score").unwrap();
+
+        let mut ctx = Context::new();
+        ctx.set_attribute(&score, 5.0).unwrap();
+        ctx.add_alias(&old, &score);
+        let json = registry.to_json_string(&ctx).unwrap();
+        assert!(json.contains("\"ability.magic theory\":\"ability.magic theory.score\""));
+
+        let mut other = TagRegistry::new_with_reserved(&["score"]);
+        let loaded: Context = other.from_json_str(&json).unwrap();
+        assert_eq!(loaded.get_value(&other.get_tag("ability.magic theory").unwrap().unwrap()).unwrap(), Some(5.0));
+    }
+
+    /// Tests free text and enum values are written with the context and read back unchanged
+    #[test]
+    fn serde_text_test()
+    {
+        let mut registry = TagRegistry::new();
+        let name = registry.get_or_register_tag("character.name").unwrap();
+        let form = registry.get_or_register_tag("spell.form").unwrap();
+        let options = registry.get_or_register_tag("form").unwrap();
+        let ignem = registry.get_or_register_tag("form.ignem").unwrap();
+
+        let mut ctx = Context::new();
+        ctx.set_text(&name, TextValue::Text("Bonisagus of House Bonisagus".to_string())).unwrap();
+        ctx.set_text(&form, TextValue::new_enum(options, ignem).unwrap()).unwrap();
+        let json = registry.to_json_string(&ctx).unwrap();
+        assert!(json.contains("\"Bonisagus of House Bonisagus\""));
+        assert!(json.contains("\"form.ignem\""));
+
+        let loaded: Context = registry.from_json_str(&json).unwrap();
+        assert_eq!(loaded.get_text(&name), ctx.get_text(&name));
+        assert_eq!(loaded.get_text(&form), ctx.get_text(&form));
+        assert_eq!(loaded.get_text(&form).unwrap().to_string(&registry), Some("form.ignem".to_string()));
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&registry.to_json_string(&loaded).unwrap()).unwrap(), serde_json::from_str::<serde_json::Value>(&json).unwrap());
+    }
+
+    /// Tests a context made in one registry evaluates the same once its registry is merged into another
+    #[test]
+    fn remap_test()
+    {
+        let mut source = TagRegistry::new();
+        let names = ["ability.latin.exp", "ability.latin", "bonus.puissant", "bonus.enhancement", "bonus.enhancement.ring", "bonus.enhancement.staff",
+            "puissant", "enhancement", "scale", "always", "character.magus", "spell.form", "form", "form.ignem"];
+        let tags: Vec<Tag> = names.iter().map(|s| source.get_or_register_tag(s).unwrap()).collect();
+        let t = |s: &str| tags[names.iter().position(|n| *n == s).unwrap()].clone();
+
+        // Equations parse their tags from strings, so only their names are made in the source registry
+        let ctx = &mut Context::new();
+        ctx.set_attribute(&t("ability.latin.exp"), 15.0).unwrap();
+        ctx.set_attribute(&t("bonus.puissant"), 3.0).unwrap();
+        ctx.set_attribute(&t("scale"), 2.0).unwrap();
+        ctx.set_equation(Equation::new(t("ability.latin"), "2 + 3").unwrap()).unwrap();
+        ctx.set_conditional(Conditional::new(t("always"), "1 < 2").unwrap()).unwrap();
+        ctx.set_modifier(Modifier::new(t("puissant"), ModifierTarget::Single(t("ability.latin")), t("always"), ModifierChange::FromOtherValue(t("bonus.puissant")))).unwrap();
+        ctx.set_modifier(Modifier::new(t("bonus.enhancement.ring"), ModifierTarget::Single(t("ability.latin")), t("always"), ModifierChange::BasicValue(1.0)).with_group(t("bonus.enhancement"))).unwrap();
+        ctx.set_modifier(Modifier::new(t("bonus.enhancement.staff"), ModifierTarget::Single(t("ability.latin")), t("always"), ModifierChange::BasicValue(4.0)).with_group(t("bonus.enhancement"))).unwrap();
+        ctx.set_modifier(Modifier::new(t("enhancement"), ModifierTarget::Single(t("ability.latin.exp")), t("always"), ModifierChange::Multiply(ModifierAmount::FromOtherValue(t("scale"))))).unwrap();
+        ctx.set_stacking_policy(&t("bonus.enhancement"), StackingPolicy::Max);
+        ctx.set_text(&t("spell.form"), TextValue::new_enum(t("form"), t("form.ignem")).unwrap()).unwrap();
+        ctx.add_explicit_tag(&t("character.magus"));
+        ctx.enable_cache();
+
+        let values: Vec<(&str, Option<f32>)> = ["ability.latin.exp", "ability.latin", "bonus.puissant"].into_iter().map(|s| (s, ctx.get_value(&t(s)).unwrap())).collect();
+        assert_eq!(values, vec![("ability.latin.exp", Some(30.0)), ("ability.latin", Some(12.0)), ("bonus.puissant", Some(3.0))]);
+
+        let mut target = TagRegistry::new_with_reserved(&["spell", "character", "bonus", "unused"]);
+        let remap = target.merge(&source);
+        assert!(!remap.is_identity());
+        let mut remapped = ctx.clone();
+        remapped.remap_tags(&remap);
+        let r = |s: &str| target.get_tag(s).unwrap().unwrap();
+        for (s, v) in values
+        {
+            assert_eq!(remapped.get_value(&r(s)).unwrap(), v);
+        }
+        assert!(remapped.is_cache_enabled());
+        assert!(remapped.eval_conditional(&r("always")).unwrap());
+        assert!(remapped.has_tag(&r("character.magus")));
+        assert!(remapped.has_tag(&r("bonus.enhancement")));
+        assert_eq!(remapped.get_text(&r("spell.form")), Some(&TextValue::Enum { options: r("form"), selected: r("form.ignem") }));
+    }
+
+    /// Builds a context where magic theory is an equation, with its tags registered in the given registry
+    fn magic_theory_context(registry: &mut TagRegistry) -> Context
+    {
+        let mut t = |s: &str| registry.get_or_register_tag(s).unwrap();
+        let (magic_theory, exp, bonus, intelligence, lab_total) = (t("ability.magic theory"), t("ability.magic theory.exp"), t("ability.magic theory.bonus"), t("ability.intelligence"), t("lab total"));
+        let (is_theorist, affinity, magus, puissant) = (t("is theorist"), t("affinity"), t("character.magus"), t("ability.magic theory.puissant"));
+        let mut ctx = Context::new();
+        ctx.set_attribute(&exp, 30.0).unwrap();
+        ctx.set_attribute(&bonus, 2.0).unwrap();
+        ctx.set_equation(Equation::new_with_registry(magic_theory.clone(), "rounddown((sqrt(8 * ability.magic theory.exp / 5 + 1) - 1) / 2)", registry).unwrap()).unwrap();
+        ctx.set_equation(Equation::new_with_registry(lab_total, "ability.magic theory + ability.intelligence", registry).unwrap()).unwrap();
+        ctx.set_attribute(&intelligence, 3.0).unwrap();
+        ctx.set_conditional(Conditional::new_with_registry(is_theorist.clone(), "ability.intelligence >= 3 && character.magus", registry).unwrap()).unwrap();
+        ctx.set_modifier(Modifier::new(affinity, ModifierTarget::Single(magic_theory), is_theorist, ModifierChange::FromOtherValue(bonus))).unwrap();
+        ctx.add_explicit_tag(&magus);
+        ctx.add_explicit_tag(&puissant);
+        ctx
+    }
+
+    /// Tests renaming a tag prefix rewrites every value, reference and state tag under it
+    #[test]
+    fn rename_test()
+    {
+        let mut registry = TagRegistry::new();
+        let ctx = &mut magic_theory_context(&mut registry);
+        registry.get_or_register_tag("ability.magic theory.score").unwrap();
+        let t = |s: &str| registry.get_tag(s).unwrap().unwrap();
+        ctx.enable_cache();
+        assert_eq!(ctx.get_value(&t("lab total")).unwrap(), Some(8.0));
+
+        ctx.rename_tag(&t("ability.magic theory"), &t("ability.magic theory.score"), &registry).unwrap();
+        assert!(!ctx.has_value(&t("ability.magic theory")));
+        assert!(!ctx.has_tag(&t("ability.magic theory.puissant")));
+        assert!(ctx.has_tag(&t("ability.magic theory.score.puissant")));
+        assert_eq!(ctx.get_value(&t("ability.magic theory.score.exp")).unwrap(), Some(30.0));
+        assert_eq!(ctx.get_value(&t("ability.magic theory.score")).unwrap(), Some(5.0));
+        assert_eq!(ctx.get_value(&t("lab total")).unwrap(), Some(8.0));
+        assert!(ctx.eval_conditional(&t("is theorist")).unwrap());
+        assert_eq!(ctx.equations.get(&t("lab total")).unwrap().get_equation_string(), "ability.magic theory.score + ability.intelligence");
+        assert_eq!(ctx.modifiers.get_modifier(&t("affinity")).unwrap().target, ModifierTarget::Single(t("ability.magic theory.score")));
+
+        // The cache follows the renamed values
+        ctx.set_attribute(&t("ability.magic theory.score.exp"), 75.0).unwrap();
+        assert_eq!(ctx.get_value(&t("lab total")).unwrap(), Some(10.0));
+
+        // Renamed values can not replace values which are not renamed
+        let e = ctx.rename_tag(&t("ability.intelligence"), &t("lab total"), &registry);
+        assert_eq!(e, Err(DataError::RenameConflict(t("lab total"))));
+        assert_eq!(ctx.get_value(&t("ability.intelligence")).unwrap(), Some(3.0));
+
+        // Nor can renamed references form a cycle, such as a conditional reading itself
+        let e = ctx.rename_tag(&t("character.magus"), &t("is theorist"), &registry);
+        assert!(matches!(e, Err(DataError::CyclicEvaluation(_))));
+        assert!(ctx.has_tag(&t("character.magus")));
+        assert!(ctx.eval_conditional(&t("is theorist")).unwrap());
+    }
+
+    /// Tests old names resolve through an alias for reads, writes and equations added after the rename
+    #[test]
+    fn alias_test()
+    {
+        let mut registry = TagRegistry::new();
+        let ctx = &mut magic_theory_context(&mut registry);
+        for name in ["ability.magic theory.score", "exp"]
+        {
+            registry.get_or_register_tag(name).unwrap();
+        }
+        let teaching = registry.get_or_register_tag("teaching").unwrap();
+        let teaching = Equation::new_with_registry(teaching, "ability.magic theory * 2", &mut registry).unwrap();
+        let t = |s: &str| registry.get_tag(s).unwrap().unwrap();
+        ctx.enable_cache();
+        ctx.rename_tag(&t("ability.magic theory"), &t("ability.magic theory.score"), &registry).unwrap();
+        assert_eq!(ctx.get_value(&t("ability.magic theory.exp")).unwrap(), None);
+        ctx.add_alias(&t("ability.magic theory"), &t("ability.magic theory.score"));
+
+        assert_eq!(ctx.get_value(&t("ability.magic theory")).unwrap(), Some(5.0));
+        assert_eq!(ctx.get_value(&t("ability.magic theory.score")).unwrap(), Some(5.0));
+        assert!(ctx.has_tag(&t("ability.magic theory.puissant")));
+        ctx.set_attribute(&t("ability.magic theory.exp"), 75.0).unwrap();
+        assert_eq!(ctx.get_value(&t("ability.magic theory.score.exp")).unwrap(), Some(75.0));
+
+        // Equations written with the old name read the new name, so the cache knows what they read.
+        // Their strings are kept as written, as the old name still resolves the same through the alias.
+        ctx.set_equation(teaching).unwrap();
+        assert_eq!(ctx.equations.get(&t("teaching")).unwrap().get_equation_string(), "ability.magic theory * 2");
+        assert_eq!(ctx.get_value(&t("teaching")).unwrap(), Some(14.0));
+        ctx.set_attribute(&t("ability.magic theory.score.exp"), 30.0).unwrap();
+        assert_eq!(ctx.get_value(&t("teaching")).unwrap(), Some(10.0));
+
+        // Aliased names can not be renamed, as they no longer hold values
+        assert_eq!(ctx.rename_tag(&t("ability.magic theory.exp"), &t("exp"), &registry), Err(DataError::RenameConflict(t("ability.magic theory.exp"))));
+        assert_eq!(ctx.remove_alias(&t("ability.magic theory")), Some(t("ability.magic theory.score")));
+        assert_eq!(ctx.get_value(&t("ability.magic theory")).unwrap(), None);
+    }
+
+    #[test]
+    fn diagnose_test()
+    {
+        let mut registry = TagRegistry::new();
+        let mut tag = |s: &str| registry.get_or_register_tag(s).unwrap();
+        let magic_theory = tag("ability.magic theory");
+        let latin = tag("ability.latin");
+        let misspelled = tag("ability.magic theroy");
+        let form = tag("spell.form");
+
+        let ctx = &mut Context::new();
+        ctx.set_attribute(&magic_theory, 3.0).unwrap();
+        ctx.set_attribute(&latin, 4.0).unwrap();
+
+        let e = DataError::Evaluation(EvalError::ValueNotFound(misspelled.clone()));
+        let d = ctx.diagnose("ability.magic theroy / 5", &e, &registry);
+        assert_eq!(d.message, "no value found for tag `ability.magic theroy`");
+        assert_eq!(d.suggestions.len(), 1);
+        assert_eq!(d.suggestions[0].replacement, "ability.magic theory");
+
+        // Nothing close enough to suggest
+        let e = DataError::Evaluation(EvalError::ValueNotFound(form));
+        assert!(ctx.diagnose("spell.form + 1", &e, &registry).suggestions.is_empty());
+
+        // Tags of another registry are not named as suggestions
+        let e = DataError::Evaluation(EvalError::ValueNotFound(misspelled));
+        assert!(ctx.diagnose("ability.magic theroy / 5", &e, &TagRegistry::new()).suggestions.is_empty());
+    }
+
+    #[test]
+    fn template_input_test()
+    {
+        let t = |s: &str| Tag::from_str(s).unwrap();
+        let mut template = ContextTemplate::new(Context::new())
+            .with_template(TemplateValue::Equation(EquationTemplate::new("[ability].score", "[ability].exp / 5 + event.bonus").unwrap().into_template().unwrap()))
+            .with_template(TemplateValue::Effect(EffectTemplate::AddToAttribute(TagTemplate::from_str("[ability].exp").unwrap(), 5.0)));
+        template.declare_input("ability", TemplateInputType::TagWithPrefix(t("ability"))).unwrap();
+        template.declare_input("event.bonus", TemplateInputType::Number { min: Some(0.0), max: Some(3.0) }).unwrap();
+        assert!(template.declare_input("event.[bonus]", TemplateInputType::Number { min: None, max: None }).is_err());
+        assert_eq!(template.get_inputs(), vec![
+            ("ability".to_string(), TemplateInputType::TagWithPrefix(t("ability"))),
+            ("event.bonus".to_string(), TemplateInputType::Number { min: Some(0.0), max: Some(3.0) }),
+        ]);
+
+        // Invalid values are rejected without changing the template
+        let original = template.clone();
+        assert_eq!(template.fill_input("spell", &TemplateInputValue::Tag(t("spell.pilum of fire"))), Err(DataError::Template(TemplateError::UnknownInput("spell".to_string()))));
+        assert_eq!(template.fill_input("ability", &TemplateInputValue::Number(1.0)), Err(DataError::Template(TemplateError::InputTypeMismatch("ability".to_string()))));
+        assert!(template.fill_input("ability", &TemplateInputValue::Tag(t("art.creo"))).is_err());
+        assert!(template.fill_input("event.bonus", &TemplateInputValue::Number(5.0)).is_err());
+        assert_eq!(template, original);
+
+        assert_eq!(template.fill_input("ability", &TemplateInputValue::Tag(t("ability.latin"))), Ok(None));
+        assert!(template.attempt_complete().is_err());
+        let ctx = template.fill_input("event.bonus", &TemplateInputValue::Number(2.0)).unwrap().unwrap();
+        assert!(template.is_complete());
+        assert_eq!(ctx.get_value(&t("ability.latin.exp")).unwrap(), Some(5.0));
+        assert_eq!(ctx.get_value(&t("ability.latin.score")).unwrap(), Some(3.0));
+
+        // Errors setting completed values are returned, rather than ignored
+        let mut partial = Context::new();
+        partial.set_equation(Equation::new(t("ability.latin"), "3").unwrap()).unwrap();
+        let mut template = ContextTemplate::new(partial)
+            .with_template(TemplateValue::Attribute(AttributeTemplate::new(TagTemplate::from_str("[ability]").unwrap(), 1.0)));
+        let original = template.clone();
+        assert!(template.fill_input("ability", &TemplateInputValue::Tag(t("ability.latin"))).is_err());
+        assert_eq!(template, original);
+        // Filling through the template trait gives no error, but leaves the template unchanged the same way
+        assert_eq!(template.fill_template_value("ability", &t("ability.latin")), None);
+        assert_eq!(template, original);
+    }
+}
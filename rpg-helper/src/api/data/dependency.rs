@@ -0,0 +1,114 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::api::data::tag::Tag;
+
+/// A directed graph of evaluation dependencies between tags.
+/// An edge from A to B means evaluating A requires evaluating B.
+///
+/// Edges may be created by a modifier (a value depends on the modifier's condition
+/// and change), in which case the modifier's name is stored with the edge
+/// so it can be reported as part of any cycle found.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct DependencyGraph
+{
+    edges: HashMap<Tag, Vec<(Tag, Option<Tag>)>>,
+    /// Tags which depend upon every tag under some prefix (such as `sum(ability)`).
+    /// These are not used to find cycles, as the tags matching the prefix are
+    /// already in `edges`, but are needed to know what a newly added tag affects.
+    prefix_edges: HashMap<Tag, HashSet<Tag>>,
+}
+
+impl DependencyGraph
+{
+    pub fn new() -> DependencyGraph
+    {
+        DependencyGraph { edges: HashMap::new(), prefix_edges: HashMap::new() }
+    }
+
+    /// Adds the dependency `from` -> `to`. If the dependency comes from
+    /// a modifier, `via` is the name of that modifier.
+    pub fn add_dependency(&mut self, from: &Tag, to: &Tag, via: Option<&Tag>)
+    {
+        self.edges.entry(from.clone()).or_default().push((to.clone(), via.cloned()));
+    }
+
+    /// Adds a dependency from `from` to every tag (existing or not) under `prefix`
+    pub fn add_prefix_dependency(&mut self, from: &Tag, prefix: &Tag)
+    {
+        self.prefix_edges.entry(prefix.clone()).or_default().insert(from.clone());
+    }
+
+    /// Iterates over every dependency as (from, to, via) 
+    pub fn iter(&self) -> impl Iterator<Item = (&Tag, &Tag, Option<&Tag>)> + '_
+    {
+        self.edges.iter().flat_map(|(from, v)| v.iter().map(move |(to, via)| (from, to, via.as_ref())))
+    }
+
+    /// Iterates over every prefix dependency as (prefix, tags depending on the prefix)
+    pub fn iter_prefix_dependencies(&self) -> impl Iterator<Item = (&Tag, &HashSet<Tag>)> + '_
+    {
+        self.prefix_edges.iter()
+    }
+
+    /// All the tags the given tag directly depends upon
+    pub fn get_dependencies(&self, t: &Tag) -> impl Iterator<Item = &Tag> + '_
+    {
+        self.edges.get(t).into_iter().flat_map(|v| v.iter().map(|(t, _)| t))
+    }
+
+    /// Finds the cycles of evaluation in this graph.
+    ///
+    /// Every edge which closes a loop in a depth first search is reported as
+    /// a cycle, so every tag that is part of some cycle appears in the result.
+    /// Each cycle is given in order of evaluation, starting from the first tag
+    /// of the cycle found, and includes the names of any modifiers along the way.
+    ///
+    /// Ex: `[atr, modifier, condition]` for an attribute `atr` with a modifier
+    /// `modifier` whose condition `condition` reads the value of `atr`.
+    pub fn find_cycles(&self) -> Vec<Vec<Tag>>
+    {
+        let mut cycles = vec![];
+        let mut finished = HashSet::new();
+
+        // Sorting the starting tags keeps the reported cycles stable between runs
+        let mut starts: Vec<&Tag> = self.edges.keys().collect();
+        starts.sort();
+
+        for start in starts
+        {
+            if !finished.contains(start)
+            {
+                let mut path = vec![start.clone()];
+                let mut on_path = HashMap::from([(start.clone(), 0)]);
+                self.recursive_find_cycles(start, &mut path, &mut on_path, &mut finished, &mut cycles);
+            }
+        }
+        cycles
+    }
+
+    fn recursive_find_cycles(&self, t: &Tag, path: &mut Vec<Tag>, on_path: &mut HashMap<Tag, usize>, finished: &mut HashSet<Tag>, cycles: &mut Vec<Vec<Tag>>)
+    {
+        for (next, via) in self.edges.get(t).into_iter().flatten()
+        {
+            if let Some(i) = on_path.get(next)
+            {
+                let mut cycle = path[*i..].to_vec();
+                cycle.extend(via.iter().cloned());
+                cycles.push(cycle);
+            }
+            else if !finished.contains(next)
+            {
+                let len = path.len();
+                path.extend(via.iter().cloned());
+                path.push(next.clone());
+                on_path.insert(next.clone(), path.len() - 1);
+
+                self.recursive_find_cycles(next, path, on_path, finished, cycles);
+
+                on_path.remove(next);
+                path.truncate(len);
+            }
+        }
+        finished.insert(t.clone());
+    }
+}
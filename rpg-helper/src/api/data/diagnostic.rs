@@ -0,0 +1,186 @@
+use serde::{Deserialize, Serialize};
+
+/// A range of bytes within the source string of a diagnostic.
+/// The end is exclusive, so an empty span points between two characters.
+#[derive(Debug, Deserialize, PartialEq, Eq, Serialize, Clone, Copy)]
+pub struct Span
+{
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span
+{
+    pub fn new(start: usize, end: usize) -> Span
+    {
+        Span { start, end }
+    }
+
+    /// A span of the single character starting at the given byte
+    pub fn at(s: &str, start: usize) -> Span
+    {
+        let len = s.get(start..).and_then(|rest| rest.chars().next()).map(|c| c.len_utf8()).unwrap_or(0);
+        Span { start, end: start + len }
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.start >= self.end
+    }
+}
+
+/// A replacement of the text in a span which may fix the problem of a diagnostic.
+/// Ex: replacing "ability.magic theroy" with "ability.magic theory"
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+pub struct Suggestion
+{
+    pub span: Span,
+    pub replacement: String,
+}
+
+/// A problem found in some source string, such as an equation, that can be shown to a user.
+///
+/// The diagnostic is kept as structured data so the client can highlight the span in an input,
+/// and can also be rendered as text for the CLI. Ex:
+/// ```text
+/// error: unknown tag `ability.magic theroy`
+///  | rounddown(ability.magic theroy / 5)
+///  |           ^^^^^^^^^^^^^^^^^^^^
+///  = help: did you mean `ability.magic theory`?
+/// ```
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+pub struct Diagnostic
+{
+    pub message: String,
+    pub source: String,
+    pub span: Option<Span>,
+    pub notes: Vec<String>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl Diagnostic
+{
+    pub fn new(message: String, source: &str) -> Diagnostic
+    {
+        Diagnostic { message, source: source.to_string(), span: None, notes: vec![], suggestions: vec![] }
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self
+    {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_note(mut self, note: String) -> Self
+    {
+        self.notes.push(note);
+        self
+    }
+
+    pub fn with_suggestion(mut self, span: Span, replacement: String) -> Self
+    {
+        self.suggestions.push(Suggestion { span, replacement });
+        self
+    }
+
+    /// Renders the diagnostic as text, with the span of the source underlined by carets
+    pub fn render(&self) -> String
+    {
+        let mut result = format!("error: {}\n", self.message);
+        if !self.source.is_empty()
+        {
+            result.push_str(&format!(" | {}\n", self.source));
+            if let Some(span) = self.span
+            {
+                let start = span.start.min(self.source.len());
+                let end = span.end.clamp(start, self.source.len());
+                let offset = self.source.get(..start).map(|s| s.chars().count()).unwrap_or(start);
+                let width = self.source.get(start..end).map(|s| s.chars().count()).unwrap_or(end - start).max(1);
+                result.push_str(&format!(" | {}{}\n", " ".repeat(offset), "^".repeat(width)));
+            }
+        }
+        for note in self.notes.iter()
+        {
+            result.push_str(&format!(" = note: {}\n", note));
+        }
+        for suggestion in self.suggestions.iter()
+        {
+            result.push_str(&format!(" = help: did you mean `{}`?\n", suggestion.replacement));
+        }
+        result
+    }
+}
+
+impl std::fmt::Display for Diagnostic
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "{}", self.render())
+    }
+}
+
+/// Finds the candidate closest to the input, ignoring case.
+/// Candidates which need more edits than a third of the input's length
+/// (or which are the input itself) are not considered similar.
+pub fn closest_match<I>(input: &str, candidates: I) -> Option<String>
+where
+    I: IntoIterator<Item = String>
+{
+    let input = input.trim().to_lowercase();
+    let max_distance = (input.chars().count() / 3).max(1);
+    candidates.into_iter()
+        .map(|c| (edit_distance(&input, &c.to_lowercase()), c))
+        .filter(|(d, _)| *d > 0 && *d <= max_distance)
+        .min_by(|(d1, c1), (d2, c2)| d1.cmp(d2).then_with(|| c1.cmp(c2)))
+        .map(|(_, c)| c)
+}
+
+/// The Levenshtein distance between two strings, counted in characters
+fn edit_distance(a: &str, b: &str) -> usize
+{
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate()
+    {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate()
+        {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod unit_tests
+{
+    use super::*;
+
+    #[test]
+    fn render_test()
+    {
+        let source = "rounddown(ability.magic theroy / 5)";
+        let span = Span::new(10, 30);
+        let d = Diagnostic::new("unknown tag `ability.magic theroy`".to_string(), source)
+            .with_span(span)
+            .with_suggestion(span, "ability.magic theory".to_string());
+        assert_eq!(d.render(), "error: unknown tag `ability.magic theroy`\n | rounddown(ability.magic theroy / 5)\n |           ^^^^^^^^^^^^^^^^^^^^\n = help: did you mean `ability.magic theory`?\n");
+
+        // Spans past the end of the source point just after it
+        let d = Diagnostic::new("unbalanced parentheses".to_string(), "(a + b").with_span(Span::at("(a + b", 6));
+        assert_eq!(d.render(), "error: unbalanced parentheses\n | (a + b\n |       ^\n");
+    }
+
+    #[test]
+    fn closest_match_test()
+    {
+        let candidates = vec!["ability.magic theory".to_string(), "ability.latin".to_string(), "ability.magic theory.exp".to_string()];
+        assert_eq!(closest_match("ability.magic theroy", candidates.clone()), Some("ability.magic theory".to_string()));
+        assert_eq!(closest_match("Ability.Latim", candidates.clone()), Some("ability.latin".to_string()));
+        assert_eq!(closest_match("spell.form", candidates.clone()), None);
+        assert_eq!(closest_match("ability.latin", candidates), None);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+}
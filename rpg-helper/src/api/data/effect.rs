@@ -1,4 +1,6 @@
-use crate::api::data::{conditional::Conditional, equation::Equation, modifier::Modifier, tag::Tag};
+use std::collections::HashSet;
+
+use crate::api::data::{conditional::{Conditional, ConditionalTemplate}, equation::{Equation, EquationTemplate}, error::TemplateError, modifier::{Modifier, ModifierTemplate}, tag::{RemapTags, Tag, TagMapping, TagTemplate}, template::{Template, Templated}, text::TextValue};
 
 use serde::{Deserialize, Serialize};
 
@@ -6,6 +8,9 @@ use serde::{Deserialize, Serialize};
 // Sets and attribute value
 // Sets the value of an equation
 // Sets the value of a conditional
+//
+// Effects can also be combined, either as a sequence
+// or as an effect applied only when a conditional is true.
 #[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
 pub enum Effect
 {
@@ -16,6 +21,254 @@ pub enum Effect
     SetEquation(Equation),
     SetConditional(Conditional),
     SetModifier(Modifier),
+    SetText(Tag, TextValue),
+    AddToAttribute(Tag, f32),           // A missing attribute is treated as 0
+    MultiplyAttribute(Tag, f32),        // Only changes the value if it exists
+    Sequence(Vec<Effect>),              // Applies each effect in order. If one fails, none are applied
+    ApplyIf(Tag, Box<Effect>),          // Applies the effect only if the conditional of the tag is true when applied
+}
+
+impl RemapTags for Effect
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        match self
+        {
+            Effect::AddStateTag(t) | Effect::RemoveStateTag(t) | Effect::SetAttribute(t, _) => t.remap_tags(remap),
+            Effect::SetAttributeFromValue(t, value) =>
+            {
+                t.remap_tags(remap);
+                value.remap_tags(remap);
+            },
+            Effect::SetEquation(equation) => equation.remap_tags(remap),
+            Effect::SetConditional(conditional) => conditional.remap_tags(remap),
+            Effect::SetModifier(modifier) => modifier.remap_tags(remap),
+            Effect::SetText(t, text) =>
+            {
+                t.remap_tags(remap);
+                text.remap_tags(remap);
+            },
+            Effect::AddToAttribute(t, _) | Effect::MultiplyAttribute(t, _) => t.remap_tags(remap),
+            Effect::Sequence(effects) => effects.remap_tags(remap),
+            Effect::ApplyIf(condition, effect) =>
+            {
+                condition.remap_tags(remap);
+                effect.remap_tags(remap);
+            },
+        }
+    }
+}
+
+/// A template of an effect, where the tags of the effect are filled in by template inputs.
+/// Ex: `AddToAttribute("ability.[ability].exp", 5.0)` for an effect which grants experience to any ability
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+pub enum EffectTemplate
+{
+    AddStateTag(TagTemplate),
+    RemoveStateTag(TagTemplate),
+    SetAttribute(TagTemplate, f32),
+    SetAttributeFromValue(Templated<TagTemplate, Tag>, Templated<TagTemplate, Tag>),
+    SetEquation(EquationTemplate),
+    SetConditional(ConditionalTemplate),
+    SetModifier(ModifierTemplate),
+    SetText(TagTemplate, TextValue),
+    AddToAttribute(TagTemplate, f32),
+    MultiplyAttribute(TagTemplate, f32),
+    Sequence(Vec<Templated<EffectTemplate, Effect>>),
+    ApplyIf(Templated<TagTemplate, Tag>, Box<Templated<EffectTemplate, Effect>>),
+}
+
+impl EffectTemplate
+{
+    /// Follows the convention of a template's `new` method, returning
+    /// the finished effect if the template has no inputs to fill.
+    /// Ex: a sequence of only complete effects
+    pub fn new(template: EffectTemplate) -> Templated<EffectTemplate, Effect>
+    {
+        match template.attempt_complete()
+        {
+            Ok(effect) => Templated::Complete(effect),
+            Err(_) => Templated::Template(template),
+        }
+    }
+}
+
+impl Template<Effect> for EffectTemplate
+{
+    fn get_required_inputs(&self) -> HashSet<String>
+    {
+        match self
+        {
+            EffectTemplate::AddStateTag(t) | EffectTemplate::RemoveStateTag(t) | EffectTemplate::SetAttribute(t, _) |
+            EffectTemplate::SetText(t, _) | EffectTemplate::AddToAttribute(t, _) | EffectTemplate::MultiplyAttribute(t, _) => t.get_required_inputs(),
+            EffectTemplate::SetAttributeFromValue(t, value) =>
+            {
+                let mut result = t.get_required_inputs();
+                result.extend(value.get_required_inputs());
+                result
+            },
+            EffectTemplate::SetEquation(equation) => equation.get_required_inputs(),
+            EffectTemplate::SetConditional(conditional) => conditional.get_required_inputs(),
+            EffectTemplate::SetModifier(modifier) => modifier.get_required_inputs(),
+            EffectTemplate::Sequence(effects) => effects.iter().flat_map(|e| e.get_required_inputs()).collect(),
+            EffectTemplate::ApplyIf(condition, effect) =>
+            {
+                let mut result = condition.get_required_inputs();
+                result.extend(effect.get_required_inputs());
+                result
+            },
+        }
+    }
+
+    /// Inserts given tag value into all matching template inputs
+    fn fill_template_value(&mut self, input_name: &str, input_value: &Tag) -> Option<Effect>
+    {
+        match self
+        {
+            EffectTemplate::AddStateTag(t) | EffectTemplate::RemoveStateTag(t) | EffectTemplate::SetAttribute(t, _) |
+            EffectTemplate::SetText(t, _) | EffectTemplate::AddToAttribute(t, _) | EffectTemplate::MultiplyAttribute(t, _) =>
+            {
+                t.fill_template_value(input_name, input_value);
+            },
+            EffectTemplate::SetAttributeFromValue(t, value) =>
+            {
+                t.fill_template_value(input_name, input_value);
+                value.fill_template_value(input_name, input_value);
+            },
+            EffectTemplate::SetEquation(equation) => { equation.fill_template_value(input_name, input_value); },
+            EffectTemplate::SetConditional(conditional) => { conditional.fill_template_value(input_name, input_value); },
+            EffectTemplate::SetModifier(modifier) => { modifier.fill_template_value(input_name, input_value); },
+            EffectTemplate::Sequence(effects) => effects.iter_mut().for_each(|e| e.fill_template_value(input_name, input_value)),
+            EffectTemplate::ApplyIf(condition, effect) =>
+            {
+                condition.fill_template_value(input_name, input_value);
+                effect.fill_template_value(input_name, input_value);
+            },
+        }
+        self.attempt_complete().ok()
+    }
+
+    fn attempt_complete(&self) -> Result<Effect, TemplateError>
+    {
+        if !self.is_complete()
+        {
+            return Err(TemplateError::MissingTemplateValues(self.get_required_inputs().into_iter().collect()));
+        }
+
+        match self
+        {
+            EffectTemplate::AddStateTag(t) => Ok(Effect::AddStateTag(t.attempt_complete()?)),
+            EffectTemplate::RemoveStateTag(t) => Ok(Effect::RemoveStateTag(t.attempt_complete()?)),
+            EffectTemplate::SetAttribute(t, v) => Ok(Effect::SetAttribute(t.attempt_complete()?, *v)),
+            EffectTemplate::SetAttributeFromValue(t, value) => Ok(Effect::SetAttributeFromValue(t.attempt_complete()?, value.attempt_complete()?)),
+            EffectTemplate::SetEquation(equation) => Ok(Effect::SetEquation(equation.attempt_complete()?)),
+            EffectTemplate::SetConditional(conditional) => Ok(Effect::SetConditional(conditional.attempt_complete()?)),
+            EffectTemplate::SetModifier(modifier) => Ok(Effect::SetModifier(modifier.attempt_complete()?)),
+            EffectTemplate::SetText(t, text) => Ok(Effect::SetText(t.attempt_complete()?, text.clone())),
+            EffectTemplate::AddToAttribute(t, v) => Ok(Effect::AddToAttribute(t.attempt_complete()?, *v)),
+            EffectTemplate::MultiplyAttribute(t, v) => Ok(Effect::MultiplyAttribute(t.attempt_complete()?, *v)),
+            EffectTemplate::Sequence(effects) => Ok(Effect::Sequence(effects.iter().map(|e| e.attempt_complete()).collect::<Result<Vec<_>, _>>()?)),
+            EffectTemplate::ApplyIf(condition, effect) => Ok(Effect::ApplyIf(condition.attempt_complete()?, Box::new(effect.attempt_complete()?))),
+        }
+    }
 }
 
-// Effect Templating!! YAY!!!
\ No newline at end of file
+#[cfg(test)]
+mod unit_tests
+{
+    use std::collections::HashSet;
+
+    use crate::api::data::{conditional::Conditional, context::Context, effect::{Effect, EffectTemplate}, tag::{Tag, TagRegistry, TagTemplate}, template::{Template, Templated}, text::TextValue};
+
+    fn tag(s: &str) -> Tag
+    {
+        Tag::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn compound_effect_test()
+    {
+        let mut ctx = Context::new();
+        ctx.set_attribute(&tag("ability.latin.exp"), 5.0).unwrap();
+        ctx.set_attribute(&tag("ability.latin"), 1.0).unwrap();
+        ctx.set_conditional(Conditional::new(tag("character.is magus"), "character.magus").unwrap()).unwrap();
+
+        // Relative changes use the value of the attribute before modifiers
+        ctx.apply_effect(&Effect::AddToAttribute(tag("ability.latin.exp"), 10.0)).unwrap();
+        assert_eq!(ctx.get_value(&tag("ability.latin.exp")).unwrap(), Some(15.0));
+        ctx.apply_effect(&Effect::AddToAttribute(tag("ability.greek.exp"), 5.0)).unwrap();
+        assert_eq!(ctx.get_value(&tag("ability.greek.exp")).unwrap(), Some(5.0));
+        ctx.apply_effect(&Effect::MultiplyAttribute(tag("ability.latin.exp"), 2.0)).unwrap();
+        assert_eq!(ctx.get_value(&tag("ability.latin.exp")).unwrap(), Some(30.0));
+        ctx.apply_effect(&Effect::MultiplyAttribute(tag("ability.arabic.exp"), 2.0)).unwrap();
+        assert!(!ctx.has_attribute(&tag("ability.arabic.exp")));
+
+        // Only applies once the conditional is true
+        let gift = Effect::ApplyIf(tag("character.is magus"), Box::new(Effect::AddToAttribute(tag("ability.latin.exp"), 5.0)));
+        ctx.apply_effect(&gift).unwrap();
+        assert_eq!(ctx.get_value(&tag("ability.latin.exp")).unwrap(), Some(30.0));
+        ctx.apply_effect(&Effect::AddStateTag(tag("character.magus"))).unwrap();
+        ctx.apply_effect(&gift).unwrap();
+        assert_eq!(ctx.get_value(&tag("ability.latin.exp")).unwrap(), Some(35.0));
+
+        // A sequence applies in order, and is not applied at all if any effect fails
+        let before = ctx.clone();
+        let failing = Effect::Sequence(vec![
+            Effect::AddToAttribute(tag("ability.latin.exp"), 5.0),
+            Effect::Sequence(vec![Effect::AddStateTag(tag("character.gifted")), Effect::SetAttribute(tag("ability.arabic"), 1.0)]),
+            Effect::AddToAttribute(tag("character.is magus"), 1.0),
+        ]);
+        assert!(ctx.apply_effect(&failing).is_err());
+        assert_eq!(ctx, before);
+
+        ctx.apply_effect(&Effect::Sequence(vec![
+            Effect::SetAttribute(tag("ability.latin"), 2.0),
+            Effect::MultiplyAttribute(tag("ability.latin"), 3.0),
+        ])).unwrap();
+        assert_eq!(ctx.get_value(&tag("ability.latin")).unwrap(), Some(6.0));
+    }
+
+    #[test]
+    fn template_test()
+    {
+        let exp = EffectTemplate::AddToAttribute(TagTemplate::from_str("ability.[ability].exp").unwrap(), 5.0);
+        let mut template = EffectTemplate::Sequence(vec![
+            EffectTemplate::new(exp),
+            Templated::Complete(Effect::AddStateTag(tag("character.studied"))),
+        ]);
+        assert_eq!(template.get_required_inputs(), HashSet::from(["ability".to_string()]));
+        assert!(template.attempt_complete().is_err());
+        assert_eq!(
+            template.fill_template_value("ability", &tag("latin")),
+            Some(Effect::Sequence(vec![Effect::AddToAttribute(tag("ability.latin.exp"), 5.0), Effect::AddStateTag(tag("character.studied"))]))
+        );
+
+        // Templates without inputs are completed right away
+        let complete = EffectTemplate::new(EffectTemplate::Sequence(vec![Templated::Complete(Effect::AddStateTag(tag("character.studied")))]));
+        assert_eq!(complete, Templated::Complete(Effect::Sequence(vec![Effect::AddStateTag(tag("character.studied"))])));
+    }
+
+    /// Tests effects written through one registry can be loaded into another
+    #[test]
+    fn serde_registry_test()
+    {
+        let mut registry = TagRegistry::new();
+        let mut tag = |s: &str| registry.get_or_register_tag(s).unwrap();
+        let e = Effect::Sequence(vec![
+            Effect::AddToAttribute(tag("ability.latin.exp"), 5.0),
+            Effect::SetText(tag("spell.form"), TextValue::new_enum(tag("form"), tag("form.ignem")).unwrap()),
+            Effect::ApplyIf(tag("character.is magus"), Box::new(Effect::AddStateTag(tag("character.gifted")))),
+        ]);
+        let json = registry.to_json_string(&e).unwrap();
+        assert!(json.contains("\"character.is magus\""));
+
+        let mut other = TagRegistry::new_with_reserved(&["character", "form"]);
+        let loaded: Effect = other.from_json_str(&json).unwrap();
+        let other_tag = |s: &str| other.get_tag(s).unwrap().unwrap();
+        assert_eq!(loaded, Effect::Sequence(vec![
+            Effect::AddToAttribute(other_tag("ability.latin.exp"), 5.0),
+            Effect::SetText(other_tag("spell.form"), TextValue::new_enum(other_tag("form"), other_tag("form.ignem")).unwrap()),
+            Effect::ApplyIf(other_tag("character.is magus"), Box::new(Effect::AddStateTag(other_tag("character.gifted")))),
+        ]));
+    }
+}
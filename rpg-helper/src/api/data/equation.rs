@@ -1,32 +1,59 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::OnceLock};
 
 use serde::{Deserialize, Serialize};
 
-use crate::api::data::{context::Context, error::{DataError, DoesNotExistError, TemplateError}, evaltree::EvalTree, tag::{Tag, TagTemplate}, template::{Template, Templated}};
+use crate::api::data::{context::Context, error::{DataError, DoesNotExistError, TemplateError}, evaltree::{bytecode::Bytecode, EvalTree}, tag::{RemapTags, Tag, TagMapping, TagRegistry, TagTemplate}, template::{Template, Templated}};
 
-#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Equation
 {
     pub name: Tag,
     equation_string: String,
     ast: EvalTree,
+    // Compiled from the ast on first evaluation. The ast is kept for display and templating.
+    #[serde(skip)]
+    bytecode: OnceLock<Bytecode>,
+}
+
+impl PartialEq for Equation
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        self.name == other.name && self.equation_string == other.equation_string && self.ast == other.ast
+    }
 }
 
 impl Equation
 {
     pub fn new(name: Tag, equation: &str) -> Result<Equation, DataError>
     {
-        let ast = EvalTree::from_str(equation)?;
+        Self::from_tree(name, equation, EvalTree::from_str(equation)?)
+    }
+
+    /// Creates the equation the same as `new`, with the tags of the equation registered in the given registry
+    pub fn new_with_registry(name: Tag, equation: &str, registry: &mut TagRegistry) -> Result<Equation, DataError>
+    {
+        Self::from_tree(name, equation, EvalTree::from_str_with_registry(equation, registry)?)
+    }
+
+    fn from_tree(name: Tag, equation: &str, ast: EvalTree) -> Result<Equation, DataError>
+    {
         if ast.is_template()
         {
             return Err(DataError::StringInputInvalid(format!("Given equation \"{}\" contains template values. An equation can not contain template values.", equation)));
         }
-        Ok(Equation { name, equation_string: equation.to_string(), ast })
+        Ok(Equation { name, equation_string: equation.to_string(), ast, bytecode: OnceLock::new() })
     }
 
     pub fn eval(&self, ctx: &Context) -> Result<f32, DataError>
     {
-        self.ast.eval_as_num(ctx)
+        self.bytecode.get_or_init(||
+        {
+            // Only the compiled form is simplified, so the ast keeps matching the equation string
+            let mut ast = self.ast.clone();
+            ast.simplify();
+            ast.compile()
+        }).eval_as_num(ctx)
     }
 
     pub fn get_equation_string(&self) -> String
@@ -38,6 +65,18 @@ impl Equation
     {
         self.ast.check_only_allowed_tags(allowed_tags)
     }
+
+    /// The tags this equation reads from when evaluated in the given context
+    pub fn get_dependencies(&self, ctx: &Context) -> Vec<Tag>
+    {
+        self.ast.get_dependencies(ctx)
+    }
+
+    /// The prefixes of tags this equation aggregates over
+    pub fn get_prefix_dependencies(&self) -> Vec<Tag>
+    {
+        self.ast.get_prefix_dependencies()
+    }
 }
 
 #[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
@@ -96,6 +135,26 @@ impl EquationSet
     }
 }
 
+impl RemapTags for Equation
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        self.name.remap_tags(remap);
+        self.equation_string = remap.remap_source(&self.equation_string);
+        self.ast.remap_tags(remap);
+        // The bytecode holds the tags of the ast, so it is compiled again on the next evaluation
+        self.bytecode = OnceLock::new();
+    }
+}
+
+impl RemapTags for EquationSet
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        self.equations.remap_tags(remap);
+    }
+}
+
 impl IntoIterator for EquationSet
 {
     type Item = (Tag, Equation);
@@ -134,7 +193,8 @@ impl EquationTemplate
             Templated::Template(name_template)
         };
 
-        let ast = EvalTree::from_str(equation)?;
+        let mut ast = EvalTree::from_str(equation)?;
+        ast.simplify();
         if !name_template.is_complete() || ast.is_template()
         {
             return Ok(Templated::Template(EquationTemplate { name_template, templated_equation_string: equation.to_string(), ast }));
@@ -142,7 +202,7 @@ impl EquationTemplate
 
         if let Some(name) = name_template.into_complete()
         {
-            Ok(Templated::Complete(Equation { name, equation_string: ast.to_expression_string(), ast }))
+            Ok(Templated::Complete(Equation { name, equation_string: ast.to_expression_string(), ast, bytecode: OnceLock::new() }))
         }
         else
         {
@@ -171,7 +231,7 @@ impl Template<Equation> for EquationTemplate
         {
             if let Some(name) = self.name_template.as_complete()
             {
-                return Some(Equation { name: name.clone(), equation_string: self.ast.to_expression_string(), ast: self.ast.clone() });
+                return Some(Equation { name: name.clone(), equation_string: self.ast.to_expression_string(), ast: self.ast.clone(), bytecode: OnceLock::new() });
             }
         }
         None
@@ -192,6 +252,6 @@ impl Template<Equation> for EquationTemplate
             Templated::Complete(c) => c.clone(),
         };
 
-        Ok(Equation { name, equation_string: self.ast.to_expression_string(), ast: self.ast.clone() })
+        Ok(Equation { name, equation_string: self.ast.to_expression_string(), ast: self.ast.clone(), bytecode: OnceLock::new() })
     }
 }
\ No newline at end of file
@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-use crate::api::{data::{evaltree::{tokenize::Token, EvalError}, tag::Tag, DataType}, ApiError};
+use crate::api::{data::{diagnostic::{Diagnostic, Span}, evaltree::{tokenize::Token, EvalError}, tag::{Tag, TagRegistry}, template::TemplateInputValue, DataType}, ApiError};
 
 #[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
 pub enum DataError
@@ -17,6 +17,15 @@ pub enum DataError
     /// and vice versa. This is used in conditional and equation construction
     /// to ensure valid state of conditionals and equations.
     StringInputInvalid(String),
+    /// Setting a value would cause evaluation to loop forever.
+    /// Contains every cycle found, each as the list of tags involved in evaluation order.
+    CyclicEvaluation(Vec<Vec<Tag>>),
+    /// The selected tag of an enum text value is not one of its options.
+    /// Contains the options prefix, then the selected tag.
+    EnumSelectionInvalid(Tag, Tag),
+    /// Renaming a tag would replace the contained tag, which is either
+    /// a value that is not renamed or an alias.
+    RenameConflict(Tag),
 }
 
 impl DataError
@@ -50,6 +59,116 @@ impl DataError
     {
         DataError::DoesNotExist(DoesNotExistError::Value(t))
     }
+
+    pub fn text_dne(t: Tag) -> DataError
+    {
+        DataError::DoesNotExist(DoesNotExistError::Text(t))
+    }
+}
+
+impl DataError
+{
+    /// Describes this error as a diagnostic of the given source, such as the equation which failed.
+    /// Tags named by the error are named through the registry and underlined where they appear in the source.
+    pub fn to_diagnostic(&self, source: &str, registry: &TagRegistry) -> Diagnostic
+    {
+        match self
+        {
+            DataError::Parsing(e) =>
+            {
+                let mut result = e.to_diagnostic();
+                // Errors of a single tag only hold the tag string, so point them into the full source
+                if e.string != source
+                {
+                    if let Some(start) = source.find(&e.string)
+                    {
+                        result.source = source.to_string();
+                        result.span = Some(Span::new(start + e.span.start, start + e.span.end));
+                    }
+                }
+                result
+            },
+            DataError::Evaluation(EvalError::ValueNotFound(t)) | DataError::DoesNotExist(DoesNotExistError::Value(t)) =>
+            {
+                tag_diagnostic(format!("no value found for tag `{}`", tag_name(t, registry)), source, t)
+            },
+            DataError::DoesNotExist(e) =>
+            {
+                let (kind, t) = match e
+                {
+                    DoesNotExistError::Tag(t) => ("tag", t),
+                    DoesNotExistError::Attribute(t) => ("attribute", t),
+                    DoesNotExistError::Condition(t) => ("conditional", t),
+                    DoesNotExistError::Modifier(t) => ("modifier", t),
+                    DoesNotExistError::Equation(t) => ("equation", t),
+                    DoesNotExistError::Value(t) => ("value", t),
+                    DoesNotExistError::Text(t) => ("text", t),
+                };
+                tag_diagnostic(format!("unknown {} `{}`", kind, tag_name(t, registry)), source, t)
+            },
+            DataError::ConflictingExpectedType(e) =>
+            {
+                tag_diagnostic(format!("`{}` is a {:?}, not a {:?}", tag_name(&e.tag, registry), e.found, e.expected), source, &e.tag)
+            },
+            DataError::CyclicEvaluation(cycles) =>
+            {
+                let mut result = Diagnostic::new("evaluation would never finish".to_string(), source);
+                for cycle in cycles
+                {
+                    result = result.with_note(format!("cycle: {}", cycle.iter().map(|t| tag_name(t, registry)).collect::<Vec<_>>().join(" -> ")));
+                }
+                result
+            },
+            DataError::EnumSelectionInvalid(options, selected) =>
+            {
+                tag_diagnostic(format!("`{}` is not one of the options of `{}`", tag_name(selected, registry), tag_name(options, registry)), source, selected)
+            },
+            DataError::RenameConflict(t) =>
+            {
+                tag_diagnostic(format!("renaming would replace `{}`", tag_name(t, registry)), source, t)
+            },
+            DataError::Template(TemplateError::UnknownInput(input)) =>
+            {
+                Diagnostic::new(format!("template has no input `{}`", input), source)
+            },
+            DataError::Template(TemplateError::InputTypeMismatch(input)) =>
+            {
+                Diagnostic::new(format!("value of the wrong type given for input `{}`", input), source)
+            },
+            DataError::Template(TemplateError::InputValueInvalid(input, _)) =>
+            {
+                Diagnostic::new(format!("value given for input `{}` is not allowed", input), source)
+            },
+            DataError::Evaluation(EvalError::TemplatedEquation) | DataError::Template(_) =>
+            {
+                Diagnostic::new("equation has template inputs which have not been filled".to_string(), source)
+            },
+            DataError::Evaluation(EvalError::ExpectedValueMismatch) | DataError::Evaluation(EvalError::EvaluationMismatch) =>
+            {
+                Diagnostic::new("equation does not result in the expected type".to_string(), source)
+                    .with_note("conditionals must result in true or false, while equations must result in a number".to_string())
+            },
+            DataError::StringInputInvalid(s) | DataError::InvalidState(s) => Diagnostic::new(s.clone(), source),
+            _ => Diagnostic::new(format!("{:?}", self), source),
+        }
+    }
+}
+
+/// A diagnostic which underlines the first place the tag is written in the source, if any
+/// The name of a tag, or its subtags if the tag is not from the registry
+fn tag_name(t: &Tag, registry: &TagRegistry) -> String
+{
+    t.to_string(registry).unwrap_or_else(|| format!("{:?}", t.as_subtag_slice()))
+}
+
+fn tag_diagnostic(message: String, source: &str, t: &Tag) -> Diagnostic
+{
+    let result = Diagnostic::new(message, source);
+    match crate::api::data::evaltree::find_tag_span(source, t)
+    {
+        Some(span) => result.with_span(span),
+        None => result,
+    }
 }
 
 impl From<DataError> for ApiError
@@ -69,6 +188,7 @@ pub enum DoesNotExistError
     Modifier(Tag),
     Equation(Tag),
     Value(Tag),
+    Text(Tag),
 }
 
 #[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
@@ -91,15 +211,46 @@ impl ConflictError
 pub struct ParseError
 {
     pub string: String,
-    pub index_of_error: usize,
+    /// The bytes of the string which could not be parsed
+    pub span: Span,
     pub error_type: ParseErrorType,
 }
 
 impl ParseError
 {
-    pub fn new(string: String, index_of_error: usize, error_type: ParseErrorType) -> ParseError
+    pub fn new(string: String, span: Span, error_type: ParseErrorType) -> ParseError
+    {
+        ParseError { string, span, error_type }
+    }
+
+    pub fn to_diagnostic(&self) -> Diagnostic
     {
-        ParseError { string, index_of_error, error_type }
+        let message = match &self.error_type
+        {
+            ParseErrorType::Tag(TagParseError::TagEmpty) => "tag is empty",
+            ParseErrorType::Tag(TagParseError::SubTagEmpty) => "tag has an empty subtag",
+            ParseErrorType::Tag(TagParseError::InvalidCharacter) => "invalid character in tag",
+            ParseErrorType::Tag(TagParseError::FirstTagNumeric) => "tag can not start with a number",
+            ParseErrorType::Tag(TagParseError::MissingTemplate) => "template tag has no template input",
+            ParseErrorType::Evaluation(EvalParseError::TokenInvalid) => "unrecognized token",
+            ParseErrorType::Evaluation(EvalParseError::NumberMultipleDecimals) => "number has more than one decimal point",
+            ParseErrorType::Evaluation(EvalParseError::UnbalancedParentheses) => "unclosed parentheses",
+            ParseErrorType::Evaluation(EvalParseError::MissingParentheses) => "closing bracket does not match an opening bracket",
+            ParseErrorType::Evaluation(EvalParseError::OperationTypeMismatch) => "operation can not be applied here",
+            ParseErrorType::Evaluation(EvalParseError::TextUnterminated) => "text is missing a closing quote",
+            ParseErrorType::Query(QueryParseError::PatternEmpty) => "query has an empty pattern or subtag",
+            ParseErrorType::Query(QueryParseError::SubtagInvalid) => "invalid subtag in query pattern",
+            ParseErrorType::Query(QueryParseError::PredicateInvalid) => "invalid value predicate",
+        };
+        let result = Diagnostic::new(message.to_string(), &self.string).with_span(self.span);
+        match &self.error_type
+        {
+            ParseErrorType::Tag(TagParseError::InvalidCharacter) => result.with_note("tags may only contain letters, numbers, spaces and '.'".to_string()),
+            ParseErrorType::Tag(TagParseError::MissingTemplate) => result.with_note("template inputs are written in brackets, such as \"ability.[input]\"".to_string()),
+            ParseErrorType::Query(QueryParseError::SubtagInvalid) => result.with_note("subtags of a pattern are either a subtag, \"*\" or \"**\"".to_string()),
+            ParseErrorType::Query(QueryParseError::PredicateInvalid) => result.with_note("predicates are written as \"value\", a comparison, then a number, such as \"value >= 5\"".to_string()),
+            _ => result,
+        }
     }
 }
 
@@ -116,12 +267,21 @@ pub enum ParseErrorType
 {
     Tag(TagParseError),
     Evaluation(EvalParseError),
+    Query(QueryParseError),
 }
 
 #[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
 pub enum TemplateError
 {
     MissingTemplateValues(Vec<String>),
+    /// The input is not required by the template, nor declared on it
+    UnknownInput(String),
+    /// The value given for the input is not of its declared type,
+    /// such as a tag given for a number input
+    InputTypeMismatch(String),
+    /// The value given for the input does not meet the constraints of its declared type.
+    /// Contains the input name, then the value given.
+    InputValueInvalid(String, TemplateInputValue),
 }
 
 #[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
@@ -143,6 +303,15 @@ pub enum EvalParseError
     UnbalancedParentheses,
     MissingParentheses,
     OperationTypeMismatch,
+    TextUnterminated,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+pub enum QueryParseError
+{
+    PatternEmpty,
+    SubtagInvalid,
+    PredicateInvalid,
 }
 
 #[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
@@ -1,12 +1,13 @@
 use serde::{Deserialize, Serialize};
 
-use crate::api::data::{context::Context, error::{DataError, TokenizationError}, evaltree::{parse::remove_parentheses, tokenize::Token}, tag::{Tag, TagTemplate}, template::{Template, Templated}};
+use crate::api::data::{context::Context, diagnostic::Span, error::{DataError, TokenizationError}, evaltree::{parse::remove_parentheses, tokenize::Token}, tag::{RemapTags, Tag, TagMapping, TagRegistry, TagTemplate}, template::{Template, Templated}, text::TextValue};
 
 #[derive(Debug, Deserialize, PartialEq, Eq, Serialize, Clone)]
 pub enum EvalError
 {
     DivideByZero,
-    ValueNotFound,
+    /// No value was found for the tag when evaluating
+    ValueNotFound(Tag),
     ExpectedValueMismatch,
     EvaluationMismatch,
     UnsupportedOperation,
@@ -77,6 +78,12 @@ impl EvalTree
         self.root.recursive_check_contains_template()
     }
 
+    /// The tag read by the tree, if the tree is only a reference to a tag. Ex: "event.exp"
+    pub fn as_tag(&self) -> Option<&Tag>
+    {
+        self.root.as_referenced_tag()
+    }
+
     pub fn get_template_inputs(&self) -> Vec<String>
     {
         let mut result = vec![];
@@ -90,6 +97,25 @@ impl EvalTree
     pub fn insert_template_input(&mut self, s: &str, t: &Tag)
     {
         self.root.recursive_insert_template_input(s, t, ExpectedResult::Unknown);
+        self.simplify();
+    }
+
+    /// Compiles this tree to flat bytecode, which evaluates to the same result
+    /// as the tree without walking its nodes. See `bytecode::Bytecode`.
+    pub fn compile(&self) -> bytecode::Bytecode
+    {
+        bytecode::Bytecode::compile(&self.root)
+    }
+
+    /// Folds constant subexpressions and removes redundant operations,
+    /// without changing the result of evaluation for any context.
+    /// Ex: (3 + 4) * tag              =>  7 * tag
+    /// Ex: -(-(tag))                  =>  tag
+    /// Ex: true && cond               =>  cond
+    /// Ex: false ? tag : other * 2    =>  other * 2
+    pub fn simplify(&mut self)
+    {
+        self.root.recursive_simplify();
     }
 
     /// Used to check that this equation only contains tags in the given list.
@@ -100,6 +126,24 @@ impl EvalTree
         self.root.recursive_check_only_allowed_tags(allowed_tags)
     }
 
+    /// Gets all the tags this tree reads from when evaluated in the given context.
+    /// Aggregates are expanded to every tag currently matching their prefix in the context.
+    pub fn get_dependencies(&self, ctx: &Context) -> Vec<Tag>
+    {
+        let mut result = vec![];
+        self.root.recursive_dependencies(ctx, &mut result);
+        result
+    }
+
+    /// Gets the prefixes of all aggregates in this tree.
+    /// The value of this tree can change when any tag under these prefixes is added or removed.
+    pub fn get_prefix_dependencies(&self) -> Vec<Tag>
+    {
+        let mut result = vec![];
+        self.root.recursive_prefix_dependencies(&mut result);
+        result
+    }
+
     /// Constructs a full abstract syntax tree from the given string.
     /// The syntax for an equation is as follows:
     ///     "3 + 4 * 10 / 5"
@@ -114,6 +158,17 @@ impl EvalTree
         })
     }
 
+    /// Constructs the tree the same as `from_str`, with its tags registered in the given registry
+    pub fn from_str_with_registry(s: &str, registry: &mut TagRegistry) -> Result<Self, DataError>
+    {
+        parse::brackets_are_balanced(s)?;
+
+        Ok(EvalTree
+        {
+            root: EvalNode::from_token_list(tokenize::tokenize_expression_in(s, registry)?)?,
+        })
+    }
+
     /// Using the constructed AST, reverses back to the equation form.
     /// The resultant equation uses the minimum required parentheses
     /// with some perfered syntax formatting for some operations 
@@ -162,6 +217,8 @@ impl EvalTree
                     Operation::PowMethod =>       "pow(".to_owned() + v.get(0).expect("") + ", " + v.get(1).expect("") + ")",
                     Operation::PowSymbol =>       "pow(".to_owned() + v.get(0).expect("") + ", " + v.get(1).expect("") + ")",
                     Operation::Range =>         "range(".to_owned() + v.get(0).expect("") + ", " + v.get(1).expect("") + ")",
+                    // Aggregate over a tag prefix
+                    Operation::Aggregate(a, m) => format!("{}(", a.method_name(&m)) + v.first().expect("") + ")",
                     // 3 Child Operations
                     Operation::Ternary =>       v.get(0).expect("").to_owned() + " ? " + v.get(1).expect("") + " : " + v.get(2).expect(""),
                 }
@@ -171,6 +228,7 @@ impl EvalTree
             {
                 OperandNode::ExplicitNumber(n) => n.to_string(),
                 OperandNode::ExplicitBool(b) => b.to_string(),
+                OperandNode::ExplicitText(s) => format!("\"{}\"", s),
                 OperandNode::ReferencedValue(tag) | OperandNode::ReferencedCondition(tag) | OperandNode::ReferencedTag(tag) => format!("{}", tag.to_str()),
                 OperandNode::TagTemplate(template) => format!("{}", template),
             }
@@ -178,6 +236,14 @@ impl EvalTree
     }
 }
 
+impl RemapTags for EvalTree
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        self.root.recursive_remap_tags(remap);
+    }
+}
+
 impl std::fmt::Display for EvalTree
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) ->  std::fmt::Result
@@ -219,6 +285,8 @@ enum OperandNode
 {
     ExplicitNumber(f32),
     ExplicitBool(bool),
+    // Text in quotes. Can only be compared against other text (or a referenced text value of the context)
+    ExplicitText(String),
     // The type of tag reference is determined by the expected value requested.
     // If the value can not be determined, we fallback to ReferencedTag
     ReferencedValue(Tag),
@@ -245,6 +313,9 @@ enum OperationNode
     RoundUp(Box<EvalNode>),
     Range(Box<EvalNode>, Box<EvalNode>, Box<EvalNode>),
     Ternary(Box<EvalNode>, Box<EvalNode>, Box<EvalNode>),
+    // Expects numeric or boolean result depending on the aggregation.
+    // The child is always a tag operand, which is used as the prefix to match.
+    Aggregate(Aggregation, PrefixMatch, Box<EvalNode>),
     // Expects boolean result
     Equal(Box<EvalNode>, Box<EvalNode>),
     NotEqual(Box<EvalNode>, Box<EvalNode>),
@@ -285,6 +356,7 @@ impl OperationNode
             Operation::Not => OperationNode::Not(Box::new(children.remove(0))),
             Operation::Or => OperationNode::Or(Box::new(children.remove(0)), Box::new(children.remove(0))),
             Operation::And => OperationNode::And(Box::new(children.remove(0)), Box::new(children.remove(0))),
+            Operation::Aggregate(a, m) => OperationNode::Aggregate(a, m, Box::new(children.remove(0))),
         }
     }
 
@@ -313,6 +385,7 @@ impl OperationNode
             OperationNode::Not(_) => Operation::Not,
             OperationNode::Or(_, _) => Operation::Or,
             OperationNode::And(_, _) => Operation::And,
+            OperationNode::Aggregate(a, m, _) => Operation::Aggregate(*a, *m),
         }
     }
 
@@ -322,7 +395,7 @@ impl OperationNode
         {
             OperationNode::Add(n, n1) | OperationNode::Subtract(n, n1) | OperationNode::Multiply(n, n1) | OperationNode::Divide(n, n1) | OperationNode::Pow(n, n1) |
             OperationNode::Equal(n, n1) | OperationNode::NotEqual(n, n1) | OperationNode::LessThan(n, n1) | OperationNode::LessThanEq(n, n1) | OperationNode::GreaterThan(n, n1) | OperationNode::GreaterThanEq(n, n1) | OperationNode::Or(n, n1) | OperationNode::And(n, n1) => vec![n, n1],
-            OperationNode::Negate(n) | OperationNode::Sqrt(n) | OperationNode::Round(n) | OperationNode::RoundDown(n) | OperationNode::RoundUp(n) | OperationNode::Not(n) => vec![n],
+            OperationNode::Negate(n) | OperationNode::Sqrt(n) | OperationNode::Round(n) | OperationNode::RoundDown(n) | OperationNode::RoundUp(n) | OperationNode::Not(n) | OperationNode::Aggregate(_, _, n) => vec![n],
             OperationNode::Range(n, n1, n2) | OperationNode::Ternary(n, n1, n2) => vec![n, n1, n2],
         }
     }
@@ -333,7 +406,7 @@ impl OperationNode
         {
             OperationNode::Add(n, n1) | OperationNode::Subtract(n, n1) | OperationNode::Multiply(n, n1) | OperationNode::Divide(n, n1) | OperationNode::Pow(n, n1) |
             OperationNode::Equal(n, n1) | OperationNode::NotEqual(n, n1) | OperationNode::LessThan(n, n1) | OperationNode::LessThanEq(n, n1) | OperationNode::GreaterThan(n, n1) | OperationNode::GreaterThanEq(n, n1) | OperationNode::Or(n, n1) | OperationNode::And(n, n1) => vec![n, n1],
-            OperationNode::Negate(n) | OperationNode::Sqrt(n) | OperationNode::Round(n) | OperationNode::RoundDown(n) | OperationNode::RoundUp(n) | OperationNode::Not(n) => vec![n],
+            OperationNode::Negate(n) | OperationNode::Sqrt(n) | OperationNode::Round(n) | OperationNode::RoundDown(n) | OperationNode::RoundUp(n) | OperationNode::Not(n) | OperationNode::Aggregate(_, _, n) => vec![n],
             OperationNode::Range(n, n1, n2) | OperationNode::Ternary(n, n1, n2) => vec![n, n1, n2],
         }
     }
@@ -408,6 +481,7 @@ impl EvalNode
                     Token::Operation(o) => panic!("Found an operation token {:?} when expected none from not finding root index.", o),
                     Token::Number(n) => Some(OperandNode::ExplicitNumber(n)),
                     Token::Bool(b) => Some(OperandNode::ExplicitBool(b)),
+                    Token::Text(s) => Some(OperandNode::ExplicitText(s)),
                 };
 
                 if result.is_none() && found.is_some()
@@ -443,7 +517,7 @@ impl EvalNode
         while let Some((i, t)) = it.next() {
             match t
             {
-                Token::Bool(_) | Token::Number(_) | Token::Tag(_) | Token::TagTemplate(_) | Token::Colon | Token::Comma => (),
+                Token::Bool(_) | Token::Number(_) | Token::Text(_) | Token::Tag(_) | Token::TagTemplate(_) | Token::Colon | Token::Comma => (),
                 Token::OpenParen => brace_count = brace_count + 1,
                 Token::ClosedParen => brace_count = brace_count - 1,
                 Token::Operation(operation) => 
@@ -505,7 +579,7 @@ impl EvalNode
         {
             match t
             {
-                Token::Comma | Token::Colon | Token::Tag(_) | Token::TagTemplate(_) | Token::Number(_) | Token::Bool(_) => (),
+                Token::Comma | Token::Colon | Token::Tag(_) | Token::TagTemplate(_) | Token::Number(_) | Token::Bool(_) | Token::Text(_) => (),
                 Token::OpenParen => num_paren += 1,
                 Token::ClosedParen => num_paren -= 1,
                 Token::Operation(o) =>
@@ -533,7 +607,7 @@ impl EvalNode
                     child_tokens.push(Vec::from_iter(tokens[i + 1..tokens.len() - num_paren].iter().cloned()));
                     break;
                 },
-                Token::Comma | Token::Tag(_) | Token::Number(_) | Token::Bool(_) | Token::Operation(_) | Token::TagTemplate(_) => (),
+                Token::Comma | Token::Tag(_) | Token::Number(_) | Token::Bool(_) | Token::Text(_) | Token::Operation(_) | Token::TagTemplate(_) => (),
             }
         }
         
@@ -585,6 +659,15 @@ impl EvalNode
         {
             children.push(Self::build_node(remove_parentheses(v), op.get_input_type(i).unwrap())?);
         }
+
+        // Aggregates only make sense over a prefix, so the single child must be a plain (or templated) tag
+        if let Operation::Aggregate(_, _) = op
+        {
+            if !children.iter().all(|c| matches!(c, EvalNode::Operand(OperandNode::ReferencedTag(_)) | EvalNode::Operand(OperandNode::TagTemplate(_))))
+            {
+                return Err(DataError::Syntax(tokens.get(root_index).unwrap().clone()))
+            }
+        }
         Ok(EvalNode::Operation(OperationNode::new(op, children)))
     }
 
@@ -597,44 +680,10 @@ impl EvalNode
             {
                 OperandNode::ExplicitNumber(n) => Ok(EvalResult::Number(*n)),
                 OperandNode::ExplicitBool(b) => Ok(EvalResult::Boolean(*b)),
-                OperandNode::ReferencedValue(tag) => 
-                            {
-                                if let Some(v) = ctx.get_value(tag)?
-                                {
-                                    Ok(EvalResult::Number(v))
-                                }
-                                else
-                                {
-                                    Err(EvalError::ValueNotFound.into())
-                                }
-                            },
-                OperandNode::ReferencedCondition(tag) =>
-                            {
-                                if ctx.has_conditional(tag)
-                                {
-                                    Ok(EvalResult::Boolean(ctx.eval_conditional(tag)?))
-                                }
-                                else
-                                {
-                                    Ok(EvalResult::Boolean(ctx.has_tag(tag)))
-                                }
-                            },
-                OperandNode::ReferencedTag(tag) => 
-                            {
-                                if ctx.has_conditional(tag)
-                                {
-                                    Ok(EvalResult::Boolean(ctx.eval_conditional(tag)?))
-                                }
-                                else if let Some(v) = ctx.get_value(tag)?
-                                {
-                                    Ok(EvalResult::Number(v))
-                                }
-                                else
-                                {
-                                    Ok(EvalResult::Boolean(ctx.has_tag(tag)))
-                                }
-
-                            },
+                OperandNode::ExplicitText(s) => Ok(EvalResult::Text(s.clone())),
+                OperandNode::ReferencedValue(tag) => referenced_value(tag, ctx),
+                OperandNode::ReferencedCondition(tag) => referenced_condition(tag, ctx),
+                OperandNode::ReferencedTag(tag) => referenced_tag(tag, ctx),
                 OperandNode::TagTemplate(_) =>
                             {
                                 Err(DataError::Evaluation(EvalError::TemplatedEquation))
@@ -653,10 +702,10 @@ impl EvalNode
                 OperationNode::Round(v1) => Ok(EvalResult::Number(v1.recursive_eval(ctx)?.as_number()?.round())),
                 OperationNode::RoundDown(v1) => Ok(EvalResult::Number(v1.recursive_eval(ctx)?.as_number()?.floor())),
                 OperationNode::RoundUp(v1) => Ok(EvalResult::Number(v1.recursive_eval(ctx)?.as_number()?.ceil())),
-                OperationNode::Range(v1, v2, v3) => Ok(EvalResult::Number(v1.recursive_eval(ctx)?.as_number()?.clamp(v2.recursive_eval(ctx)?.as_number()?, v3.recursive_eval(ctx)?.as_number()?))),
+                OperationNode::Range(v1, v2, v3) => Ok(EvalResult::Number(range(v1.recursive_eval(ctx)?.as_number()?, v2.recursive_eval(ctx)?.as_number()?, v3.recursive_eval(ctx)?.as_number()?))),
 
-                OperationNode::Equal(v1, v2) => bool_op(v1, v2, ctx, |n1, n2| Ok(n1 == n2)),
-                OperationNode::NotEqual(v1, v2) => bool_op(v1, v2, ctx, |n1, n2| Ok(n1 != n2)),
+                OperationNode::Equal(v1, v2) => Ok(EvalResult::Boolean(equal_op(v1, v2, ctx)?)),
+                OperationNode::NotEqual(v1, v2) => Ok(EvalResult::Boolean(!equal_op(v1, v2, ctx)?)),
                 OperationNode::LessThan(v1, v2) => bool_op(v1, v2, ctx, |n1, n2| Ok(n1.as_number()? < n2.as_number()?)),
                 OperationNode::LessThanEq(v1, v2) => bool_op(v1, v2, ctx, |n1, n2| Ok(n1.as_number()? <= n2.as_number()?)),
                 OperationNode::GreaterThan(v1, v2) => bool_op(v1, v2, ctx, |n1, n2| Ok(n1.as_number()? > n2.as_number()?)),
@@ -676,6 +725,7 @@ impl EvalNode
                         v3.recursive_eval(ctx)
                     }
                 },
+                OperationNode::Aggregate(a, m, v1) => aggregate_op(*a, *m, v1, ctx),
             },
         }
     }
@@ -694,6 +744,58 @@ impl EvalNode
         }
     }
 
+    fn recursive_dependencies(&self, ctx: &Context, deps: &mut Vec<Tag>)
+    {
+        match self
+        {
+            EvalNode::Operand(operand_node) =>
+            match operand_node
+            {
+                OperandNode::ReferencedValue(tag) | OperandNode::ReferencedCondition(tag) | OperandNode::ReferencedTag(tag) => deps.push(tag.clone()),
+                _ => (),
+            },
+            EvalNode::Operation(OperationNode::Aggregate(_, m, prefix)) =>
+            {
+                if let EvalNode::Operand(OperandNode::ReferencedTag(prefix)) = prefix.as_ref()
+                {
+                    match m
+                    {
+                        PrefixMatch::Immediate => deps.extend(ctx.get_tagset().get_immediate_matching_prefix(prefix)),
+                        PrefixMatch::Deep => deps.extend(ctx.get_tagset().get_matching_prefix(prefix).into_iter().filter(|t| t != prefix)),
+                    }
+                }
+            },
+            EvalNode::Operation(operation_node) => operation_node.get_children().iter().for_each(|c| c.recursive_dependencies(ctx, deps)),
+        }
+    }
+
+    fn recursive_prefix_dependencies(&self, prefixes: &mut Vec<Tag>)
+    {
+        match self
+        {
+            EvalNode::Operand(_) => (),
+            EvalNode::Operation(OperationNode::Aggregate(_, _, prefix)) =>
+            {
+                if let EvalNode::Operand(OperandNode::ReferencedTag(prefix)) = prefix.as_ref()
+                {
+                    prefixes.push(prefix.clone());
+                }
+            },
+            EvalNode::Operation(operation_node) => operation_node.get_children().iter().for_each(|c| c.recursive_prefix_dependencies(prefixes)),
+        }
+    }
+
+    fn recursive_remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        match self
+        {
+            EvalNode::Operand(OperandNode::ReferencedValue(t)) | EvalNode::Operand(OperandNode::ReferencedCondition(t)) | EvalNode::Operand(OperandNode::ReferencedTag(t)) => t.remap_tags(remap),
+            EvalNode::Operand(OperandNode::TagTemplate(t)) => t.remap_tags(remap),
+            EvalNode::Operand(_) => (),
+            EvalNode::Operation(operation_node) => operation_node.get_mut_children().into_iter().for_each(|c| c.recursive_remap_tags(remap)),
+        }
+    }
+
     fn recursive_insert_template_input(&mut self, s: &str, t: &Tag, value_hint: ExpectedResult)
     {
         match self
@@ -771,6 +873,111 @@ impl EvalNode
         Ok(())
     }
 
+    fn recursive_simplify(&mut self)
+    {
+        if let EvalNode::Operation(operation_node) = self
+        {
+            operation_node.get_mut_children().into_iter().for_each(|c| c.recursive_simplify());
+            if let Some(simplified) = self.fold_constant().or_else(|| self.remove_redundant_operation())
+            {
+                *self = simplified;
+            }
+        }
+    }
+
+    /// Evaluates an operation whose operands are all explicit values.
+    /// Operations which would fail or give a non-finite number are left
+    /// for evaluation to handle.
+    fn fold_constant(&self) -> Option<EvalNode>
+    {
+        if let EvalNode::Operation(operation_node) = self
+        {
+            if operation_node.get_children().iter().all(|c| c.is_explicit())
+            {
+                return match self.recursive_eval(&Context::new())
+                {
+                    Ok(EvalResult::Number(n)) if n.is_finite() => Some(EvalNode::Operand(OperandNode::ExplicitNumber(n))),
+                    Ok(EvalResult::Boolean(b)) => Some(EvalNode::Operand(OperandNode::ExplicitBool(b))),
+                    _ => None,
+                };
+            }
+        }
+        None
+    }
+
+    /// Removes double negations and short-circuits operations with constant conditions.
+    /// An operation is only replaced by a child if the child has the same expected result,
+    /// so a tree never changes whether it can be evaluated as a number or boolean.
+    /// 
+    /// Both sides of `&&` and `||` are evaluated, so only a constant which does not decide
+    /// the result is removed. Ex: `cond && false` is kept, as `cond` could fail to evaluate.
+    fn remove_redundant_operation(&self) -> Option<EvalNode>
+    {
+        let expected = self.expected_result();
+        let keep = |n: &EvalNode| if n.expected_result() == expected { Some(n.clone()) } else { None };
+        match self
+        {
+            EvalNode::Operation(OperationNode::Negate(v1)) =>
+            match v1.as_ref()
+            {
+                EvalNode::Operation(OperationNode::Negate(v2)) => keep(v2),
+                _ => None,
+            },
+            EvalNode::Operation(OperationNode::Not(v1)) =>
+            match v1.as_ref()
+            {
+                EvalNode::Operation(OperationNode::Not(v2)) => keep(v2),
+                _ => None,
+            },
+            EvalNode::Operation(OperationNode::And(v1, v2)) =>
+            match (v1.as_explicit_bool(), v2.as_explicit_bool())
+            {
+                (Some(true), _) => keep(v2),
+                (_, Some(true)) => keep(v1),
+                _ => None,
+            },
+            EvalNode::Operation(OperationNode::Or(v1, v2)) =>
+            match (v1.as_explicit_bool(), v2.as_explicit_bool())
+            {
+                (Some(false), _) => keep(v2),
+                (_, Some(false)) => keep(v1),
+                _ => None,
+            },
+            EvalNode::Operation(OperationNode::Ternary(v1, v2, v3)) =>
+            match v1.as_explicit_bool()
+            {
+                Some(true) => keep(v2),
+                Some(false) => keep(v3),
+                None => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn is_explicit(&self) -> bool
+    {
+        matches!(self, EvalNode::Operand(OperandNode::ExplicitNumber(_) | OperandNode::ExplicitBool(_) | OperandNode::ExplicitText(_)))
+    }
+
+    fn as_explicit_bool(&self) -> Option<bool>
+    {
+        match self
+        {
+            EvalNode::Operand(OperandNode::ExplicitBool(b)) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// The tag of this node if it is a tag reference whose type is only known when evaluated
+    fn as_referenced_tag(&self) -> Option<&Tag>
+    {
+        match self
+        {
+            EvalNode::Operand(OperandNode::ReferencedTag(tag)) => Some(tag),
+            _ => None,
+        }
+    }
+
     fn expected_result(&self) -> ExpectedResult
     {
         match &self
@@ -779,7 +986,7 @@ impl EvalNode
             match operand_node {
                 OperandNode::ExplicitNumber(_) | OperandNode::ReferencedValue(_) => ExpectedResult::Number,
                 OperandNode::ExplicitBool(_) | OperandNode::ReferencedCondition(_) => ExpectedResult::Boolean,
-                OperandNode::ReferencedTag(_) | OperandNode::TagTemplate(_) => ExpectedResult::Unknown,
+                OperandNode::ExplicitText(_) | OperandNode::ReferencedTag(_) | OperandNode::TagTemplate(_) => ExpectedResult::Unknown,
             },
             EvalNode::Operation(operation_node) =>
             match operation_node {
@@ -789,6 +996,8 @@ impl EvalNode
 
                 // While a ternary could return a boolean, we just prevent that use-case as you should just use boolean operators instead.
                 OperationNode::Ternary(_, _, _) => ExpectedResult::Number,
+
+                OperationNode::Aggregate(a, _, _) => a.expected_result(),
             },
         }
     }
@@ -804,6 +1013,7 @@ impl std::fmt::Display for EvalNode
             match operand_node {
                 OperandNode::ExplicitNumber(n) => format!("{}", n),
                 OperandNode::ExplicitBool(b) => format!("{}", b),
+                OperandNode::ExplicitText(s) => format!("\"{}\"", s),
                 OperandNode::ReferencedValue(tag) | OperandNode::ReferencedCondition(tag) | OperandNode::ReferencedTag(tag) => format!("{}", tag.to_str()),
                 OperandNode::TagTemplate(template) => format!("{:?}", template)
             },
@@ -832,12 +1042,105 @@ impl std::fmt::Display for EvalNode
                 Operation::Not => "!",
                 Operation::Or => "||",
                 Operation::And => "&&",
+                Operation::Aggregate(a, m) => a.method_name(&m),
             }.to_string(),
         };
         write!(f, "{}", s)
     }
 }
 
+fn referenced_value(tag: &Tag, ctx: &Context) -> Result<EvalResult, DataError>
+{
+    if let Some(v) = ctx.get_value(tag)?
+    {
+        Ok(EvalResult::Number(v))
+    }
+    else
+    {
+        Err(EvalError::ValueNotFound(tag.clone()).into())
+    }
+}
+
+/// Finds the bytes of the source expression where the tag is first referenced.
+/// Tags are matched whole, so "ability.latin" is not found in "ability.latin.exp".
+pub fn find_tag_span(source: &str, t: &Tag) -> Option<Span>
+{
+    tokenize::tokenize_expression_spanned(source).ok()?
+        .into_iter()
+        .find(|(token, _)| token == &Token::Tag(t.clone()))
+        .map(|(_, span)| span)
+}
+
+/// Rewrites the tags of an equation's source with `f`, which is given the name of each tag as written
+/// and returns the name to write instead, keeping the rest of the source as written.
+/// Tags which `f` does not rewrite (and sources which can not be tokenized) are left unchanged.
+pub fn replace_tags<F: Fn(&str) -> Option<String>>(source: &str, f: F) -> String
+{
+    let tokens = match tokenize::tokenize_expression_spanned(source)
+    {
+        Ok(tokens) => tokens,
+        Err(_) => return source.to_string(),
+    };
+
+    let mut result = String::new();
+    let mut last = 0;
+    for (token, span) in tokens
+    {
+        if let Token::Tag(_) = token
+        {
+            if let Some(replacement) = f(&source[span.start..span.end])
+            {
+                result.push_str(&source[last..span.start]);
+                result.push_str(&replacement);
+                last = span.end;
+            }
+        }
+    }
+    result.push_str(&source[last..]);
+    result
+}
+
+fn referenced_condition(tag: &Tag, ctx: &Context) -> Result<EvalResult, DataError>
+{
+    if ctx.has_conditional(tag)
+    {
+        Ok(EvalResult::Boolean(ctx.eval_conditional(tag)?))
+    }
+    else
+    {
+        Ok(EvalResult::Boolean(ctx.has_tag(tag)))
+    }
+}
+
+/// A tag whose type was not known when parsing is read as
+/// a conditional, then a value, then a text, and finally as a state tag.
+fn referenced_tag(tag: &Tag, ctx: &Context) -> Result<EvalResult, DataError>
+{
+    if ctx.has_conditional(tag)
+    {
+        Ok(EvalResult::Boolean(ctx.eval_conditional(tag)?))
+    }
+    else if let Some(v) = ctx.get_value(tag)?
+    {
+        Ok(EvalResult::Number(v))
+    }
+    else if let Some(text) = ctx.get_text(tag)
+    {
+        Ok(text.into())
+    }
+    else
+    {
+        Ok(EvalResult::Boolean(ctx.has_tag(tag)))
+    }
+}
+
+/// Limits n to be within min and max. Unlike `f32::clamp`, this does not panic
+/// when min is greater than max (max is used) or either bound is NaN (the bound is ignored).
+fn range(n: f32, min: f32, max: f32) -> f32
+{
+    n.max(min).min(max)
+}
+
 fn number_op<F>(v1: &Box<EvalNode>, v2: &Box<EvalNode>, ctx: &Context, f: F) -> Result<EvalResult, DataError>
 where
     F: Fn(f32, f32) -> f32
@@ -847,6 +1150,88 @@ where
     Ok(EvalResult::Number(f(v1, v2)))
 }
 
+/// Evaluates an aggregation over every tag in the context which matches the prefix given by `prefix`.
+/// 
+/// Numeric aggregations (sum, max, min, avg) only consider matching tags which have a value
+/// (attributes or equations). Count considers every matching tag. Any and all evaluate each matching
+/// tag the same way a referenced condition would (a conditional is evaluated, otherwise the tag's presence is used).
+/// 
+/// Aggregations over an empty match return the identity of the aggregate (0 for numeric aggregations,
+/// false for any and true for all).
+fn aggregate_op(a: Aggregation, m: PrefixMatch, prefix: &EvalNode, ctx: &Context) -> Result<EvalResult, DataError>
+{
+    let prefix = match prefix
+    {
+        EvalNode::Operand(OperandNode::ReferencedTag(t)) | EvalNode::Operand(OperandNode::ReferencedValue(t)) | EvalNode::Operand(OperandNode::ReferencedCondition(t)) => t,
+        EvalNode::Operand(OperandNode::TagTemplate(_)) => return Err(EvalError::TemplatedEquation.into()),
+        _ => return Err(EvalError::UnsupportedOperation.into()),
+    };
+    aggregate_prefix(a, m, prefix, ctx)
+}
+
+fn aggregate_prefix(a: Aggregation, m: PrefixMatch, prefix: &Tag, ctx: &Context) -> Result<EvalResult, DataError>
+{
+    let matching = match m
+    {
+        PrefixMatch::Immediate => ctx.get_tagset().get_immediate_matching_prefix(prefix),
+        // The prefix itself is counted by the tag set, so we exclude it here
+        PrefixMatch::Deep => ctx.get_tagset().get_matching_prefix(prefix).into_iter().filter(|t| t != prefix).collect(),
+    };
+
+    match a
+    {
+        Aggregation::Count => Ok(EvalResult::Number(matching.len() as f32)),
+        Aggregation::Sum | Aggregation::Max | Aggregation::Min | Aggregation::Avg =>
+        {
+            let mut values = vec![];
+            for t in matching.iter()
+            {
+                if let Some(v) = ctx.get_value(t)?
+                {
+                    values.push(v);
+                }
+            }
+
+            if values.is_empty()
+            {
+                return Ok(EvalResult::Number(0.0));
+            }
+
+            Ok(EvalResult::Number(match a
+            {
+                Aggregation::Sum => values.iter().sum(),
+                Aggregation::Max => values.iter().cloned().fold(f32::MIN, f32::max),
+                Aggregation::Min => values.iter().cloned().fold(f32::MAX, f32::min),
+                _ => values.iter().sum::<f32>() / values.len() as f32,
+            }))
+        },
+        Aggregation::Any | Aggregation::All =>
+        {
+            for t in matching.iter()
+            {
+                let b = if ctx.has_conditional(t)
+                {
+                    ctx.eval_conditional(t)?
+                }
+                else
+                {
+                    ctx.has_tag(t)
+                };
+
+                if a == Aggregation::Any && b
+                {
+                    return Ok(EvalResult::Boolean(true));
+                }
+                else if a == Aggregation::All && !b
+                {
+                    return Ok(EvalResult::Boolean(false));
+                }
+            }
+            Ok(EvalResult::Boolean(a == Aggregation::All))
+        },
+    }
+}
+
 fn bool_op<F>(v1: &Box<EvalNode>, v2: &Box<EvalNode>, ctx: &Context, f: F) -> Result<EvalResult, DataError>
 where
     F: Fn(EvalResult, EvalResult) -> Result<bool, DataError>
@@ -856,6 +1241,28 @@ where
     Ok(EvalResult::Boolean(f(v1, v2)?))
 }
 
+/// Compares the results of both sides for equality. Results of different types are never equal.
+/// 
+/// An enum is compared against the name of a tag, rather than the tag's value,
+/// when the other side is a tag which is not itself a value, conditional, or text.
+/// This allows for checks such as "spell.form == form.ignem".
+fn equal_op(v1: &EvalNode, v2: &EvalNode, ctx: &Context) -> Result<bool, DataError>
+{
+    let r1 = v1.recursive_eval(ctx)?;
+    let r2 = v2.recursive_eval(ctx)?;
+    Ok(equal_results(r1, r2, v1.as_referenced_tag(), v2.as_referenced_tag()))
+}
+
+fn equal_results(r1: EvalResult, r2: EvalResult, t1: Option<&Tag>, t2: Option<&Tag>) -> bool
+{
+    match (r1, r2)
+    {
+        (EvalResult::Enum(selected), EvalResult::Boolean(_)) => t2.is_some_and(|t| *t == selected),
+        (EvalResult::Boolean(_), EvalResult::Enum(selected)) => t1.is_some_and(|t| *t == selected),
+        (r1, r2) => r1 == r2,
+    }
+}
+
 #[derive(Debug, Deserialize, PartialEq, Eq, Serialize, Clone, Copy)]
 enum ExpectedResult
 {
@@ -868,7 +1275,9 @@ enum ExpectedResult
 enum EvalResult
 {
     Number(f32),
-    Boolean(bool)
+    Boolean(bool),
+    Text(String),
+    Enum(Tag),
 }
 
 impl EvalResult
@@ -878,7 +1287,7 @@ impl EvalResult
         match self
         {
             EvalResult::Number(n) => Ok(n),
-            EvalResult::Boolean(_) => Err(EvalError::EvaluationMismatch),
+            EvalResult::Boolean(_) | EvalResult::Text(_) | EvalResult::Enum(_) => Err(EvalError::EvaluationMismatch),
         }
     }
 
@@ -886,8 +1295,20 @@ impl EvalResult
     {
         match self
         {
-            EvalResult::Number(_) => Err(EvalError::EvaluationMismatch),
             EvalResult::Boolean(b) => Ok(b),
+            EvalResult::Number(_) | EvalResult::Text(_) | EvalResult::Enum(_) => Err(EvalError::EvaluationMismatch),
+        }
+    }
+}
+
+impl From<&TextValue> for EvalResult
+{
+    fn from(value: &TextValue) -> Self
+    {
+        match value
+        {
+            TextValue::Text(s) => EvalResult::Text(s.clone()),
+            TextValue::Enum { selected, .. } => EvalResult::Enum(selected.clone()),
         }
     }
 }
@@ -919,21 +1340,109 @@ pub enum Operation
     Not,
     Or,
     And,
+    // Expects either depending on the aggregation
+    Aggregate(Aggregation, PrefixMatch),
 }
 
-impl Operation
+/// The method used to combine all values which match some tag prefix.
+/// See `aggregate_op` for how each aggregation is evaluated.
+#[derive(Debug, Deserialize, PartialEq, Eq, Serialize, Clone, Copy)]
+pub enum Aggregation
 {
-    fn get_number_of_operands(&self) -> usize
+    Sum,
+    Count,
+    Max,
+    Min,
+    Avg,
+    Any,
+    All,
+}
+
+/// How tags are matched against the prefix of an aggregation.
+/// 
+/// Immediate only matches tags one subtag deeper than the prefix,
+/// `sum(ability)` => `ability.Magic Theory`, `ability.Latin`
+/// 
+/// Deep matches all tags under the prefix,
+/// `deepsum(ability)` => `ability.Magic Theory`, `ability.Magic Theory.Exp`, `ability.Latin`, `ability.Latin.Exp`
+#[derive(Debug, Deserialize, PartialEq, Eq, Serialize, Clone, Copy)]
+pub enum PrefixMatch
+{
+    Immediate,
+    Deep,
+}
+
+impl Aggregation
+{
+    fn expected_result(&self) -> ExpectedResult
     {
         match self
         {
-            Operation::Add | Operation::Subtract | Operation::Multiply | Operation::Divide | Operation::PowSymbol | Operation::PowMethod => 2,
-            Operation::Negate | Operation::Sqrt => 1,
-            Operation::Round | Operation::RoundDown | Operation::RoundUp => 1,
-            Operation::Range | Operation::Ternary => 3,
-            Operation::Equal | Operation::NotEqual | Operation::LessThan | Operation::LessThanEq | Operation::GreaterThan | Operation::GreaterThanEq => 2,
+            Aggregation::Sum | Aggregation::Count | Aggregation::Max | Aggregation::Min | Aggregation::Avg => ExpectedResult::Number,
+            Aggregation::Any | Aggregation::All => ExpectedResult::Boolean,
+        }
+    }
+
+    fn method_name(&self, m: &PrefixMatch) -> &'static str
+    {
+        match (m, self)
+        {
+            (PrefixMatch::Immediate, Aggregation::Sum) => "sum",
+            (PrefixMatch::Immediate, Aggregation::Count) => "count",
+            (PrefixMatch::Immediate, Aggregation::Max) => "max",
+            (PrefixMatch::Immediate, Aggregation::Min) => "min",
+            (PrefixMatch::Immediate, Aggregation::Avg) => "avg",
+            (PrefixMatch::Immediate, Aggregation::Any) => "any",
+            (PrefixMatch::Immediate, Aggregation::All) => "all",
+            (PrefixMatch::Deep, Aggregation::Sum) => "deepsum",
+            (PrefixMatch::Deep, Aggregation::Count) => "deepcount",
+            (PrefixMatch::Deep, Aggregation::Max) => "deepmax",
+            (PrefixMatch::Deep, Aggregation::Min) => "deepmin",
+            (PrefixMatch::Deep, Aggregation::Avg) => "deepavg",
+            (PrefixMatch::Deep, Aggregation::Any) => "deepany",
+            (PrefixMatch::Deep, Aggregation::All) => "deepall",
+        }
+    }
+
+    /// Finds the aggregate operation for a method name
+    /// used in an expression. Ex: "deepsum" => (Sum, Deep)
+    pub(super) fn from_method_name(s: &str) -> Option<(Aggregation, PrefixMatch)>
+    {
+        let (m, name) = match s.strip_prefix("deep")
+        {
+            Some(name) => (PrefixMatch::Deep, name),
+            None => (PrefixMatch::Immediate, s),
+        };
+
+        let a = match name
+        {
+            "sum" => Aggregation::Sum,
+            "count" => Aggregation::Count,
+            "max" => Aggregation::Max,
+            "min" => Aggregation::Min,
+            "avg" => Aggregation::Avg,
+            "any" => Aggregation::Any,
+            "all" => Aggregation::All,
+            _ => return None,
+        };
+        Some((a, m))
+    }
+}
+
+impl Operation
+{
+    fn get_number_of_operands(&self) -> usize
+    {
+        match self
+        {
+            Operation::Add | Operation::Subtract | Operation::Multiply | Operation::Divide | Operation::PowSymbol | Operation::PowMethod => 2,
+            Operation::Negate | Operation::Sqrt => 1,
+            Operation::Round | Operation::RoundDown | Operation::RoundUp => 1,
+            Operation::Range | Operation::Ternary => 3,
+            Operation::Equal | Operation::NotEqual | Operation::LessThan | Operation::LessThanEq | Operation::GreaterThan | Operation::GreaterThanEq => 2,
             Operation::Not => 1,
             Operation::Or | Operation::And => 2,
+            Operation::Aggregate(_, _) => 1,
         }
     }
 
@@ -942,7 +1451,7 @@ impl Operation
         match self
         {
             Operation::Add | Operation::Subtract | Operation::Multiply | Operation::Divide | Operation::Negate | Operation::PowSymbol => false,
-            Operation::PowMethod | Operation::Sqrt | Operation::Round | Operation::RoundDown | Operation::RoundUp | Operation::Range => true,
+            Operation::PowMethod | Operation::Sqrt | Operation::Round | Operation::RoundDown | Operation::RoundUp | Operation::Range | Operation::Aggregate(_, _) => true,
             Operation::Ternary | Operation::Equal | Operation::NotEqual | Operation::LessThan | Operation::LessThanEq | Operation::GreaterThan | Operation::GreaterThanEq | Operation::Not | Operation::Or | Operation::And => false,
         }
     }
@@ -954,7 +1463,7 @@ impl Operation
             Operation::Add | Operation::Subtract => 1,
             Operation::Multiply | Operation::Divide => 2,
             Operation::Negate | Operation::PowSymbol | Operation::PowMethod => 3,
-            Operation::Sqrt | Operation::Round | Operation::RoundDown | Operation::RoundUp | Operation::Range => 3,
+            Operation::Sqrt | Operation::Round | Operation::RoundDown | Operation::RoundUp | Operation::Range | Operation::Aggregate(_, _) => 3,
             Operation::Ternary => 0,
             Operation::Equal | Operation::NotEqual | Operation::LessThan | Operation::LessThanEq | Operation::GreaterThan | Operation::GreaterThanEq => 2,
             Operation::Not | Operation::Or | Operation::And => 1,
@@ -1014,13 +1523,386 @@ impl Operation
                                 {
                                     None
                                 },
+            // The prefix is a tag, not a value or condition
+            Operation::Aggregate(_, _) => if input_index < 1
+                                {
+                                    Some(ExpectedResult::Unknown)
+                                }
+                                else
+                                {
+                                    None
+                                },
+        }
+    }
+}
+
+/// The compiled form of an `EvalTree`, evaluated by a small stack machine.
+/// 
+/// The tree is flattened into postfix order, so each operation finds its operands
+/// on the stack. Every tag the tree references is resolved to a slot in a table
+/// of tags, which the load instructions index into.
+/// 
+/// Evaluation gives the same result as walking the tree, including which error is returned.
+/// Ex: "rounddown(tag.a / 2) + 1"
+///     LoadValue(0)    // tag.a
+///     PushNumber(2)
+///     Divide
+///     RoundDown
+///     PushNumber(1)
+///     Add
+pub mod bytecode
+{
+    use crate::api::data::{context::Context, error::DataError, evaltree::{aggregate_prefix, equal_results, range, referenced_condition, referenced_tag, referenced_value, Aggregation, EvalError, EvalNode, EvalResult, ExpectedResult, OperandNode, OperationNode, PrefixMatch}, tag::Tag};
+
+    #[derive(Debug, PartialEq, Clone)]
+    enum Instruction
+    {
+        PushNumber(f32),
+        PushBool(bool),
+        PushText(String),
+        // Loads of the tag in the given slot, matching the operand nodes of the tree
+        LoadValue(usize),
+        LoadCondition(usize),
+        LoadTag(usize),
+        // Fails evaluation once reached. The tree only fails on templates and
+        // invalid aggregates when they are evaluated, so compiling never fails.
+        Fail(EvalError),
+        // Checks the top of the stack is a number without removing it.
+        // The tree checks the left operand of a numeric operation before evaluating
+        // the right, so this keeps errors in the same order.
+        CheckNumber,
+        Add,
+        Subtract,
+        Multiply,
+        Divide,
+        Negate,
+        Pow,
+        Sqrt,
+        Round,
+        RoundDown,
+        RoundUp,
+        Range,
+        // Slots of each side when it is an untyped tag reference, which an enum compares against by name
+        Equal(Option<usize>, Option<usize>),
+        NotEqual(Option<usize>, Option<usize>),
+        LessThan,
+        LessThanEq,
+        GreaterThan,
+        GreaterThanEq,
+        Not,
+        Or,
+        And,
+        Aggregate(Aggregation, PrefixMatch, usize),
+        // Jumps to the given instruction index
+        JumpIfFalse(usize),
+        Jump(usize),
+    }
+
+    #[derive(Debug, PartialEq, Clone)]
+    pub struct Bytecode
+    {
+        instructions: Vec<Instruction>,
+        tags: Vec<Tag>,
+        expected: ExpectedResult,
+    }
+
+    impl Bytecode
+    {
+        pub(super) fn compile(root: &EvalNode) -> Bytecode
+        {
+            let mut result = Bytecode { instructions: vec![], tags: vec![], expected: root.expected_result() };
+            result.compile_node(root);
+            result
+        }
+
+        pub fn eval_as_num(&self, ctx: &Context) -> Result<f32, DataError>
+        {
+            if self.expected == ExpectedResult::Boolean
+            {
+                return Err(EvalError::ExpectedValueMismatch.into());
+            }
+
+            if let EvalResult::Number(n) = self.run(ctx)?
+            {
+                Ok(n)
+            }
+            else
+            {
+                Err(EvalError::EvaluationMismatch.into())
+            }
+        }
+
+        pub fn eval_as_bool(&self, ctx: &Context) -> Result<bool, DataError>
+        {
+            if self.expected == ExpectedResult::Number
+            {
+                return Err(EvalError::ExpectedValueMismatch.into());
+            }
+
+            if let EvalResult::Boolean(b) = self.run(ctx)?
+            {
+                Ok(b)
+            }
+            else
+            {
+                Err(EvalError::EvaluationMismatch.into())
+            }
+        }
+
+        fn run(&self, ctx: &Context) -> Result<EvalResult, DataError>
+        {
+            let mut stack: Vec<EvalResult> = Vec::with_capacity(self.instructions.len());
+            let mut pc = 0;
+            while pc < self.instructions.len()
+            {
+                match &self.instructions[pc]
+                {
+                    Instruction::PushNumber(n) => stack.push(EvalResult::Number(*n)),
+                    Instruction::PushBool(b) => stack.push(EvalResult::Boolean(*b)),
+                    Instruction::PushText(s) => stack.push(EvalResult::Text(s.clone())),
+                    Instruction::LoadValue(slot) => stack.push(referenced_value(&self.tags[*slot], ctx)?),
+                    Instruction::LoadCondition(slot) => stack.push(referenced_condition(&self.tags[*slot], ctx)?),
+                    Instruction::LoadTag(slot) => stack.push(referenced_tag(&self.tags[*slot], ctx)?),
+                    Instruction::Fail(e) => return Err(e.clone().into()),
+                    Instruction::CheckNumber =>
+                    {
+                        if !matches!(stack.last(), Some(EvalResult::Number(_)))
+                        {
+                            return Err(EvalError::EvaluationMismatch.into());
+                        }
+                    },
+                    Instruction::Add => number_op(&mut stack, |n1, n2| n1 + n2)?,
+                    Instruction::Subtract => number_op(&mut stack, |n1, n2| n1 - n2)?,
+                    Instruction::Multiply => number_op(&mut stack, |n1, n2| n1 * n2)?,
+                    Instruction::Divide => number_op(&mut stack, |n1, n2| n1 / n2)?,
+                    Instruction::Pow => number_op(&mut stack, |n1, n2| n1.powf(n2))?,
+                    Instruction::Negate => unary_number_op(&mut stack, |n| -n)?,
+                    Instruction::Sqrt => unary_number_op(&mut stack, |n| n.sqrt())?,
+                    Instruction::Round => unary_number_op(&mut stack, |n| n.round())?,
+                    Instruction::RoundDown => unary_number_op(&mut stack, |n| n.floor())?,
+                    Instruction::RoundUp => unary_number_op(&mut stack, |n| n.ceil())?,
+                    Instruction::Range =>
+                    {
+                        let max = pop(&mut stack).as_number()?;
+                        let min = pop(&mut stack).as_number()?;
+                        let n = pop(&mut stack).as_number()?;
+                        stack.push(EvalResult::Number(range(n, min, max)));
+                    },
+                    Instruction::Equal(s1, s2) =>
+                    {
+                        let r2 = pop(&mut stack);
+                        let r1 = pop(&mut stack);
+                        stack.push(EvalResult::Boolean(equal_results(r1, r2, s1.map(|s| &self.tags[s]), s2.map(|s| &self.tags[s]))));
+                    },
+                    Instruction::NotEqual(s1, s2) =>
+                    {
+                        let r2 = pop(&mut stack);
+                        let r1 = pop(&mut stack);
+                        stack.push(EvalResult::Boolean(!equal_results(r1, r2, s1.map(|s| &self.tags[s]), s2.map(|s| &self.tags[s]))));
+                    },
+                    Instruction::LessThan => bool_op(&mut stack, |r1, r2| Ok(r1.as_number()? < r2.as_number()?))?,
+                    Instruction::LessThanEq => bool_op(&mut stack, |r1, r2| Ok(r1.as_number()? <= r2.as_number()?))?,
+                    Instruction::GreaterThan => bool_op(&mut stack, |r1, r2| Ok(r1.as_number()? > r2.as_number()?))?,
+                    Instruction::GreaterThanEq => bool_op(&mut stack, |r1, r2| Ok(r1.as_number()? >= r2.as_number()?))?,
+                    Instruction::Or => bool_op(&mut stack, |r1, r2| Ok(r1.as_bool()? || r2.as_bool()?))?,
+                    Instruction::And => bool_op(&mut stack, |r1, r2| Ok(r1.as_bool()? && r2.as_bool()?))?,
+                    Instruction::Not =>
+                    {
+                        let b = pop(&mut stack).as_bool()?;
+                        stack.push(EvalResult::Boolean(!b));
+                    },
+                    Instruction::Aggregate(a, m, slot) => stack.push(aggregate_prefix(*a, *m, &self.tags[*slot], ctx)?),
+                    Instruction::JumpIfFalse(target) =>
+                    {
+                        if !pop(&mut stack).as_bool()?
+                        {
+                            pc = *target;
+                            continue;
+                        }
+                    },
+                    Instruction::Jump(target) =>
+                    {
+                        pc = *target;
+                        continue;
+                    },
+                }
+                pc += 1;
+            }
+            Ok(pop(&mut stack))
+        }
+
+        fn compile_node(&mut self, node: &EvalNode)
+        {
+            match node
+            {
+                EvalNode::Operand(operand_node) =>
+                {
+                    let instruction = match operand_node
+                    {
+                        OperandNode::ExplicitNumber(n) => Instruction::PushNumber(*n),
+                        OperandNode::ExplicitBool(b) => Instruction::PushBool(*b),
+                        OperandNode::ExplicitText(s) => Instruction::PushText(s.clone()),
+                        OperandNode::ReferencedValue(tag) => Instruction::LoadValue(self.slot(tag)),
+                        OperandNode::ReferencedCondition(tag) => Instruction::LoadCondition(self.slot(tag)),
+                        OperandNode::ReferencedTag(tag) => Instruction::LoadTag(self.slot(tag)),
+                        OperandNode::TagTemplate(_) => Instruction::Fail(EvalError::TemplatedEquation),
+                    };
+                    self.instructions.push(instruction);
+                },
+                EvalNode::Operation(operation_node) =>
+                match operation_node
+                {
+                    OperationNode::Add(v1, v2) => self.compile_number_op(v1, v2, Instruction::Add),
+                    OperationNode::Subtract(v1, v2) => self.compile_number_op(v1, v2, Instruction::Subtract),
+                    OperationNode::Multiply(v1, v2) => self.compile_number_op(v1, v2, Instruction::Multiply),
+                    OperationNode::Divide(v1, v2) => self.compile_number_op(v1, v2, Instruction::Divide),
+                    OperationNode::Pow(v1, v2) => self.compile_number_op(v1, v2, Instruction::Pow),
+                    OperationNode::Negate(v1) => self.compile_unary_op(v1, Instruction::Negate),
+                    OperationNode::Sqrt(v1) => self.compile_unary_op(v1, Instruction::Sqrt),
+                    OperationNode::Round(v1) => self.compile_unary_op(v1, Instruction::Round),
+                    OperationNode::RoundDown(v1) => self.compile_unary_op(v1, Instruction::RoundDown),
+                    OperationNode::RoundUp(v1) => self.compile_unary_op(v1, Instruction::RoundUp),
+                    OperationNode::Not(v1) => self.compile_unary_op(v1, Instruction::Not),
+                    OperationNode::Range(v1, v2, v3) =>
+                    {
+                        self.compile_checked_number(v1);
+                        self.compile_checked_number(v2);
+                        self.compile_node(v3);
+                        self.instructions.push(Instruction::Range);
+                    },
+                    OperationNode::Equal(v1, v2) =>
+                    {
+                        let slots = (v1.as_referenced_tag().map(|t| self.slot(t)), v2.as_referenced_tag().map(|t| self.slot(t)));
+                        self.compile_binary_op(v1, v2, Instruction::Equal(slots.0, slots.1));
+                    },
+                    OperationNode::NotEqual(v1, v2) =>
+                    {
+                        let slots = (v1.as_referenced_tag().map(|t| self.slot(t)), v2.as_referenced_tag().map(|t| self.slot(t)));
+                        self.compile_binary_op(v1, v2, Instruction::NotEqual(slots.0, slots.1));
+                    },
+                    OperationNode::LessThan(v1, v2) => self.compile_binary_op(v1, v2, Instruction::LessThan),
+                    OperationNode::LessThanEq(v1, v2) => self.compile_binary_op(v1, v2, Instruction::LessThanEq),
+                    OperationNode::GreaterThan(v1, v2) => self.compile_binary_op(v1, v2, Instruction::GreaterThan),
+                    OperationNode::GreaterThanEq(v1, v2) => self.compile_binary_op(v1, v2, Instruction::GreaterThanEq),
+                    OperationNode::Or(v1, v2) => self.compile_binary_op(v1, v2, Instruction::Or),
+                    OperationNode::And(v1, v2) => self.compile_binary_op(v1, v2, Instruction::And),
+                    OperationNode::Ternary(v1, v2, v3) =>
+                    {
+                        self.compile_node(v1);
+                        let jump_to_else = self.instructions.len();
+                        self.instructions.push(Instruction::JumpIfFalse(0));
+                        self.compile_node(v2);
+                        let jump_to_end = self.instructions.len();
+                        self.instructions.push(Instruction::Jump(0));
+                        self.instructions[jump_to_else] = Instruction::JumpIfFalse(self.instructions.len());
+                        self.compile_node(v3);
+                        self.instructions[jump_to_end] = Instruction::Jump(self.instructions.len());
+                    },
+                    OperationNode::Aggregate(a, m, prefix) =>
+                    {
+                        let instruction = match prefix.as_ref()
+                        {
+                            EvalNode::Operand(OperandNode::ReferencedTag(t)) | EvalNode::Operand(OperandNode::ReferencedValue(t)) | EvalNode::Operand(OperandNode::ReferencedCondition(t)) => Instruction::Aggregate(*a, *m, self.slot(t)),
+                            EvalNode::Operand(OperandNode::TagTemplate(_)) => Instruction::Fail(EvalError::TemplatedEquation),
+                            _ => Instruction::Fail(EvalError::UnsupportedOperation),
+                        };
+                        self.instructions.push(instruction);
+                    },
+                },
+            }
         }
+
+        fn compile_number_op(&mut self, v1: &EvalNode, v2: &EvalNode, op: Instruction)
+        {
+            self.compile_checked_number(v1);
+            self.compile_node(v2);
+            self.instructions.push(op);
+        }
+
+        fn compile_binary_op(&mut self, v1: &EvalNode, v2: &EvalNode, op: Instruction)
+        {
+            self.compile_node(v1);
+            self.compile_node(v2);
+            self.instructions.push(op);
+        }
+
+        fn compile_unary_op(&mut self, v1: &EvalNode, op: Instruction)
+        {
+            self.compile_node(v1);
+            self.instructions.push(op);
+        }
+
+        /// Compiles a node which must be a number before the next operand is evaluated.
+        /// The check is skipped for nodes which can only result in a number.
+        fn compile_checked_number(&mut self, node: &EvalNode)
+        {
+            self.compile_node(node);
+            let always_number = match node
+            {
+                EvalNode::Operand(OperandNode::ExplicitNumber(_)) | EvalNode::Operand(OperandNode::ReferencedValue(_)) => true,
+                EvalNode::Operation(OperationNode::Ternary(_, _, _)) | EvalNode::Operation(OperationNode::Aggregate(_, _, _)) => false,
+                EvalNode::Operation(_) => node.expected_result() == ExpectedResult::Number,
+                EvalNode::Operand(_) => false,
+            };
+            if !always_number
+            {
+                self.instructions.push(Instruction::CheckNumber);
+            }
+        }
+
+        fn slot(&mut self, t: &Tag) -> usize
+        {
+            if let Some(i) = self.tags.iter().position(|s| s == t)
+            {
+                i
+            }
+            else
+            {
+                self.tags.push(t.clone());
+                self.tags.len() - 1
+            }
+        }
+    }
+
+    fn pop(stack: &mut Vec<EvalResult>) -> EvalResult
+    {
+        stack.pop().expect("Bytecode was compiled with an operation missing its operands")
+    }
+
+    fn number_op<F>(stack: &mut Vec<EvalResult>, f: F) -> Result<(), DataError>
+    where
+        F: Fn(f32, f32) -> f32
+    {
+        let n2 = pop(stack);
+        let n1 = pop(stack).as_number()?;
+        let n2 = n2.as_number()?;
+        stack.push(EvalResult::Number(f(n1, n2)));
+        Ok(())
+    }
+
+    fn unary_number_op<F>(stack: &mut Vec<EvalResult>, f: F) -> Result<(), DataError>
+    where
+        F: Fn(f32) -> f32
+    {
+        let n = pop(stack).as_number()?;
+        stack.push(EvalResult::Number(f(n)));
+        Ok(())
+    }
+
+    fn bool_op<F>(stack: &mut Vec<EvalResult>, f: F) -> Result<(), DataError>
+    where
+        F: Fn(EvalResult, EvalResult) -> Result<bool, DataError>
+    {
+        let r2 = pop(stack);
+        let r1 = pop(stack);
+        stack.push(EvalResult::Boolean(f(r1, r2)?));
+        Ok(())
     }
 }
 
 pub(super) mod parse
 {
-    use crate::api::data::{error::{EvalParseError, ParseError, ParseErrorType}, evaltree::{tokenize::Token, Operation}};
+    use crate::api::data::{diagnostic::Span, error::{EvalParseError, ParseError, ParseErrorType}, evaltree::{tokenize::Token, Operation}};
 
     /// Matching bracket implementation comes from StackOverflow:
     /// https://codereview.stackexchange.com/questions/253279/matching-brackets-in-rust
@@ -1045,8 +1927,19 @@ pub(super) mod parse
     pub fn brackets_are_balanced(string: &str) -> Result<(), ParseError>
     {
         let mut brackets: Vec<char> = vec![];
-        for (i, c) in string.chars().enumerate()
+        let mut in_text = false;
+        for (i, c) in string.char_indices()
         {
+            // Brackets inside of text are just part of the text
+            if c == '"'
+            {
+                in_text = !in_text;
+            }
+            if in_text
+            {
+                continue;
+            }
+
             match Bracket::from_char(c)
             {
                 Some(Bracket::Open(char_bracket)) =>
@@ -1057,7 +1950,7 @@ pub(super) mod parse
                 {
                     if brackets.pop() != Some(char_close_bracket)
                     {
-                        return Err(ParseError::new(string.to_string(), i, ParseErrorType::Evaluation(EvalParseError::MissingParentheses)));
+                        return Err(ParseError::new(string.to_string(), Span::at(string, i), ParseErrorType::Evaluation(EvalParseError::MissingParentheses)));
                     }
                 }
                 _ => {}
@@ -1070,7 +1963,7 @@ pub(super) mod parse
         }
         else
         {
-            Err(ParseError::new(string.to_string(), string.len(), ParseErrorType::Evaluation(EvalParseError::UnbalancedParentheses)))
+            Err(ParseError::new(string.to_string(), Span::at(string, string.len()), ParseErrorType::Evaluation(EvalParseError::UnbalancedParentheses)))
         }
     }
 
@@ -1120,7 +2013,7 @@ pub(super) mod parse
 
             match token
             {
-                Token::Comma | Token::Colon | Token::Number(_) | Token::Bool(_) | Token::Tag(_) | Token::TagTemplate(_) => (),
+                Token::Comma | Token::Colon | Token::Number(_) | Token::Bool(_) | Token::Text(_) | Token::Tag(_) | Token::TagTemplate(_) => (),
                 Token::OpenParen =>
                 {
                     stack.push(ParenPair::new(Some(i), prev_is_method));
@@ -1222,7 +2115,7 @@ pub mod tokenize
 {
     use serde::{Deserialize, Serialize};
 
-    use crate::api::data::{error::{EvalParseError, ParseError, ParseErrorType, TagParseError}, evaltree::Operation, tag::{Tag, TagTemplate}};
+    use crate::api::data::{diagnostic::Span, error::{EvalParseError, ParseError, ParseErrorType, TagParseError}, evaltree::{Aggregation, Operation}, tag::{Tag, TagRegistry, TagTemplate}};
 
     #[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
     pub enum Token
@@ -1237,6 +2130,7 @@ pub mod tokenize
         Operation(Operation),
         Number(f32),
         Bool(bool),
+        Text(String),
     }
 
     impl Token
@@ -1258,15 +2152,36 @@ pub mod tokenize
     /// so they can be more easily parsed into
     /// a ast.
     pub(super) fn tokenize_expression(s: &str) -> Result<Vec<Token>, ParseError>
+    {
+        Ok(tokenize_expression_spanned(s)?.into_iter().map(|(t, _)| t).collect())
+    }
+
+    /// Tokenizes the same as `tokenize_expression`, but also gives the
+    /// bytes of the input string each token was read from.
+    pub(super) fn tokenize_expression_spanned(input: &str) -> Result<Vec<(Token, Span)>, ParseError>
+    {
+        tokenize_expression_spanned_in(input, None)
+    }
+
+    /// Tokenizes the same as `tokenize_expression`, with the tags registered in the given registry
+    pub(super) fn tokenize_expression_in(s: &str, registry: &mut TagRegistry) -> Result<Vec<Token>, ParseError>
+    {
+        Ok(tokenize_expression_spanned_in(s, Some(registry))?.into_iter().map(|(t, _)| t).collect())
+    }
+
+    fn tokenize_expression_spanned_in(input: &str, mut registry: Option<&mut TagRegistry>) -> Result<Vec<(Token, Span)>, ParseError>
     {
         let mut res = vec![];
-        let s = remove_unneeded_whitespace(s);
+        let mut spans = vec![];
+        let (s, offsets) = remove_unneeded_whitespace_mapped(input);
         let chars: Vec<char> = s.chars().collect();
         let mut i = 0;
 
         while i < chars.len()
         {
             let c = chars[i];
+            let start = i;
+            let token_count = res.len();
             match c
             {
                 '(' =>
@@ -1314,20 +2229,33 @@ pub mod tokenize
                     res.push(Token::Colon);
                     i += 1;
                 },
+                '"' =>
+                {
+                    // Text continues until the closing quote
+                    if let Some(len) = chars[i + 1..].iter().position(|c| *c == '"')
+                    {
+                        res.push(Token::Text(chars[i + 1..i + 1 + len].iter().collect()));
+                        i += len + 2;
+                    }
+                    else
+                    {
+                        return Err(ParseError::new(input.to_string(), span_of(&offsets, input, i, i + 1), ParseErrorType::Evaluation(EvalParseError::TextUnterminated)));
+                    }
+                },
                 '-' =>
                 {
                     if let Some(l) = res.last()
                     {
                         let v = match l
                         {
-                            Token::Tag(_) | Token::TagTemplate(_) => Token::Operation(Operation::Subtract),
+                            Token::Tag(_) | Token::TagTemplate(_) | Token::Text(_) => Token::Operation(Operation::Subtract),
                             Token::OpenParen => Token::Operation(Operation::Negate),
                             Token::ClosedParen => Token::Operation(Operation::Subtract),
                             Token::Comma => Token::Operation(Operation::Negate),
                             Token::Colon => Token::Operation(Operation::Negate),
                             Token::Operation(_) => Token::Operation(Operation::Negate),
                             Token::Number(_) => Token::Operation(Operation::Negate),
-                            Token::Bool(_) => return Err(ParseError::new(s.to_string(), i, ParseErrorType::Evaluation(EvalParseError::OperationTypeMismatch))),
+                            Token::Bool(_) => return Err(ParseError::new(input.to_string(), span_of(&offsets, input, i, i + 1), ParseErrorType::Evaluation(EvalParseError::OperationTypeMismatch))),
                         };
                         res.push(v)
                     }
@@ -1344,7 +2272,7 @@ pub mod tokenize
                         i += 2;
                     } else {
                         // Single = is not a valid token
-                        return Err(ParseError::new(c.to_string(), i, ParseErrorType::Evaluation(EvalParseError::TokenInvalid)));
+                        return Err(ParseError::new(input.to_string(), span_of(&offsets, input, i, i + 1), ParseErrorType::Evaluation(EvalParseError::TokenInvalid)));
                     }
                 },
                 '!' =>
@@ -1384,7 +2312,7 @@ pub mod tokenize
                         i += 2;
                     } else {
                         // Single = is not a valid token
-                        return Err(ParseError::new(c.to_string(), i, ParseErrorType::Evaluation(EvalParseError::TokenInvalid)));
+                        return Err(ParseError::new(input.to_string(), span_of(&offsets, input, i, i + 1), ParseErrorType::Evaluation(EvalParseError::TokenInvalid)));
                     }
                 },
                 '&' =>
@@ -1394,7 +2322,7 @@ pub mod tokenize
                         i += 2;
                     } else {
                         // Single = is not a valid token
-                        return Err(ParseError::new(c.to_string(), i, ParseErrorType::Evaluation(EvalParseError::TokenInvalid)));
+                        return Err(ParseError::new(input.to_string(), span_of(&offsets, input, i, i + 1), ParseErrorType::Evaluation(EvalParseError::TokenInvalid)));
                     }
                 },
                 _ if c.is_digit(10) || (c == '.' && i + 1 < chars.len() && chars[i + 1].is_digit(10)) => 
@@ -1409,7 +2337,7 @@ pub mod tokenize
                         {
                             if has_dot
                             {
-                                return Err(ParseError::new(s.to_string(), j, ParseErrorType::Evaluation(EvalParseError::NumberMultipleDecimals)))
+                                return Err(ParseError::new(input.to_string(), span_of(&offsets, input, j, j + 1), ParseErrorType::Evaluation(EvalParseError::NumberMultipleDecimals)))
                             }
                             else
                             {
@@ -1427,7 +2355,7 @@ pub mod tokenize
                     }
                     else
                     {
-                        return Err(ParseError::new(s.to_string(), i, ParseErrorType::Evaluation(EvalParseError::TokenInvalid)));
+                        return Err(ParseError::new(input.to_string(), span_of(&offsets, input, i, j), ParseErrorType::Evaluation(EvalParseError::TokenInvalid)));
                     }
                     i = j;
                 },
@@ -1488,9 +2416,19 @@ pub mod tokenize
                             "rounddown" => Token::Operation(Operation::RoundDown),
                             "pow" => Token::Operation(Operation::PowMethod),
                             "sqrt" => Token::Operation(Operation::Sqrt),
+                            _ if Aggregation::from_method_name(ident_str).is_some() =>
+                            {
+                                let (a, m) = Aggregation::from_method_name(ident_str).unwrap();
+                                Token::Operation(Operation::Aggregate(a, m))
+                            },
                             _ => 
                             {
-                                match Tag::from_str(ident_str)
+                                let tag = match registry.as_deref_mut()
+                                {
+                                    Some(r) => r.get_or_register_tag(ident_str),
+                                    None => Tag::from_str(ident_str),
+                                };
+                                match tag
                                 {
                                     Ok(t) => Token::Tag(t),
                                     Err(e) => 
@@ -1502,12 +2440,12 @@ pub mod tokenize
                                         }
                                         else
                                         {
-                                            return Err(ParseError::new(s.to_string(), i, ParseErrorType::Evaluation(EvalParseError::TokenInvalid)));
+                                            return Err(ParseError::new(input.to_string(), span_of(&offsets, input, i, j), ParseErrorType::Evaluation(EvalParseError::TokenInvalid)));
                                         }
                                     }
                                     else
                                     {
-                                        return Err(ParseError::new(s.to_string(), i, ParseErrorType::Evaluation(EvalParseError::TokenInvalid)));
+                                        return Err(ParseError::new(input.to_string(), span_of(&offsets, input, i, j), ParseErrorType::Evaluation(EvalParseError::TokenInvalid)));
                                     },
                                 }
                             },
@@ -1519,9 +2457,13 @@ pub mod tokenize
                 _ =>
                 {
                     // Unknown character
-                    return Err(ParseError::new(c.to_string(), i, ParseErrorType::Evaluation(EvalParseError::TokenInvalid)));
+                    return Err(ParseError::new(input.to_string(), span_of(&offsets, input, i, i + 1), ParseErrorType::Evaluation(EvalParseError::TokenInvalid)));
                 }
             }
+            if res.len() > token_count
+            {
+                spans.push(span_of(&offsets, input, start, i));
+            }
         }
 
         // Removed error check because duplicate template inputs are A-O.K.
@@ -1546,46 +2488,73 @@ pub mod tokenize
         //     }
         // }
 
-        Ok(res)
+        Ok(res.into_iter().zip(spans).collect())
+    }
+
+    /// The bytes of the input covering the characters `start..end` of the string with whitespace removed
+    fn span_of(offsets: &[usize], input: &str, start: usize, end: usize) -> Span
+    {
+        let span_start = offsets.get(start).copied().unwrap_or(input.len());
+        let span_end = match end.checked_sub(1).and_then(|last| offsets.get(last))
+        {
+            Some(last) if end > start => Span::at(input, *last).end,
+            _ => span_start,
+        };
+        Span::new(span_start, span_end)
     }
 
     /// All whitespace that is not part of a tag
     /// should be removed to make tokenization easier
+    #[cfg(test)]
     fn remove_unneeded_whitespace(s: &str) -> String
+    {
+        remove_unneeded_whitespace_mapped(s).0
+    }
+
+    /// Removes whitespace the same as `remove_unneeded_whitespace`, also giving
+    /// the byte in `s` each character of the result came from.
+    fn remove_unneeded_whitespace_mapped(s: &str) -> (String, Vec<usize>)
     {
         let mut result = String::new();
+        let mut offsets = vec![];
         let mut previous: Option<char> = None;
+        let mut in_text = false;
 
-        for (index, c) in s.chars().enumerate() {
-            if c.is_whitespace() {
+        for (index, c) in s.char_indices() {
+            // Whitespace inside of text is kept as is
+            if c == '"' {
+                in_text = !in_text;
+            }
+
+            if in_text {
+                result.push(c);
+                offsets.push(index);
+            } else if c.is_whitespace() {
                 // Only include normal spaces in tags 
                 if c == ' ' {
                     // Only include if the previous char was an alpha or '.' and next character is a '.' or alpha
                     if let Some(prev) = previous {
-                        let mut i = index;
-                        let mut next = s.chars().nth(i);
-                        while next.is_some_and(|c| c == ' ') {
-                            i = i + 1;
-                            next = s.chars().nth(i);
-                        }
+                        let next = s[index..].chars().find(|c| *c != ' ');
 
                         if next.is_some_and(|next| (next.is_alphabetic() || next == '.') && (prev.is_alphabetic() || prev == '.')) {
                             result.push(' ');
+                            offsets.push(index);
                         }
                     }
                 }
             } else {
                 previous = Some(c);
                 result.push(c);
+                offsets.push(index);
             }
         }
-        result
+        (result, offsets)
     }
 
     #[cfg(test)]
     mod unit_tests
     {
-        use crate::api::data::{evaltree::{tokenize::{remove_unneeded_whitespace, tokenize_expression, Token}, Operation}, tag::Tag};
+        use crate::api::data::{diagnostic::Span, evaltree::{tokenize::{remove_unneeded_whitespace, tokenize_expression, tokenize_expression_spanned, Token}, Operation}, tag::Tag};
 
         #[test]
         fn whitespace_test_1()
@@ -1631,13 +2600,34 @@ pub mod tokenize
         {
             assert_eq!(tokenize_expression("Conditional.Tag == true").unwrap(), vec![Token::Tag(Tag::from_str("Conditional.Tag").unwrap()), Token::Operation(Operation::Equal), Token::Bool(true)]);
         }
+
+        #[test]
+        fn tokenize_test_5()
+        {
+            assert_eq!(tokenize_expression("Character.Name == \"Bonisagus of  House (Bonisagus)\"").unwrap(), vec![Token::Tag(Tag::from_str("Character.Name").unwrap()), Token::Operation(Operation::Equal), Token::Text("Bonisagus of  House (Bonisagus)".to_string())]);
+        }
+
+        /// Tests the spans of tokens point into the string before whitespace is removed
+        #[test]
+        fn tokenize_span_test()
+        {
+            let source = " rounddown( test . tag  >= 10) ";
+            let spans: Vec<Span> = tokenize_expression_spanned(source).unwrap().into_iter().map(|(_, span)| span).collect();
+            assert_eq!(spans, vec![Span::new(1, 10), Span::new(10, 11), Span::new(12, 22), Span::new(24, 26), Span::new(27, 29), Span::new(29, 30)]);
+            assert_eq!(&source[12..22], "test . tag");
+
+            let e = tokenize_expression_spanned("a + b & c").unwrap_err();
+            assert_eq!(e.span, Span::new(6, 7));
+        }
     }
 }
 
 #[cfg(test)]
 mod unit_tests
 {
-    use crate::api::data::{context::Context, evaltree::{EvalNode, EvalTree, OperandNode, OperationNode}, tag::Tag};
+    use proptest::prelude::*;
+
+    use crate::api::data::{conditional::Conditional, context::Context, diagnostic::Span, equation::Equation, evaltree::{find_tag_span, replace_tags, Aggregation, EvalNode, EvalTree, OperandNode, OperationNode, PrefixMatch}, tag::{NamedMapping, RemapTags, Tag, TagMapping, TagRegistry, TagRename}, text::TextValue};
 
     #[test]
     fn equation_test_1()
@@ -1771,4 +2761,383 @@ mod unit_tests
         // Should be the same string inputed
         assert_eq!(EvalTree::from_str("rounddown((sqrt(8 * Ability.Magic Theory.Exp / 5 + 1)-1)/2)").expect("AST constructor failed").to_expression_string(), "rounddown((sqrt(8 * Ability.Magic Theory.Exp / 5 + 1)-1)/2)");
     }
+
+    #[test]
+    fn aggregate_test_1()
+    {
+        let ctx = &mut Context::new();
+        ctx.set_attribute(&Tag::from_str("spell.lvl.magnitude.range").unwrap(), 2.0).unwrap();
+        ctx.set_attribute(&Tag::from_str("spell.lvl.magnitude.target").unwrap(), 1.0).unwrap();
+        ctx.set_attribute(&Tag::from_str("spell.lvl.magnitude.duration").unwrap(), 3.0).unwrap();
+        ctx.set_attribute(&Tag::from_str("spell.lvl.flat").unwrap(), 4.0).unwrap();
+
+        assert_eq!(EvalTree::from_str("sum(spell.lvl.magnitude) * 5 + spell.lvl.flat").unwrap().eval_as_num(ctx).unwrap(), 34.0);
+        assert_eq!(EvalTree::from_str("count(spell.lvl.magnitude)").unwrap().eval_as_num(ctx).unwrap(), 3.0);
+        assert_eq!(EvalTree::from_str("max(spell.lvl.magnitude)").unwrap().eval_as_num(ctx).unwrap(), 3.0);
+        assert_eq!(EvalTree::from_str("min(spell.lvl.magnitude)").unwrap().eval_as_num(ctx).unwrap(), 1.0);
+        assert_eq!(EvalTree::from_str("avg(spell.lvl.magnitude)").unwrap().eval_as_num(ctx).unwrap(), 2.0);
+        assert_eq!(EvalTree::from_str("sum(spell.lvl)").unwrap().eval_as_num(ctx).unwrap(), 4.0);
+        assert_eq!(EvalTree::from_str("deepsum(spell.lvl)").unwrap().eval_as_num(ctx).unwrap(), 10.0);
+        assert_eq!(EvalTree::from_str("sum(spell.missing)").unwrap().eval_as_num(ctx).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn aggregate_test_2()
+    {
+        let ctx = &mut Context::new();
+        ctx.add_explicit_tag(&Tag::from_str("state.magus").unwrap());
+        ctx.add_explicit_tag(&Tag::from_str("state.gifted").unwrap());
+
+        assert!(EvalTree::from_str("any(state)").unwrap().eval_as_bool(ctx).unwrap());
+        assert!(EvalTree::from_str("all(state)").unwrap().eval_as_bool(ctx).unwrap());
+        assert!(!EvalTree::from_str("any(other)").unwrap().eval_as_bool(ctx).unwrap());
+        assert!(EvalTree::from_str("count(state) == 2").unwrap().eval_as_bool(ctx).unwrap());
+    }
+
+    #[test]
+    fn aggregate_test_3()
+    {
+        let mut tree = EvalTree::from_str("sum(ability.[name].lvl)").unwrap();
+        assert!(tree.is_template());
+        tree.insert_template_input("name", &Tag::from_str("pilum of fire").unwrap());
+        assert!(!tree.is_template());
+        assert_eq!(tree.to_expression_string(), "sum(ability.pilum of fire.lvl)");
+
+        // Aggregates only accept a tag prefix
+        assert!(EvalTree::from_str("sum(5 + 3)").is_err());
+    }
+
+    #[test]
+    fn text_test_1()
+    {
+        let ctx = &mut Context::new();
+        ctx.set_text(&Tag::from_str("character.name").unwrap(), TextValue::Text("Bonisagus of House Bonisagus".to_string())).unwrap();
+        ctx.set_text(&Tag::from_str("spell.form").unwrap(), TextValue::new_enum(Tag::from_str("form").unwrap(), Tag::from_str("form.ignem").unwrap()).unwrap()).unwrap();
+        ctx.set_text(&Tag::from_str("other spell.form").unwrap(), TextValue::new_enum(Tag::from_str("form").unwrap(), Tag::from_str("form.ignem").unwrap()).unwrap()).unwrap();
+
+        assert!(EvalTree::from_str("character.name == \"Bonisagus of House Bonisagus\"").unwrap().eval_as_bool(ctx).unwrap());
+        assert!(EvalTree::from_str("character.name != \"Bonisagus\"").unwrap().eval_as_bool(ctx).unwrap());
+        assert!(EvalTree::from_str("spell.form == form.ignem").unwrap().eval_as_bool(ctx).unwrap());
+        assert!(EvalTree::from_str("spell.form != form.creo").unwrap().eval_as_bool(ctx).unwrap());
+        assert!(EvalTree::from_str("spell.form == other spell.form").unwrap().eval_as_bool(ctx).unwrap());
+
+        // Text can only be compared
+        assert!(!EvalTree::from_str("character.name == 1").unwrap().eval_as_bool(ctx).unwrap());
+        assert!(EvalTree::from_str("\"text\" + 1").unwrap().eval_as_num(ctx).is_err());
+    }
+
+    #[test]
+    fn text_test_2()
+    {
+        assert_eq!(EvalTree::from_str("name == \"( a  b )\"").unwrap().to_expression_string(), "name == \"( a  b )\"");
+        assert!(EvalTree::from_str("name == \"unterminated").is_err());
+    }
+
+    /// Tests every tag of a tree is remapped, including the prefixes of aggregates
+    #[test]
+    fn remap_test()
+    {
+        let mut source = TagRegistry::new();
+        (0..64).for_each(|i| { source.get_or_register_subtag(&format!("subtag {}", i)).unwrap(); });
+        let remap = TagRegistry::new_with_reserved(&["unused"]).merge(&source);
+
+        let mut tree = EvalTree::from_str("ability.latin + sum(spell.lvl) > 3 && !character.magus").unwrap();
+        let ctx = &Context::new();
+        let dependencies = tree.get_dependencies(ctx);
+        let prefixes = tree.get_prefix_dependencies();
+        tree.remap_tags(&remap);
+        assert_eq!(tree.get_dependencies(ctx), dependencies.iter().map(|t| remap.remap_tag(t)).collect::<Vec<_>>());
+        assert_eq!(tree.get_prefix_dependencies(), prefixes.iter().map(|t| remap.remap_tag(t)).collect::<Vec<_>>());
+        assert_ne!(tree.get_prefix_dependencies(), prefixes);
+    }
+
+    /// Tests the literal subtags of a template are renamed by name, as they are not a tag until the template is filled
+    #[test]
+    fn remap_template_test()
+    {
+        let mut registry = TagRegistry::new();
+        let mut tree = EvalTree::from_str_with_registry("ability.magic theory.[bonus] + ability.magic theory", &mut registry).unwrap();
+        let rename = TagRename::new(registry.get_or_register_tag("ability.magic theory").unwrap(), registry.get_or_register_tag("ability.magic theory.score").unwrap());
+        tree.remap_tags(&NamedMapping::new(&rename, &registry));
+        assert_eq!(tree, EvalTree::from_str_with_registry("ability.magic theory.score.[bonus] + ability.magic theory.score", &mut registry).unwrap());
+
+        // Without names, the literal subtags are kept as written
+        let mut tree = EvalTree::from_str_with_registry("ability.magic theory.[bonus]", &mut registry).unwrap();
+        tree.remap_tags(&rename);
+        assert_eq!(tree, EvalTree::from_str_with_registry("ability.magic theory.[bonus]", &mut registry).unwrap());
+    }
+
+    #[test]
+    fn replace_tags_test()
+    {
+        let rename = |name: &str| name.strip_prefix("ability.magic theory").map(|rest| format!("ability.magic theory.score{}", rest));
+        assert_eq!(replace_tags("rounddown(sqrt(8*ability.magic theory.exp/5+1)-1)/2", rename), "rounddown(sqrt(8*ability.magic theory.score.exp/5+1)-1)/2");
+        assert_eq!(replace_tags("ability.magic theory + ability.latin", rename), "ability.magic theory.score + ability.latin");
+        assert_eq!(replace_tags("sum( ability.magic theory ) > 3", rename), "sum( ability.magic theory.score ) > 3");
+        // Sources which fail to tokenize are kept as written
+        assert_eq!(replace_tags("ability.magic theory & 3", rename), "ability.magic theory & 3");
+    }
+
+    #[test]
+    fn simplify_test_1()
+    {
+        let mut tree = EvalTree::from_str("(3 + 4) * tag").unwrap();
+        tree.simplify();
+        assert_eq!(tree.to_expression_string(), "7 * tag");
+
+        let mut tree = EvalTree::from_str("tag - -(3)").unwrap();
+        tree.simplify();
+        assert_eq!(tree.to_expression_string(), "tag - -3");
+        let mut reparsed = EvalTree::from_str(&tree.to_expression_string()).unwrap();
+        reparsed.simplify();
+        assert_eq!(reparsed, tree);
+
+        let mut tree = EvalTree::from_str("-(-(tag)) + rounddown(sqrt(16) / 3)").unwrap();
+        tree.simplify();
+        assert_eq!(tree.to_expression_string(), "tag + 1");
+
+        let mut tree = EvalTree::from_str("!(!(cond)) && 1 < 2").unwrap();
+        tree.simplify();
+        assert_eq!(tree.to_expression_string(), "cond");
+
+        // Division by zero is left for evaluation
+        let mut tree = EvalTree::from_str("1 / 0").unwrap();
+        tree.simplify();
+        assert_eq!(tree.to_expression_string(), "1 / 0");
+    }
+
+    #[test]
+    fn simplify_test_2()
+    {
+        let mut tree = EvalTree::from_str("cond || 2 > 3").unwrap();
+        tree.simplify();
+        assert_eq!(tree.to_expression_string(), "cond");
+
+        // The other side still decides whether evaluation fails, so the constant result is not folded
+        let mut tree = EvalTree::from_str("value > 1 && false").unwrap();
+        tree.simplify();
+        assert_eq!(tree.to_expression_string(), "value > 1 && false");
+        assert!(tree.eval_as_bool(&Context::new()).is_err());
+
+        let mut tree = EvalTree::from_str("false || cond").unwrap();
+        tree.simplify();
+        assert_eq!(tree.to_expression_string(), "cond");
+
+        let mut tree = EvalTree::from_str("cond || true").unwrap();
+        tree.simplify();
+        assert_eq!(tree.to_expression_string(), "cond || true");
+
+        let mut tree = EvalTree::from_str("1 == 1 ? tag * 2 : other").unwrap();
+        tree.simplify();
+        assert_eq!(tree.to_expression_string(), "tag * 2");
+
+        let mut tree = EvalTree::from_str("\"a\" != \"b\" && cond").unwrap();
+        tree.simplify();
+        assert_eq!(tree.to_expression_string(), "cond");
+
+        // An untyped tag is not moved into a typed position, so the tree keeps its expected result
+        let mut tree = EvalTree::from_str("true && (tag == 1)").unwrap();
+        tree.simplify();
+        assert_eq!(tree.to_expression_string(), "tag == 1");
+        assert!(!tree.can_eval_as_number());
+    }
+
+    #[test]
+    fn simplify_test_3()
+    {
+        let ctx = &mut Context::new();
+        ctx.set_attribute(&Tag::from_str("magic.bonus").unwrap(), 3.0).unwrap();
+
+        let mut tree = EvalTree::from_str("(2 * 5 + magic.[input]) * (1 + 1)").unwrap();
+        tree.simplify();
+        assert!(matches!(&tree.root, EvalNode::Operation(OperationNode::Multiply(lhs, rhs))
+            if matches!(lhs.as_ref(), EvalNode::Operation(OperationNode::Add(n, _)) if **n == EvalNode::Operand(OperandNode::ExplicitNumber(10.0)))
+            && **rhs == EvalNode::Operand(OperandNode::ExplicitNumber(2.0))));
+        tree.insert_template_input("input", &Tag::from_str("bonus").unwrap());
+        assert!(!tree.is_template());
+        assert_eq!(tree.eval_as_num(ctx).unwrap(), 26.0);
+
+        // An equation keeps the tree of its string, only evaluating the simplified tree
+        let equation = Equation::new(Tag::from_str("magic.total").unwrap(), "(3 + 4) * magic.bonus").unwrap();
+        assert_eq!(equation.get_equation_string(), "(3 + 4) * magic.bonus");
+        assert_eq!(equation.eval(ctx).unwrap(), 21.0);
+    }
+
+    #[test]
+    fn bytecode_test_1()
+    {
+        let ctx = &mut Context::new();
+        ctx.set_attribute(&Tag::from_str("Ability.Magic Theory.Exp").unwrap(), 15.0).unwrap();
+        let tree = EvalTree::from_str("rounddown((sqrt(8 * Ability.Magic Theory.Exp / 5 + 1)-1)/2)").unwrap();
+        assert_eq!(tree.compile().eval_as_num(ctx).unwrap(), 2.0);
+
+        let tree = EvalTree::from_str("Ability.Magic Theory.Exp > 10 ? 1 : 2").unwrap();
+        assert_eq!(tree.compile().eval_as_num(ctx).unwrap(), 1.0);
+        assert!(tree.compile().eval_as_bool(ctx).is_err());
+
+        let tree = EvalTree::from_str("missing + 1").unwrap();
+        assert_eq!(tree.compile().eval_as_num(ctx), tree.eval_as_num(ctx));
+        assert!(tree.compile().eval_as_num(ctx).is_err());
+    }
+
+    #[test]
+    fn diagnostic_test_1()
+    {
+        let e = EvalTree::from_str("(a + b").unwrap_err();
+        assert_eq!(e.to_diagnostic("(a + b", &TagRegistry::new()).render(), "error: unclosed parentheses\n | (a + b\n |       ^\n");
+
+        let source = "rounddown(ability.latin  $ 2)";
+        let d = EvalTree::from_str(source).unwrap_err().to_diagnostic(source, &TagRegistry::new());
+        assert_eq!(d.message, "unrecognized token");
+        assert_eq!(d.span, Some(Span::new(25, 26)));
+
+        // Tags are found whole, after whitespace is removed
+        let source = "ability.latin.exp + ability . latin";
+        assert_eq!(find_tag_span(source, &Tag::from_str("ability.latin").unwrap()), Some(Span::new(20, 35)));
+        assert_eq!(find_tag_span(source, &Tag::from_str("ability").unwrap()), None);
+    }
+
+    /// A context with every kind of data the generated trees may reference
+    fn bytecode_test_context() -> Context
+    {
+        let mut ctx = Context::new();
+        ctx.set_attribute(&Tag::from_str("a").unwrap(), 3.0).unwrap();
+        ctx.set_attribute(&Tag::from_str("b").unwrap(), -1.5).unwrap();
+        ctx.set_attribute(&Tag::from_str("ability.latin").unwrap(), 4.0).unwrap();
+        ctx.set_attribute(&Tag::from_str("ability.latin.exp").unwrap(), 50.0).unwrap();
+        ctx.set_attribute(&Tag::from_str("ability.magic theory").unwrap(), 2.0).unwrap();
+        ctx.set_equation(Equation::new(Tag::from_str("eq").unwrap(), "a * 2 + b").unwrap()).unwrap();
+        ctx.set_conditional(Conditional::new(Tag::from_str("cond").unwrap(), "a > b").unwrap()).unwrap();
+        ctx.set_text(&Tag::from_str("name").unwrap(), TextValue::Text("Bonisagus".to_string())).unwrap();
+        ctx.set_text(&Tag::from_str("spell.form").unwrap(), TextValue::new_enum(Tag::from_str("form").unwrap(), Tag::from_str("form.ignem").unwrap()).unwrap()).unwrap();
+        ctx.add_explicit_tag(&Tag::from_str("state").unwrap());
+        ctx
+    }
+
+    fn arb_tag() -> impl Strategy<Value = Tag>
+    {
+        prop::sample::select(vec!["a", "b", "eq", "cond", "name", "spell.form", "form.ignem", "state", "ability", "ability.latin", "missing"])
+            .prop_map(|t| Tag::from_str(t).unwrap())
+    }
+
+    fn arb_number() -> impl Strategy<Value = EvalNode>
+    {
+        let leaf = prop_oneof![
+            (-8i32..8).prop_map(|n| EvalNode::Operand(OperandNode::ExplicitNumber(n as f32 / 2.0))),
+            prop::sample::select(vec!["a", "b", "eq", "ability.latin"]).prop_map(|t| EvalNode::Operand(OperandNode::ReferencedValue(Tag::from_str(t).unwrap()))),
+            (prop::sample::select(vec![Aggregation::Sum, Aggregation::Count, Aggregation::Max, Aggregation::Min, Aggregation::Avg]), prop::sample::select(vec![PrefixMatch::Immediate, PrefixMatch::Deep]))
+                .prop_map(|(a, m)| EvalNode::Operation(OperationNode::Aggregate(a, m, Box::new(EvalNode::Operand(OperandNode::ReferencedTag(Tag::from_str("ability").unwrap())))))),
+        ];
+
+        leaf.prop_recursive(5, 48, 3, |inner|
+        {
+            let b = |n: EvalNode| Box::new(n);
+            prop_oneof![
+                (inner.clone(), inner.clone()).prop_map(move |(l, r)| EvalNode::Operation(OperationNode::Add(b(l), b(r)))),
+                (inner.clone(), inner.clone()).prop_map(move |(l, r)| EvalNode::Operation(OperationNode::Subtract(b(l), b(r)))),
+                (inner.clone(), inner.clone()).prop_map(move |(l, r)| EvalNode::Operation(OperationNode::Multiply(b(l), b(r)))),
+                (inner.clone(), inner.clone()).prop_map(move |(l, r)| EvalNode::Operation(OperationNode::Divide(b(l), b(r)))),
+                (inner.clone(), inner.clone()).prop_map(move |(l, r)| EvalNode::Operation(OperationNode::Pow(b(l), b(r)))),
+                inner.clone().prop_map(move |n| EvalNode::Operation(OperationNode::Negate(b(n)))),
+                inner.clone().prop_map(move |n| EvalNode::Operation(OperationNode::Sqrt(b(n)))),
+                inner.clone().prop_map(move |n| EvalNode::Operation(OperationNode::Round(b(n)))),
+                inner.clone().prop_map(move |n| EvalNode::Operation(OperationNode::RoundDown(b(n)))),
+                inner.clone().prop_map(move |n| EvalNode::Operation(OperationNode::RoundUp(b(n)))),
+                (inner.clone(), inner.clone(), inner.clone()).prop_map(move |(n, l, h)| EvalNode::Operation(OperationNode::Range(b(n), b(l), b(h)))),
+                (inner.clone(), inner.clone(), inner.clone(), inner.clone()).prop_map(move |(l, r, t, f)| EvalNode::Operation(OperationNode::Ternary(b(EvalNode::Operation(OperationNode::LessThan(b(l), b(r)))), b(t), b(f)))),
+            ]
+        })
+    }
+
+    fn arb_bool() -> impl Strategy<Value = EvalNode>
+    {
+        let leaf = prop_oneof![
+            any::<bool>().prop_map(|b| EvalNode::Operand(OperandNode::ExplicitBool(b))),
+            prop::sample::select(vec!["cond", "state", "missing"]).prop_map(|t| EvalNode::Operand(OperandNode::ReferencedCondition(Tag::from_str(t).unwrap()))),
+            (arb_number(), arb_number()).prop_map(|(l, r)| EvalNode::Operation(OperationNode::LessThanEq(Box::new(l), Box::new(r)))),
+            (arb_number(), arb_number()).prop_map(|(l, r)| EvalNode::Operation(OperationNode::GreaterThan(Box::new(l), Box::new(r)))),
+            (arb_number(), arb_number()).prop_map(|(l, r)| EvalNode::Operation(OperationNode::Equal(Box::new(l), Box::new(r)))),
+            (arb_tag(), arb_tag()).prop_map(|(l, r)| EvalNode::Operation(OperationNode::Equal(Box::new(EvalNode::Operand(OperandNode::ReferencedTag(l))), Box::new(EvalNode::Operand(OperandNode::ReferencedTag(r)))))),
+        ];
+
+        leaf.prop_recursive(4, 32, 2, |inner|
+        {
+            let b = |n: EvalNode| Box::new(n);
+            prop_oneof![
+                inner.clone().prop_map(move |n| EvalNode::Operation(OperationNode::Not(b(n)))),
+                (inner.clone(), inner.clone()).prop_map(move |(l, r)| EvalNode::Operation(OperationNode::Or(b(l), b(r)))),
+                (inner.clone(), inner.clone()).prop_map(move |(l, r)| EvalNode::Operation(OperationNode::And(b(l), b(r)))),
+                (inner.clone(), inner.clone()).prop_map(move |(l, r)| EvalNode::Operation(OperationNode::NotEqual(b(l), b(r)))),
+            ]
+        })
+    }
+
+    /// Trees with mismatched types and missing values, so errors are compared as well
+    fn arb_untyped() -> impl Strategy<Value = EvalNode>
+    {
+        let leaf = prop_oneof![
+            (-8i32..8).prop_map(|n| EvalNode::Operand(OperandNode::ExplicitNumber(n as f32 / 2.0))),
+            any::<bool>().prop_map(|b| EvalNode::Operand(OperandNode::ExplicitBool(b))),
+            prop::sample::select(vec!["Bonisagus", "Tremere"]).prop_map(|s| EvalNode::Operand(OperandNode::ExplicitText(s.to_string()))),
+            arb_tag().prop_map(|t| EvalNode::Operand(OperandNode::ReferencedValue(t))),
+            arb_tag().prop_map(|t| EvalNode::Operand(OperandNode::ReferencedCondition(t))),
+            arb_tag().prop_map(|t| EvalNode::Operand(OperandNode::ReferencedTag(t))),
+        ];
+
+        leaf.prop_recursive(4, 32, 3, |inner|
+        {
+            let b = |n: EvalNode| Box::new(n);
+            prop_oneof![
+                (inner.clone(), inner.clone()).prop_map(move |(l, r)| EvalNode::Operation(OperationNode::Add(b(l), b(r)))),
+                inner.clone().prop_map(move |n| EvalNode::Operation(OperationNode::Negate(b(n)))),
+                (inner.clone(), inner.clone(), inner.clone()).prop_map(move |(n, l, h)| EvalNode::Operation(OperationNode::Range(b(n), b(l), b(h)))),
+                (inner.clone(), inner.clone(), inner.clone()).prop_map(move |(c, t, f)| EvalNode::Operation(OperationNode::Ternary(b(c), b(t), b(f)))),
+                (inner.clone(), inner.clone()).prop_map(move |(l, r)| EvalNode::Operation(OperationNode::Equal(b(l), b(r)))),
+                (inner.clone(), inner.clone()).prop_map(move |(l, r)| EvalNode::Operation(OperationNode::LessThan(b(l), b(r)))),
+                inner.clone().prop_map(move |n| EvalNode::Operation(OperationNode::Not(b(n)))),
+                (inner.clone(), inner.clone()).prop_map(move |(l, r)| EvalNode::Operation(OperationNode::And(b(l), b(r)))),
+                (prop::sample::select(vec![Aggregation::Sum, Aggregation::Any]), arb_tag()).prop_map(move |(a, t)| EvalNode::Operation(OperationNode::Aggregate(a, PrefixMatch::Deep, b(EvalNode::Operand(OperandNode::ReferencedTag(t)))))),
+            ]
+        })
+    }
+
+    fn arb_node() -> impl Strategy<Value = EvalNode>
+    {
+        prop_oneof![arb_number(), arb_bool(), arb_untyped()]
+    }
+
+    proptest!
+    {
+        /// The tree and its compiled bytecode give the same result, or the same error, for any tree.
+        /// Numbers are compared by their bits so NaN results are also checked.
+        #[test]
+        fn bytecode_matches_tree(root in arb_node())
+        {
+            let ctx = bytecode_test_context();
+            let tree = EvalTree { root };
+            let bytecode = tree.compile();
+            prop_assert_eq!(tree.eval_as_num(&ctx).map(f32::to_bits), bytecode.eval_as_num(&ctx).map(f32::to_bits));
+            prop_assert_eq!(tree.eval_as_bool(&ctx), bytecode.eval_as_bool(&ctx));
+        }
+
+        /// Simplifying a tree never changes the result of a successful evaluation, nor whether evaluation fails
+        #[test]
+        fn simplify_matches_tree(root in arb_node())
+        {
+            let ctx = bytecode_test_context();
+            let tree = EvalTree { root };
+            let mut simplified = tree.clone();
+            simplified.simplify();
+            match tree.eval_as_num(&ctx)
+            {
+                Ok(n) => prop_assert_eq!(Ok(n.to_bits()), simplified.eval_as_num(&ctx).map(f32::to_bits)),
+                Err(_) => prop_assert!(simplified.eval_as_num(&ctx).is_err()),
+            }
+            match tree.eval_as_bool(&ctx)
+            {
+                Ok(b) => prop_assert_eq!(Ok(b), simplified.eval_as_bool(&ctx)),
+                Err(_) => prop_assert!(simplified.eval_as_bool(&ctx).is_err()),
+            }
+        }
+    }
 }
@@ -0,0 +1,206 @@
+use serde::{Deserialize, Serialize};
+
+use crate::api::data::{changeset::ContextChangeset, context::Context, effect::Effect, error::DataError};
+
+/// A point in a journal which the context can be rolled back to. See `ContextJournal::checkpoint`
+pub type Checkpoint = usize;
+
+/// Records the inverse of each effect applied to a context, so the context can be
+/// rolled back to any earlier checkpoint without rebuilding it from scratch.
+///
+/// The journal only knows about the changes recorded in it. If the context is changed
+/// some other way, rolling back past that change can fail or give an unexpected result.
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize, Clone)]
+pub struct ContextJournal
+{
+    inverses: Vec<ContextChangeset>,    // The inverse of each recorded change, oldest first
+}
+
+impl ContextJournal
+{
+    pub fn new() -> ContextJournal
+    {
+        ContextJournal { inverses: vec![] }
+    }
+
+    /// Applies an effect to the context and records its inverse.
+    /// Nothing is recorded if the effect fails, as the context is left unchanged.
+    pub fn apply_effect(&mut self, ctx: &mut Context, e: &Effect) -> Result<(), DataError>
+    {
+        let inverse = ctx.apply_effect(e)?;
+        self.record(inverse);
+        Ok(())
+    }
+
+    /// Records the inverse of a change made to the context outside of the journal,
+    /// such as the result of `Context::apply_effect`
+    pub fn record(&mut self, inverse: ContextChangeset)
+    {
+        self.inverses.push(inverse);
+    }
+
+    /// The current point of the journal, which can be rolled back to after more changes are recorded
+    pub fn checkpoint(&self) -> Checkpoint
+    {
+        self.inverses.len()
+    }
+
+    /// Undoes the last recorded change. Returns false if there was nothing to undo.
+    ///
+    /// If the change can not be undone, it stays recorded and the context is left unchanged.
+    pub fn undo(&mut self, ctx: &mut Context) -> Result<bool, DataError>
+    {
+        match self.inverses.last()
+        {
+            Some(inverse) =>
+            {
+                ctx.apply_changeset(inverse)?;
+                self.inverses.pop();
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    /// Undoes every change recorded after the checkpoint, newest first.
+    ///
+    /// If a change can not be undone, rolling back stops there, so the context is
+    /// left at the point of the journal where it failed.
+    pub fn rollback(&mut self, ctx: &mut Context, checkpoint: Checkpoint) -> Result<(), DataError>
+    {
+        while self.inverses.len() > checkpoint
+        {
+            self.undo(ctx)?;
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.inverses.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.inverses.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod unit_tests
+{
+    use proptest::prelude::*;
+
+    use crate::api::data::{conditional::Conditional, context::Context, effect::Effect, equation::Equation, journal::ContextJournal, modifier::{Modifier, ModifierChange, ModifierTarget}, tag::Tag, text::TextValue};
+
+    fn tag(s: &str) -> Tag
+    {
+        Tag::from_str(s).unwrap()
+    }
+
+    fn journal_test_context() -> Context
+    {
+        let mut ctx = Context::new();
+        ctx.set_attribute(&tag("ability.latin.exp"), 5.0).unwrap();
+        ctx.set_attribute(&tag("ability.greek.exp"), 0.0).unwrap();
+        ctx.set_equation(Equation::new(tag("ability.latin"), "ability.latin.exp / 5").unwrap()).unwrap();
+        ctx.set_conditional(Conditional::new(tag("character.is magus"), "character.magus").unwrap()).unwrap();
+        ctx.add_explicit_tag(&tag("character.magus"));
+        ctx
+    }
+
+    #[test]
+    fn rollback_test()
+    {
+        let original = journal_test_context();
+        let mut ctx = original.clone();
+        let mut journal = ContextJournal::new();
+
+        journal.apply_effect(&mut ctx, &Effect::AddToAttribute(tag("ability.latin.exp"), 10.0)).unwrap();
+        journal.apply_effect(&mut ctx, &Effect::RemoveStateTag(tag("character.magus"))).unwrap();
+        let checkpoint = journal.checkpoint();
+        let at_checkpoint = ctx.clone();
+
+        journal.apply_effect(&mut ctx, &Effect::SetEquation(Equation::new(tag("ability.greek"), "ability.greek.exp / 5").unwrap())).unwrap();
+        journal.apply_effect(&mut ctx, &Effect::Sequence(vec![
+            Effect::SetAttribute(tag("ability.arabic.exp"), 5.0),
+            Effect::SetText(tag("character.name"), TextValue::Text("Bonisagus".to_string())),
+        ])).unwrap();
+        // Failed effects change nothing, so nothing is recorded
+        assert!(journal.apply_effect(&mut ctx, &Effect::SetAttribute(tag("ability.latin"), 1.0)).is_err());
+        assert_eq!(journal.len(), 4);
+
+        journal.rollback(&mut ctx, checkpoint).unwrap();
+        assert_eq!(ctx, at_checkpoint);
+        assert!(journal.undo(&mut ctx).unwrap());
+        assert!(ctx.has_tag(&tag("character.magus")));
+        journal.rollback(&mut ctx, 0).unwrap();
+        assert_eq!(ctx, original);
+        assert!(!journal.undo(&mut ctx).unwrap());
+    }
+
+    fn arb_attribute() -> impl Strategy<Value = Tag>
+    {
+        prop::sample::select(vec!["ability.latin.exp", "ability.greek.exp", "ability.arabic.exp", "ability.latin"]).prop_map(tag)
+    }
+
+    fn arb_leaf_effect() -> impl Strategy<Value = Effect>
+    {
+        let state_tag = prop::sample::select(vec!["character.magus", "spell.range.voice", "spell.range.touch"]).prop_map(tag);
+        prop_oneof![
+            state_tag.clone().prop_map(Effect::AddStateTag),
+            state_tag.prop_map(Effect::RemoveStateTag),
+            (arb_attribute(), -5i32..20).prop_map(|(t, v)| Effect::SetAttribute(t, v as f32)),
+            (arb_attribute(), -5i32..20).prop_map(|(t, v)| Effect::AddToAttribute(t, v as f32)),
+            (arb_attribute(), 0i32..4).prop_map(|(t, v)| Effect::MultiplyAttribute(t, v as f32 / 2.0)),
+            (arb_attribute(), arb_attribute()).prop_map(|(t, v)| Effect::SetAttributeFromValue(t, v)),
+            (arb_attribute(), prop::sample::select(vec!["ability.latin.exp / 5", "ability.greek.exp + 1", "3"]))
+                .prop_map(|(t, e)| Effect::SetEquation(Equation::new(t, e).unwrap())),
+            (arb_attribute(), -3i32..3).prop_map(|(t, v)| Effect::SetModifier(Modifier::new(tag("virtue.puissant"), ModifierTarget::Single(t), tag("character.is magus"), ModifierChange::BasicValue(v as f32)))),
+            prop::sample::select(vec!["Bonisagus", "Tytalus"]).prop_map(|s| Effect::SetText(tag("character.house"), TextValue::Text(s.to_string()))),
+        ]
+    }
+
+    fn arb_effect() -> impl Strategy<Value = Effect>
+    {
+        prop_oneof![
+            4 => arb_leaf_effect(),
+            1 => prop::collection::vec(arb_leaf_effect(), 0..4).prop_map(Effect::Sequence),
+            1 => arb_leaf_effect().prop_map(|e| Effect::ApplyIf(tag("character.is magus"), Box::new(e))),
+        ]
+    }
+
+    proptest!
+    {
+        /// Applying any effects then rolling back returns the context to how it was at each checkpoint
+        #[test]
+        fn rollback_restores_context(effects in prop::collection::vec(arb_effect(), 0..20), split in 0usize..20)
+        {
+            let original = journal_test_context();
+            let mut ctx = original.clone();
+            let mut journal = ContextJournal::new();
+            let mut checkpoint = None;
+            for (i, e) in effects.iter().enumerate()
+            {
+                if i == split
+                {
+                    checkpoint = Some((journal.checkpoint(), ctx.clone()));
+                }
+                // Effects which fail (such as setting an attribute with an equation's name) leave the context unchanged
+                let before = ctx.clone();
+                if journal.apply_effect(&mut ctx, e).is_err()
+                {
+                    prop_assert_eq!(&ctx, &before);
+                }
+            }
+
+            if let Some((checkpoint, at_checkpoint)) = checkpoint
+            {
+                journal.rollback(&mut ctx, checkpoint).unwrap();
+                prop_assert_eq!(&ctx, &at_checkpoint);
+            }
+            journal.rollback(&mut ctx, 0).unwrap();
+            prop_assert_eq!(ctx, original);
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use serde::{Deserialize, Serialize};
+
+use crate::api::data::{context::{Context, LayerHandle}, error::DataError, tag::Tag};
+
+/// A stack of contexts, such as ruleset -> setting -> game -> character -> location,
+/// where each layer is kept separate so it can be removed again later.
+///
+/// Lookups fall through the layers from top to bottom, so the value of a tag comes from the
+/// highest layer which has it, whatever kind of value the layers below have it as.
+/// Modifiers and state tags of every layer apply together. See `Context::from_layers`
+///
+/// Equations of one layer read the values of the others, so the layers are resolved into a
+/// single context for evaluation whenever the stack changes.
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+pub struct LayeredContext
+{
+    layers: Vec<ContextLayer>,      // Ordered from the bottom layer to the top layer
+    next_handle: LayerHandle,
+    combined: Context,              // Every layer resolved for evaluation
+}
+
+impl Default for LayeredContext
+{
+    fn default() -> Self
+    {
+        LayeredContext::new()
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+struct ContextLayer
+{
+    handle: LayerHandle,
+    ctx: Context,
+}
+
+impl LayeredContext
+{
+    pub fn new() -> LayeredContext
+    {
+        LayeredContext { layers: vec![], next_handle: 0, combined: Context::new() }
+    }
+
+    /// Pushes a context as the new top layer, returning the handle used to remove it.
+    ///
+    /// Fails if the layer would cause a cycle of evaluation with the layers below it,
+    /// in which case the layer is not pushed.
+    pub fn push_layer(&mut self, ctx: Context) -> Result<LayerHandle, DataError>
+    {
+        let handle = self.next_handle;
+        self.layers.push(ContextLayer { handle, ctx });
+        if let Err(e) = self.recombine()
+        {
+            self.layers.pop();
+            return Err(e);
+        }
+        self.next_handle += 1;
+        Ok(handle)
+    }
+
+    /// Removes the layer of the handle from anywhere in the stack, returning its context.
+    /// Returns None if no layer has the handle.
+    ///
+    /// Removing a layer can reveal values it was overriding. If those would cause a cycle
+    /// of evaluation, the layer is not removed and the error is returned.
+    pub fn pop_layer(&mut self, handle: LayerHandle) -> Result<Option<Context>, DataError>
+    {
+        let index = match self.layers.iter().position(|l| l.handle == handle)
+        {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+        let layer = self.layers.remove(index);
+        if let Err(e) = self.recombine()
+        {
+            self.layers.insert(index, layer);
+            return Err(e);
+        }
+        Ok(Some(layer.ctx))
+    }
+
+    /// Replaces the context of a layer, returning the old context.
+    /// Returns None if no layer has the handle.
+    ///
+    /// If the new context would cause a cycle of evaluation, the layer is left unchanged.
+    pub fn replace_layer(&mut self, handle: LayerHandle, ctx: Context) -> Result<Option<Context>, DataError>
+    {
+        let layer = match self.layers.iter_mut().find(|l| l.handle == handle)
+        {
+            Some(layer) => layer,
+            None => return Ok(None),
+        };
+        let old = std::mem::replace(&mut layer.ctx, ctx);
+        if let Err(e) = self.recombine()
+        {
+            if let Some(layer) = self.layers.iter_mut().find(|l| l.handle == handle)
+            {
+                layer.ctx = old;
+            }
+            return Err(e);
+        }
+        Ok(Some(old))
+    }
+
+    pub fn get_layer(&self, handle: LayerHandle) -> Option<&Context>
+    {
+        self.layers.iter().find(|l| l.handle == handle).map(|l| &l.ctx)
+    }
+
+    pub fn has_layer(&self, handle: LayerHandle) -> bool
+    {
+        self.layers.iter().any(|l| l.handle == handle)
+    }
+
+    /// Iterates the layers from the bottom layer to the top layer
+    pub fn iter_layers(&self) -> impl Iterator<Item = (LayerHandle, &Context)> + '_
+    {
+        self.layers.iter().map(|l| (l.handle, &l.ctx))
+    }
+
+    pub fn count_layers(&self) -> usize
+    {
+        self.layers.len()
+    }
+
+    /// The context of every layer combined, which is what values are evaluated in
+    pub fn get_context(&self) -> &Context
+    {
+        &self.combined
+    }
+
+    pub fn get_value(&self, t: &Tag) -> Result<Option<f32>, DataError>
+    {
+        self.combined.get_value(t)
+    }
+
+    /// Finds the layer a value comes from, which is the highest layer that has the value.
+    /// For tags which are not a value in any layer (such as state tags), this is the highest
+    /// layer with the tag.
+    pub fn get_source_layer(&self, t: &Tag) -> Option<LayerHandle>
+    {
+        self.layers.iter().rev()
+            .find(|l| l.ctx.has_value(t) || l.ctx.has_conditional(t) || l.ctx.has_modifier(t) || l.ctx.has_text(t))
+            .or_else(|| self.layers.iter().rev().find(|l| l.ctx.has_tag(t)))
+            .map(|l| l.handle)
+    }
+
+    /// Resolves the layers into the context used for evaluation again. Only replaces
+    /// the combined context once every layer is resolved successfully.
+    fn recombine(&mut self) -> Result<(), DataError>
+    {
+        let mut combined = Context::from_layers(self.layers.iter().map(|l| &l.ctx))?;
+        if self.combined.is_cache_enabled()
+        {
+            combined.enable_cache();
+        }
+        self.combined = combined;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod unit_tests
+{
+    use crate::api::data::{context::Context, equation::Equation, layer::LayeredContext, tag::Tag};
+
+    fn tag(s: &str) -> Tag
+    {
+        Tag::from_str(s).unwrap()
+    }
+
+    /// The ruleset defines how an ability is calculated, which the character fills in
+    fn ruleset_and_character() -> (Context, Context)
+    {
+        let mut ruleset = Context::new();
+        ruleset.set_attribute(&tag("ability.magic theory.exp"), 0.0).unwrap();
+        ruleset.set_equation(Equation::new(tag("ability.magic theory"), "ability.magic theory.exp / 5").unwrap()).unwrap();
+
+        let mut character = Context::new();
+        character.set_attribute(&tag("ability.magic theory.exp"), 15.0).unwrap();
+        character.add_explicit_tag(&tag("character.magus"));
+        (ruleset, character)
+    }
+
+    #[test]
+    fn push_pop_test()
+    {
+        let (ruleset, character) = ruleset_and_character();
+        let mut stack = LayeredContext::new();
+        let ruleset_layer = stack.push_layer(ruleset.clone()).unwrap();
+        assert_eq!(stack.get_value(&tag("ability.magic theory")).unwrap(), Some(0.0));
+
+        let character_layer = stack.push_layer(character.clone()).unwrap();
+        assert_ne!(ruleset_layer, character_layer);
+        assert_eq!(stack.get_value(&tag("ability.magic theory")).unwrap(), Some(3.0));
+        assert_eq!(stack.get_source_layer(&tag("ability.magic theory.exp")), Some(character_layer));
+        assert_eq!(stack.get_source_layer(&tag("ability.magic theory")), Some(ruleset_layer));
+        assert_eq!(stack.get_source_layer(&tag("character.magus")), Some(character_layer));
+        assert_eq!(stack.get_source_layer(&tag("ability.latin")), None);
+
+        // A location layered on top can be removed again, restoring the values it overrode
+        let mut location = Context::new();
+        location.set_attribute(&tag("ability.magic theory.exp"), 30.0).unwrap();
+        location.add_explicit_tag(&tag("location.covenant"));
+        let location_layer = stack.push_layer(location.clone()).unwrap();
+        assert_eq!(stack.get_value(&tag("ability.magic theory")).unwrap(), Some(6.0));
+        assert!(stack.get_context().has_tag(&tag("location.covenant")));
+
+        assert_eq!(stack.pop_layer(location_layer).unwrap(), Some(location));
+        assert_eq!(stack.pop_layer(location_layer).unwrap(), None);
+        assert_eq!(stack.get_value(&tag("ability.magic theory")).unwrap(), Some(3.0));
+        assert!(!stack.get_context().has_tag(&tag("location.covenant")));
+
+        // Layers can be removed from the middle of the stack
+        assert_eq!(stack.pop_layer(ruleset_layer).unwrap(), Some(ruleset));
+        assert_eq!(stack.get_value(&tag("ability.magic theory")).unwrap(), None);
+        assert_eq!(stack.get_value(&tag("ability.magic theory.exp")).unwrap(), Some(15.0));
+        assert_eq!(stack.iter_layers().map(|(h, _)| h).collect::<Vec<_>>(), vec![character_layer]);
+    }
+
+    /// Tests a value hides the value of the same name in the layers below, even when it is another kind of value
+    #[test]
+    fn shadow_test()
+    {
+        let (ruleset, mut character) = ruleset_and_character();
+        character.set_attribute(&tag("ability.magic theory"), 7.0).unwrap();
+        let mut stack = LayeredContext::new();
+        let ruleset_layer = stack.push_layer(ruleset).unwrap();
+        let character_layer = stack.push_layer(character).unwrap();
+        assert_eq!(stack.get_value(&tag("ability.magic theory")).unwrap(), Some(7.0));
+        assert_eq!(stack.get_source_layer(&tag("ability.magic theory")), Some(character_layer));
+        assert!(!stack.get_context().has_equation(&tag("ability.magic theory")));
+
+        // Removing the attribute reveals the equation below it again
+        stack.pop_layer(character_layer).unwrap();
+        assert_eq!(stack.get_value(&tag("ability.magic theory")).unwrap(), Some(0.0));
+        assert_eq!(stack.get_source_layer(&tag("ability.magic theory")), Some(ruleset_layer));
+    }
+
+    #[test]
+    fn cycle_test()
+    {
+        let mut bottom = Context::new();
+        bottom.set_equation(Equation::new(tag("a"), "b + 1").unwrap()).unwrap();
+        let mut middle = Context::new();
+        middle.set_attribute(&tag("b"), 1.0).unwrap();
+        let mut top = Context::new();
+        top.set_equation(Equation::new(tag("c"), "a + 1").unwrap()).unwrap();
+
+        let mut stack = LayeredContext::new();
+        stack.push_layer(bottom).unwrap();
+        let middle_layer = stack.push_layer(middle).unwrap();
+        let top_layer = stack.push_layer(top).unwrap();
+        assert_eq!(stack.get_value(&tag("c")).unwrap(), Some(3.0));
+
+        // Replacing "b" with an equation of "c" makes a cycle, so the layer is kept as it was
+        let mut cyclic = Context::new();
+        cyclic.set_equation(Equation::new(tag("b"), "c + 1").unwrap()).unwrap();
+        assert!(stack.replace_layer(middle_layer, cyclic).is_err());
+        assert_eq!(stack.get_value(&tag("c")).unwrap(), Some(3.0));
+
+        let mut replacement = Context::new();
+        replacement.set_attribute(&tag("b"), 5.0).unwrap();
+        assert!(stack.replace_layer(middle_layer, replacement).unwrap().is_some());
+        assert_eq!(stack.get_value(&tag("c")).unwrap(), Some(7.0));
+        assert_eq!(stack.count_layers(), 3);
+        assert!(stack.has_layer(top_layer));
+    }
+}
@@ -1,4 +1,4 @@
-use crate::api::data::{context::Context, error::{DataError, TemplateError}, tag::{Tag, TagTemplate}, template::{Template, Templated}};
+use crate::api::data::{context::Context, error::{DataError, TemplateError}, tag::{RemapTags, Tag, TagMapping, TagTemplate}, template::{Template, Templated}};
 
 use std::collections::{HashMap, HashSet};
 
@@ -18,13 +18,35 @@ pub struct Modifier
     // This allows modifiers to be checked conditionally for each value it modifies.
     pub condition: Tag,
     pub change: ModifierChange,
+    // Modifiers are applied in stages (See ModifierStage). Within a stage,
+    // modifiers of lower priority are applied first, so overrides and caps
+    // of a higher priority have the final say on the value.
+    #[serde(default)]
+    pub priority: i32,
+    // Modifiers of the same stacking group are resolved together according to the
+    // policy of the group (See StackingPolicy). Ex: "bonus.enhancement", where only the
+    // highest enhancement bonus applies.
+    #[serde(default)]
+    pub group: Option<Tag>,
 }
 
 impl Modifier
 {
     pub fn new(name: Tag, target: ModifierTarget, condition: Tag, change: ModifierChange) -> Modifier
     {
-        Modifier { name, target, condition, change }
+        Modifier { name, target, condition, change, priority: 0, group: None }
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self
+    {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_group(mut self, group: Tag) -> Self
+    {
+        self.group = Some(group);
+        self
     }
 }
 
@@ -38,17 +60,161 @@ pub enum ModifierTarget
 
 #[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
 pub enum ModifierChange
+{
+    BasicValue(f32),                // Adds the value
+    FromOtherValue(Tag),            // Adds the other value
+    Multiply(ModifierAmount),       // Multiplies by the amount (Ex: "double this")
+    Override(ModifierAmount),       // Sets the value to the amount
+    Min(ModifierAmount),            // The value is at least the amount
+    Max(ModifierAmount),            // The value is at most the amount
+}
+
+/// The amount used by a modifier change which is not additive
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+pub enum ModifierAmount
 {
     BasicValue(f32),
     FromOtherValue(Tag),
 }
 
+/// The order in which modifier changes are applied to a value.
+/// All additive changes are applied first, then multiplicative changes,
+/// and finally overrides and caps (in order of modifier priority).
+#[derive(Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord, Serialize, Clone, Copy, Hash)]
+pub enum ModifierStage
+{
+    Additive,
+    Multiplicative,
+    Final,
+}
+
+/// How the modifiers of a stacking group combine when applied to the same value.
+/// Only modifiers of the same stage are resolved together, so a group can hold
+/// both an additive and multiplicative modifier without one replacing the other.
+/// 
+/// Groups without a declared policy sum their modifiers, same as modifiers without a group.
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone, Copy, Default)]
+pub enum StackingPolicy
+{
+    #[default]
+    Sum,                // Every modifier applies
+    Max,                // Only the modifier with the largest amount applies
+    Min,                // Only the modifier with the smallest amount applies
+    FirstByPriority,    // Only the modifier of the highest priority applies
+}
+
+impl ModifierChange
+{
+    /// The amount used by this change, which is combined with the value it modifies in `apply`
+    pub fn eval(&self, ctx: &Context) -> Result<f32, DataError>
+    {
+        match self
+        {
+            ModifierChange::BasicValue(v) => Ok(*v),
+            ModifierChange::FromOtherValue(tag) => ctx.get_value(tag)?.ok_or_else(|| DataError::value_dne(tag.clone())),
+            ModifierChange::Multiply(amount) | ModifierChange::Override(amount) | ModifierChange::Min(amount) | ModifierChange::Max(amount) => amount.eval(ctx),
+        }
+    }
+
+    /// Combines the value being modified with the amount of this change
+    pub fn apply(&self, v: f32, amount: f32) -> f32
+    {
+        match self
+        {
+            ModifierChange::BasicValue(_) | ModifierChange::FromOtherValue(_) => v + amount,
+            ModifierChange::Multiply(_) => v * amount,
+            ModifierChange::Override(_) => amount,
+            ModifierChange::Min(_) => v.max(amount),
+            ModifierChange::Max(_) => v.min(amount),
+        }
+    }
+
+    pub fn get_stage(&self) -> ModifierStage
+    {
+        match self
+        {
+            ModifierChange::BasicValue(_) | ModifierChange::FromOtherValue(_) => ModifierStage::Additive,
+            ModifierChange::Multiply(_) => ModifierStage::Multiplicative,
+            ModifierChange::Override(_) | ModifierChange::Min(_) | ModifierChange::Max(_) => ModifierStage::Final,
+        }
+    }
+
+    /// The value this change reads from, if any
+    pub fn get_other_value(&self) -> Option<&Tag>
+    {
+        match self
+        {
+            ModifierChange::BasicValue(_) => None,
+            ModifierChange::FromOtherValue(tag) => Some(tag),
+            ModifierChange::Multiply(amount) | ModifierChange::Override(amount) | ModifierChange::Min(amount) | ModifierChange::Max(amount) => amount.get_other_value(),
+        }
+    }
+}
+
+impl ModifierAmount
+{
+    pub fn eval(&self, ctx: &Context) -> Result<f32, DataError>
+    {
+        match self
+        {
+            ModifierAmount::BasicValue(v) => Ok(*v),
+            ModifierAmount::FromOtherValue(tag) => ctx.get_value(tag)?.ok_or_else(|| DataError::value_dne(tag.clone())),
+        }
+    }
+
+    pub fn get_other_value(&self) -> Option<&Tag>
+    {
+        match self
+        {
+            ModifierAmount::BasicValue(_) => None,
+            ModifierAmount::FromOtherValue(tag) => Some(tag),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+#[serde(from = "ModifierSetJson")]
 pub struct ModifierSet
 {
     all_modifiers: HashMap<Tag, Modifier>,          // Stores all the modifier data for this modifier set
     single_target_modifiers: HashMap<Tag, HashSet<Tag>>,     // Modifiers which target a single value. Input is target tag, output is the set of tags for modifiers
     conditional_modifiers: HashSet<Tag>,                // Modifiers which target based on a value's tag (See modifier target)
+    stacking_policies: HashMap<Tag, StackingPolicy>,    // Input is a stacking group, output is how the modifiers of the group combine
+}
+
+/// The JSON a modifier set is read from. Sets written before stacking groups are a list of modifiers,
+/// otherwise the modifiers are read along with the optional stacking policies of their groups.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ModifierSetJson
+{
+    Modifiers(Vec<Modifier>),
+    Set
+    {
+        all_modifiers: HashMap<Tag, Modifier>,
+        #[serde(default)]
+        stacking_policies: HashMap<Tag, StackingPolicy>,
+    },
+}
+
+impl From<ModifierSetJson> for ModifierSet
+{
+    fn from(json: ModifierSetJson) -> Self
+    {
+        let (modifiers, stacking_policies) = match json
+        {
+            ModifierSetJson::Modifiers(modifiers) => (modifiers, HashMap::new()),
+            ModifierSetJson::Set { all_modifiers, stacking_policies } => (all_modifiers.into_values().collect(), stacking_policies),
+        };
+
+        // The modifiers are set one by one to index them by their targets
+        let mut result = ModifierSet { stacking_policies, ..ModifierSet::new() };
+        for m in modifiers
+        {
+            result.set_modifier(m);
+        }
+        result
+    }
 }
 
 impl ModifierSet
@@ -60,86 +226,147 @@ impl ModifierSet
             all_modifiers: HashMap::new(),
             single_target_modifiers: HashMap::new(),
             conditional_modifiers: HashSet::new(),
+            stacking_policies: HashMap::new(),
         }
     }
 
     pub fn apply_modifiers(&self, dataset: &Context, t: &Tag, mut v: f32) -> Result<f32, DataError>
     {
-        // First apply single target modifiers that we know will affect this tag
+        let mut passed = vec![];
+        for (modifier, condition) in self.get_applicable_modifiers(t)?
+        {
+            if dataset.eval_conditional(&condition)?
+            {
+                passed.push((modifier, modifier.change.eval(dataset)?));
+            }
+        }
+
+        for ((modifier, amount), applied) in passed.iter().zip(self.resolve_stacking_groups(&passed))
+        {
+            if applied
+            {
+                v = modifier.change.apply(v, *amount);
+            }
+        }
+        Ok(v)
+    }
+
+    /// Given the modifiers whose condition passed (in order of application) along with their amounts,
+    /// decides which are applied once each stacking group is resolved by the policy of the group.
+    /// 
+    /// The result is in the same order as the given modifiers.
+    pub fn resolve_stacking_groups(&self, passed: &[(&Modifier, f32)]) -> Vec<bool>
+    {
+        // Input is a group and stage, output is the index of the modifier chosen for it
+        let mut chosen: HashMap<(&Tag, ModifierStage), usize> = HashMap::new();
+        for (i, (modifier, amount)) in passed.iter().enumerate()
+        {
+            if let Some(group) = &modifier.group
+            {
+                let key = (group, modifier.change.get_stage());
+                let replace = match (self.get_stacking_policy(group), chosen.get(&key).map(|j| passed[*j]))
+                {
+                    (StackingPolicy::Sum, _) => false,
+                    (_, None) => true,
+                    (StackingPolicy::Max, Some((_, other))) => *amount > other,
+                    (StackingPolicy::Min, Some((_, other))) => *amount < other,
+                    (StackingPolicy::FirstByPriority, Some((other, _))) => modifier.priority > other.priority,
+                };
+
+                if replace
+                {
+                    chosen.insert(key, i);
+                }
+            }
+        }
+
+        // Modifiers without a group (or in a group that sums) are never chosen, so they are always applied
+        passed.iter().enumerate().map(|(i, (modifier, _))|
+        {
+            match &modifier.group
+            {
+                Some(group) => chosen.get(&(group, modifier.change.get_stage())).is_none_or(|j| *j == i),
+                None => true,
+            }
+        }).collect()
+    }
+
+    /// The policy of a stacking group. Groups which were never declared sum their modifiers.
+    pub fn get_stacking_policy(&self, group: &Tag) -> StackingPolicy
+    {
+        self.stacking_policies.get(group).cloned().unwrap_or_default()
+    }
+
+    /// Declares how the modifiers of a stacking group combine, returning the old policy if there was one
+    pub fn set_stacking_policy(&mut self, group: Tag, policy: StackingPolicy) -> Option<StackingPolicy>
+    {
+        self.stacking_policies.insert(group, policy)
+    }
+
+    pub fn remove_stacking_policy(&mut self, group: &Tag) -> Option<StackingPolicy>
+    {
+        self.stacking_policies.remove(group)
+    }
+
+    pub fn iter_stacking_policies(&self) -> std::collections::hash_map::Iter<'_, Tag, StackingPolicy>
+    {
+        self.stacking_policies.iter()
+    }
+
+    /// Gets every modifier which could apply to the given tag, along with the
+    /// conditional tag which must be true for the modifier to be applied.
+    /// 
+    /// Single target modifiers check their condition directly, while modifiers targeting
+    /// a matching prefix / suffix check the condition as a suffix of the given tag.
+    /// 
+    /// The modifiers are given in the order they are applied: by stage, then by priority,
+    /// with the name of the modifier breaking ties so the result is stable.
+    pub fn get_applicable_modifiers(&self, t: &Tag) -> Result<Vec<(&Modifier, Tag)>, DataError>
+    {
+        let mut result = vec![];
+
+        // First the single target modifiers that we know will affect this tag
         if let Some(modifiers) = self.single_target_modifiers.get(t)
         {
             for modifier in modifiers
             {
                 if let Some(modifier) = self.all_modifiers.get(modifier)
                 {
-                    if dataset.eval_conditional(&modifier.condition)?
-                    {
-                        match &modifier.change
-                        {
-                            ModifierChange::BasicValue(add) => v += add,
-                            ModifierChange::FromOtherValue(tag) => 
-                            {
-                                if let Some(add) = dataset.get_value(tag)?
-                                {
-                                    v += add;
-                                } 
-                                else
-                                {
-                                    return Err(DataError::value_dne(tag.clone()))
-                                }
-                            },
-                        }
-                    }
+                    result.push((modifier, modifier.condition.clone()));
                 }
                 else
                 {
-                    return Err(DataError::InvalidState(format!("Expected to have modifier {} in modifier set.", modifier.clone())));
+                    return Err(DataError::InvalidState(format!("Expected to have modifier {:?} in modifier set.", modifier)));
                 }
             }
         }
 
-        // Now apply modifiers based on conditional prefix / suffix comparison and conditionals
+        // Now modifiers based on conditional prefix / suffix comparison and conditionals
         for modifier in self.conditional_modifiers.iter()
         {
             if let Some(modifier) = self.get_modifier(modifier)
             {
                 // Check if suffix or prefix matches
-                let conditional_tag = t.add_suffix(&modifier.condition);
-                let apply_modifier = match &modifier.target
+                let applies = match &modifier.target
                 {
-                    ModifierTarget::Single(_) => return Err(DataError::InvalidState(format!("Expected modifier {} to be conditional prefix / suffix modifier. Was single target.", modifier.name.clone()))),
-                    ModifierTarget::MatchingEnd(tag) => t.has_suffix(tag.to_str()),
-                    ModifierTarget::MatchingStart(tag) => t.has_prefix(tag.to_str()),
+                    ModifierTarget::Single(_) => return Err(DataError::InvalidState(format!("Expected modifier {:?} to be conditional prefix / suffix modifier. Was single target.", modifier.name))),
+                    ModifierTarget::MatchingEnd(tag) => t.has_suffix(tag),
+                    ModifierTarget::MatchingStart(tag) => t.has_prefix(tag),
                 };
 
-                if apply_modifier
+                if applies
                 {
-                    if dataset.eval_conditional(&conditional_tag)?
-                    {
-                        match &modifier.change
-                        {
-                            ModifierChange::BasicValue(add) => v += add,
-                            ModifierChange::FromOtherValue(tag) => 
-                            {
-                                if let Some(add) = dataset.get_value(tag)?
-                                {
-                                    v += add;
-                                } 
-                                else
-                                {
-                                    return Err(DataError::value_dne(tag.clone()))
-                                }
-                            },
-                        }
-                    }
+                    result.push((modifier, t.add_suffix(&modifier.condition)));
                 }
             }
             else
             {
-                return Err(DataError::InvalidState(format!("Expected to have modifier {} in modifier set.", modifier.clone())));
+                return Err(DataError::InvalidState(format!("Expected to have modifier {:?} in modifier set.", modifier)));
             }
         }
-        Ok(v)
+
+        result.sort_by(|(a, _), (b, _)| (a.change.get_stage(), a.priority, &a.name).cmp(&(b.change.get_stage(), b.priority, &b.name)));
+        Ok(result)
     }
 
     /// Sets the value of a modifier for this modifier set.
@@ -164,6 +391,10 @@ impl ModifierSet
                     if let Some(set) = self.single_target_modifiers.get_mut(target)
                     {
                         set.remove(&old_mod.name);
+                        if set.is_empty()
+                        {
+                            self.single_target_modifiers.remove(target);
+                        }
                     }
                 },
                 ModifierTarget::MatchingEnd(_) | ModifierTarget::MatchingStart(_) =>
@@ -244,6 +475,71 @@ impl ModifierSet
     }
 }
 
+impl RemapTags for Modifier
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        self.name.remap_tags(remap);
+        self.target.remap_tags(remap);
+        self.condition.remap_tags(remap);
+        self.change.remap_tags(remap);
+        self.group.remap_tags(remap);
+    }
+}
+
+impl RemapTags for ModifierTarget
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        match self
+        {
+            ModifierTarget::Single(t) | ModifierTarget::MatchingEnd(t) | ModifierTarget::MatchingStart(t) => t.remap_tags(remap),
+        }
+    }
+}
+
+impl RemapTags for ModifierChange
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        match self
+        {
+            ModifierChange::BasicValue(_) => (),
+            ModifierChange::FromOtherValue(t) => t.remap_tags(remap),
+            ModifierChange::Multiply(a) | ModifierChange::Override(a) | ModifierChange::Min(a) | ModifierChange::Max(a) => a.remap_tags(remap),
+        }
+    }
+}
+
+impl RemapTags for ModifierAmount
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        if let ModifierAmount::FromOtherValue(t) = self
+        {
+            t.remap_tags(remap);
+        }
+    }
+}
+
+impl RemapTags for ModifierSet
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        self.all_modifiers.remap_tags(remap);
+        // Renaming can move the modifiers of two targets to the same target, so their modifiers are combined
+        let mut single_target_modifiers: HashMap<Tag, HashSet<Tag>> = HashMap::new();
+        for (target, mut modifiers) in self.single_target_modifiers.drain()
+        {
+            modifiers.remap_tags(remap);
+            single_target_modifiers.entry(remap.remap_tag(&target)).or_default().extend(modifiers);
+        }
+        self.single_target_modifiers = single_target_modifiers;
+        self.conditional_modifiers.remap_tags(remap);
+        self.stacking_policies = self.stacking_policies.drain().map(|(group, policy)| (remap.remap_tag(&group), policy)).collect();
+    }
+}
+
 impl IntoIterator for ModifierSet
 {
     type Item = (Tag, Modifier);
@@ -263,11 +559,15 @@ pub struct ModifierTemplate
     target_template: Templated<ModifierTargetTemplate, ModifierTarget>,
     condition_template: Templated<TagTemplate, Tag>,
     change_template: Templated<ModifierChangeTemplate, ModifierChange>,
+    #[serde(default)]
+    priority: i32,
+    #[serde(default)]
+    group: Option<Tag>,
 }
 
 impl ModifierTemplate
 {
-    pub fn new(name: Templated<TagTemplate, Tag>, target: Templated<ModifierTargetTemplate, ModifierTarget>, condition: Templated<TagTemplate, Tag>, change: Templated<ModifierChangeTemplate, ModifierChange>) -> Templated<ModifierTemplate, Modifier>
+    pub fn new(name: Templated<TagTemplate, Tag>, target: Templated<ModifierTargetTemplate, ModifierTarget>, condition: Templated<TagTemplate, Tag>, change: Templated<ModifierChangeTemplate, ModifierChange>, priority: i32, group: Option<Tag>) -> Templated<ModifierTemplate, Modifier>
     {
         match (name, target, condition, change)
         {
@@ -278,11 +578,11 @@ impl ModifierTemplate
                 Templated::Complete(change),
             ) =>
             {
-                Templated::Complete(Modifier::new(name, target, condition, change))
+                Templated::Complete(Modifier { name, target, condition, change, priority, group })
             },
             (name_template, target_template, condition_template, change_template) =>
             {
-                Templated::Template(ModifierTemplate { name_template, target_template, condition_template, change_template })
+                Templated::Template(ModifierTemplate { name_template, target_template, condition_template, change_template, priority, group })
             }
         }
     }
@@ -319,6 +619,8 @@ impl Template<Modifier> for ModifierTemplate
                 target: target.clone(),
                 condition: condition.clone(),
                 change: change.clone(),
+                priority: self.priority,
+                group: self.group.clone(),
             }),
             _ => None,
         }
@@ -339,6 +641,8 @@ impl Template<Modifier> for ModifierTemplate
                 target: target.clone(),
                 condition: condition.clone(),
                 change: change.clone(),
+                priority: self.priority,
+                group: self.group.clone(),
             }),
             _ => Err(TemplateError::MissingTemplateValues(self.get_required_inputs().into_iter().collect()))
         }
@@ -396,6 +700,10 @@ pub enum ModifierChangeTemplate
 {
     BasicValue(f32),
     FromOtherValue(TagTemplate),
+    Multiply(ModifierAmountTemplate),
+    Override(ModifierAmountTemplate),
+    Min(ModifierAmountTemplate),
+    Max(ModifierAmountTemplate),
 }
 
 impl Template<ModifierChange> for ModifierChangeTemplate
@@ -406,6 +714,8 @@ impl Template<ModifierChange> for ModifierChangeTemplate
         {
             ModifierChangeTemplate::BasicValue(_) => HashSet::new(),
             ModifierChangeTemplate::FromOtherValue(tag_template) => tag_template.get_required_inputs(),
+            ModifierChangeTemplate::Multiply(amount) | ModifierChangeTemplate::Override(amount) |
+            ModifierChangeTemplate::Min(amount) | ModifierChangeTemplate::Max(amount) => amount.get_required_inputs(),
         }
     }
 
@@ -423,6 +733,10 @@ impl Template<ModifierChange> for ModifierChangeTemplate
             {
                 None
             },
+            ModifierChangeTemplate::Multiply(amount) => amount.fill_template_value(input_name, input_value).map(ModifierChange::Multiply),
+            ModifierChangeTemplate::Override(amount) => amount.fill_template_value(input_name, input_value).map(ModifierChange::Override),
+            ModifierChangeTemplate::Min(amount) => amount.fill_template_value(input_name, input_value).map(ModifierChange::Min),
+            ModifierChangeTemplate::Max(amount) => amount.fill_template_value(input_name, input_value).map(ModifierChange::Max),
         }
     }
 
@@ -437,6 +751,226 @@ impl Template<ModifierChange> for ModifierChangeTemplate
                 Ok(tag) => Ok(ModifierChange::FromOtherValue(tag)),
                 Err(e) => Err(e),
             },
+            ModifierChangeTemplate::Multiply(amount) => amount.attempt_complete().map(ModifierChange::Multiply),
+            ModifierChangeTemplate::Override(amount) => amount.attempt_complete().map(ModifierChange::Override),
+            ModifierChangeTemplate::Min(amount) => amount.attempt_complete().map(ModifierChange::Min),
+            ModifierChangeTemplate::Max(amount) => amount.attempt_complete().map(ModifierChange::Max),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+pub enum ModifierAmountTemplate
+{
+    BasicValue(f32),
+    FromOtherValue(TagTemplate),
+}
+
+impl Template<ModifierAmount> for ModifierAmountTemplate
+{
+    fn get_required_inputs(&self) -> HashSet<String>
+    {
+        match self
+        {
+            ModifierAmountTemplate::BasicValue(_) => HashSet::new(),
+            ModifierAmountTemplate::FromOtherValue(tag_template) => tag_template.get_required_inputs(),
+        }
+    }
+
+    fn fill_template_value(&mut self, input_name: &str, input_value: &Tag) -> Option<ModifierAmount>
+    {
+        match self
+        {
+            ModifierAmountTemplate::BasicValue(v) => Some(ModifierAmount::BasicValue(*v)),
+            ModifierAmountTemplate::FromOtherValue(tag_template) => tag_template.fill_template_value(input_name, input_value).map(ModifierAmount::FromOtherValue),
+        }
+    }
+
+    fn attempt_complete(&self) -> Result<ModifierAmount, super::error::TemplateError>
+    {
+        match self
+        {
+            ModifierAmountTemplate::BasicValue(v) => Ok(ModifierAmount::BasicValue(*v)),
+            ModifierAmountTemplate::FromOtherValue(tag_template) => tag_template.attempt_complete().map(ModifierAmount::FromOtherValue),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod unit_tests
+{
+    use crate::api::data::{conditional::Conditional, context::Context, tag::{Tag, TagRegistry}};
+
+    use super::*;
+
+    fn setup_context() -> Context
+    {
+        let mut ctx = Context::new();
+        ctx.set_attribute(&Tag::from_str("strength").unwrap(), 4.0).unwrap();
+        ctx.set_attribute(&Tag::from_str("giant blood").unwrap(), 3.0).unwrap();
+        ctx.set_conditional(Conditional::new(Tag::from_str("always").unwrap(), "true").unwrap()).unwrap();
+        ctx.set_conditional(Conditional::new(Tag::from_str("never").unwrap(), "false").unwrap()).unwrap();
+        ctx
+    }
+
+    fn modifier(name: &str, condition: &str, change: ModifierChange) -> Modifier
+    {
+        Modifier::new(Tag::from_str(name).unwrap(), ModifierTarget::Single(Tag::from_str("strength").unwrap()), Tag::from_str(condition).unwrap(), change)
+    }
+
+    #[test]
+    fn stacking_order_test()
+    {
+        let mut ctx = setup_context();
+        let strength = Tag::from_str("strength").unwrap();
+
+        // Set in the opposite order of application, (4 + 1 + 3) * 2 = 16
+        ctx.set_modifier(modifier("double", "always", ModifierChange::Multiply(ModifierAmount::BasicValue(2.0)))).unwrap();
+        ctx.set_modifier(modifier("giant", "always", ModifierChange::FromOtherValue(Tag::from_str("giant blood").unwrap()))).unwrap();
+        ctx.set_modifier(modifier("bonus", "always", ModifierChange::BasicValue(1.0))).unwrap();
+        assert_eq!(ctx.get_value(&strength).unwrap(), Some(16.0));
+
+        ctx.set_modifier(modifier("cap", "always", ModifierChange::Max(ModifierAmount::BasicValue(10.0)))).unwrap();
+        assert_eq!(ctx.get_value(&strength).unwrap(), Some(10.0));
+
+        // Skipped modifiers do not take part in any stage
+        ctx.set_modifier(modifier("curse", "never", ModifierChange::Override(ModifierAmount::BasicValue(0.0)))).unwrap();
+        assert_eq!(ctx.get_value(&strength).unwrap(), Some(10.0));
+
+        ctx.remove_modifier(&Tag::from_str("cap").unwrap()).unwrap();
+        ctx.set_modifier(modifier("floor", "always", ModifierChange::Min(ModifierAmount::FromOtherValue(Tag::from_str("giant blood").unwrap())))).unwrap();
+        ctx.set_modifier(modifier("weak", "always", ModifierChange::Multiply(ModifierAmount::BasicValue(0.0)))).unwrap();
+        assert_eq!(ctx.get_value(&strength).unwrap(), Some(3.0));
+    }
+
+    #[test]
+    fn priority_test()
+    {
+        let mut ctx = setup_context();
+        let strength = Tag::from_str("strength").unwrap();
+
+        // The higher priority is applied last and has the final say
+        ctx.set_modifier(modifier("set low", "always", ModifierChange::Override(ModifierAmount::BasicValue(1.0))).with_priority(1)).unwrap();
+        ctx.set_modifier(modifier("set high", "always", ModifierChange::Override(ModifierAmount::BasicValue(9.0)))).unwrap();
+        assert_eq!(ctx.get_value(&strength).unwrap(), Some(1.0));
+
+        ctx.set_modifier(modifier("set high", "always", ModifierChange::Override(ModifierAmount::BasicValue(9.0))).with_priority(2)).unwrap();
+        assert_eq!(ctx.get_value(&strength).unwrap(), Some(9.0));
+
+        // Priority does not move a modifier out of its stage
+        ctx.set_modifier(modifier("bonus", "always", ModifierChange::BasicValue(1.0)).with_priority(10)).unwrap();
+        assert_eq!(ctx.get_value(&strength).unwrap(), Some(9.0));
+    }
+
+    #[test]
+    fn stacking_group_test()
+    {
+        let mut ctx = setup_context();
+        let strength = Tag::from_str("strength").unwrap();
+        let enhancement = Tag::from_str("bonus.enhancement").unwrap();
+
+        ctx.set_modifier(modifier("belt", "always", ModifierChange::BasicValue(2.0)).with_group(enhancement.clone()).with_priority(1)).unwrap();
+        ctx.set_modifier(modifier("potion", "always", ModifierChange::BasicValue(4.0)).with_group(enhancement.clone())).unwrap();
+        ctx.set_modifier(modifier("ring", "never", ModifierChange::BasicValue(6.0)).with_group(enhancement.clone())).unwrap();
+        ctx.set_modifier(modifier("luck", "always", ModifierChange::BasicValue(1.0))).unwrap();
+
+        // Undeclared groups stack like any other modifier
+        assert_eq!(ctx.get_stacking_policy(&enhancement), StackingPolicy::Sum);
+        assert_eq!(ctx.get_value(&strength).unwrap(), Some(11.0));
+
+        ctx.set_stacking_policy(&enhancement, StackingPolicy::Max);
+        assert_eq!(ctx.get_value(&strength).unwrap(), Some(9.0));
+        ctx.set_stacking_policy(&enhancement, StackingPolicy::Min);
+        assert_eq!(ctx.get_value(&strength).unwrap(), Some(7.0));
+        ctx.set_stacking_policy(&enhancement, StackingPolicy::FirstByPriority);
+        assert_eq!(ctx.get_value(&strength).unwrap(), Some(7.0));
+
+        // Groups are resolved separately for each stage
+        ctx.set_stacking_policy(&enhancement, StackingPolicy::Max);
+        ctx.set_modifier(modifier("enlarge", "always", ModifierChange::Multiply(ModifierAmount::BasicValue(2.0))).with_group(enhancement.clone())).unwrap();
+        assert_eq!(ctx.get_value(&strength).unwrap(), Some(18.0));
+
+        ctx.remove_stacking_policy(&enhancement);
+        assert_eq!(ctx.get_value(&strength).unwrap(), Some(22.0));
+    }
+
+    #[test]
+    fn stacking_group_cache_and_trace_test()
+    {
+        let mut ctx = setup_context();
+        ctx.enable_cache();
+        let strength = Tag::from_str("strength").unwrap();
+        let morale = Tag::from_str("bonus.morale").unwrap();
+
+        ctx.set_modifier(modifier("rage", "always", ModifierChange::BasicValue(4.0)).with_group(morale.clone())).unwrap();
+        ctx.set_modifier(modifier("heroism", "always", ModifierChange::BasicValue(2.0)).with_group(morale.clone())).unwrap();
+        assert_eq!(ctx.get_value(&strength).unwrap(), Some(10.0));
+
+        ctx.set_stacking_policy(&morale, StackingPolicy::Max);
+        assert_eq!(ctx.get_value(&strength).unwrap(), Some(8.0));
+
+        let trace = ctx.get_value_trace(&strength).unwrap().unwrap();
+        assert_eq!(trace.result, 8.0);
+        let heroism = trace.modifiers.iter().find(|m| m.name == Tag::from_str("heroism").unwrap()).unwrap();
+        assert!(heroism.condition.result);
+        assert!(heroism.suppressed);
+        assert!(!heroism.is_applied());
+        let rage = trace.modifiers.iter().find(|m| m.name == Tag::from_str("rage").unwrap()).unwrap();
+        assert!(rage.is_applied());
+    }
+
+    #[test]
+    fn json_backwards_compatible_test()
+    {
+        // Modifiers saved before priorities existed have no priority field
+        let mut registry = TagRegistry::new();
+        let mut tag = |s: &str| registry.get_or_register_tag(s).unwrap();
+        let m = Modifier::new(tag("bonus"), ModifierTarget::Single(tag("strength")), tag("always"), ModifierChange::BasicValue(1.0));
+        let mut json: serde_json::Value = serde_json::from_str(&registry.to_json_string(&m).unwrap()).unwrap();
+        json.as_object_mut().unwrap().remove("priority");
+        let m: Modifier = registry.from_json_str(&json.to_string()).unwrap();
+        assert_eq!(m.priority, 0);
+        assert_eq!(m.change, ModifierChange::BasicValue(1.0));
+
+        // The existing additive changes keep their form
+        assert_eq!(serde_json::from_str::<ModifierChange>(r#"{"BasicValue":1.5}"#).unwrap(), ModifierChange::BasicValue(1.5));
+        let change = ModifierChange::Multiply(ModifierAmount::BasicValue(2.0));
+        assert_eq!(serde_json::to_string(&change).unwrap(), r#"{"Multiply":{"BasicValue":2.0}}"#);
+        assert_eq!(serde_json::from_str::<ModifierChange>(r#"{"Multiply":{"BasicValue":2.0}}"#).unwrap(), change);
+    }
+
+    #[test]
+    fn json_stacking_policy_test()
+    {
+        let mut registry = TagRegistry::new();
+        let mut tag = |s: &str| registry.get_or_register_tag(s).unwrap();
+        let morale = tag("bonus.morale");
+        let rage = Modifier::new(tag("rage"), ModifierTarget::Single(tag("strength")), tag("always"), ModifierChange::BasicValue(4.0)).with_group(morale.clone());
+        let mut set = ModifierSet::new();
+        set.set_modifier(rage.clone());
+        set.set_stacking_policy(morale.clone(), StackingPolicy::Max);
+
+        let json = registry.to_json_string(&set).unwrap();
+        let loaded: ModifierSet = registry.from_json_str(&json).unwrap();
+        assert_eq!(loaded.get_stacking_policy(&morale), StackingPolicy::Max);
+        assert_eq!(loaded, set);
+
+        // Sets written as a list of modifiers, before stacking groups, are still read
+        let json = registry.to_json_string(&vec![rage.clone()]).unwrap();
+        let loaded: ModifierSet = registry.from_json_str(&json).unwrap();
+        assert_eq!(loaded.get_stacking_policy(&morale), StackingPolicy::Sum);
+        assert_eq!(loaded.get_modifier(&rage.name), Some(&rage));
+    }
+
+    #[test]
+    fn template_test()
+    {
+        let mut template = ModifierChangeTemplate::Min(ModifierAmountTemplate::FromOtherValue(TagTemplate::from_str("[ability].score").unwrap()));
+        assert_eq!(template.get_required_inputs(), HashSet::from(["ability".to_string()]));
+        assert!(template.attempt_complete().is_err());
+        assert_eq!(
+            template.fill_template_value("ability", &Tag::from_str("latin").unwrap()),
+            Some(ModifierChange::Min(ModifierAmount::FromOtherValue(Tag::from_str("latin.score").unwrap())))
+        );
+    }
+}
@@ -1,11 +1,11 @@
-use std::{collections::{HashMap, HashSet}, fmt::Display, ops::Deref};
+use std::{borrow::Cow, cell::RefCell, collections::{HashMap, HashSet}, fmt::Display, ops::Deref};
 
-use serde::{Deserialize, Serialize};
+use serde::{de::{DeserializeOwned, Visitor}, Deserialize, Deserializer, Serialize, Serializer};
 use string_interner::{backend::StringBackend, symbol::{SymbolU16, SymbolU32}, StringInterner, Symbol};
 
 use std::ops::Index;
 
-use crate::api::data::{error::{ParseError, ParseErrorType, TagParseError, TemplateError}, template::{Template, Templated}};
+use crate::api::data::{diagnostic::{closest_match, Span}, evaltree::replace_tags, error::{ParseError, ParseErrorType, TagParseError, TemplateError}, template::{Template, Templated}};
 
 static TAG_DELIMITER: char = '.';
 
@@ -28,6 +28,56 @@ pub struct TagRegistry
     string_interner: StringInterner<StringBackend<SymbolU32>>,
 }
 
+impl Default for TagRegistry
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+thread_local!
+{
+    /// The registry of the innermost `TagRegistry::serde_scope`, if any
+    static SERDE_REGISTRY: RefCell<Option<TagRegistry>> = const { RefCell::new(None) };
+}
+
+/// Moves a registry into `SERDE_REGISTRY` for the lifetime of the scope,
+/// moving it back (even on panic) when dropped.
+struct SerdeScope<'a>
+{
+    registry: &'a mut TagRegistry,
+    previous: Option<TagRegistry>,
+}
+
+impl<'a> SerdeScope<'a>
+{
+    fn new(registry: &'a mut TagRegistry) -> SerdeScope<'a>
+    {
+        let scoped = std::mem::take(registry);
+        let previous = SERDE_REGISTRY.with(|r| r.replace(Some(scoped)));
+        SerdeScope { registry, previous }
+    }
+}
+
+impl Drop for SerdeScope<'_>
+{
+    fn drop(&mut self)
+    {
+        if let Some(scoped) = SERDE_REGISTRY.with(|r| r.replace(self.previous.take()))
+        {
+            *self.registry = scoped;
+        }
+    }
+}
+
+/// Calls `f` with the registry of the current serde scope.
+/// Returns None when not in a scope, in which case tags can not be (de)serialized.
+fn with_serde_registry<T, F: FnOnce(&mut TagRegistry) -> T>(f: F) -> Option<T>
+{
+    SERDE_REGISTRY.with(|r| r.borrow_mut().as_mut().map(f))
+}
+
 impl TagRegistry
 {
     /// Create a new empty tag registry. This is primarily used for testing,
@@ -78,7 +128,7 @@ impl TagRegistry
 
         // if first_str.chars().all(|c| c.is_numeric() || c.is_whitespace())
         // {
-        //     return Err(ParseError::new(s.to_string(), s.len() - 1, ParseErrorType::Tag(TagParseError::FirstTagNumeric)));
+        //     return Err(ParseError::new(s.to_string(), Span::at(s, s.len() - 1), ParseErrorType::Tag(TagParseError::FirstTagNumeric)));
         // }
 
         // Loop through each substring s
@@ -102,7 +152,7 @@ impl TagRegistry
         Self::check_parse_error(tag_str, Self::is_valid_tag_char)?;
 
         Ok(tag_str.split(TAG_DELIMITER)
-            .map(|substring| self.string_interner.get(substring))
+            .map(|substring| self.string_interner.get(substring.trim().to_lowercase()))
             .try_fold(vec![], |mut acc, subtag|
                 {
                     match subtag
@@ -125,12 +175,65 @@ impl TagRegistry
         Ok(self.string_interner.get(subtag_str.trim().to_lowercase()).map(|i| i.into()))
     }
 
+    /// Finds the known tag whose string is closest to the given misspelled tag string,
+    /// to be given as a "did you mean" suggestion. Known tags which are not in the registry are ignored.
+    pub fn suggest_tag(&self, tag_str: &str, known: &[Tag]) -> Option<String>
+    {
+        closest_match(tag_str, known.iter().filter_map(|t| t.to_string(self)))
+    }
+
+    /// Runs `f` with this registry used for the serialization of tags.
+    /// 
+    /// Tags are written as their dotted strings (such as "ability.magic theory") and read back
+    /// by registering them in this registry, so exported characters and rulesets can be loaded
+    /// with a different registry. Outside of this, serializing a tag fails, as the ids of its
+    /// subtags are only meaningful to the registry which made them.
+    /// 
+    /// For JSON, use [`TagRegistry::to_json_string`] and [`TagRegistry::from_json_str`].
+    pub fn serde_scope<T, F: FnOnce() -> T>(&mut self, f: F) -> T
+    {
+        let _scope = SerdeScope::new(self);
+        f()
+    }
+
+    /// Serializes the value to JSON, with the tags written as strings. See [`TagRegistry::serde_scope`]
+    pub fn to_json_string<T: Serialize>(&mut self, value: &T) -> Result<String, serde_json::Error>
+    {
+        self.serde_scope(|| serde_json::to_string(value))
+    }
+
+    /// Deserializes the value from JSON, registering the tags written as strings. See [`TagRegistry::serde_scope`]
+    pub fn from_json_str<T: DeserializeOwned>(&mut self, s: &str) -> Result<T, serde_json::Error>
+    {
+        self.serde_scope(|| serde_json::from_str(s))
+    }
+
+    /// Registers every subtag of the other registry in this registry, such as when loading
+    /// a character made with another ruleset. Subtags already in this registry keep their ids.
+    /// 
+    /// Returns the table which rewrites the tags of the other registry into this registry,
+    /// to be applied with [`RemapTags::remap_tags`] to anything holding tags of the other registry.
+    pub fn merge(&mut self, other: &TagRegistry) -> TagRemap
+    {
+        let mut remap = TagRemap::default();
+        for (symbol, s) in &other.string_interner
+        {
+            let id = symbol.to_usize();
+            if remap.subtags.len() <= id
+            {
+                remap.subtags.resize_with(id + 1, Subtag::default);
+            }
+            remap.subtags[id] = Subtag::from(self.string_interner.get_or_intern(s));
+        }
+        remap
+    }
+
     pub fn find_all_parse_errors(s: &str) -> Result<(), Vec<ParseError>>
     {
         let mut res = vec![];
         if s.is_empty() || s.chars().all(char::is_whitespace)
         {
-            res.push(ParseError::new(s.to_string(), s.len(), ParseErrorType::Tag(TagParseError::TagEmpty)));
+            res.push(ParseError::new(s.to_string(), Span::at(s, s.len()), ParseErrorType::Tag(TagParseError::TagEmpty)));
             return Err(res);
         }
 
@@ -145,14 +248,14 @@ impl TagRegistry
 
         // if first_str.chars().all(char::is_numeric)
         // {
-        //     res.push(ParseError::new(s.to_string(), s.len() - 1, ParseErrorType::Tag(TagParseError::FirstTagNumeric)));
+        //     res.push(ParseError::new(s.to_string(), Span::at(s, s.len() - 1), ParseErrorType::Tag(TagParseError::FirstTagNumeric)));
         // }
 
-        for (i, c) in s.chars().enumerate()
+        for (i, c) in s.char_indices()
         {
             if !Self::is_valid_tag_char(c)
             {
-                res.push(ParseError::new(s.to_string(), i, ParseErrorType::Tag(TagParseError::InvalidCharacter)));
+                res.push(ParseError::new(s.to_string(), Span::at(s, i), ParseErrorType::Tag(TagParseError::InvalidCharacter)));
             }
         }
 
@@ -160,7 +263,7 @@ impl TagRegistry
         {
             if sub.chars().all(char::is_whitespace)
             {
-                res.push(ParseError::new(s.to_string(), s.find(sub).unwrap(), ParseErrorType::Tag(TagParseError::SubTagEmpty)));
+                res.push(ParseError::new(s.to_string(), Span::at(s, s.find(sub).unwrap()), ParseErrorType::Tag(TagParseError::SubTagEmpty)));
             }
         }
 
@@ -191,19 +294,326 @@ impl TagRegistry
         // Initial error check to ensure not empty
         if s.is_empty() || s.chars().all(char::is_whitespace)
         {
-            return Err(ParseError::new(s.to_string(), s.len(), ParseErrorType::Tag(TagParseError::TagEmpty)));
+            return Err(ParseError::new(s.to_string(), Span::at(s, s.len()), ParseErrorType::Tag(TagParseError::TagEmpty)));
         }
 
         // Check that the string only contains valid characters
         if !s.chars().all(|c| valid(c))
         {
-            return Err(ParseError::new(s.to_string(), s.find(|c| !valid(c)).unwrap(), ParseErrorType::Tag(TagParseError::InvalidCharacter)));
+            return Err(ParseError::new(s.to_string(), Span::at(s, s.find(|c| !valid(c)).unwrap()), ParseErrorType::Tag(TagParseError::InvalidCharacter)));
         }
 
         Ok(())
     }
 }
 
+/// A rewrite of tags, such as moving tags from one registry to another (See [`TagRemap`])
+/// or renaming a tag (See [`TagRename`]). Applied with [`RemapTags::remap_tags`].
+pub trait TagMapping
+{
+    fn remap_tag(&self, t: &Tag) -> Tag;
+
+    /// Subtags held on their own (such as the time context of a date) are mapped as a tag of a single subtag
+    fn remap_subtag(&self, st: &Subtag) -> Subtag
+    {
+        match self.remap_tag(&Tag::from(st)).subtags.as_slice()
+        {
+            [subtag] => *subtag,
+            _ => *st,
+        }
+    }
+
+    /// Rewrites a tag written by name, such as the literal subtags of a tag template.
+    /// Returns None if the name is not changed, which is always the case by default,
+    /// as names are registry independent. See [`NamedMapping`]
+    fn remap_name(&self, _name: &str) -> Option<String>
+    {
+        None
+    }
+
+    /// Rewrites the tags written in the source string of an equation or conditional.
+    /// Sources are kept as written by default, as they are registry independent. See [`NamedMapping`]
+    fn remap_source(&self, source: &str) -> String
+    {
+        source.to_string()
+    }
+}
+
+/// Applies a mapping along with rewriting the tags written in sources, which are read and
+/// written by their names in a registry. Names the registry does not hold are kept as written.
+/// Ex: a rename of "ability.magic theory" to "ability.magic theory.score" rewrites
+/// "ability.magic theory / 5" to "ability.magic theory.score / 5"
+pub struct NamedMapping<'a>
+{
+    mapping: &'a dyn TagMapping,
+    registry: &'a TagRegistry,
+}
+
+impl<'a> NamedMapping<'a>
+{
+    pub fn new(mapping: &'a dyn TagMapping, registry: &'a TagRegistry) -> NamedMapping<'a>
+    {
+        NamedMapping { mapping, registry }
+    }
+}
+
+impl TagMapping for NamedMapping<'_>
+{
+    fn remap_tag(&self, t: &Tag) -> Tag
+    {
+        self.mapping.remap_tag(t)
+    }
+
+    fn remap_subtag(&self, st: &Subtag) -> Subtag
+    {
+        self.mapping.remap_subtag(st)
+    }
+
+    fn remap_name(&self, name: &str) -> Option<String>
+    {
+        let t = self.registry.get_tag(name).ok()??;
+        let remapped = self.mapping.remap_tag(&t);
+        if remapped == t
+        {
+            None
+        }
+        else
+        {
+            remapped.to_string(self.registry)
+        }
+    }
+
+    fn remap_source(&self, source: &str) -> String
+    {
+        replace_tags(source, |name| self.remap_name(name))
+    }
+}
+
+/// The table made by [`TagRegistry::merge`], which rewrites the subtags of one registry
+/// into the ids of another. Subtags not in the table are left unchanged.
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone, Default)]
+pub struct TagRemap
+{
+    /// The new subtag, indexed by the id of the old subtag
+    subtags: Vec<Subtag>,
+}
+
+impl TagRemap
+{
+    /// Whether remapping leaves every subtag unchanged, such as when merging a registry into itself
+    pub fn is_identity(&self) -> bool
+    {
+        self.subtags.iter().enumerate().all(|(i, st)| st.intern_id.to_usize() == i)
+    }
+}
+
+impl TagMapping for TagRemap
+{
+    fn remap_tag(&self, t: &Tag) -> Tag
+    {
+        Tag { subtags: t.subtags.iter().map(|st| self.remap_subtag(st)).collect() }
+    }
+
+    fn remap_subtag(&self, st: &Subtag) -> Subtag
+    {
+        *self.subtags.get(st.intern_id.to_usize()).unwrap_or(st)
+    }
+}
+
+/// Renames a tag along with every tag it prefixes.
+/// Ex: renaming "ability.magic theory" to "ability.magic theory.score"
+/// also renames "ability.magic theory.exp" to "ability.magic theory.score.exp"
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+pub struct TagRename
+{
+    pub from: Tag,
+    pub to: Tag,
+}
+
+impl TagRename
+{
+    pub fn new(from: Tag, to: Tag) -> TagRename
+    {
+        TagRename { from, to }
+    }
+
+    /// The new name of the tag, or None if the tag is not renamed.
+    /// Tags already under the new name are not renamed again, such as when the new name is under the old name.
+    pub fn rename(&self, t: &Tag) -> Option<Tag>
+    {
+        if t.has_prefix(&self.from) && !t.has_prefix(&self.to)
+        {
+            Some(Tag { subtags: self.to.subtags.iter().chain(t.subtags[self.from.subtags.len()..].iter()).copied().collect() })
+        }
+        else
+        {
+            None
+        }
+    }
+}
+
+impl TagMapping for TagRename
+{
+    fn remap_tag(&self, t: &Tag) -> Tag
+    {
+        self.rename(t).unwrap_or_else(|| t.clone())
+    }
+}
+
+/// Old names of tags which still resolve to their new names, such as after a rename
+/// while rulesets and characters are migrated. Each alias also applies to the tags
+/// prefixed by the old name, except those already under the new name.
+/// Ex: with the alias "ability.magic theory" -> "ability.magic theory.score",
+/// "ability.magic theory.exp" resolves to "ability.magic theory.score.exp"
+/// while "ability.magic theory.score" resolves to itself.
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone, Default)]
+pub struct TagAliases
+{
+    aliases: HashMap<Tag, Tag>,
+}
+
+impl TagAliases
+{
+    pub fn new() -> TagAliases
+    {
+        TagAliases { aliases: HashMap::new() }
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.aliases.is_empty()
+    }
+
+    pub fn get(&self, from: &Tag) -> Option<&Tag>
+    {
+        self.aliases.get(from)
+    }
+
+    /// Adds an alias, returning the previous new name of the alias if there was one.
+    /// 
+    /// Aliases are resolved once, so an alias to an old name instead resolves to that name's new name,
+    /// and existing aliases to the given old name are moved to the new name.
+    pub fn insert(&mut self, from: Tag, to: Tag) -> Option<Tag>
+    {
+        let to = self.resolve(&to).into_owned();
+        let rename = TagRename::new(from.clone(), to.clone());
+        self.aliases.values_mut().for_each(|v| v.remap_tags(&rename));
+        self.aliases.insert(from, to)
+    }
+
+    pub fn remove(&mut self, from: &Tag) -> Option<Tag>
+    {
+        self.aliases.remove(from)
+    }
+
+    pub fn iter(&self) -> std::collections::hash_map::Iter<'_, Tag, Tag>
+    {
+        self.aliases.iter()
+    }
+
+    /// The new name of a tag, or the tag itself if no alias applies.
+    /// When more than one alias applies, the alias of the longest old name is used.
+    pub fn resolve<'a>(&self, t: &'a Tag) -> Cow<'a, Tag>
+    {
+        if self.aliases.is_empty()
+        {
+            return Cow::Borrowed(t);
+        }
+
+        for len in (1..=t.subtags.len()).rev()
+        {
+            if let Some((from, to)) = self.aliases.get_key_value(&Tag { subtags: t.subtags[..len].to_vec() })
+            {
+                if let Some(renamed) = TagRename::new(from.clone(), to.clone()).rename(t)
+                {
+                    return Cow::Owned(renamed);
+                }
+            }
+        }
+        Cow::Borrowed(t)
+    }
+}
+
+impl TagMapping for TagAliases
+{
+    fn remap_tag(&self, t: &Tag) -> Tag
+    {
+        self.resolve(t).into_owned()
+    }
+}
+
+/// Data holding tags, which can be rewritten by a [`TagMapping`].
+/// Ex: moving tags to the ids of another registry (See [`TagRegistry::merge`])
+pub trait RemapTags
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping);
+}
+
+/// Maps keyed by tags are rebuilt, as the hash of a key changes with its subtags
+impl<V: RemapTags> RemapTags for HashMap<Tag, V>
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        *self = self.drain().map(|(mut k, mut v)|
+        {
+            k.remap_tags(remap);
+            v.remap_tags(remap);
+            (k, v)
+        }).collect();
+    }
+}
+
+impl RemapTags for HashSet<Tag>
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        *self = self.drain().map(|t| remap.remap_tag(&t)).collect();
+    }
+}
+
+impl RemapTags for Subtag
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        *self = remap.remap_subtag(self);
+    }
+}
+
+impl RemapTags for Tag
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        *self = remap.remap_tag(self);
+    }
+}
+
+impl RemapTags for TagAliases
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        self.aliases.remap_tags(remap);
+    }
+}
+
+impl<T: RemapTags> RemapTags for Option<T>
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        if let Some(v) = self
+        {
+            v.remap_tags(remap);
+        }
+    }
+}
+
+impl<T: RemapTags> RemapTags for Vec<T>
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        self.iter_mut().for_each(|v| v.remap_tags(remap));
+    }
+}
+
 /// Subtags make up a larger part of a tag. As singular instances, they can be used as
 /// a unique string-based indentifier for a ruleset.
 /// 
@@ -223,7 +633,7 @@ impl TagRegistry
 /// **Counter Examples**
 /// - `a.multivalue.tag`
 /// - `non alphanumeric! "tag" values`
-#[derive(Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord, Serialize, Clone, Copy, Hash)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
 pub struct Subtag
 {
     intern_id: SymbolU32,
@@ -269,9 +679,95 @@ impl Default for Subtag
     }
 }
 
+impl Serialize for Subtag
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    {
+        match with_serde_registry(|registry| registry.string_interner.resolve(self.intern_id).map(|s| s.to_string()))
+        {
+            Some(Some(s)) => serializer.serialize_str(&s),
+            Some(None) => Err(serde::ser::Error::custom("subtag is not in the registry used for serialization")),
+            None => Err(serde::ser::Error::custom("subtags can only be written through a TagRegistry")),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Subtag
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>
+    {
+        deserializer.deserialize_str(SubtagVisitor)
+    }
+}
+
+struct SubtagVisitor;
+
+impl<'de> Visitor<'de> for SubtagVisitor
+{
+    type Value = Subtag;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
+    {
+        write!(f, "a subtag string")
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Subtag, E>
+    {
+        match with_serde_registry(|registry| registry.get_or_register_subtag(v))
+        {
+            Some(Ok(subtag)) => Ok(subtag),
+            Some(Err(e)) => Err(E::custom(format!("invalid subtag \"{}\": {:?}", v, e.error_type))),
+            None => Err(E::custom(format!("subtag \"{}\" can only be read with a registry", v))),
+        }
+    }
+}
+
+impl Serialize for Tag
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    {
+        match with_serde_registry(|registry| self.to_string(registry))
+        {
+            Some(Some(s)) => serializer.serialize_str(&s),
+            Some(None) => Err(serde::ser::Error::custom("tag is not in the registry used for serialization")),
+            None => Err(serde::ser::Error::custom("tags can only be written through a TagRegistry")),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Tag
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>
+    {
+        deserializer.deserialize_str(TagVisitor)
+    }
+}
+
+struct TagVisitor;
+
+impl<'de> Visitor<'de> for TagVisitor
+{
+    type Value = Tag;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
+    {
+        write!(f, "a tag string")
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Tag, E>
+    {
+        match with_serde_registry(|registry| registry.get_or_register_tag(v))
+        {
+            Some(Ok(tag)) => Ok(tag),
+            Some(Err(e)) => Err(E::custom(format!("invalid tag \"{}\": {:?}", v, e.error_type))),
+            None => Err(E::custom(format!("tag \"{}\" can only be read with a registry", v))),
+        }
+    }
+}
+
 // TODO: A macro that creates a tag from a variable number of Subtags
 // tag_from_subtags!(TIMELINE, DEFAULT)
-#[derive(Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord, Serialize, Clone, Hash)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
 pub struct Tag
 {
     subtags: Vec<Subtag>,
@@ -565,11 +1061,13 @@ impl Default for Tag
 /// A tag can be made of smaller sub-tags, which are children to the
 /// greater tag. For example, Ability.Magic Theory as a tag has
 /// Ability as the first sub-tag and Magic Theory as the second.
-#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct TagSet
 {
     primary_tags: HashMap<Tag, i32>,
-    tags: HashMap<Tag, i32>,
+    /// The count of every tag and the tags prefixing it, so prefix queries
+    /// only visit the tags they find
+    tags: TagTrie,
 }
 
 impl TagSet
@@ -577,20 +1075,26 @@ impl TagSet
     /// Creates a new empty TagSet
     pub fn new() -> TagSet
     {
-        TagSet { primary_tags: HashMap::new(), tags: HashMap::new() }
+        TagSet { primary_tags: HashMap::new(), tags: TagTrie::default() }
     }
 
+    /// Gets the count of a tag, which is 0 if the tag has never been added
     pub fn get_tag_count(&self, t: &Tag) -> i32
     {
-        self[t]
+        self.tags.get(t).map(|n| n.count).unwrap_or(0)
+    }
+
+    /// Gets the count of a tag added directly, ignoring the tags it prefixes
+    pub fn get_primary_tag_count(&self, t: &Tag) -> i32
+    {
+        self.primary_tags.get(t).copied().unwrap_or(0)
     }
 
     pub fn add_tag_count(&mut self, t: &Tag, c: i32)
     {
-        for st in t.as_collective_subtags()
+        if !t.subtags.is_empty()
         {
-            let v = self.tags.get(&st).unwrap_or(&0);
-            self.tags.insert(st, v + c);
+            TagTrie::add_count(&mut self.tags.roots, t, 0, c);
         }
         self.primary_tags.insert(t.clone(), c + self.primary_tags.get(t).unwrap_or(&0));
     }
@@ -637,17 +1141,16 @@ impl TagSet
     /// ```
     pub fn get_matching_prefix(&self, prefix: &Tag) -> Vec<Tag>
     {
-        self.tags.iter().filter_map(|(t, c)| 
+        let mut result = vec![];
+        if prefix.subtags.is_empty()
         {
-            if *c > 0 && t.has_prefix(&prefix)
-            {
-                Some(t.clone())
-            }
-            else
-            {
-                None
-            }
-        }).collect()
+            self.tags.roots.iter().for_each(|n| n.collect_matching(&mut result));
+        }
+        else if let Some(n) = self.tags.get(prefix)
+        {
+            n.collect_matching(&mut result);
+        }
+        result
     }
 
     /// Given a tag prefix, this method returns all immediate tags which exist in this tag set (the count of the tag must be > 0)
@@ -678,24 +1181,26 @@ impl TagSet
     /// ```
     pub fn get_immediate_matching_prefix(&self, prefix: &Tag) -> Vec<Tag>
     {
-        let num_subtags = prefix.count_subtags() + 1;
-        self.tags.iter().filter_map(|(t, c)|
+        let children = if prefix.subtags.is_empty()
         {
-            if *c > 0 && t.count_subtags() == num_subtags && t.has_prefix(&prefix) 
-            {
-                Some(t.clone())
-            }
-            else
+            &self.tags.roots
+        }
+        else
+        {
+            match self.tags.get(prefix)
             {
-                None
+                Some(n) => &n.children,
+                None => return vec![],
             }
-        }
-        ).collect()
+        };
+        children.iter().filter(|n| n.count > 0).map(|n| n.tag.clone()).collect()
     }
 
+    /// Iterates every tag which has been counted, including the tags prefixing added tags.
+    /// Tags are visited depth first, so a tag is followed by the tags it prefixes.
     pub fn iter(&self) -> impl Iterator<Item = &Tag> + '_
     {
-        self.tags.keys()
+        TagTrieIter { stack: vec![self.tags.roots.iter()] }
     }
 
     pub fn iter_primary_tags(&self) -> impl Iterator<Item = (&Tag, &i32)> + '_
@@ -716,6 +1221,33 @@ impl TagSet
     }
 }
 
+/// Tag sets are equal when they count every tag the same, even if one has counted
+/// (and since removed) tags the other has not
+impl PartialEq for TagSet
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        fn counted(ts: &TagSet) -> HashMap<&Tag, &i32>
+        {
+            ts.primary_tags.iter().filter(|(_, c)| **c != 0).collect()
+        }
+        counted(self) == counted(other)
+    }
+}
+
+impl RemapTags for TagSet
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        let mut res = Self::new();
+        for (tag, count) in self.primary_tags.iter()
+        {
+            res.add_tag_count(&remap.remap_tag(tag), *count);
+        }
+        *self = res;
+    }
+}
+
 impl Index<&Tag> for TagSet
 {
     type Output = i32;
@@ -723,7 +1255,113 @@ impl Index<&Tag> for TagSet
     #[inline]
     fn index(&self, index: &Tag) -> &Self::Output
     {
-        &self.tags[index]
+        match self.tags.get(index)
+        {
+            Some(n) => &n.count,
+            None => &0,
+        }
+    }
+}
+
+/// A tree of tags, where the children of a tag are the tags with one more subtag.
+/// Ex: `ability` has the child `ability.latin`, which has the child `ability.latin.exp`
+/// 
+/// Children are kept sorted by their last subtag, so finding a tag takes a
+/// binary search per subtag. Like the counts of a map, a tag whose count
+/// returns to 0 is kept.
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone, Default)]
+struct TagTrie
+{
+    roots: Vec<TagTrieNode>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+struct TagTrieNode
+{
+    tag: Tag,
+    count: i32,
+    children: Vec<TagTrieNode>,
+}
+
+impl TagTrie
+{
+    fn get(&self, t: &Tag) -> Option<&TagTrieNode>
+    {
+        let mut children = &self.roots;
+        let mut result = None;
+        for subtag in t.subtags.iter()
+        {
+            let node = &children[TagTrieNode::find(children, subtag).ok()?];
+            children = &node.children;
+            result = Some(node);
+        }
+        result
+    }
+
+    /// Adds the count to the tag and every tag prefixing it, starting from the subtag at `depth`
+    fn add_count(children: &mut Vec<TagTrieNode>, t: &Tag, depth: usize, c: i32)
+    {
+        let i = match TagTrieNode::find(children, &t.subtags[depth])
+        {
+            Ok(i) => i,
+            Err(i) =>
+            {
+                children.insert(i, TagTrieNode { tag: Tag { subtags: t.subtags[..=depth].to_vec() }, count: 0, children: vec![] });
+                i
+            },
+        };
+
+        let node = &mut children[i];
+        node.count += c;
+        if depth + 1 < t.subtags.len()
+        {
+            Self::add_count(&mut node.children, t, depth + 1, c);
+        }
+    }
+}
+
+impl TagTrieNode
+{
+    fn find(children: &[TagTrieNode], subtag: &Subtag) -> Result<usize, usize>
+    {
+        children.binary_search_by(|n| n.tag.subtags.last().cmp(&Some(subtag)))
+    }
+
+    /// Collects this tag and every tag under it which has a positive count
+    fn collect_matching(&self, result: &mut Vec<Tag>)
+    {
+        if self.count > 0
+        {
+            result.push(self.tag.clone());
+        }
+        self.children.iter().for_each(|n| n.collect_matching(result));
+    }
+}
+
+/// Walks a trie depth first, keeping the remaining siblings of every visited level
+struct TagTrieIter<'a>
+{
+    stack: Vec<std::slice::Iter<'a, TagTrieNode>>,
+}
+
+impl<'a> Iterator for TagTrieIter<'a>
+{
+    type Item = &'a Tag;
+
+    fn next(&mut self) -> Option<&'a Tag>
+    {
+        loop
+        {
+            match self.stack.last_mut()?.next()
+            {
+                Some(n) =>
+                {
+                    self.stack.push(n.children.iter());
+                    return Some(&n.tag);
+                },
+                None => { self.stack.pop(); },
+            }
+        }
     }
 }
 
@@ -792,13 +1430,13 @@ impl TagTemplate
         // Initial error check to ensure not empty
         if s.is_empty() || s.chars().all(char::is_whitespace)
         {
-            return Err(ParseError::new(s.to_string(), s.len(), ParseErrorType::Tag(TagParseError::TagEmpty)));
+            return Err(ParseError::new(s.to_string(), Span::at(s, s.len()), ParseErrorType::Tag(TagParseError::TagEmpty)));
         }
 
         // Check that the string only contains alpha-numeric values or '.'s
         if !s.chars().all(|c| Self::is_valid_tag_char(c))
         {
-            return Err(ParseError::new(s.to_string(), s.find(|c| !Self::is_valid_tag_char(c)).unwrap(), ParseErrorType::Tag(TagParseError::InvalidCharacter)));
+            return Err(ParseError::new(s.to_string(), Span::at(s, s.find(|c| !Self::is_valid_tag_char(c)).unwrap()), ParseErrorType::Tag(TagParseError::InvalidCharacter)));
         }
 
         // Ensure first sub-string is not just a number
@@ -813,7 +1451,7 @@ impl TagTemplate
 
         if first_str.chars().all(|c| c.is_numeric() || c.is_whitespace())
         {
-            return Err(ParseError::new(s.to_string(), s.len() - 1, ParseErrorType::Tag(TagParseError::FirstTagNumeric)));
+            return Err(ParseError::new(s.to_string(), Span::at(s, s.len() - 1), ParseErrorType::Tag(TagParseError::FirstTagNumeric)));
         }
 
         let mut decomposed_tag = vec![];
@@ -823,7 +1461,7 @@ impl TagTemplate
         {
             if sub.chars().all(char::is_whitespace)
             {
-                return Err(ParseError::new(s.to_string(), s.find(sub).unwrap(), ParseErrorType::Tag(TagParseError::SubTagEmpty)));
+                return Err(ParseError::new(s.to_string(), Span::at(s, s.find(sub).unwrap()), ParseErrorType::Tag(TagParseError::SubTagEmpty)));
             }
 
             let sub = sub.trim();
@@ -842,12 +1480,12 @@ impl TagTemplate
                         }
                         else
                         {
-                            return Err(ParseError::new(s.to_string(), s.find(sub).unwrap_or(0), ParseErrorType::Tag(TagParseError::InvalidCharacter)));
+                            return Err(ParseError::new(s.to_string(), Span::at(s, s.find(sub).unwrap_or(0)), ParseErrorType::Tag(TagParseError::InvalidCharacter)));
                         }
                     }
                     else
                     {
-                        return Err(ParseError::new(s.to_string(), s.find(sub).unwrap_or(0), ParseErrorType::Tag(TagParseError::SubTagEmpty)));
+                        return Err(ParseError::new(s.to_string(), Span::at(s, s.find(sub).unwrap_or(0)), ParseErrorType::Tag(TagParseError::SubTagEmpty)));
                     }
                 }
                 else
@@ -858,7 +1496,7 @@ impl TagTemplate
 
                 if check.contains(|c| !Tag::is_valid_tag_char(c))
                 {
-                    return Err(ParseError::new(s.to_string(), s.find(sub).unwrap_or(0), ParseErrorType::Tag(TagParseError::InvalidCharacter)))
+                    return Err(ParseError::new(s.to_string(), Span::at(s, s.find(sub).unwrap_or(0)), ParseErrorType::Tag(TagParseError::InvalidCharacter)))
                 }
 
                 if is_literal
@@ -872,13 +1510,13 @@ impl TagTemplate
             }
             else
             {
-                return Err(ParseError::new(s.to_string(), s.find(sub).unwrap(), ParseErrorType::Tag(TagParseError::SubTagEmpty)))
+                return Err(ParseError::new(s.to_string(), Span::at(s, s.find(sub).unwrap()), ParseErrorType::Tag(TagParseError::SubTagEmpty)))
             }
         }
 
         if decomposed_tag.iter().all(|tok| tok.is_literal())
         {
-            Err(ParseError::new(s.to_string(), s.len() - 1, ParseErrorType::Tag(TagParseError::MissingTemplate)))
+            Err(ParseError::new(s.to_string(), Span::at(s, s.len() - 1), ParseErrorType::Tag(TagParseError::MissingTemplate)))
         }
         else
         {
@@ -897,6 +1535,30 @@ impl TagTemplate
     }
 }
 
+/// Only the literal subtags before the first input form a tag on their own, so only those are remapped.
+/// They are written as text, so are remapped by name (See [`TagMapping::remap_name`]).
+impl RemapTags for TagTemplate
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        let literals: Vec<&str> = self.decomposed_tag.iter().map_while(|st| match st
+        {
+            TagTemplateSubtag::Literal(l) => Some(l.as_str()),
+            TagTemplateSubtag::Subtag(_) => None,
+        }).collect();
+        if literals.is_empty()
+        {
+            return;
+        }
+
+        if let Some(renamed) = remap.remap_name(&literals.join("."))
+        {
+            let len = literals.len();
+            self.decomposed_tag.splice(..len, renamed.split(TAG_DELIMITER).map(|l| TagTemplateSubtag::Literal(l.to_string())));
+        }
+    }
+}
+
 impl Template<Tag> for TagTemplate
 {
     fn get_required_inputs(&self) -> HashSet<String>
@@ -1781,6 +2443,204 @@ mod unit_tests
         }
     }
 
+    /// Tests removed tags are no longer found by prefix, while the tags still counted are
+    #[test]
+    fn tagset_remove_1()
+    {
+        let mut ts = TagSet::new();
+        let magic_theory_exp = Tag::from_str("ability.Magic Theory.Exp").unwrap();
+        let latin = Tag::from_str("ability.Latin").unwrap();
+        ts.add_tag(&magic_theory_exp);
+        ts.add_tag_count(&latin, 2);
+        assert_eq!(ts.get_tag_count(&Tag::from_str("ability").unwrap()), 3);
+
+        ts.remove_tag(&magic_theory_exp);
+        ts.remove_tag(&latin);
+        assert_eq!(ts.get_tag_count(&Tag::from_str("ability.Magic Theory").unwrap()), 0);
+        assert_eq!(ts[&magic_theory_exp], 0);
+        assert_eq!(ts[&latin], 1);
+        assert_eq!(ts.get_immediate_matching_prefix(&Tag::from_str("ability").unwrap()), vec![latin.clone()]);
+
+        let mut result = ts.get_matching_prefix(&Tag::from_str("ability").unwrap());
+        result.sort();
+        let mut expected = vec![Tag::from_str("ability").unwrap(), latin];
+        expected.sort();
+        assert_eq!(result, expected);
+        // Like a map of counts, the removed tags are still iterated
+        assert_eq!(ts.iter().count(), 4);
+    }
+
+    fn arb_tagset_tag() -> impl proptest::strategy::Strategy<Value = Tag>
+    {
+        use proptest::prelude::*;
+        prop::collection::vec(prop::sample::select(vec!["ability", "latin", "exp", "spell", "magnitude"]), 1..4)
+            .prop_map(|subtags| Tag::from_str(&subtags.join(".")).unwrap())
+    }
+
+    proptest::proptest!
+    {
+        /// Compares the tag set against counting every prefix of the added tags in a map
+        #[test]
+        fn tagset_matches_map(changes in proptest::collection::vec((arb_tagset_tag(), -2i32..3), 0..40), prefix in arb_tagset_tag())
+        {
+            let mut ts = TagSet::new();
+            let mut map: HashMap<Tag, i32> = HashMap::new();
+            for (t, c) in changes.iter()
+            {
+                ts.add_tag_count(t, *c);
+                for st in t.as_collective_subtags()
+                {
+                    *map.entry(st).or_insert(0) += c;
+                }
+            }
+
+            for (t, c) in map.iter()
+            {
+                proptest::prop_assert_eq!(ts.get_tag_count(t), *c);
+            }
+
+            let mut expected: Vec<Tag> = map.iter().filter(|(t, c)| **c > 0 && t.has_prefix(&prefix)).map(|(t, _)| t.clone()).collect();
+            let mut result = ts.get_matching_prefix(&prefix);
+            expected.sort();
+            result.sort();
+            proptest::prop_assert_eq!(result, expected);
+
+            let mut expected: Vec<Tag> = map.iter().filter(|(t, c)| **c > 0 && t.count_subtags() == prefix.count_subtags() + 1 && t.has_prefix(&prefix)).map(|(t, _)| t.clone()).collect();
+            let mut result = ts.get_immediate_matching_prefix(&prefix);
+            expected.sort();
+            result.sort();
+            proptest::prop_assert_eq!(result, expected);
+
+            let mut expected: Vec<&Tag> = map.keys().collect();
+            let mut result: Vec<&Tag> = ts.iter().collect();
+            expected.sort();
+            result.sort();
+            proptest::prop_assert_eq!(result, expected);
+        }
+    }
+
+    /// Tests tags can not be serialized without a registry, as their ids are only meaningful to their registry
+    #[test]
+    fn serde_no_registry_test()
+    {
+        let mut registry = TagRegistry::new();
+        let tag = registry.get_or_register_tag("ability.latin").unwrap();
+        assert!(serde_json::to_string(&tag).is_err());
+        assert!(serde_json::from_str::<Tag>("\"ability.latin\"").is_err());
+        assert!(serde_json::from_str::<Tag>("[0,1]").is_err());
+    }
+
+    /// Tests tags serialized in a registry scope are read into another registry by their strings
+    #[test]
+    fn serde_registry_test()
+    {
+        let mut registry = TagRegistry::new();
+        let tags = vec![registry.get_or_register_tag("ability.latin").unwrap(), registry.get_or_register_tag("spell.form").unwrap()];
+        let json = registry.to_json_string(&tags).unwrap();
+        assert_eq!(json, "[\"ability.latin\",\"spell.form\"]");
+
+        let mut other = TagRegistry::new_with_reserved(&["spell", "form"]);
+        let loaded: Vec<Tag> = other.from_json_str(&json).unwrap();
+        assert_ne!(loaded, tags);
+        assert_eq!(loaded[0], other.get_tag("ability.latin").unwrap().unwrap());
+        assert_eq!(loaded[1], other.get_tag("spell.form").unwrap().unwrap());
+
+        // Tags from another registry can not be written
+        let unknown = vec![TagRegistry::new_with_reserved(&["a", "b", "c", "d", "e"]).get_tag("e").unwrap().unwrap()];
+        assert!(registry.to_json_string(&unknown).is_err());
+        assert!(registry.from_json_str::<Tag>("\"not! valid\"").is_err());
+    }
+
+    /// Tests the counted tags of a set are written as string keys
+    #[test]
+    fn serde_tagset_test()
+    {
+        let mut registry = TagRegistry::new();
+        let latin = registry.get_or_register_tag("ability.latin").unwrap();
+        let mut tags = TagSet::new();
+        tags.add_tag_count(&latin, 2);
+        let json = registry.to_json_string(&tags).unwrap();
+        assert!(json.contains("{\"ability.latin\":2}"));
+
+        let mut other = TagRegistry::new_with_reserved(&["latin"]);
+        let loaded: TagSet = other.from_json_str(&json).unwrap();
+        assert_eq!(loaded.get_tag_count(&other.get_tag("ability.latin").unwrap().unwrap()), 2);
+        assert_eq!(loaded.get_tag_count(&other.get_tag("ability").unwrap().unwrap()), 2);
+    }
+
+    #[test]
+    fn suggest_tag_test()
+    {
+        let mut registry = TagRegistry::new();
+        let known = vec![registry.get_or_register_tag("ability.magic theory").unwrap(), registry.get_or_register_tag("ability.latin").unwrap()];
+
+        assert_eq!(registry.suggest_tag("Ability.Magic Theroy", &known), Some("ability.magic theory".to_string()));
+        assert_eq!(registry.suggest_tag("spell.form", &known), None);
+    }
+
+    /// Tests merging a registry gives the tags of the other registry the ids of this registry
+    #[test]
+    fn merge_test()
+    {
+        let mut registry = TagRegistry::new_with_reserved(&["spell", "form"]);
+        let spell_form = registry.get_tag("spell.form").unwrap().unwrap();
+        let mut other = TagRegistry::new();
+        let latin = other.get_or_register_tag("ability.latin").unwrap();
+        let ignem = other.get_or_register_tag("spell.form.ignem").unwrap();
+
+        let remap = registry.merge(&other);
+        assert!(!remap.is_identity());
+        assert_eq!(remap.remap_tag(&latin), registry.get_tag("ability.latin").unwrap().unwrap());
+        assert_eq!(remap.remap_tag(&ignem), registry.get_tag("spell.form.ignem").unwrap().unwrap());
+        // Subtags already in the registry keep their ids
+        assert_eq!(registry.get_tag("spell.form").unwrap().unwrap(), spell_form);
+        assert!(registry.clone().merge(&registry).is_identity());
+
+        let mut set = TagSet::new();
+        set.add_tag(&latin);
+        set.add_tag_count(&ignem, 2);
+        set.remap_tags(&remap);
+        assert_eq!(set.get_tag_count(&registry.get_tag("spell.form.ignem").unwrap().unwrap()), 2);
+        assert_eq!(set.get_immediate_matching_prefix(&spell_form), vec![registry.get_tag("spell.form.ignem").unwrap().unwrap()]);
+        assert!(set.has_tag(&registry.get_tag("ability").unwrap().unwrap()));
+    }
+
+    #[test]
+    fn rename_and_alias_test()
+    {
+        let mut registry = TagRegistry::new();
+        let mut t = |s: &str| registry.get_or_register_tag(s).unwrap();
+        let (mt, score, exp, latin) = (t("ability.magic theory"), t("ability.magic theory.score"), t("ability.magic theory.exp"), t("ability.latin"));
+        let (score_exp, skill) = (t("ability.magic theory.score.exp"), t("skill.magic theory"));
+
+        let rename = TagRename::new(mt.clone(), score.clone());
+        assert_eq!(rename.rename(&exp), Some(score_exp.clone()));
+        assert_eq!(rename.rename(&mt), Some(score.clone()));
+        assert_eq!(rename.rename(&latin), None);
+        // Tags already under the new name are not renamed again
+        assert_eq!(rename.rename(&score_exp), None);
+
+        let mut aliases = TagAliases::new();
+        aliases.insert(mt.clone(), score.clone());
+        assert_eq!(*aliases.resolve(&exp), score_exp);
+        assert_eq!(*aliases.resolve(&mt), score);
+        // Tags under the new name are not aliased again
+        assert_eq!(*aliases.resolve(&score_exp), score_exp);
+        assert_eq!(*aliases.resolve(&latin), latin);
+
+        // Aliases resolve once, so moving the new name moves the existing alias too
+        aliases.insert(score.clone(), skill.clone());
+        assert_eq!(aliases.get(&mt), Some(&skill));
+        assert_eq!(*aliases.resolve(&exp), t("skill.magic theory.exp"));
+
+        // Sources are rewritten by name, keeping the rest of the source as written
+        let named = NamedMapping::new(&rename, &registry);
+        assert_eq!(named.remap_source("rounddown(sqrt(8*ability.magic theory.exp/5+1)-1)/2"), "rounddown(sqrt(8*ability.magic theory.score.exp/5+1)-1)/2");
+        assert_eq!(named.remap_source("sum( ability.magic theory ) > ability.latin"), "sum( ability.magic theory.score ) > ability.latin");
+        assert_eq!(named.remap_source("ability.magic theory.score * 2"), "ability.magic theory.score * 2");
+        assert_eq!(TagRename::new(mt, score).remap_source("ability.magic theory * 2"), "ability.magic theory * 2");
+    }
+
     /// Test to ensure the interner uses usize indexes in order
     #[test]
     fn tag_intern_test()
@@ -2,7 +2,7 @@ use std::collections::HashSet;
 
 use serde::{Deserialize, Serialize};
 
-use crate::api::data::{attribute::AttributeTemplate, conditional::ConditionalTemplate, equation::EquationTemplate, error::TemplateError, modifier::ModifierTemplate, tag::{Tag, TagTemplate}};
+use crate::api::data::{attribute::AttributeTemplate, conditional::ConditionalTemplate, effect::EffectTemplate, equation::EquationTemplate, error::TemplateError, modifier::ModifierTemplate, tag::{Tag, TagTemplate}};
 
 /// Used to indicate that a struct is a template struct.
 /// Mainly useful for the Templated enum such that
@@ -80,6 +80,17 @@ where
         }
     }
 
+    /// Gets a copy of the completed value, attempting to
+    /// complete the template if this is still a template.
+    pub fn attempt_complete(&self) -> Result<C, TemplateError>
+    {
+        match self
+        {
+            Templated::Template(t) => t.attempt_complete(),
+            Templated::Complete(c) => Ok(c.clone()),
+        }
+    }
+
     /// Convert this templated value into a completed
     /// value (if it exists). None is returned if the
     /// value is not finished templating.
@@ -150,6 +161,7 @@ pub enum TemplateValue
 {
     Attribute(AttributeTemplate),
     Conditional(ConditionalTemplate),
+    Effect(EffectTemplate),
     Equation(EquationTemplate),
     Modifier(ModifierTemplate),
     Tag(TagTemplate),
@@ -164,6 +176,7 @@ impl TemplateValue
         {
             TemplateValue::Attribute(attribute_template) => attribute_template.get_required_inputs(),
             TemplateValue::Conditional(conditional_template) => conditional_template.get_required_inputs(),
+            TemplateValue::Effect(effect_template) => effect_template.get_required_inputs(),
             TemplateValue::Equation(equation_template) => equation_template.get_required_inputs(),
             TemplateValue::Modifier(modifier_template) => modifier_template.get_required_inputs(),
             TemplateValue::Tag(tag_template) => tag_template.get_required_inputs(),
@@ -176,9 +189,97 @@ impl TemplateValue
         {
             TemplateValue::Attribute(attribute_template) => attribute_template.is_complete(),
             TemplateValue::Conditional(conditional_template) => conditional_template.is_complete(),
+            TemplateValue::Effect(effect_template) => effect_template.is_complete(),
             TemplateValue::Equation(equation_template) => equation_template.is_complete(),
             TemplateValue::Modifier(modifier_template) => modifier_template.is_complete(),
             TemplateValue::Tag(tag_template) => tag_template.is_complete(),
         }
     }
-}
\ No newline at end of file
+}
+
+/// The kind of value a template input accepts, along with any constraints on it.
+/// Declared on a context template so forms for its inputs can be built and checked from the template alone.
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+pub enum TemplateInputType
+{
+    /// Any tag. Inputs which are not declared are treated as this.
+    Tag,
+    /// A tag under the prefix. Ex: "ability.latin" for the prefix "ability"
+    TagWithPrefix(Tag),
+    /// One of the given tags
+    OneOf(Vec<Tag>),
+    /// A number, with an optional inclusive min and max.
+    /// Number inputs are set as an attribute named by the input, which templated values can reference.
+    Number { min: Option<f32>, max: Option<f32> },
+}
+
+/// A value given to fill a template input
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+pub enum TemplateInputValue
+{
+    Tag(Tag),
+    Number(f32),
+}
+
+impl TemplateInputType
+{
+    /// Checks the value meets the type and constraints of this input
+    pub fn validate(&self, input_name: &str, value: &TemplateInputValue) -> Result<(), TemplateError>
+    {
+        let is_valid = match (self, value)
+        {
+            (TemplateInputType::Tag, TemplateInputValue::Tag(_)) => true,
+            (TemplateInputType::TagWithPrefix(prefix), TemplateInputValue::Tag(t)) => t.has_prefix(prefix) && t != prefix,
+            (TemplateInputType::OneOf(options), TemplateInputValue::Tag(t)) => options.contains(t),
+            (TemplateInputType::Number { min, max }, TemplateInputValue::Number(n)) =>
+                !n.is_nan() && min.is_none_or(|min| *n >= min) && max.is_none_or(|max| *n <= max),
+            _ => return Err(TemplateError::InputTypeMismatch(input_name.to_string())),
+        };
+
+        if is_valid
+        {
+            Ok(())
+        }
+        else
+        {
+            Err(TemplateError::InputValueInvalid(input_name.to_string(), value.clone()))
+        }
+    }
+
+    pub fn is_number(&self) -> bool
+    {
+        matches!(self, TemplateInputType::Number { .. })
+    }
+}
+
+#[cfg(test)]
+mod unit_tests
+{
+    use super::*;
+
+    fn tag(s: &str) -> Tag
+    {
+        Tag::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn validate_input_test()
+    {
+        let prefix = TemplateInputType::TagWithPrefix(tag("ability"));
+        assert!(prefix.validate("ability", &TemplateInputValue::Tag(tag("ability.latin"))).is_ok());
+        assert!(prefix.validate("ability", &TemplateInputValue::Tag(tag("ability"))).is_err());
+        assert_eq!(prefix.validate("ability", &TemplateInputValue::Tag(tag("art.creo"))),
+            Err(TemplateError::InputValueInvalid("ability".to_string(), TemplateInputValue::Tag(tag("art.creo")))));
+        assert_eq!(prefix.validate("ability", &TemplateInputValue::Number(5.0)), Err(TemplateError::InputTypeMismatch("ability".to_string())));
+
+        let one_of = TemplateInputType::OneOf(vec![tag("spell.range.voice"), tag("spell.range.touch")]);
+        assert!(one_of.validate("range", &TemplateInputValue::Tag(tag("spell.range.touch"))).is_ok());
+        assert!(one_of.validate("range", &TemplateInputValue::Tag(tag("spell.range.sight"))).is_err());
+
+        let number = TemplateInputType::Number { min: Some(1.0), max: None };
+        assert!(number.validate("exp", &TemplateInputValue::Number(1.0)).is_ok());
+        assert!(number.validate("exp", &TemplateInputValue::Number(0.5)).is_err());
+        assert!(number.validate("exp", &TemplateInputValue::Number(f32::NAN)).is_err());
+        assert!(TemplateInputType::Tag.validate("exp", &TemplateInputValue::Number(1.0)).is_err());
+    }
+}
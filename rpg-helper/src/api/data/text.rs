@@ -0,0 +1,127 @@
+use crate::api::data::{error::DataError, tag::{RemapTags, Tag, TagMapping, TagRegistry}};
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// A non-numeric value of a context, such as the name of a character
+/// or the Form of a spell. Text values can be compared with `==` and `!=`
+/// in equations and conditionals, but not used in arithmetic.
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+pub enum TextValue
+{
+    /// Free text. Ex: "Bonisagus of House Bonisagus"
+    Text(String),
+    /// One tag chosen from the tags under a prefix. Ex: "form.ignem" chosen from the options "form".
+    /// In an equation, an enum is equal to the tag it has selected, so "spell.form == form.ignem" is true.
+    Enum
+    {
+        options: Tag,
+        selected: Tag,
+    },
+}
+
+impl TextValue
+{
+    /// Creates an enum value, failing if the selected tag is not one of the options
+    pub fn new_enum(options: Tag, selected: Tag) -> Result<TextValue, DataError>
+    {
+        let result = TextValue::Enum { options, selected };
+        result.validate()?;
+        Ok(result)
+    }
+
+    /// Ensures an enum value has selected a tag under its options prefix
+    pub fn validate(&self) -> Result<(), DataError>
+    {
+        match self
+        {
+            TextValue::Enum { options, selected } if !selected.has_prefix(options) || selected == options =>
+            {
+                Err(DataError::EnumSelectionInvalid(options.clone(), selected.clone()))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// The text, or the name of the selected tag of an enum. None if the selected tag is not in the registry
+    pub fn to_string(&self, registry: &TagRegistry) -> Option<String>
+    {
+        match self
+        {
+            TextValue::Text(s) => Some(s.clone()),
+            TextValue::Enum { selected, .. } => selected.to_string(registry),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone, Default)]
+pub struct TextSet
+{
+    texts: HashMap<Tag, TextValue>,
+}
+
+impl TextSet
+{
+    pub fn new() -> TextSet
+    {
+        TextSet { texts: HashMap::new() }
+    }
+
+    pub fn get(&self, text_name: &Tag) -> Option<&TextValue>
+    {
+        self.texts.get(text_name)
+    }
+
+    pub fn has_text(&self, text_name: &Tag) -> bool
+    {
+        self.texts.contains_key(text_name)
+    }
+
+    pub fn set_text(&mut self, text_name: &Tag, value: TextValue) -> Option<TextValue>
+    {
+        self.texts.insert(text_name.clone(), value)
+    }
+
+    pub fn remove_text(&mut self, text_name: &Tag) -> Option<TextValue>
+    {
+        self.texts.remove(text_name)
+    }
+
+    pub fn iter(&self) -> std::collections::hash_map::Iter<'_, Tag, TextValue>
+    {
+        self.texts.iter()
+    }
+}
+
+impl RemapTags for TextValue
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        if let TextValue::Enum { options, selected } = self
+        {
+            options.remap_tags(remap);
+            selected.remap_tags(remap);
+        }
+    }
+}
+
+impl RemapTags for TextSet
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        self.texts.remap_tags(remap);
+    }
+}
+
+impl IntoIterator for TextSet
+{
+    type Item = (Tag, TextValue);
+
+    type IntoIter = std::collections::hash_map::IntoIter<Tag, TextValue>;
+
+    fn into_iter(self) -> Self::IntoIter
+    {
+        self.texts.into_iter()
+    }
+}
@@ -0,0 +1,263 @@
+use serde::{Deserialize, Serialize};
+
+use crate::api::data::{modifier::ModifierChange, tag::{Tag, TagRegistry}, text::TextValue};
+
+/// Explains how a value of a context was evaluated.
+///
+/// Created through `Context::get_value_trace`, this contains the base value
+/// (from an attribute or an equation), the trace of every value the equation
+/// referenced, and every modifier that could have applied to the value.
+///
+/// Ex: "Why is my Magic Theory 7?"
+/// ```text
+/// ability.magic theory = 7
+/// |__equation rounddown((sqrt(8 * ability.magic theory.exp / 5 + 1)-1)/2) = 5
+/// |  |__ability.magic theory.exp = 75
+/// |     |__attribute = 75
+/// |__modifier puissant magic theory applied (+2)
+///    |__puissant.active = true (virtue.puissant)
+///       |__virtue.puissant = true
+/// ```
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+pub struct ValueTrace
+{
+    pub name: Tag,
+    pub base: BaseTrace,
+    pub modifiers: Vec<ModifierTrace>,
+    pub result: f32,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+pub enum BaseTrace
+{
+    Attribute(f32),
+    Equation
+    {
+        equation: String,
+        references: Vec<Trace>,
+        result: f32,
+    },
+}
+
+/// Explains how a conditional of a context was evaluated.
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+pub struct ConditionalTrace
+{
+    pub name: Tag,
+    pub equation: String,
+    pub references: Vec<Trace>,
+    pub result: bool,
+}
+
+/// A modifier which could apply to a value. Whether it was applied is decided by its condition
+/// and, if it is part of a stacking group, whether the group's policy chose it.
+/// Modifiers are listed in the order they are applied (See `ModifierSet::get_applicable_modifiers`).
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+pub struct ModifierTrace
+{
+    pub name: Tag,
+    pub condition: ConditionalTrace,
+    pub change: ModifierChange,
+    pub amount: ModifierAmountTrace,
+    pub group: Option<Tag>,
+    /// The condition passed, but another modifier of the stacking group was applied instead
+    pub suppressed: bool,
+}
+
+impl ModifierTrace
+{
+    pub fn is_applied(&self) -> bool
+    {
+        self.condition.result && !self.suppressed
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+pub enum ModifierAmountTrace
+{
+    BasicValue(f32),
+    /// The other value is only evaluated when the modifier is applied
+    FromOtherValue(Tag, Option<Box<ValueTrace>>),
+}
+
+impl ModifierAmountTrace
+{
+    /// The amount used by the change, if it is known
+    pub fn get_amount(&self) -> Option<f32>
+    {
+        match self
+        {
+            ModifierAmountTrace::BasicValue(v) => Some(*v),
+            ModifierAmountTrace::FromOtherValue(_, trace) => trace.as_ref().map(|t| t.result),
+        }
+    }
+}
+
+/// Anything referenced while evaluating an equation or conditional
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+pub enum Trace
+{
+    Value(ValueTrace),
+    Conditional(ConditionalTrace),
+    Text(Tag, TextValue),
+    /// A tag which is neither a value, conditional, or text, thus only its presence in the context is checked
+    Tag(Tag, bool),
+}
+
+impl ValueTrace
+{
+    /// Writes the trace as a tree (See `ValueTrace`), naming tags through the registry
+    pub fn to_string(&self, registry: &TagRegistry) -> String
+    {
+        let mut result = String::new();
+        value_trace_display_helper(&mut result, registry, "", "", self);
+        result
+    }
+}
+
+impl ConditionalTrace
+{
+    /// Writes the trace as a tree (See `ValueTrace`), naming tags through the registry
+    pub fn to_string(&self, registry: &TagRegistry) -> String
+    {
+        let mut result = String::new();
+        conditional_trace_display_helper(&mut result, registry, "", "", self);
+        result
+    }
+}
+
+/// The name of a tag, or its subtags if the tag is not from the registry
+fn tag_name(t: &Tag, registry: &TagRegistry) -> String
+{
+    t.to_string(registry).unwrap_or_else(|| format!("{:?}", t.as_subtag_slice()))
+}
+
+/// Pushes a line for the node and returns the prefix to use for its children
+fn push_line(result: &mut String, prefix: &str, branch: &str, line: String)
+{
+    result.push_str(prefix);
+    result.push_str(branch);
+    result.push_str(&line);
+    result.push('\n');
+}
+
+/// Writes each child under the given prefix, using the last child to close off the branch
+fn children_display_helper<T>(result: &mut String, registry: &TagRegistry, prefix: &str, children: &[T], f: impl Fn(&mut String, &TagRegistry, &str, &str, &T))
+{
+    for (i, c) in children.iter().enumerate()
+    {
+        if i + 1 == children.len()
+        {
+            f(result, registry, &format!("{}   ", prefix), &format!("{}|__", prefix), c);
+        }
+        else
+        {
+            f(result, registry, &format!("{}|  ", prefix), &format!("{}|__", prefix), c);
+        }
+    }
+}
+
+fn value_trace_display_helper(result: &mut String, registry: &TagRegistry, prefix: &str, branch: &str, trace: &ValueTrace)
+{
+    push_line(result, "", branch, format!("{} = {}", tag_name(&trace.name, registry), trace.result));
+
+    let num_children = 1 + trace.modifiers.len();
+    let base_prefix = if num_children == 1 { format!("{}   ", prefix) } else { format!("{}|  ", prefix) };
+    match &trace.base
+    {
+        BaseTrace::Attribute(v) => push_line(result, prefix, "|__", format!("attribute = {}", v)),
+        BaseTrace::Equation { equation, references, result: v } =>
+        {
+            push_line(result, prefix, "|__", format!("equation {} = {}", equation, v));
+            children_display_helper(result, registry, &base_prefix, references, trace_display_helper);
+        },
+    }
+    children_display_helper(result, registry, prefix, &trace.modifiers, modifier_trace_display_helper);
+}
+
+fn conditional_trace_display_helper(result: &mut String, registry: &TagRegistry, prefix: &str, branch: &str, trace: &ConditionalTrace)
+{
+    push_line(result, "", branch, format!("{} = {} ({})", tag_name(&trace.name, registry), trace.result, trace.equation));
+    children_display_helper(result, registry, prefix, &trace.references, trace_display_helper);
+}
+
+fn modifier_trace_display_helper(result: &mut String, registry: &TagRegistry, prefix: &str, branch: &str, trace: &ModifierTrace)
+{
+    let amount = trace.amount.get_amount().map(|v| v.to_string()).unwrap_or_else(|| "?".to_string());
+    let amount = match &trace.change
+    {
+        ModifierChange::BasicValue(_) | ModifierChange::FromOtherValue(_) if amount.starts_with('-') => amount,
+        ModifierChange::BasicValue(_) | ModifierChange::FromOtherValue(_) => format!("+{}", amount),
+        ModifierChange::Multiply(_) => format!("x{}", amount),
+        ModifierChange::Override(_) => format!("={}", amount),
+        ModifierChange::Min(_) => format!("min {}", amount),
+        ModifierChange::Max(_) => format!("max {}", amount),
+    };
+    let state = match &trace.group
+    {
+        Some(group) if trace.suppressed => format!("stacked out by {}", tag_name(group, registry)),
+        _ if trace.is_applied() => "applied".to_string(),
+        _ => "skipped".to_string(),
+    };
+    push_line(result, "", branch, format!("modifier {} {} ({})", tag_name(&trace.name, registry), state, amount));
+
+    match &trace.amount
+    {
+        ModifierAmountTrace::FromOtherValue(_, Some(other)) =>
+        {
+            conditional_trace_display_helper(result, registry, &format!("{}|  ", prefix), &format!("{}|__", prefix), &trace.condition);
+            value_trace_display_helper(result, registry, &format!("{}   ", prefix), &format!("{}|__", prefix), other);
+        },
+        _ => conditional_trace_display_helper(result, registry, &format!("{}   ", prefix), &format!("{}|__", prefix), &trace.condition),
+    }
+}
+
+fn trace_display_helper(result: &mut String, registry: &TagRegistry, prefix: &str, branch: &str, trace: &Trace)
+{
+    match trace
+    {
+        Trace::Value(v) => value_trace_display_helper(result, registry, prefix, branch, v),
+        Trace::Conditional(c) => conditional_trace_display_helper(result, registry, prefix, branch, c),
+        Trace::Text(t, TextValue::Text(s)) => push_line(result, "", branch, format!("{} = \"{}\"", tag_name(t, registry), s)),
+        Trace::Text(t, TextValue::Enum { selected, .. }) => push_line(result, "", branch, format!("{} = {}", tag_name(t, registry), tag_name(selected, registry))),
+        Trace::Tag(t, present) => push_line(result, "", branch, format!("{} = {}", tag_name(t, registry), present)),
+    }
+}
+
+#[cfg(test)]
+mod unit_tests
+{
+    use crate::api::data::{modifier::ModifierChange, tag::TagRegistry, text::TextValue, trace::{BaseTrace, ConditionalTrace, ModifierAmountTrace, ModifierTrace, Trace, ValueTrace}};
+
+    #[test]
+    fn to_string_test()
+    {
+        let mut registry = TagRegistry::new();
+        let mut tag = |s: &str| registry.get_or_register_tag(s).unwrap();
+        let exp = ValueTrace { name: tag("ability.magic theory.exp"), base: BaseTrace::Attribute(75.0), modifiers: vec![], result: 75.0 };
+        let condition = ConditionalTrace { name: tag("puissant.active"), equation: "virtue.puissant".to_string(), references: vec![Trace::Tag(tag("virtue.puissant"), true)], result: true };
+        let trace = ValueTrace
+        {
+            name: tag("ability.magic theory"),
+            base: BaseTrace::Equation { equation: "rounddown((sqrt(8 * ability.magic theory.exp / 5 + 1)-1)/2)".to_string(), references: vec![Trace::Value(exp)], result: 5.0 },
+            modifiers: vec![ModifierTrace { name: tag("puissant magic theory"), condition, change: ModifierChange::BasicValue(2.0), amount: ModifierAmountTrace::BasicValue(2.0), group: None, suppressed: false }],
+            result: 7.0,
+        };
+        let text = Trace::Text(tag("spell.form"), TextValue::Enum { options: tag("form"), selected: tag("form.ignem") });
+        let is_ignem = ConditionalTrace { name: tag("spell.is ignem"), equation: "spell.form == form.ignem".to_string(), references: vec![text], result: true };
+
+        assert_eq!(trace.to_string(&registry), concat!(
+            "ability.magic theory = 7\n",
+            "|__equation rounddown((sqrt(8 * ability.magic theory.exp / 5 + 1)-1)/2) = 5\n",
+            "|  |__ability.magic theory.exp = 75\n",
+            "|     |__attribute = 75\n",
+            "|__modifier puissant magic theory applied (+2)\n",
+            "   |__puissant.active = true (virtue.puissant)\n",
+            "      |__virtue.puissant = true\n"));
+
+        assert_eq!(is_ignem.to_string(&registry), "spell.is ignem = true (spell.form == form.ignem)\n|__spell.form = form.ignem\n");
+
+        // Tags from another registry are shown by their subtags
+        assert!(trace.to_string(&TagRegistry::new()).starts_with("[Subtag"));
+    }
+}
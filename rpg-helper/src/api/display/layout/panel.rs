@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-use crate::api::{data::tag::Tag, display::{layout::{Dimension, LayoutDirection}, style::StyleId}};
+use crate::api::{data::{context::{Context, TagFilter}, error::DataError, tag::Tag}, display::{layout::{Dimension, LayoutDirection}, style::StyleId}};
 
 #[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
 pub struct Panel
@@ -30,11 +30,26 @@ pub enum PanelType
     // QueryValueResult
     // - Gives the immediate result tag (ex: "values.characteristics.intelligence")
     // - Gives the numeric value
-    QueryValues,
+    // The values are found with the filter, such as "attributes.characteristics.*"
+    QueryValues(TagFilter),
     QueryAbilities,
     QueryItems,
 }
 
+impl PanelType
+{
+    /// The (tag, value) pairs to display for a query values panel.
+    /// Other panels do not query for values, so have none.
+    pub fn query_values(&self, ctx: &Context) -> Result<Vec<(Tag, f32)>, DataError>
+    {
+        match self
+        {
+            PanelType::QueryValues(filter) => ctx.query_values(filter),
+            _ => Ok(vec![]),
+        }
+    }
+}
+
 // A leaf display node. Simply displays a numeric value derived from an attribute or equation
 // There are two options for this display. One is a direct query for the value,
 // in which the full path of the tag is given. The other is by providing a tag
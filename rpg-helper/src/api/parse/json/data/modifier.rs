@@ -1,6 +1,6 @@
-use serde_json::Value;
+use serde_json::{Map, Value};
 
-use crate::api::{data::modifier::{Modifier, ModifierSet}, parse::json::{JsonParseError, ParseJson}};
+use crate::api::{data::{modifier::{Modifier, ModifierSet}, tag::Tag}, parse::json::{JsonParseError, ParseJson}};
 
 impl ParseJson for Modifier
 {
@@ -17,23 +17,49 @@ impl ParseJson for Modifier
 
 impl ParseJson for ModifierSet
 {
+    /// Read from a list of modifiers, or from an object with the list of modifiers under "modifiers"
+    /// and the stacking policies of their groups under an optional "stacking_policies"
     fn from_json(json: Value) -> Result<Self, crate::api::ApiError> where Self: Sized
     {
         match json
         {
-            Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_) | Value::Object(_) => Err(JsonParseError::InvalidRootValue(json).into()),
-            Value::Array(mods) => 
+            Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_) => Err(JsonParseError::InvalidRootValue(json).into()),
+            Value::Array(mods) =>
             {
                 let mut set = ModifierSet::new();
                 for m in mods
                 {
-                    set.add_modifier(Modifier::from_json(m)?);
+                    set.set_modifier(Modifier::from_json(m)?);
+                }
+                Ok(set)
+            },
+            Value::Object(mut m) =>
+            {
+                let mut set = match m.remove("modifiers")
+                {
+                    Some(mods @ Value::Array(_)) => ModifierSet::from_json(mods)?,
+                    Some(v) => return Err(JsonParseError::InvalidValueFound(v).into()),
+                    None => return Err(JsonParseError::ExpectedValueNotFound("modifiers".to_string()).into()),
+                };
+                match m.remove("stacking_policies")
+                {
+                    Some(Value::Object(policies)) =>
+                    {
+                        for (group, policy) in policies
+                        {
+                            set.set_stacking_policy(Tag::from_json(Value::String(group))?, serde_json::from_value(policy)?);
+                        }
+                    },
+                    Some(v) => return Err(JsonParseError::InvalidValueFound(v).into()),
+                    None => (),
                 }
                 Ok(set)
             },
         }
     }
 
+    /// Written as a list of modifiers, unless a group of the set has a stacking policy.
+    /// The policies are then written along with the list, as they decide which modifiers of a group apply.
     fn to_json(&self) -> Value
     {
         let mut result = vec![];
@@ -41,7 +67,24 @@ impl ParseJson for ModifierSet
         {
             result.push(m.to_json());
         }
-        Value::Array(result)
+
+        let mut policies = Map::new();
+        for (group, policy) in self.iter_stacking_policies()
+        {
+            policies.insert(group.to_str().to_string(), Value::String(format!("{:?}", policy)));
+        }
+
+        if policies.is_empty()
+        {
+            Value::Array(result)
+        }
+        else
+        {
+            let mut m = Map::new();
+            m.insert("modifiers".to_string(), Value::Array(result));
+            m.insert("stacking_policies".to_string(), Value::Object(policies));
+            Value::Object(m)
+        }
     }
 }
 
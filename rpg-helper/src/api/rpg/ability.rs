@@ -2,7 +2,7 @@ use std::collections::{HashMap, HashSet};
 
 use serde::{Deserialize, Serialize};
 
-use crate::api::{data::{context::{Context, ContextTemplate, CtxValue, TagFilter}, effect::Effect, tag::Tag, template::TemplateValue}, rpg::input::InputAction};
+use crate::api::{data::{context::{Context, ContextTemplate, CtxValue, TagFilter}, effect::Effect, error::DataError, tag::{RemapTags, Tag, TagMapping}, template::TemplateValue}, rpg::input::InputAction};
 
 /// An ability is given to a character
 /// It grants modifiers, can alter attributes, equations, conditionals, and state-tags
@@ -43,14 +43,94 @@ pub struct Ability
                                             //       such as adding them all together or tallying up values that land on a side
 }
 
+impl Ability
+{
+    pub fn new(id: Tag, ctx: Context) -> Ability
+    {
+        Ability { id, passive_effects: vec![], conditional_effects: HashMap::new(), ctx }
+    }
+
+    pub fn with_passive_effect(mut self, effect: Effect) -> Self
+    {
+        self.passive_effects.push(effect);
+        self
+    }
+
+    pub fn with_conditional_effect(mut self, condition: Tag, effect: Effect) -> Self
+    {
+        self.conditional_effects.entry(condition).or_default().push(effect);
+        self
+    }
+
+    pub fn get_id(&self) -> &Tag
+    {
+        &self.id
+    }
+
+    pub fn get_context(&self) -> &Context
+    {
+        &self.ctx
+    }
+
+    /// Layers the values of this ability onto a character's context, then applies its effects.
+    /// Conditional effects are applied when their condition is true after the passive effects.
+    pub fn apply_to(&self, ctx: &mut Context) -> Result<(), DataError>
+    {
+        ctx.layer_context(&self.ctx)?;
+        for e in self.passive_effects.iter()
+        {
+            ctx.apply_effect(e)?;
+        }
+
+        // Sorted so the effects are applied in the same order every time
+        let mut conditions: Vec<&Tag> = self.conditional_effects.keys().collect();
+        conditions.sort();
+        for c in conditions
+        {
+            if ctx.eval_conditional(c)?
+            {
+                for e in self.conditional_effects[c].iter()
+                {
+                    ctx.apply_effect(e)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl RemapTags for Ability
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        self.id.remap_tags(remap);
+        self.passive_effects.remap_tags(remap);
+        self.conditional_effects.remap_tags(remap);
+        self.ctx.remap_tags(remap);
+    }
+}
+
 #[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
 pub struct AbilitySet
 {
     abilities: HashMap<Tag, Ability>,
 }
 
+impl Default for AbilitySet
+{
+    fn default() -> Self
+    {
+        AbilitySet::new()
+    }
+}
+
 impl AbilitySet
 {
+    pub fn new() -> AbilitySet
+    {
+        AbilitySet { abilities: HashMap::new() }
+    }
+
     pub fn get_ability(&self, ability_id: &Tag) -> Option<&Ability>
     {
         self.abilities.get(ability_id)
@@ -23,7 +23,7 @@ use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::api::{data::{context::Context, effect::Effect, error::DataError, tag::Tag}, rpg::{ability::{Ability, AbilitySet}, event::Event, timeline::{Date, Timeline}}};
+use crate::api::{data::{context::{Context, LayerHandle}, effect::Effect, error::DataError, layer::LayeredContext, tag::{Subtag, Tag}}, rpg::{ability::{Ability, AbilitySet}, event::{Event, EventError, EventModification}, inventory::Inventory, timeline::{Date, Timeline}}};
 
 // First todo:
 //      1. Parse json in order to import character data
@@ -60,13 +60,42 @@ pub struct Character
     data: CharacterData,    // The character's base starting data
     timeline: Timeline,     // All the changes applied to character-creation data
     current_date: Date,
-    context_data: Context,  // Additional context data applied not through the timeline (ruleset data)
+    context_data: LayeredContext,  // Additional context data applied not through the timeline (ruleset data, locations)
 
     // Whenever we change the current date, the final data of the character changes
     // This is the data we actually read for the purposes of gameplay.
     // The cached data is set to None whenever the
     // cache is invalidated.
     cached_final_data: Option<CharacterData>,
+    // The events which could not be applied when the final data was last built
+    invalid_events: Vec<InvalidEvent>,
+
+    // Snapshots of the data part way through the timeline, ordered by the events replayed.
+    // Moving to another date only replays the events after the nearest snapshot.
+    #[serde(skip)]
+    snapshots: Vec<TimelineSnapshot>,
+    #[serde(default = "default_snapshot_interval")]
+    snapshot_interval: usize,   // A snapshot is taken every time this many events are replayed. 0 takes no snapshots
+}
+
+/// The number of events replayed between each snapshot, unless set through `Character::with_snapshot_interval`
+const DEFAULT_SNAPSHOT_INTERVAL: usize = 16;
+
+/// Characters saved before snapshots were taken use the default interval
+fn default_snapshot_interval() -> usize
+{
+    DEFAULT_SNAPSHOT_INTERVAL
+}
+
+/// The data of a character after replaying the start of its timeline.
+/// The replayed events do not depend on the current date, so a snapshot can be used
+/// for any date which is after every event it replayed.
+#[derive(Debug, PartialEq, Clone)]
+struct TimelineSnapshot
+{
+    replayed: usize,                    // The number of events from the start of the timeline replayed
+    data: CharacterData,
+    invalid_events: Vec<InvalidEvent>,  // The invalid events among those replayed
 }
 
 /// The character state tracks the less
@@ -87,15 +116,53 @@ struct CharacterState
 }
 
 #[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
-struct CharacterData
+pub struct CharacterData
 {
     ctx: Context,
-    // abilities: AbilitySet,
-    // inventory: Inventory,
+    abilities: AbilitySet,
+    inventory: Inventory,
+    time_context: Option<Subtag>,   // Set by events which move the character to another time context
+}
+
+/// An event of a character's timeline which could not be applied when the timeline was replayed.
+/// The event is skipped, so the events after it are applied as if it never happened.
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+pub struct InvalidEvent
+{
+    pub index: usize,   // The place of the event in the timeline
+    pub id: Tag,
+    pub error: EventError,
 }
 
 impl Character
 {
+    pub fn new(data: Context, current_date: Date) -> Character
+    {
+        Character
+        {
+            data: CharacterData::new(data),
+            timeline: Timeline::new(),
+            current_date,
+            context_data: LayeredContext::new(),
+            cached_final_data: None,
+            invalid_events: vec![],
+            snapshots: vec![],
+            snapshot_interval: DEFAULT_SNAPSHOT_INTERVAL,
+        }
+    }
+
+    /// Sets how many events are replayed between each snapshot of the character's data.
+    /// Smaller intervals make changing dates faster at the cost of memory. 0 takes no snapshots.
+    pub fn with_snapshot_interval(mut self, interval: usize) -> Self
+    {
+        self.snapshot_interval = interval;
+        if interval == 0
+        {
+            self.snapshots.clear();
+        }
+        self
+    }
+
     /// Sets the active current date for the character.
     /// This changes the data of the character
     /// according to the events applied to the character.
@@ -112,10 +179,61 @@ impl Character
 
     /// Changes the data of a character. For the event to take place,
     /// the event's date must occur before the set current date.
+    /// The event is placed in order with the events of its time context (See `Timeline::add_event`),
+    /// and only the events from it onward are replayed again.
     pub fn add_event(&mut self, event: Event)
     {
-        self.timeline.add_event(event);
-        self.cached_final_data = None;
+        let index = self.timeline.add_event(event);
+        self.invalidate_snapshots(index);
+    }
+
+    /// Inserts an event at a place in the timeline, such as an event the character
+    /// experienced before their latest event. Only the events from it onward are replayed again.
+    pub fn insert_event(&mut self, index: usize, event: Event)
+    {
+        let index = index.min(self.timeline.len());
+        self.timeline.insert_event(index, event);
+        self.invalidate_snapshots(index);
+    }
+
+    /// Replaces the event with the same id, then replays every event after it.
+    /// An event whose date changes is moved to the place of its new date (See `Timeline::replace_event`),
+    /// in which case every event from the earlier of its old and new places is replayed.
+    /// Returns the old event along with every event which is invalid after the edit,
+    /// or None if there is no event with the id.
+    pub fn edit_event(&mut self, event: Event) -> Result<Option<(Event, Vec<InvalidEvent>)>, DataError>
+    {
+        let id = event.id.clone();
+        let index = self.timeline.get_event_index(&id);
+        match self.timeline.replace_event(event)
+        {
+            Some(old) =>
+            {
+                let moved_to = self.timeline.get_event_index(&id);
+                self.invalidate_snapshots(index.min(moved_to).unwrap_or(0));
+                self.update_final_data()?;
+                Ok(Some((old, self.invalid_events.clone())))
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Removes an event, then replays every event after it.
+    /// Returns the removed event along with every event which is invalid after the removal,
+    /// or None if there is no event with the id.
+    pub fn remove_event(&mut self, id: &Tag) -> Result<Option<(Event, Vec<InvalidEvent>)>, DataError>
+    {
+        let index = self.timeline.get_event_index(id);
+        match self.timeline.remove_event(id)
+        {
+            Some(old) =>
+            {
+                self.invalidate_snapshots(index.unwrap_or(0));
+                self.update_final_data()?;
+                Ok(Some((old, self.invalid_events.clone())))
+            },
+            None => Ok(None),
+        }
     }
     
     pub fn get_timeline(&self) -> &Timeline
@@ -123,13 +241,62 @@ impl Character
         &self.timeline
     }
 
+    /// Sorts the timeline by date (See `Timeline::sort`), replaying the events from the first one moved.
+    pub fn sort_timeline(&mut self)
+    {
+        if let Some(index) = self.timeline.sort()
+        {
+            self.invalidate_snapshots(index);
+        }
+    }
+
+    /// The data of the character at the current date, with every event up to it applied
+    pub fn get_final_data(&mut self) -> Result<&CharacterData, DataError>
+    {
+        if self.cached_final_data.is_none()
+        {
+            self.update_final_data()?;
+        }
+        Ok(self.cached_final_data.as_ref().unwrap())
+    }
+
+    /// The events up to the current date which could not be applied, in timeline order
+    pub fn get_invalid_events(&mut self) -> Result<&[InvalidEvent], DataError>
+    {
+        if self.cached_final_data.is_none()
+        {
+            self.update_final_data()?;
+        }
+        Ok(&self.invalid_events)
+    }
+
     /// Used to layer additional data, such as equations
-    /// from a ruleset
-    pub fn layer_ctx(mut self, ctx: &Context) -> Result<Self, DataError>
+    /// from a ruleset or the data of the location the character is in.
+    /// Returns the handle used to remove the data again (See `Character::remove_ctx`)
+    pub fn layer_ctx(&mut self, ctx: Context) -> Result<LayerHandle, DataError>
+    {
+        let handle = self.context_data.push_layer(ctx)?;
+        // Every snapshot was built on top of the layered data
+        self.invalidate_snapshots(0);
+        if let Err(e) = self.update_final_data()
+        {
+            self.context_data.pop_layer(handle)?;
+            return Err(e);
+        }
+        Ok(handle)
+    }
+
+    /// Removes data layered through `Character::layer_ctx`, returning the removed data.
+    /// For example, when a character leaves a location.
+    pub fn remove_ctx(&mut self, handle: LayerHandle) -> Result<Option<Context>, DataError>
     {
-        self.context_data.layer_context(&ctx)?;
-        self.update_final_data()?;
-        Ok(self)
+        let old = self.context_data.pop_layer(handle)?;
+        if old.is_some()
+        {
+            self.invalidate_snapshots(0);
+            self.update_final_data()?;
+        }
+        Ok(old)
     }
 
     /// Given a prefix tag, gets all immediate sub-tag values with that prefix
@@ -144,57 +311,424 @@ impl Character
 
     fn update_final_data(&mut self) -> Result<(), DataError>
     {
-        // Change the character's data based on the current year and all timeline data
-        let mut final_data = self.data.clone();
-        final_data.ctx.layer_context(&self.context_data)?;
+        // Events after the current date are skipped rather than ending the replay, as events do not have to be
+        // in order. Events of another time context can not be compared to the current date, so they are replayed.
+        // Snapshots only hold the events before the first skipped one, where every event is replayed.
+        let first_skipped = self.timeline.iter().position(|e| e.date > self.current_date).unwrap_or(self.timeline.len());
+
+        // Start from the latest snapshot which did not replay past the current date
+        let (mut final_data, mut invalid_events, start) = match self.snapshots.iter().rev().find(|s| s.replayed <= first_skipped)
+        {
+            Some(snapshot) => (snapshot.data.clone(), snapshot.invalid_events.clone(), snapshot.replayed),
+            None =>
+            {
+                // Change the character's data based on the current year and all timeline data
+                let mut data = self.data.clone();
+                data.ctx.layer_context(self.context_data.get_context())?;
+                (data, vec![], 0)
+            },
+        };
 
         // We create an empty timeline context which will be used
         // as a "scratch pad" of sorts for events.
         // This is useful for values such as the progress of completion for
         // a crafting of an item.
 
-        for e in self.timeline.iter()
+        for (index, e) in self.timeline.iter().enumerate().skip(start)
         {
-            if e.date <= self.current_date
+            if e.date > self.current_date
             {
-                final_data.apply_event(e)?;
+                continue;
             }
-            else
+
+            // Invalid events are skipped, so one bad edit does not hide the rest of the timeline
+            if let Err(error) = final_data.apply_event(e)
             {
-                // We can end early, as we know the rest of the list will only be greater
-                break;
+                invalid_events.push(InvalidEvent { index, id: e.id.clone(), error });
+            }
+
+            let replayed = index + 1;
+            if self.snapshot_interval > 0 && replayed % self.snapshot_interval == 0 && replayed <= first_skipped
+            {
+                if let Err(i) = self.snapshots.binary_search_by_key(&replayed, |s| s.replayed)
+                {
+                    self.snapshots.insert(i, TimelineSnapshot { replayed, data: final_data.clone(), invalid_events: invalid_events.clone() });
+                }
             }
         }
 
         // Save resultant cached_character
         self.cached_final_data = Some(final_data);
+        self.invalid_events = invalid_events;
         Ok(())
     }
+
+    /// Removes every snapshot which replayed the event at the index of the timeline,
+    /// such as after the event is edited. Snapshots before it are kept.
+    fn invalidate_snapshots(&mut self, index: usize)
+    {
+        self.snapshots.retain(|s| s.replayed <= index);
+        self.cached_final_data = None;
+    }
 }
 
 impl CharacterData
 {
+    pub fn new(ctx: Context) -> CharacterData
+    {
+        CharacterData { ctx, abilities: AbilitySet::new(), inventory: Inventory::new(), time_context: None }
+    }
+
+    /// The values of the character, without the values of granted abilities
+    pub fn get_context(&self) -> &Context
+    {
+        &self.ctx
+    }
+
+    pub fn get_abilities(&self) -> &AbilitySet
+    {
+        &self.abilities
+    }
+
+    pub fn get_inventory(&self) -> &Inventory
+    {
+        &self.inventory
+    }
+
+    /// The time context the character was last moved to by an event, if any
+    pub fn get_time_context(&self) -> Option<Subtag>
+    {
+        self.time_context
+    }
+
+    /// Builds the full context of the character, which is the values of the character
+    /// with every granted ability applied on top (ordered by ability id).
+    pub fn build_context(&self) -> Result<Context, DataError>
+    {
+        let mut result = self.ctx.clone();
+        let mut abilities: Vec<&Ability> = self.abilities.iter().collect();
+        abilities.sort_by(|a, b| a.get_id().cmp(b.get_id()));
+        for a in abilities
+        {
+            a.apply_to(&mut result)?;
+        }
+        Ok(result)
+    }
+
     /// Actually apply the changes of an event to the data of this character.
-    fn apply_event(&mut self, event: &Event) -> Result<(), DataError>
-    {
-        // for eff in event.get_character_mods(&self.ctx).iter()
-        // {
-        //     match &eff
-        //     {
-        //         CharacterModification::Effect(effect) =>
-        //         {
-        //             self.ctx.apply_effect(&effect.effect)?;
-        //         },
-        //         CharacterModification::Ability(ability_modification) =>
-        //         {
-        //             todo!()
-        //         },
-        //         CharacterModification::Item(item_modification) =>
-        //         {
-        //             todo!()
-        //         },
-        //     }
-        // }
+    /// If any modification of the event fails, the data is left unchanged.
+    fn apply_event(&mut self, event: &Event) -> Result<(), EventError>
+    {
+        let previous = self.clone();
+        // The context of the event is shared by its modifications, until one changes the values of the character
+        let mut event_ctx = None;
+        let result = event.get_event_modifications().iter().try_for_each(|m| self.apply_modification(event, m, &mut event_ctx));
+        if result.is_err()
+        {
+            *self = previous;
+        }
+        result
+    }
+
+    fn apply_modification(&mut self, event: &Event, modification: &EventModification, event_ctx: &mut Option<Context>) -> Result<(), EventError>
+    {
+        match modification
+        {
+            EventModification::AddProgress(target, value, clamp) =>
+            {
+                let amount = self.event_context(event, event_ctx)?.get_value(value)?
+                    .ok_or_else(|| DataError::value_dne(value.clone()))?;
+                // Progress which has not started yet counts as 0
+                let mut progress = self.ctx.get_value(target)?.unwrap_or(0.0) + amount;
+                if let Some((min, max)) = clamp
+                {
+                    progress = progress.max(*min).min(*max);
+                }
+                self.ctx.set_attribute(target, progress)?;
+            },
+            EventModification::CheckProgress(condition, modifications) =>
+            {
+                if self.event_context(event, event_ctx)?.eval_conditional(condition)?
+                {
+                    for m in modifications.iter()
+                    {
+                        self.apply_modification(event, m, event_ctx)?;
+                    }
+                }
+            },
+            EventModification::ClearProgress(t) =>
+            {
+                self.ctx.remove_attribute(t)?;
+            },
+            EventModification::AddToAttribute(t, v) =>
+            {
+                self.ctx.apply_effect(&Effect::AddToAttribute(t.clone(), *v))?;
+            },
+            EventModification::GrantAbility(ability) =>
+            {
+                if self.abilities.get_ability(ability.get_id()).is_some()
+                {
+                    return Err(EventError::AbilityAlreadyGranted(ability.get_id().clone()));
+                }
+                self.abilities.set_ability(ability.clone());
+            },
+            EventModification::GiveItem(item) => self.inventory.give_item(item.clone()),
+            EventModification::RevokeAbility(t) =>
+            {
+                if self.abilities.remove_ability(t).is_none()
+                {
+                    return Err(EventError::AbilityNotGranted(t.clone()));
+                }
+            },
+            EventModification::RemoveItem(t) =>
+            {
+                if self.inventory.remove_item(t).is_none()
+                {
+                    return Err(EventError::ItemNotFound(t.clone()));
+                }
+            },
+            EventModification::ChangeTimeContext(st) => self.time_context = Some(*st),
+        }
+
+        // Later modifications of the event read the changed values, so the context is built again for them
+        if matches!(modification, EventModification::AddProgress(..) | EventModification::ClearProgress(_) | EventModification::AddToAttribute(..)
+            | EventModification::GrantAbility(_) | EventModification::RevokeAbility(_))
+        {
+            *event_ctx = None;
+        }
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// The context values of an event are evaluated in, which is the full context
+    /// of the character with the event's own values layered on top. Built into "cached"
+    /// when it is needed, unless it is already built.
+    fn event_context<'a>(&self, event: &Event, cached: &'a mut Option<Context>) -> Result<&'a Context, DataError>
+    {
+        if cached.is_none()
+        {
+            let mut result = self.build_context()?;
+            result.layer_context(&event.ctx)?;
+            *cached = Some(result);
+        }
+        Ok(cached.as_ref().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod unit_tests
+{
+    use crate::api::{data::{conditional::Conditional, equation::Equation, tag::TagRegistry}, rpg::{inventory::Item, location::Location}};
+
+    use super::*;
+
+    fn tag(s: &str) -> Tag
+    {
+        Tag::from_str(s).unwrap()
+    }
+
+    fn date(year: i16, day: u16) -> Date
+    {
+        Date::new(tag("mundane").as_subtag_slice()[0], year, day)
+    }
+
+    fn event(id: &str, day: u16) -> Event
+    {
+        Event::new(tag("schema.test"), tag(id), date(0, day), Context::new())
+    }
+
+    fn puissant_latin() -> Ability
+    {
+        Ability::new(tag("virtue.puissant latin"), Context::new())
+            .with_passive_effect(Effect::AddToAttribute(tag("ability.latin.exp"), 3.0))
+    }
+
+    fn magus() -> Character
+    {
+        let mut ctx = Context::new();
+        ctx.set_attribute(&tag("ability.latin.exp"), 0.0).unwrap();
+        ctx.set_conditional(Conditional::new(tag("project.done"), "project.progress >= 10").unwrap()).unwrap();
+
+        let mut lab = event("event.lab", 3);
+        lab.ctx.set_attribute(&tag("event.lab total"), 12.0).unwrap();
+        let lab = lab
+            .with_modification(EventModification::AddProgress(tag("project.progress"), tag("event.lab total"), Some((0.0, 10.0))))
+            .with_modification(EventModification::CheckProgress(tag("project.done"), vec![
+                EventModification::GiveItem(Item::new(tag("item.talisman"), tag("spec.talisman"), 1)),
+                EventModification::ClearProgress(tag("project.progress")),
+            ]));
+
+        let mut character = Character::new(ctx, date(1, 0));
+        character.add_event(event("event.study", 1).with_modification(EventModification::AddToAttribute(tag("ability.latin.exp"), 5.0)));
+        character.add_event(event("event.virtue", 2).with_modification(EventModification::GrantAbility(puissant_latin())));
+        character.add_event(lab);
+        character.add_event(event("event.flaw", 4).with_modification(EventModification::RevokeAbility(tag("virtue.puissant latin"))));
+        character
+    }
+
+    #[test]
+    fn replay_test()
+    {
+        let mut character = magus();
+        let data = character.get_final_data().unwrap();
+        assert_eq!(data.get_context().get_value(&tag("ability.latin.exp")).unwrap(), Some(5.0));
+        assert!(data.get_abilities().get_ability(&tag("virtue.puissant latin")).is_none());
+        assert_eq!(data.get_inventory().get_item(&tag("item.talisman")).map(|i| i.get_count()), Some(1));
+        assert!(!data.get_context().has_attribute(&tag("project.progress")));
+        assert!(character.get_invalid_events().unwrap().is_empty());
+
+        // Only the events up to the current date are applied
+        character.set_date(date(0, 2));
+        let data = character.get_final_data().unwrap();
+        assert_eq!(data.build_context().unwrap().get_value(&tag("ability.latin.exp")).unwrap(), Some(8.0));
+        assert!(data.get_inventory().get_item(&tag("item.talisman")).is_none());
+    }
+
+    #[test]
+    fn edit_event_test()
+    {
+        let mut character = magus();
+        let edited = event("event.virtue", 2).with_modification(EventModification::GrantAbility(Ability::new(tag("virtue.affinity"), Context::new())));
+        let (old, invalid) = character.edit_event(edited).unwrap().unwrap();
+        assert_eq!(old.id, tag("event.virtue"));
+
+        // Revoking the virtue is now invalid, but the events around it are still applied
+        assert_eq!(invalid, vec![InvalidEvent { index: 3, id: tag("event.flaw"), error: EventError::AbilityNotGranted(tag("virtue.puissant latin")) }]);
+        let data = character.get_final_data().unwrap();
+        assert!(data.get_abilities().get_ability(&tag("virtue.affinity")).is_some());
+        assert!(data.get_inventory().get_item(&tag("item.talisman")).is_some());
+
+        // Invalid events are skipped as a whole
+        character.add_event(event("event.trade", 5)
+            .with_modification(EventModification::AddToAttribute(tag("ability.latin.exp"), 1.0))
+            .with_modification(EventModification::RemoveItem(tag("item.missing"))));
+        assert_eq!(character.get_invalid_events().unwrap().len(), 2);
+        assert_eq!(character.get_final_data().unwrap().get_context().get_value(&tag("ability.latin.exp")).unwrap(), Some(5.0));
+
+        let (_, invalid) = character.remove_event(&tag("event.flaw")).unwrap().unwrap();
+        assert_eq!(invalid.iter().map(|e| e.id.clone()).collect::<Vec<_>>(), vec![tag("event.trade")]);
+        assert!(character.edit_event(event("event.missing", 0)).unwrap().is_none());
+    }
+
+    /// Tests an event moved to another date is replayed in the place of its new date
+    #[test]
+    fn edit_event_date_test()
+    {
+        let mut character = magus().with_snapshot_interval(1);
+        character.get_final_data().unwrap();
+        let moved = event("event.virtue", 5).with_modification(EventModification::GrantAbility(puissant_latin()));
+        let (_, invalid) = character.edit_event(moved.clone()).unwrap().unwrap();
+        let ids: Vec<Tag> = character.get_timeline().iter().map(|e| e.id.clone()).collect();
+        assert_eq!(ids, vec![tag("event.study"), tag("event.lab"), tag("event.flaw"), tag("event.virtue")]);
+        assert_eq!(invalid, vec![InvalidEvent { index: 2, id: tag("event.flaw"), error: EventError::AbilityNotGranted(tag("virtue.puissant latin")) }]);
+
+        // The snapshots of the old places are not used
+        let mut replayed = magus().with_snapshot_interval(0);
+        replayed.edit_event(moved).unwrap();
+        assert_eq!(character.get_final_data().unwrap(), replayed.get_final_data().unwrap());
+
+        // Moving it back to its old date restores its place
+        let (_, invalid) = character.edit_event(event("event.virtue", 2).with_modification(EventModification::GrantAbility(puissant_latin()))).unwrap().unwrap();
+        assert!(invalid.is_empty());
+        assert_eq!(character.get_timeline(), magus().get_timeline());
+    }
+
+    /// Tests events after the current date are skipped, even when events after them in the timeline are not
+    #[test]
+    fn future_event_test()
+    {
+        let mut character = magus().with_snapshot_interval(1);
+        let future = Event::new(tag("schema.test"), tag("event.future"), date(2, 0), Context::new())
+            .with_modification(EventModification::AddToAttribute(tag("ability.latin.exp"), 100.0));
+        character.insert_event(0, future);
+        assert_eq!(character.get_final_data().unwrap().get_context().get_value(&tag("ability.latin.exp")).unwrap(), Some(5.0));
+        // No snapshot holds the events after the skipped one, as it would not be valid once the skipped event takes place
+        assert!(character.snapshots.is_empty());
+
+        character.set_date(date(2, 0));
+        assert_eq!(character.get_final_data().unwrap().get_context().get_value(&tag("ability.latin.exp")).unwrap(), Some(105.0));
+        assert_eq!(character.snapshots.len(), 5);
+    }
+
+    #[test]
+    fn snapshot_test()
+    {
+        let mut character = magus().with_snapshot_interval(1);
+        character.get_final_data().unwrap();
+        assert_eq!(character.snapshots.iter().map(|s| s.replayed).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+
+        // Moving to an earlier date starts from a snapshot, giving the same data as a full replay
+        let mut replayed = magus().with_snapshot_interval(0);
+        for d in [date(0, 2), date(0, 0), date(1, 0)]
+        {
+            character.set_date(d);
+            replayed.set_date(d);
+            assert_eq!(character.get_final_data().unwrap(), replayed.get_final_data().unwrap());
+        }
+        assert!(replayed.snapshots.is_empty());
+
+        // Only the snapshots which replayed an edited or inserted event are replaced
+        let first = character.snapshots[0].clone();
+        let edited = event("event.virtue", 2).with_modification(EventModification::GrantAbility(Ability::new(tag("virtue.affinity"), Context::new())));
+        character.edit_event(edited.clone()).unwrap();
+        replayed.edit_event(edited).unwrap();
+        assert_eq!(character.snapshots[0], first);
+        assert_eq!(character.snapshots.len(), 4);
+        assert_eq!(character.get_invalid_events().unwrap(), replayed.get_invalid_events().unwrap());
+
+        let study = event("event.study again", 1).with_modification(EventModification::AddToAttribute(tag("ability.latin.exp"), 2.0));
+        character.insert_event(1, study.clone());
+        replayed.insert_event(1, study);
+        assert_eq!(character.snapshots, vec![first]);
+        assert_eq!(character.get_final_data().unwrap(), replayed.get_final_data().unwrap());
+        assert_eq!(character.snapshots.len(), 5);
+    }
+
+    #[test]
+    fn serde_snapshot_interval_test()
+    {
+        let mut registry = TagRegistry::new();
+        let mundane = registry.get_or_register_subtag("mundane").unwrap();
+        let character = Character::new(Context::new(), Date::new(mundane, 0, 0)).with_snapshot_interval(4);
+        let json = registry.to_json_string(&character).unwrap();
+        let loaded: Character = registry.from_json_str(&json).unwrap();
+        assert_eq!(loaded.snapshot_interval, 4);
+
+        // Characters saved without an interval take the default one
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        value.as_object_mut().unwrap().remove("snapshot_interval");
+        let loaded: Character = registry.from_json_str(&value.to_string()).unwrap();
+        assert_eq!(loaded.snapshot_interval, DEFAULT_SNAPSHOT_INTERVAL);
+    }
+
+    /// Tests layered data applies to the character's data until it is removed, such as when the character leaves a location
+    #[test]
+    fn layer_test()
+    {
+        let mut character = magus();
+        let mut ruleset = Context::new();
+        ruleset.set_equation(Equation::new(tag("ability.latin"), "ability.latin.exp / 5").unwrap()).unwrap();
+        character.layer_ctx(ruleset).unwrap();
+        assert_eq!(character.get_final_data().unwrap().get_context().get_value(&tag("ability.latin")).unwrap(), Some(1.0));
+
+        let mut aura = Context::new();
+        aura.set_attribute(&tag("aura.magic"), 3.0).unwrap();
+        let covenant = Location::new("Covenant".to_string(), tag("location.covenant"), aura);
+        let covenant_layer = character.layer_ctx(covenant.as_layer()).unwrap();
+        let ctx = character.get_final_data().unwrap().get_context();
+        assert!(ctx.has_tag(&tag("location.covenant")));
+        assert_eq!(ctx.get_value(&tag("aura.magic")).unwrap(), Some(3.0));
+
+        assert_eq!(character.remove_ctx(covenant_layer).unwrap(), Some(covenant.as_layer()));
+        assert_eq!(character.remove_ctx(covenant_layer).unwrap(), None);
+        let ctx = character.get_final_data().unwrap().get_context();
+        assert!(!ctx.has_tag(&tag("location.covenant")));
+        assert!(!ctx.has_value(&tag("aura.magic")));
+        assert_eq!(ctx.get_value(&tag("ability.latin")).unwrap(), Some(1.0));
+
+        // Data which can not be applied to the character is not kept
+        let mut cyclic = Context::new();
+        cyclic.set_equation(Equation::new(tag("ability.latin.exp"), "ability.latin * 5").unwrap()).unwrap();
+        assert!(character.layer_ctx(cyclic).is_err());
+        assert_eq!(character.get_final_data().unwrap().get_context().get_value(&tag("ability.latin.exp")).unwrap(), Some(5.0));
+    }
+}
@@ -3,7 +3,7 @@ use std::{collections::HashMap, rc::Rc};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 
-use crate::api::{data::{conditional::Conditional, context::Context, effect::Effect, error::{DataError, ParseError}, tag::{Subtag, Tag, TagTemplate}}, rpg::{ability::Ability, inventory::Item, timeline::Date}, };
+use crate::api::{data::{conditional::Conditional, context::Context, effect::Effect, error::{DataError, ParseError}, tag::{RemapTags, Subtag, Tag, TagMapping, TagTemplate}}, rpg::{ability::Ability, inventory::Item, timeline::Date}, };
 
 /// This is an instance of an Event using specifications from the EventSchema.
 /// It holds the date it took place and all the modifications performed.
@@ -22,9 +22,55 @@ pub struct Event
 
 impl Event
 {
-    pub fn get_event_modifications(&self, character_ctx: &Context) -> Vec<EventModification>
+    pub fn new(schema: Tag, id: Tag, date: Date, ctx: Context) -> Event
     {
-        todo!()
+        Event { schema, id, date, ctx, modifications: vec![] }
+    }
+
+    pub fn with_modification(mut self, modification: EventModification) -> Self
+    {
+        self.modifications.push(modification);
+        self
+    }
+
+    /// The modifications of this event, in the order they are applied to a character.
+    /// The values of modifications (such as the progress added) are evaluated when they are
+    /// applied, as they depend on the character's data at that point of the timeline.
+    pub fn get_event_modifications(&self) -> &[EventModification]
+    {
+        &self.modifications
+    }
+}
+
+/// The reason an event can not be applied to a character's data.
+/// Editing an earlier event of a timeline can make later events invalid,
+/// such as revoking an ability which is no longer granted.
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+pub enum EventError
+{
+    AbilityAlreadyGranted(Tag),
+    AbilityNotGranted(Tag),
+    ItemNotFound(Tag),
+    Data(DataError),
+}
+
+impl From<DataError> for EventError
+{
+    fn from(value: DataError) -> Self
+    {
+        EventError::Data(value)
+    }
+}
+
+impl RemapTags for Event
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        self.schema.remap_tags(remap);
+        self.id.remap_tags(remap);
+        self.date.remap_tags(remap);
+        self.ctx.remap_tags(remap);
+        self.modifications.remap_tags(remap);
     }
 }
 
@@ -51,6 +97,30 @@ pub enum EventModification
     ChangeTimeContext(Subtag),
 }
 
+impl RemapTags for EventModification
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        match self
+        {
+            EventModification::AddProgress(target, value, _) =>
+            {
+                target.remap_tags(remap);
+                value.remap_tags(remap);
+            },
+            EventModification::CheckProgress(conditional, modifications) =>
+            {
+                conditional.remap_tags(remap);
+                modifications.remap_tags(remap);
+            },
+            EventModification::ClearProgress(t) | EventModification::AddToAttribute(t, _) | EventModification::RevokeAbility(t) | EventModification::RemoveItem(t) => t.remap_tags(remap),
+            EventModification::GrantAbility(ability) => ability.remap_tags(remap),
+            EventModification::GiveItem(item) => item.remap_tags(remap),
+            EventModification::ChangeTimeContext(st) => st.remap_tags(remap),
+        }
+    }
+}
+
 /// An event schema is used to create an event during active gameplay.
 /// It contains the specifications for how to make an event of
 /// a specific type.
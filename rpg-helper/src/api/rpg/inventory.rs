@@ -2,7 +2,7 @@ use std::{collections::HashMap, rc::Rc};
 
 use serde::{Deserialize, Serialize};
 
-use crate::api::data::tag::Tag;
+use crate::api::data::tag::{RemapTags, Tag, TagMapping};
 
 /// An inventory is associated with a character. It contains
 /// a collection of items, which are identified by tag.
@@ -29,6 +29,51 @@ pub struct Inventory
     slots: HashMap<Tag, Item>, // Equiped items
 }
 
+impl Default for Inventory
+{
+    fn default() -> Self
+    {
+        Inventory::new()
+    }
+}
+
+impl Inventory
+{
+    pub fn new() -> Inventory
+    {
+        Inventory { items: vec![], slots: HashMap::new() }
+    }
+
+    pub fn get_item(&self, id: &Tag) -> Option<&Item>
+    {
+        self.items.iter().find(|i| &i.id == id)
+    }
+
+    /// Adds an item to the stored items. If an item with the same id
+    /// is already stored, the count of the given item is added to it instead.
+    pub fn give_item(&mut self, item: Item)
+    {
+        match self.items.iter_mut().find(|i| i.id == item.id)
+        {
+            Some(stored) => stored.count += item.count,
+            None => self.items.push(item),
+        }
+    }
+
+    /// Removes a stored item, along with any slot it is equiped in
+    pub fn remove_item(&mut self, id: &Tag) -> Option<Item>
+    {
+        let index = self.items.iter().position(|i| &i.id == id)?;
+        self.slots.retain(|_, i| &i.id != id);
+        Some(self.items.remove(index))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Item>
+    {
+        self.items.iter()
+    }
+}
+
 #[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
 pub struct Item
 {
@@ -37,6 +82,38 @@ pub struct Item
     count: u32,
 }
 
+impl Item
+{
+    pub fn new(id: Tag, spec: Tag, count: u32) -> Item
+    {
+        Item { id, spec, count }
+    }
+
+    pub fn get_id(&self) -> &Tag
+    {
+        &self.id
+    }
+
+    pub fn get_spec(&self) -> &Tag
+    {
+        &self.spec
+    }
+
+    pub fn get_count(&self) -> u32
+    {
+        self.count
+    }
+}
+
+impl RemapTags for Item
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        self.id.remap_tags(remap);
+        self.spec.remap_tags(remap);
+    }
+}
+
 #[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
 pub struct ItemSpec
 {
@@ -1,4 +1,4 @@
-use crate::api::data::tag::Tag;
+use crate::api::data::{context::Context, tag::Tag};
 
 /// A location layers some contextual data ontop of the character
 /// when they are in the location.
@@ -14,4 +14,32 @@ pub struct Location
 {
     name: String,
     identifier: Tag,
-}
\ No newline at end of file
+    ctx: Context,
+}
+
+impl Location
+{
+    pub fn new(name: String, identifier: Tag, ctx: Context) -> Location
+    {
+        Location { name, identifier, ctx }
+    }
+
+    pub fn get_name(&self) -> &str
+    {
+        &self.name
+    }
+
+    pub fn get_identifier(&self) -> &Tag
+    {
+        &self.identifier
+    }
+
+    /// The data layered onto a character while they are in this location,
+    /// which includes the location's tag. See `Character::layer_ctx`
+    pub fn as_layer(&self) -> Context
+    {
+        let mut result = self.ctx.clone();
+        result.add_explicit_tag(&self.identifier);
+        result
+    }
+}
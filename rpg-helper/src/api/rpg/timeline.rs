@@ -1,48 +1,484 @@
 use std::{cmp::Ordering, collections::{HashMap, HashSet}, rc::Rc};
 
-use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 
-use crate::api::{data::{attribute::AttributeSet, context::Context, equation::Equation, error::ParseError, tag::{Subtag, Tag}}, rpg::event::{Event}};
+use crate::api::{data::{conditional::Conditional, context::Context, equation::Equation, error::DataError, tag::{RemapTags, Subtag, Tag, TagMapping, TagRegistry}, template::Templated}, rpg::event::{Event, EventModification}};
 
 /// A simple wrapper around an array of events
 /// When owned by a character, the timeline represents
 /// the local time experience of the character. Events
 /// do not have to be in chronoligical order by the date
-/// ordering (although the front-end client will facilitate
-/// the creation of events such that this is handled to avoid
-/// chronological errors).
+/// ordering (although added events are placed in order with
+/// the events of their time context, see `Timeline::add_event`,
+/// and `Timeline::validate` finds chronological errors).
 #[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
 pub struct Timeline
 {
     events: Vec<Event>,
 }
 
+impl Default for Timeline
+{
+    fn default() -> Self
+    {
+        Timeline::new()
+    }
+}
+
 impl Timeline
 {
-    pub fn add_event(&mut self, e: Event)
+    pub fn new() -> Timeline
     {
-        self.events.push(e);
+        Timeline { events: vec![] }
     }
 
+    /// Adds an event after every event the character experienced so far, unless an event of the
+    /// same time context is dated after it, in which case it is added before the first such event.
+    /// This keeps the events of each time context in order. Returns the place of the event.
+    pub fn add_event(&mut self, e: Event) -> usize
+    {
+        let place = self.get_place(&e.date);
+        self.events.insert(place, e);
+        place
+    }
+
+    /// Inserts an event at a place in the timeline, even if the events of its time context
+    /// are then out of order (See `Timeline::validate`)
     pub fn insert_event(&mut self, index: usize, e: Event)
     {
         self.events.insert(index, e);
     }
 
+    pub fn get_event(&self, id: &Tag) -> Option<&Event>
+    {
+        self.events.iter().find(|e| &e.id == id)
+    }
+
+    /// The place of the event in the timeline
+    pub fn get_event_index(&self, id: &Tag) -> Option<usize>
+    {
+        self.events.iter().position(|e| &e.id == id)
+    }
+
+    /// Replaces the event with the same id as the given event. The event keeps its place in the
+    /// timeline, unless its date changed, in which case it is moved to the place of the new date.
+    /// Returns the old event, or None if there is no event with the id (in which case nothing changes).
+    pub fn replace_event(&mut self, e: Event) -> Option<Event>
+    {
+        let index = self.get_event_index(&e.id)?;
+        let old = self.events.remove(index);
+        // An event still in order with the events around it keeps its place
+        let place = if self.is_in_order(index, &e.date) { index } else { self.get_place(&e.date) };
+        self.events.insert(place, e);
+        Some(old)
+    }
+
+    /// The place of a new event of the date (See `Timeline::add_event`)
+    fn get_place(&self, date: &Date) -> usize
+    {
+        self.events.iter().position(|e| e.date.time_ctx_id == date.time_ctx_id && e.date > *date)
+            .unwrap_or(self.events.len())
+    }
+
+    /// Whether an event of the date placed at the index is in order with the events of its time context
+    fn is_in_order(&self, index: usize, date: &Date) -> bool
+    {
+        let same_context = |e: &&Event| e.date.time_ctx_id == date.time_ctx_id;
+        self.events[..index].iter().rfind(same_context).is_none_or(|e| e.date <= *date)
+            && self.events[index..].iter().find(same_context).is_none_or(|e| e.date >= *date)
+    }
+
+    pub fn remove_event(&mut self, id: &Tag) -> Option<Event>
+    {
+        let index = self.events.iter().position(|e| &e.id == id)?;
+        Some(self.events.remove(index))
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.events.is_empty()
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = &Event>
     {
         self.events.iter()
     }
 
+    /// Finds every problem with the timeline, in timeline order. See `TimelineError`
+    ///
+    /// The character starts in the time context of the first event, and moves to another
+    /// through `EventModification::ChangeTimeContext`. Modifications which depend on a check
+    /// (`EventModification::CheckProgress`) are assumed to take place.
+    pub fn validate(&self) -> Vec<TimelineError>
+    {
+        let mut result = vec![];
+        let mut ids: HashMap<&Tag, usize> = HashMap::new();
+        let mut latest: HashMap<Subtag, (usize, Date)> = HashMap::new();
+        let mut state = ValidationState
+        {
+            time_context: self.events.first().map(|e| e.date.time_ctx_id),
+            abilities: HashSet::new(),
+            items: HashSet::new(),
+        };
+
+        for (index, e) in self.events.iter().enumerate()
+        {
+            if let Some(first) = ids.get(&e.id)
+            {
+                result.push(TimelineError::DuplicateId(index, *first));
+            }
+            else
+            {
+                ids.insert(&e.id, index);
+            }
+
+            if let Some(expected) = state.time_context
+            {
+                if e.date.time_ctx_id != expected
+                {
+                    result.push(TimelineError::WrongTimeContext(index, expected));
+                }
+            }
+
+            match latest.get(&e.date.time_ctx_id)
+            {
+                Some((earlier, date)) if e.date < *date => result.push(TimelineError::OutOfOrder(index, *earlier)),
+                _ => { latest.insert(e.date.time_ctx_id, (index, e.date)); },
+            }
+
+            Self::validate_modifications(index, e.get_event_modifications(), &mut state, &mut result);
+        }
+        result
+    }
+
+    /// Checks the modifications of the event at the index in the order they are applied,
+    /// including the modifications of each check in place of the check
+    fn validate_modifications(index: usize, modifications: &[EventModification], state: &mut ValidationState, result: &mut Vec<TimelineError>)
+    {
+        for m in modifications.iter()
+        {
+            match m
+            {
+                EventModification::CheckProgress(_, checked) => Self::validate_modifications(index, checked, state, result),
+                EventModification::GrantAbility(ability) => { state.abilities.insert(ability.get_id().clone()); },
+                // Revoked abilities and removed items are taken out of the sets as they are checked
+                EventModification::RevokeAbility(t) if !state.abilities.remove(t) => result.push(TimelineError::AbilityNotGranted(index, t.clone())),
+                EventModification::GiveItem(item) => { state.items.insert(item.get_id().clone()); },
+                EventModification::RemoveItem(t) if !state.items.remove(t) => result.push(TimelineError::ItemNotFound(index, t.clone())),
+                EventModification::ChangeTimeContext(st) => state.time_context = Some(*st),
+                _ => (),
+            }
+        }
+    }
+
+    /// Sorts the events of each time context by date, keeping events of the same date in their
+    /// current order. Events of different time contexts can not be compared, so each time
+    /// context keeps the places in the timeline its events already had.
+    ///
+    /// Returns the index of the first event which moved, if any did.
+    pub fn sort(&mut self) -> Option<usize>
+    {
+        let mut places: HashMap<Subtag, Vec<usize>> = HashMap::new();
+        for (index, e) in self.events.iter().enumerate()
+        {
+            places.entry(e.date.time_ctx_id).or_default().push(index);
+        }
+
+        let mut sorted: Vec<Option<Event>> = self.events.drain(..).map(Some).collect();
+        let mut first_moved = None;
+        for indices in places.values()
+        {
+            let mut order = indices.clone();
+            // Dates of the same time context always have an order
+            order.sort_by(|a, b| sorted[*a].as_ref().unwrap().date.partial_cmp(&sorted[*b].as_ref().unwrap().date).unwrap_or(Ordering::Equal));
+            let events: Vec<Event> = order.iter().map(|i| sorted[*i].take().unwrap()).collect();
+            for ((place, from), e) in indices.iter().zip(order.iter()).zip(events)
+            {
+                if place != from
+                {
+                    first_moved = Some(first_moved.unwrap_or(*place).min(*place));
+                }
+                sorted[*place] = Some(e);
+            }
+        }
+        self.events = sorted.into_iter().map(|e| e.unwrap()).collect();
+        first_moved
+    }
+
     pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Event>
     {
         self.events.iter_mut()
     }
 
+    /// Groups the events by the time context they are dated in. Groups are ordered by their
+    /// first event, and the events of each group are in timeline order.
     pub fn group_by_time_context(&self) -> Vec<(Tag, Vec<&Event>)>
     {
-        todo!()
+        let mut result: Vec<(Tag, Vec<&Event>)> = vec![];
+        for e in self.events.iter()
+        {
+            let time_ctx = Tag::from(e.date.time_ctx_id);
+            match result.iter_mut().find(|(t, _)| *t == time_ctx)
+            {
+                Some((_, events)) => events.push(e),
+                None => result.push((time_ctx, vec![e])),
+            }
+        }
+        result
+    }
+
+    /// Places every event on each time context it can be, in timeline order.
+    ///
+    /// The character starts in the time context of the first event, and moves to another through
+    /// `EventModification::ChangeTimeContext`. They enter the new time context at the date on it of the
+    /// event which moved them, or at the first event dated in it if that event could not be placed on it.
+    /// Time passing in the new time context is converted through the map to find the dates on the
+    /// others. Time contexts without a mapping to where the character is can not be placed on, and
+    /// events dated in a time context the character is not in are only placed on their own date.
+    pub fn place_events(&self, map: &TimeContextMap) -> Vec<MultiContextDate>
+    {
+        let mut result: Vec<MultiContextDate> = vec![];
+        // Where the character entered their current time context, with the dates on the others when they did
+        let mut entry: Option<(Date, Vec<Date>)> = None;
+        // The time context the character is moving to, with the dates of the moment they left
+        let mut moving: Option<(Subtag, Vec<Date>)> = None;
+        for e in self.events.iter()
+        {
+            if let Some((_, left)) = moving.take_if(|(target, _)| *target == e.date.time_ctx_id)
+            {
+                let entry_date = left.iter().find(|d| d.time_ctx_id == e.date.time_ctx_id).copied().unwrap_or(e.date);
+                entry = Some((entry_date, left.into_iter().filter(|d| d.time_ctx_id != e.date.time_ctx_id).collect()));
+            }
+            else if entry.is_none()
+            {
+                entry = Some((e.date, vec![]));
+            }
+
+            let mut dates = vec![e.date];
+            if let Some((entry_date, others)) = &entry
+            {
+                if let Some(passed) = map.days_between(entry_date, &e.date)
+                {
+                    dates.extend(others.iter().filter_map(|d| map.add_days(d, map.convert_days(e.date.time_ctx_id, passed, d.time_ctx_id)?)));
+                }
+            }
+            if let Some(target) = Self::find_time_context_change(e.get_event_modifications())
+            {
+                moving = Some((target, dates.clone()));
+            }
+            result.push(MultiContextDate { dates });
+        }
+        result
+    }
+
+    /// The time context the modifications move the character to last, including those of checks (which are assumed to take place)
+    fn find_time_context_change(modifications: &[EventModification]) -> Option<Subtag>
+    {
+        modifications.iter().rev().find_map(|m| match m
+        {
+            EventModification::ChangeTimeContext(st) => Some(*st),
+            EventModification::CheckProgress(_, checked) => Self::find_time_context_change(checked),
+            _ => None,
+        })
+    }
+
+    /// Every event placed on the time context (See `Timeline::place_events`), ordered by their date on it.
+    /// Events of the same date keep their timeline order.
+    pub fn get_events_on(&self, time_ctx_id: Subtag, map: &TimeContextMap) -> Vec<(Date, &Event)>
+    {
+        let mut result: Vec<(Date, &Event)> = self.place_events(map).into_iter()
+            .zip(self.events.iter())
+            .filter_map(|(placed, e)| placed.get_date(time_ctx_id).map(|d| (d, e)))
+            .collect();
+        result.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        result
+    }
+}
+
+/// The dates of a single moment on several time contexts, such as an event in Faerie
+/// along with the date it is in the mundane world. See `Timeline::place_events`
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+pub struct MultiContextDate
+{
+    dates: Vec<Date>,   // The date the moment was given in first, then the others it could be placed on
+}
+
+impl MultiContextDate
+{
+    /// The date the moment was given in, such as the date of an event
+    pub fn get_primary_date(&self) -> Date
+    {
+        self.dates[0]
+    }
+
+    pub fn get_date(&self, time_ctx_id: Subtag) -> Option<Date>
+    {
+        self.dates.iter().find(|d| d.time_ctx_id == time_ctx_id).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Date>
+    {
+        self.dates.iter()
+    }
+}
+
+/// A length of time in a time context
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone, Copy)]
+pub struct TimeSpan
+{
+    pub years: i32,
+    pub days: i32,
+}
+
+impl TimeSpan
+{
+    pub fn years(years: i32) -> TimeSpan
+    {
+        TimeSpan { years, days: 0 }
+    }
+
+    pub fn days(days: i32) -> TimeSpan
+    {
+        TimeSpan { years: 0, days }
+    }
+
+    pub fn to_days(&self, days_per_year: u16) -> i64
+    {
+        self.years as i64 * days_per_year as i64 + self.days as i64
+    }
+}
+
+/// How time passes between time contexts, used to place events on every time context.
+/// Ex: "entering Faerie for 1 day costs 7 years mundane"
+///
+/// Each time context needs its number of days per year, so a date can be counted in days.
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize, Clone)]
+pub struct TimeContextMap
+{
+    days_per_year: HashMap<Subtag, u16>,
+    mappings: Vec<TimeContextMapping>,
+}
+
+/// While `from_span` passes in the `from` time context, `to_span` passes in the `to` time context
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+struct TimeContextMapping
+{
+    from: Subtag,
+    from_span: TimeSpan,
+    to: Subtag,
+    to_span: TimeSpan,
+}
+
+impl TimeContextMap
+{
+    pub fn new() -> TimeContextMap
+    {
+        TimeContextMap { days_per_year: HashMap::new(), mappings: vec![] }
+    }
+
+    pub fn with_time_context(mut self, time_ctx_id: Subtag, days_per_year: u16) -> Self
+    {
+        self.days_per_year.insert(time_ctx_id, days_per_year);
+        self
+    }
+
+    /// Adds how time passes between two time contexts. The mapping works in both directions,
+    /// so time passing in `to` is converted back to `from` as well.
+    pub fn with_mapping(mut self, from: Subtag, from_span: TimeSpan, to: Subtag, to_span: TimeSpan) -> Self
+    {
+        self.mappings.retain(|m| (m.from, m.to) != (from, to) && (m.from, m.to) != (to, from));
+        self.mappings.push(TimeContextMapping { from, from_span, to, to_span });
+        self
+    }
+
+    pub fn get_days_per_year(&self, time_ctx_id: Subtag) -> Option<u16>
+    {
+        self.days_per_year.get(&time_ctx_id).copied()
+    }
+
+    /// The days from one date to another of the same time context
+    pub fn days_between(&self, from: &Date, to: &Date) -> Option<i64>
+    {
+        if from.time_ctx_id != to.time_ctx_id
+        {
+            return None;
+        }
+        let days_per_year = self.get_days_per_year(from.time_ctx_id)?;
+        Some(to.to_days(days_per_year) - from.to_days(days_per_year))
+    }
+
+    pub fn add_days(&self, date: &Date, days: i64) -> Option<Date>
+    {
+        let days_per_year = self.get_days_per_year(date.time_ctx_id)?;
+        Some(Date::from_days(date.time_ctx_id, date.to_days(days_per_year) + days, days_per_year))
+    }
+
+    /// Converts days passing in one time context to the days passing in another, rounded down.
+    /// Returns None if there is no mapping between them.
+    pub fn convert_days(&self, from: Subtag, days: i64, to: Subtag) -> Option<i64>
+    {
+        if from == to
+        {
+            return Some(days);
+        }
+        let (from_days, to_days) = self.mappings.iter().find_map(|m|
+            if m.from == from && m.to == to
+            {
+                Some((m.from_span.to_days(self.get_days_per_year(from)?), m.to_span.to_days(self.get_days_per_year(to)?)))
+            }
+            else if m.from == to && m.to == from
+            {
+                Some((m.to_span.to_days(self.get_days_per_year(from)?), m.from_span.to_days(self.get_days_per_year(to)?)))
+            }
+            else
+            {
+                None
+            }
+        )?;
+        if from_days == 0
+        {
+            return None;
+        }
+        Some((days * to_days).div_euclid(from_days))
+    }
+}
+
+/// What the character has at a point of the timeline, as far as `Timeline::validate` checks
+struct ValidationState
+{
+    time_context: Option<Subtag>,
+    abilities: HashSet<Tag>,
+    items: HashSet<Tag>,
+}
+
+/// A problem found by `Timeline::validate`. Each contains the index of the event with the problem first.
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+pub enum TimelineError
+{
+    /// The event is dated before an earlier event of the same time context.
+    /// Contains the index of the earlier event.
+    OutOfOrder(usize, usize),
+    /// The event is dated in a time context other than the one the character is in.
+    /// Contains the time context expected.
+    WrongTimeContext(usize, Subtag),
+    /// An ability is revoked which is not granted at that point of the timeline
+    AbilityNotGranted(usize, Tag),
+    /// An item is removed which was not given at that point of the timeline
+    ItemNotFound(usize, Tag),
+    /// The event has the same id as an earlier event. Contains the index of the earlier event.
+    DuplicateId(usize, usize),
+}
+
+impl RemapTags for Timeline
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        self.events.remap_tags(remap);
     }
 }
 
@@ -72,10 +508,17 @@ pub enum TimeContext
 /// 
 /// This allows an Event Interval for a Calendar context to be defined by an
 /// interval of days in a year [start, end).
+///
+/// Days are grouped into months as they are added, while seasons cover any range of days.
+/// The day of a `Date` is the index of the day in the calendar, so conditional days
+/// (such as a leap day) keep the index of the days after them the same every year.
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
 pub struct Calendar
 {
     time_ctx_id: Subtag,
     days: Vec<Day>,
+    months: Vec<CalendarPeriod>,
+    seasons: Vec<CalendarPeriod>,
     // This ctx is appended to the ruleset context with the prefix
     // timeline.[time_ctx_id].*
     // The current day and year are stored in:
@@ -95,14 +538,26 @@ pub struct Calendar
     // These conditionals can also be used to mark future story events? Maybe
     ctx: Context,
     intervals: Vec<EventInterval>,
+    tags: DateTags,
 }
 
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
 pub struct Day
 {
     // A day could be conditional, such as the leap year day. Such a condition
     // uses timeline.[time_ctx_id].year as the only accessible value
     
-    name: Option<Subtag>,
+    name: Option<String>,
+    condition: Option<Conditional>,
+}
+
+/// A named range of days in a year, such as a month or a season. Covers the days [start, end)
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+pub struct CalendarPeriod
+{
+    name: String,
+    start: u16,
+    end: u16,
 }
 
 /// The event interval is the range of time over which
@@ -116,12 +571,494 @@ pub struct Day
 /// For this to work, an EventInterval compares two dates
 /// to see if the two dates are considered in the same event
 /// interval.
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
 pub struct EventInterval
 {
     start: u16,
     end: u16,
 }
 
+/// How a date is written by `Calendar::format_date`
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone, Copy)]
+pub enum DateFormat
+{
+    /// Ex: "1220"
+    Year,
+    /// Ex: "Spring 1220"
+    Season,
+    /// The day of the month (from 1) then the month. Ex: "21 March 1220"
+    Month,
+    /// The name of the day, or the same as `DateFormat::Month` for days without a name. Ex: "Midsummer 1220"
+    Day,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+pub enum CalendarError
+{
+    /// The date is of another time context, or its day is not in the calendar
+    /// (such as a leap day in a year without one)
+    DateNotInCalendar(Date),
+    /// The year has no days, so dates can not move through it
+    EmptyYear(i16),
+    /// The date is before or after the years a date can hold
+    YearOutOfRange(i64),
+    /// The string can not be read as a date of the calendar
+    InvalidDateString(String),
+    Data(DataError),
+}
+
+impl From<DataError> for CalendarError
+{
+    fn from(value: DataError) -> Self
+    {
+        CalendarError::Data(value)
+    }
+}
+
+impl Day
+{
+    pub fn new() -> Day
+    {
+        Day { name: None, condition: None }
+    }
+
+    pub fn with_name(mut self, name: &str) -> Self
+    {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Makes the day only exist in years where the condition is true.
+    /// The condition can only read timeline.[time_ctx_id].year and the values of the calendar's context.
+    pub fn with_condition(mut self, condition: Conditional) -> Self
+    {
+        self.condition = Some(condition);
+        self
+    }
+
+    pub fn get_name(&self) -> Option<&str>
+    {
+        self.name.as_deref()
+    }
+}
+
+impl Default for Day
+{
+    fn default() -> Self
+    {
+        Day::new()
+    }
+}
+
+impl CalendarPeriod
+{
+    pub fn new(name: &str, start: u16, end: u16) -> CalendarPeriod
+    {
+        CalendarPeriod { name: name.to_string(), start, end }
+    }
+
+    pub fn get_name(&self) -> &str
+    {
+        &self.name
+    }
+
+    pub fn contains(&self, day: u16) -> bool
+    {
+        self.start <= day && day < self.end
+    }
+}
+
+impl EventInterval
+{
+    pub fn new(start: u16, end: u16) -> EventInterval
+    {
+        EventInterval { start, end }
+    }
+
+    pub fn contains(&self, day: u16) -> bool
+    {
+        self.start <= day && day < self.end
+    }
+}
+
+/// The tags which the values of dates are stored in, registered in the ruleset's `TagRegistry`
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+pub struct DateTags
+{
+    pub timeline: Tag,
+    pub year: Tag,
+    pub month: Tag,
+    pub season: Tag,
+    pub day: Tag,
+    pub lhs: Tag,
+    pub rhs: Tag,
+    pub ordering: Tag,
+}
+
+impl DateTags
+{
+    pub fn new(registry: &mut TagRegistry) -> DateTags
+    {
+        // Each of these names is a valid tag
+        let mut tag = |name: &str| registry.get_or_register_tag(name).unwrap();
+        DateTags
+        {
+            timeline: tag("timeline"),
+            year: tag("year"),
+            month: tag("month"),
+            season: tag("season"),
+            day: tag("day"),
+            lhs: tag("lhs"),
+            rhs: tag("rhs"),
+            ordering: tag("date.ordering"),
+        }
+    }
+}
+
+impl Calendar
+{
+    pub fn new(time_ctx_id: Subtag, ctx: Context, registry: &mut TagRegistry) -> Calendar
+    {
+        Calendar { time_ctx_id, days: vec![], months: vec![], seasons: vec![], ctx, intervals: vec![], tags: DateTags::new(registry) }
+    }
+
+    /// Adds the days of a month to the end of the year
+    pub fn with_month(mut self, name: &str, days: Vec<Day>) -> Self
+    {
+        let start = self.days.len() as u16;
+        self.days.extend(days);
+        self.months.push(CalendarPeriod::new(name, start, self.days.len() as u16));
+        self
+    }
+
+    /// Adds a season covering the days [start, end) of the year
+    pub fn with_season(mut self, name: &str, start: u16, end: u16) -> Self
+    {
+        self.seasons.push(CalendarPeriod::new(name, start, end));
+        self
+    }
+
+    pub fn with_interval(mut self, interval: EventInterval) -> Self
+    {
+        self.intervals.push(interval);
+        self
+    }
+
+    pub fn get_time_context(&self) -> Subtag
+    {
+        self.time_ctx_id
+    }
+
+    pub fn get_day(&self, day: u16) -> Option<&Day>
+    {
+        self.days.get(day as usize)
+    }
+
+    /// The tag the year is stored in, timeline.[time_ctx_id].year
+    pub fn get_year_tag(&self) -> Tag
+    {
+        Tag::from(self.time_ctx_id).add_prefix(&self.tags.timeline).add_suffix(&self.tags.year)
+    }
+
+    /// The tag the day is stored in, timeline.[time_ctx_id].day
+    pub fn get_day_tag(&self) -> Tag
+    {
+        Tag::from(self.time_ctx_id).add_prefix(&self.tags.timeline).add_suffix(&self.tags.day)
+    }
+
+    /// The context of the calendar with the year and day of the date set
+    pub fn get_date_context(&self, date: &Date) -> Result<Context, DataError>
+    {
+        let mut result = self.ctx.clone();
+        result.set_attribute(&self.get_year_tag(), date.year as f32)?;
+        result.set_attribute(&self.get_day_tag(), date.day as f32)?;
+        Ok(result)
+    }
+
+    /// The days of the calendar which take place in the year, in order
+    pub fn get_days_in_year(&self, year: i16) -> Result<Vec<u16>, DataError>
+    {
+        let mut ctx = self.ctx.clone();
+        ctx.set_attribute(&self.get_year_tag(), year as f32)?;
+        let mut result = vec![];
+        for (i, d) in self.days.iter().enumerate()
+        {
+            let exists = match &d.condition
+            {
+                Some(c) => c.eval(&ctx)?,
+                None => true,
+            };
+            if exists
+            {
+                result.push(i as u16);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Checks the date is of this calendar, on a day which takes place in its year
+    pub fn validate_date(&self, date: &Date) -> Result<(), CalendarError>
+    {
+        self.position_in_year(date).map(|_| ())
+    }
+
+    /// If both dates are in the same year and event interval of this calendar
+    pub fn is_same_interval(&self, a: &Date, b: &Date) -> bool
+    {
+        a.time_ctx_id == self.time_ctx_id && b.time_ctx_id == self.time_ctx_id && a.year == b.year &&
+            self.intervals.iter().any(|i| i.contains(a.day) && i.contains(b.day))
+    }
+
+    /// Moves the date forward by the span (or back, for negative spans).
+    ///
+    /// Years are added first. If the day does not take place in the new year
+    /// (such as a leap day), the date moves to the next day which does.
+    pub fn add(&self, date: &Date, span: TimeSpan) -> Result<Date, CalendarError>
+    {
+        self.validate_date(date)?;
+        let mut year = to_year(date.year as i64 + span.years as i64)?;
+        let days = self.get_days_in_year(year)?;
+        let mut position = days.iter().position(|d| *d >= date.day).unwrap_or(days.len()) as i64 + span.days as i64;
+
+        // Move through the years until the position is inside one
+        loop
+        {
+            let count = self.get_days_in_year(year)?.len() as i64;
+            if count == 0
+            {
+                return Err(CalendarError::EmptyYear(year));
+            }
+            if position < 0
+            {
+                year = to_year(year as i64 - 1)?;
+                position += self.get_days_in_year(year)?.len() as i64;
+            }
+            else if position >= count
+            {
+                position -= count;
+                year = to_year(year as i64 + 1)?;
+            }
+            else
+            {
+                let day = self.get_days_in_year(year)?[position as usize];
+                return Ok(Date { time_ctx_id: self.time_ctx_id, year, day });
+            }
+        }
+    }
+
+    /// Moves the date back by the span. See `Calendar::add`
+    pub fn subtract(&self, date: &Date, span: TimeSpan) -> Result<Date, CalendarError>
+    {
+        self.add(date, TimeSpan { years: -span.years, days: -span.days })
+    }
+
+    /// The number of days from one date to another, which is negative if `to` is before `from`
+    pub fn days_between(&self, from: &Date, to: &Date) -> Result<i64, CalendarError>
+    {
+        let from_position = self.position_in_year(from)? as i64;
+        let to_position = self.position_in_year(to)? as i64;
+        let (start, end, sign) = if from.year <= to.year { (from.year, to.year, 1) } else { (to.year, from.year, -1) };
+        let mut years = 0;
+        for y in start..end
+        {
+            years += self.get_days_in_year(y)?.len() as i64;
+        }
+        Ok(sign * years + to_position - from_position)
+    }
+
+    pub fn format_date(&self, date: &Date, format: DateFormat) -> Result<String, CalendarError>
+    {
+        self.validate_date(date)?;
+        let month = self.months.iter().find(|m| m.contains(date.day));
+        let result = match format
+        {
+            DateFormat::Year => date.year.to_string(),
+            DateFormat::Season => match self.seasons.iter().find(|s| s.contains(date.day))
+            {
+                Some(season) => format!("{} {}", season.name, date.year),
+                None => date.year.to_string(),
+            },
+            DateFormat::Day if self.days[date.day as usize].name.is_some() =>
+                format!("{} {}", self.days[date.day as usize].name.as_ref().unwrap(), date.year),
+            DateFormat::Month | DateFormat::Day => match month
+            {
+                Some(month) => format!("{} {} {}", date.day - month.start + 1, month.name, date.year),
+                None => format!("{} {}", date.day + 1, date.year),
+            },
+        };
+        Ok(result)
+    }
+
+    /// Reads a date written the same as `Calendar::format_date`. Names are not case sensitive.
+    /// Seasons, months and years without a day are read as their first day which takes place.
+    /// Ex: "Spring 1220", "21 March 1220", "Midsummer 1220", "1220"
+    pub fn parse_date(&self, s: &str) -> Result<Date, CalendarError>
+    {
+        let invalid = || CalendarError::InvalidDateString(s.to_string());
+        let s = s.trim();
+        let (rest, year) = match s.rsplit_once(' ')
+        {
+            Some((rest, year)) => (rest.trim(), year),
+            None => ("", s),
+        };
+        let year: i16 = year.parse().map_err(|_| invalid())?;
+        let days = self.get_days_in_year(year)?;
+        let first_day_from = |start: u16| days.iter().find(|d| **d >= start).copied();
+
+        let day = if rest.is_empty()
+        {
+            days.first().copied()
+        }
+        else if let Some(period) = self.seasons.iter().chain(self.months.iter()).find(|p| p.name.eq_ignore_ascii_case(rest))
+        {
+            first_day_from(period.start).filter(|d| period.contains(*d))
+        }
+        else if let Some(day) = self.days.iter().position(|d| d.name.as_ref().is_some_and(|n| n.eq_ignore_ascii_case(rest)))
+        {
+            Some(day as u16)
+        }
+        else
+        {
+            // The day of a month, such as "21 March"
+            let (day, month) = rest.split_once(' ').ok_or_else(invalid)?;
+            let day: u16 = day.parse().map_err(|_| invalid())?;
+            let month = self.months.iter().find(|m| m.name.eq_ignore_ascii_case(month.trim())).ok_or_else(invalid)?;
+            Some(month.start + day.saturating_sub(1)).filter(|d| day > 0 && month.contains(*d))
+        };
+
+        let date = Date { time_ctx_id: self.time_ctx_id, year, day: day.ok_or_else(invalid)? };
+        self.validate_date(&date)?;
+        Ok(date)
+    }
+
+    /// The values of the date read by a `DateSpec`: the year, the month and season (counted from 1)
+    /// and the day (of the month, or of the year without months, counted from 1).
+    /// Months and seasons are left out when the calendar has none.
+    pub fn get_date_values(&self, date: &Date) -> Result<HashMap<Tag, f32>, CalendarError>
+    {
+        self.validate_date(date)?;
+        let mut result = HashMap::new();
+        result.insert(self.tags.year.clone(), date.year as f32);
+        let mut day = date.day + 1;
+        if let Some(month) = self.months.iter().position(|m| m.contains(date.day))
+        {
+            result.insert(self.tags.month.clone(), (month + 1) as f32);
+            day = date.day - self.months[month].start + 1;
+        }
+        if let Some(season) = self.seasons.iter().position(|s| s.contains(date.day))
+        {
+            result.insert(self.tags.season.clone(), (season + 1) as f32);
+        }
+        result.insert(self.tags.day.clone(), day as f32);
+        Ok(result)
+    }
+
+    /// The date spec ordering the dates of this calendar by the values of `Calendar::get_date_values`,
+    /// which only reads the month when the calendar has months.
+    /// Every month is counted as long as the longest one, so the distance between dates is approximate,
+    /// while their order is exact.
+    ///
+    /// The ordering is read through the registry, which must be the one the calendar was made with.
+    pub fn get_date_spec(&self, registry: &mut TagRegistry) -> DateSpec
+    {
+        let (required_values, ordering) = match self.months.iter().map(|m| m.end - m.start).max()
+        {
+            Some(month_length) =>
+            (
+                vec![self.tags.year.clone(), self.tags.month.clone(), self.tags.day.clone()],
+                format!("(rhs.year - lhs.year) * {} + (rhs.month - lhs.month) * {} + (rhs.day - lhs.day)", self.months.len() * month_length as usize, month_length),
+            ),
+            None =>
+            (
+                vec![self.tags.year.clone(), self.tags.day.clone()],
+                format!("(rhs.year - lhs.year) * {} + (rhs.day - lhs.day)", self.days.len().max(1)),
+            ),
+        };
+        // The ordering only reads the required values prefixed with lhs and rhs
+        DateSpec { required_values, ordering: Equation::new_with_registry(self.tags.ordering.clone(), &ordering, registry).unwrap(), tags: self.tags.clone() }
+    }
+
+    /// The position of the date among the days which take place in its year
+    fn position_in_year(&self, date: &Date) -> Result<usize, CalendarError>
+    {
+        if date.time_ctx_id != self.time_ctx_id
+        {
+            return Err(CalendarError::DateNotInCalendar(*date));
+        }
+        self.get_days_in_year(date.year)?.iter().position(|d| *d == date.day).ok_or(CalendarError::DateNotInCalendar(*date))
+    }
+}
+
+fn to_year(year: i64) -> Result<i16, CalendarError>
+{
+    i16::try_from(year).map_err(|_| CalendarError::YearOutOfRange(year))
+}
+
+/// Defines what values a date of a ruleset is made of, and how two dates are ordered.
+///
+/// The ordering equation finds the time from the `lhs` date to the `rhs` date, reading the
+/// required values prefixed with "lhs" or "rhs". Ex: "(rhs.year - lhs.year) * 365 + (rhs.day - lhs.day)"
+/// A positive result means the `lhs` date is earlier.
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone)]
+pub struct DateSpec
+{
+    pub required_values: Vec<Tag>,
+    pub ordering: Equation,
+    tags: DateTags,
+}
+
+impl DateSpec
+{
+    /// Fails if the ordering equation reads anything other than the required values prefixed with "lhs" or "rhs".
+    /// The ordering must be read through the same registry (see `Equation::new_with_registry`), as its tags are checked by id.
+    pub fn new(required_values: Vec<Tag>, ordering: Equation, registry: &mut TagRegistry) -> Result<DateSpec, DataError>
+    {
+        let result = DateSpec { required_values, ordering, tags: DateTags::new(registry) };
+        match result.ordering.check_only_allowed_tags(&result.get_allowed_tags())
+        {
+            Ok(()) => Ok(result),
+            Err(Templated::Complete(t)) => Err(DataError::value_dne(t)),
+            Err(Templated::Template(t)) => Err(DataError::StringInputInvalid(t.to_string())),
+        }
+    }
+
+    pub fn get_ordering_lhs_tag(&self) -> &Tag
+    {
+        &self.tags.lhs
+    }
+
+    pub fn get_ordering_rhs_tag(&self) -> &Tag
+    {
+        &self.tags.rhs
+    }
+
+    /// The tags the ordering equation can read, which are the required values prefixed with "lhs" and "rhs"
+    pub fn get_allowed_tags(&self) -> Vec<Tag>
+    {
+        self.required_values.iter().flat_map(|t| [t.add_prefix(&self.tags.lhs), t.add_prefix(&self.tags.rhs)]).collect()
+    }
+
+    /// The time from the `lhs` date to the `rhs` date, given the required values of each
+    /// (such as from `Calendar::get_date_values`)
+    pub fn distance(&self, lhs: &HashMap<Tag, f32>, rhs: &HashMap<Tag, f32>) -> Result<f32, DataError>
+    {
+        let mut ctx = Context::new();
+        for t in self.required_values.iter()
+        {
+            ctx.set_attribute(&t.add_prefix(&self.tags.lhs), *lhs.get(t).ok_or_else(|| DataError::value_dne(t.clone()))?)?;
+            ctx.set_attribute(&t.add_prefix(&self.tags.rhs), *rhs.get(t).ok_or_else(|| DataError::value_dne(t.clone()))?)?;
+        }
+        self.ordering.eval(&ctx)
+    }
+
+    pub fn compare(&self, lhs: &HashMap<Tag, f32>, rhs: &HashMap<Tag, f32>) -> Result<Ordering, DataError>
+    {
+        let distance = self.distance(lhs, rhs)?;
+        // A positive distance means the rhs date is later
+        Ok(0.0_f32.partial_cmp(&distance).unwrap_or(Ordering::Equal))
+    }
+}
+
 /// Dates are always measured in the context of a game, within a Time Context.
 /// The year value represents the time before or after the start date of the game.
 #[derive(Debug, Deserialize, PartialEq, Eq, Ord, Serialize, Clone, Copy)]
@@ -133,6 +1070,50 @@ pub struct Date
     day: u16,
 }
 
+impl Date
+{
+    pub fn new(time_ctx_id: Subtag, year: i16, day: u16) -> Date
+    {
+        Date { time_ctx_id, year, day }
+    }
+
+    pub fn get_time_context(&self) -> Subtag
+    {
+        self.time_ctx_id
+    }
+
+    /// The number of days since the start of year 0 (negative before it)
+    pub fn to_days(&self, days_per_year: u16) -> i64
+    {
+        self.year as i64 * days_per_year as i64 + self.day as i64
+    }
+
+    /// The date a number of days since the start of year 0. See `Date::to_days`
+    pub fn from_days(time_ctx_id: Subtag, days: i64, days_per_year: u16) -> Date
+    {
+        let days_per_year = days_per_year.max(1) as i64;
+        Date { time_ctx_id, year: days.div_euclid(days_per_year) as i16, day: days.rem_euclid(days_per_year) as u16 }
+    }
+
+    pub fn get_year(&self) -> i16
+    {
+        self.year
+    }
+
+    pub fn get_day(&self) -> u16
+    {
+        self.day
+    }
+}
+
+impl RemapTags for Date
+{
+    fn remap_tags(&mut self, remap: &dyn TagMapping)
+    {
+        self.time_ctx_id.remap_tags(remap);
+    }
+}
+
 /// Ordering assumes that dates have a matching time context
 /// If this is not the case, then the partial order will return None
 impl PartialOrd for Date
@@ -151,4 +1132,249 @@ impl PartialOrd for Date
         }
         self.day.partial_cmp(&other.day)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod unit_tests
+{
+    use crate::api::{data::context::Context, rpg::{ability::Ability, inventory::Item}};
+
+    use super::*;
+
+    fn tag(s: &str) -> Tag
+    {
+        Tag::from_str(s).unwrap()
+    }
+
+    fn date(time_ctx: &str, year: i16, day: u16) -> Date
+    {
+        Date::new(tag(time_ctx).as_subtag_slice()[0], year, day)
+    }
+
+    fn event(id: &str, date: Date) -> Event
+    {
+        Event::new(tag("schema.test"), tag(id), date, Context::new())
+    }
+
+    #[test]
+    fn validate_test()
+    {
+        let mut timeline = Timeline::new();
+        timeline.add_event(event("event.virtue", date("mundane", 0, 1))
+            .with_modification(EventModification::GrantAbility(Ability::new(tag("virtue.puissant latin"), Context::new())))
+            .with_modification(EventModification::GiveItem(Item::new(tag("item.talisman"), tag("spec.talisman"), 1))));
+        timeline.add_event(event("event.flaw", date("mundane", 0, 3))
+            .with_modification(EventModification::RevokeAbility(tag("virtue.puissant latin"))));
+        // Modifications are checked in order, so an item can be given and removed by the same event
+        timeline.add_event(event("event.trade", date("mundane", 0, 3))
+            .with_modification(EventModification::GiveItem(Item::new(tag("item.sword"), tag("spec.sword"), 1)))
+            .with_modification(EventModification::CheckProgress(tag("project.done"), vec![EventModification::RemoveItem(tag("item.sword"))])));
+        assert!(timeline.validate().is_empty());
+        timeline.remove_event(&tag("event.trade"));
+
+        // Inserting keeps the place given, even when out of order
+        timeline.insert_event(timeline.len(), event("event.study", date("mundane", 0, 2))
+            .with_modification(EventModification::RevokeAbility(tag("virtue.puissant latin")))
+            .with_modification(EventModification::CheckProgress(tag("project.done"), vec![EventModification::RemoveItem(tag("item.talisman"))])));
+        timeline.add_event(event("event.faerie", date("mundane", 0, 4))
+            .with_modification(EventModification::ChangeTimeContext(tag("faerie").as_subtag_slice()[0])));
+        timeline.add_event(event("event.study", date("mundane", 0, 5)));
+        timeline.add_event(event("event.dance", date("faerie", 0, 0))
+            .with_modification(EventModification::RemoveItem(tag("item.talisman"))));
+
+        assert_eq!(timeline.validate(), vec![
+            TimelineError::OutOfOrder(2, 1),
+            TimelineError::AbilityNotGranted(2, tag("virtue.puissant latin")),
+            TimelineError::DuplicateId(4, 2),
+            TimelineError::WrongTimeContext(4, tag("faerie").as_subtag_slice()[0]),
+            TimelineError::ItemNotFound(5, tag("item.talisman")),
+        ]);
+    }
+
+    #[test]
+    fn sort_test()
+    {
+        let mut timeline = Timeline::new();
+        for e in [event("event.c", date("mundane", 1, 0)), event("event.faerie", date("faerie", 5, 0)), event("event.a", date("mundane", 0, 3)),
+            event("event.b", date("mundane", 0, 3)), event("event.faerie earlier", date("faerie", 2, 0))]
+        {
+            timeline.insert_event(timeline.len(), e);
+        }
+
+        assert_eq!(timeline.sort(), Some(0));
+        // Each time context keeps its places, and events of the same date keep their order
+        let ids: Vec<Tag> = timeline.iter().map(|e| e.id.clone()).collect();
+        assert_eq!(ids, vec![tag("event.a"), tag("event.faerie earlier"), tag("event.b"), tag("event.c"), tag("event.faerie")]);
+        assert_eq!(timeline.sort(), None);
+    }
+
+    #[test]
+    fn add_event_test()
+    {
+        let mut timeline = Timeline::new();
+        assert_eq!(timeline.add_event(event("event.study", date("mundane", 0, 2))), 0);
+        assert_eq!(timeline.add_event(event("event.faerie", date("faerie", 5, 0))), 1);
+        // An event is added before the first later event of its time context, otherwise at the end
+        assert_eq!(timeline.add_event(event("event.virtue", date("mundane", 0, 1))), 0);
+        assert_eq!(timeline.add_event(event("event.flaw", date("mundane", 0, 2))), 3);
+        assert_eq!(timeline.add_event(event("event.lab", date("mundane", 0, 3))), 4);
+        assert!(timeline.validate().iter().all(|e| !matches!(e, TimelineError::OutOfOrder(..))));
+
+        // A replaced event moves only if it is out of order at its place
+        timeline.replace_event(event("event.flaw", date("mundane", 0, 3)));
+        assert_eq!(timeline.get_event_index(&tag("event.flaw")), Some(3));
+        timeline.replace_event(event("event.virtue", date("mundane", 0, 4)));
+        let ids: Vec<Tag> = timeline.iter().map(|e| e.id.clone()).collect();
+        assert_eq!(ids, vec![tag("event.study"), tag("event.faerie"), tag("event.flaw"), tag("event.lab"), tag("event.virtue")]);
+    }
+
+    #[test]
+    fn time_context_test()
+    {
+        let mundane = tag("mundane").as_subtag_slice()[0];
+        let fay = tag("fay").as_subtag_slice()[0];
+        let map = TimeContextMap::new()
+            .with_time_context(mundane, 365)
+            .with_time_context(fay, 365)
+            .with_mapping(fay, TimeSpan::days(1), mundane, TimeSpan::years(7));
+
+        let mut timeline = Timeline::new();
+        timeline.add_event(event("event.study", date("mundane", 1220, 10)));
+        timeline.add_event(event("event.enter faerie", date("mundane", 1220, 20))
+            .with_modification(EventModification::ChangeTimeContext(fay)));
+        timeline.add_event(event("event.revel", date("fay", 0, 0)));
+        timeline.add_event(event("event.leave faerie", date("fay", 0, 1))
+            .with_modification(EventModification::ChangeTimeContext(mundane)));
+        // The character is back in the mundane world at 1227 day 20, some days before their next event
+        timeline.add_event(event("event.return", date("mundane", 1227, 30)));
+        timeline.add_event(event("event.seven years", date("mundane", 1234, 20)));
+
+        let groups: Vec<(Tag, usize)> = timeline.group_by_time_context().into_iter().map(|(t, events)| (t, events.len())).collect();
+        assert_eq!(groups, vec![(tag("mundane"), 4), (tag("fay"), 2)]);
+
+        // A day in Faerie is seven years in the mundane world
+        let placed = timeline.place_events(&map);
+        assert_eq!(placed[0].iter().count(), 1);
+        assert_eq!(placed[1].get_date(fay), None);
+        assert_eq!(placed[2].get_date(mundane), Some(date("mundane", 1220, 20)));
+        assert_eq!(placed[3].get_primary_date(), date("fay", 0, 1));
+        assert_eq!(placed[3].get_date(mundane), Some(date("mundane", 1227, 20)));
+        // Time passes from when the character left Faerie, rather than from their first event back
+        assert_eq!(placed[4].get_date(fay), Some(date("fay", 0, 1)));
+        assert_eq!(placed[5].get_date(fay), Some(date("fay", 0, 2)));
+
+        let on_mundane: Vec<(Date, Tag)> = timeline.get_events_on(mundane, &map).into_iter().map(|(d, e)| (d, e.id.clone())).collect();
+        assert_eq!(on_mundane.len(), 6);
+        assert_eq!(on_mundane[3], (date("mundane", 1227, 20), tag("event.leave faerie")));
+        assert_eq!(timeline.get_events_on(fay, &map).len(), 4);
+
+        // Without a mapping, events can only be placed on the time context they are dated in
+        let unmapped = TimeContextMap::new().with_time_context(mundane, 365).with_time_context(fay, 365);
+        assert_eq!(timeline.get_events_on(mundane, &unmapped).len(), 4);
+
+        // Without a change of time context, an event dated in another is only placed on its own date
+        let mut wandering = Timeline::new();
+        wandering.add_event(event("event.study", date("mundane", 1220, 10)));
+        wandering.add_event(event("event.revel", date("fay", 0, 0)));
+        wandering.add_event(event("event.return", date("mundane", 1220, 20)));
+        let placed = wandering.place_events(&map);
+        assert_eq!(placed[1].iter().count(), 1);
+    }
+
+    /// A small calendar with a leap day at the end of February, every fourth year, with its tags registered in the registry
+    fn calendar(registry: &mut TagRegistry) -> Calendar
+    {
+        let leap_year = registry.get_or_register_tag("calendar.leap year").unwrap();
+        let leap = Conditional::new_with_registry(leap_year, "(rounddown(timeline.mundane.year / 4) * 4) == timeline.mundane.year", registry).unwrap();
+        let mut february = vec![Day::new(); 2];
+        february.push(Day::new().with_condition(leap));
+        Calendar::new(registry.get_or_register_subtag("mundane").unwrap(), Context::new(), registry)
+            .with_month("January", vec![Day::new().with_name("New Year"), Day::new(), Day::new()])
+            .with_month("February", february)
+            .with_month("March", vec![Day::new(); 3])
+            .with_season("Winter", 0, 6)
+            .with_season("Spring", 6, 9)
+            .with_interval(EventInterval::new(0, 6))
+            .with_interval(EventInterval::new(6, 9))
+    }
+
+    #[test]
+    fn calendar_test()
+    {
+        let mut registry = TagRegistry::new();
+        let calendar = calendar(&mut registry);
+        let fay = registry.get_or_register_subtag("fay").unwrap();
+        let date = |year: i16, day: u16| Date::new(calendar.get_time_context(), year, day);
+        assert_eq!(calendar.get_days_in_year(1219).unwrap().len(), 8);
+        assert_eq!(calendar.get_days_in_year(1220).unwrap().len(), 9);
+        assert!(calendar.validate_date(&date(1220, 5)).is_ok());
+        assert_eq!(calendar.validate_date(&date(1221, 5)), Err(CalendarError::DateNotInCalendar(date(1221, 5))));
+        assert!(calendar.validate_date(&Date::new(fay, 1220, 0)).is_err());
+
+        // Adding years from a leap day moves to the next day which takes place
+        assert_eq!(calendar.add(&date(1220, 5), TimeSpan { years: 1, days: 0 }).unwrap(), date(1221, 6));
+        assert_eq!(calendar.add(&date(1219, 4), TimeSpan { years: 0, days: 1 }).unwrap(), date(1219, 6));
+        assert_eq!(calendar.add(&date(1219, 8), TimeSpan { years: 0, days: 10 }).unwrap(), date(1221, 0));
+        assert_eq!(calendar.subtract(&date(1221, 0), TimeSpan { years: 0, days: 10 }).unwrap(), date(1219, 8));
+        assert_eq!(calendar.days_between(&date(1219, 8), &date(1221, 0)).unwrap(), 10);
+        assert_eq!(calendar.days_between(&date(1221, 0), &date(1219, 8)).unwrap(), -10);
+
+        let leap_day = date(1220, 5);
+        assert_eq!(calendar.format_date(&leap_day, DateFormat::Year).unwrap(), "1220");
+        assert_eq!(calendar.format_date(&leap_day, DateFormat::Season).unwrap(), "Winter 1220");
+        assert_eq!(calendar.format_date(&leap_day, DateFormat::Month).unwrap(), "3 February 1220");
+        assert_eq!(calendar.format_date(&date(1220, 0), DateFormat::Day).unwrap(), "New Year 1220");
+        for format in [DateFormat::Month, DateFormat::Day]
+        {
+            let s = calendar.format_date(&leap_day, format).unwrap();
+            assert_eq!(calendar.parse_date(&s).unwrap(), leap_day);
+        }
+        assert_eq!(calendar.parse_date("spring 1220").unwrap(), date(1220, 6));
+        assert_eq!(calendar.parse_date("new year -3").unwrap(), date(-3, 0));
+        assert!(calendar.parse_date("3 February 1221").is_err());
+        assert_eq!(calendar.parse_date("4 February 1220"), Err(CalendarError::InvalidDateString("4 February 1220".to_string())));
+
+        assert!(calendar.is_same_interval(&date(1220, 0), &leap_day));
+        assert!(!calendar.is_same_interval(&date(1220, 6), &leap_day));
+        assert!(!calendar.is_same_interval(&date(1221, 0), &leap_day));
+    }
+
+    #[test]
+    fn date_spec_test()
+    {
+        let mut registry = TagRegistry::new();
+        let calendar = calendar(&mut registry);
+        let date = |year: i16, day: u16| Date::new(calendar.get_time_context(), year, day);
+        let [year, month, season, day] = ["year", "month", "season", "day"].map(|s| registry.get_tag(s).unwrap().unwrap());
+        let spec = calendar.get_date_spec(&mut registry);
+        let before = calendar.get_date_values(&date(1219, 8)).unwrap();
+        let after = calendar.get_date_values(&date(1220, 0)).unwrap();
+        assert_eq!(after.get(&month), Some(&1.0));
+        assert_eq!(before.get(&day), Some(&3.0));
+        assert_eq!(spec.compare(&before, &after).unwrap(), Ordering::Less);
+        assert_eq!(spec.compare(&after, &before).unwrap(), Ordering::Greater);
+        assert_eq!(spec.compare(&after, &after).unwrap(), Ordering::Equal);
+        // The last day of a short month is still earlier than the first day of the next month
+        let short = calendar.get_date_values(&date(1219, 4)).unwrap();
+        let next = calendar.get_date_values(&date(1219, 6)).unwrap();
+        assert_eq!(spec.compare(&short, &next).unwrap(), Ordering::Less);
+
+        // Without months, the days are counted from the start of the year
+        let days = Calendar { days: vec![Day::new(); 5], ..Calendar::new(calendar.get_time_context(), Context::new(), &mut registry) };
+        let days_spec = days.get_date_spec(&mut registry);
+        let first = days.get_date_values(&date(1219, 4)).unwrap();
+        let second = days.get_date_values(&date(1220, 0)).unwrap();
+        assert_eq!(days_spec.required_values, vec![year.clone(), day.clone()]);
+        assert_eq!(first.get(&month), None);
+        assert_eq!(days_spec.distance(&first, &second).unwrap(), 1.0);
+
+        // The ordering can only read the required values of each date
+        let ordering_tag = registry.get_or_register_tag("date.ordering").unwrap();
+        let ordering = Equation::new_with_registry(ordering_tag.clone(), "(rhs.year - lhs.year) * 365 + (rhs.day - lhs.day)", &mut registry).unwrap();
+        assert!(DateSpec::new(vec![year.clone(), day], ordering.clone(), &mut registry).is_ok());
+        assert!(DateSpec::new(vec![year.clone()], ordering, &mut registry).is_err());
+        let missing = DateSpec::new(vec![year, season], Equation::new_with_registry(ordering_tag, "rhs.season - lhs.season", &mut registry).unwrap(), &mut registry).unwrap();
+        assert!(missing.distance(&before, &after).is_ok());
+        assert!(missing.distance(&HashMap::new(), &after).is_err());
+    }
+}